@@ -5,7 +5,8 @@
 
 const WINDOW_TITLE: &str = "Depth Buffer Cube";
 const WINDOW_WIDTH: u32 = 800;
-const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_HEIGHT: u32 = 600;
+const MSAA_SAMPLES: u8 = 4;
 
 use beryllium::{
   events::Event,
@@ -133,14 +134,22 @@ fn main() {
   }
   sdl.set_gl_context_flags(flags).unwrap();
 
-  let win = sdl
-    .create_gl_window(CreateWinArgs {
-      title: WINDOW_TITLE,
-      width: 800,
-      height: 600,
-      ..Default::default()
-    })
-    .expect("couldn't make a window and context");
+  // Ask for a multisampled window, so the cube edges aren't jagged. Not all
+  // drivers can do it, so if the window can't be made we try again without.
+  sdl.set_gl_multisample_buffers(1).unwrap();
+  sdl.set_gl_multisample_count(MSAA_SAMPLES).unwrap();
+  let win_args = || CreateWinArgs {
+    title: WINDOW_TITLE,
+    width: WINDOW_WIDTH as i32,
+    height: WINDOW_HEIGHT as i32,
+    ..Default::default()
+  };
+  let win = sdl.create_gl_window(win_args()).or_else(|_| {
+    sdl.set_gl_multisample_buffers(0).unwrap();
+    sdl.set_gl_multisample_count(0).unwrap();
+    sdl.create_gl_window(win_args())
+  });
+  let win = win.expect("couldn't make a window and context");
   win.set_swap_interval(GlSwapInterval::Vsync).unwrap();
 
   unsafe {
@@ -148,6 +157,7 @@ fn main() {
 
     glEnable(GL_DEPTH_TEST);
   }
+  learn::framebuffer::set_multisample(true);
 
   learn::clear_color(0.2, 0.3, 0.3, 1.0);
 
@@ -5,7 +5,7 @@
 
 const WINDOW_TITLE: &str = "Multi Cube";
 const WINDOW_WIDTH: u32 = 800;
-const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_HEIGHT: u32 = 600;
 
 use beryllium::{
   events::Event,
@@ -149,8 +149,8 @@ fn main() {
   let win = sdl
     .create_gl_window(CreateWinArgs {
       title: WINDOW_TITLE,
-      width: 800,
-      height: 600,
+      width: WINDOW_WIDTH as i32,
+      height: WINDOW_HEIGHT as i32,
       ..Default::default()
     })
     .expect("couldn't make a window and context");
@@ -5,10 +5,10 @@
 
 const WINDOW_TITLE: &str = "Mouse Look";
 const WINDOW_WIDTH: u32 = 800;
-const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_HEIGHT: u32 = 600;
 
 use beryllium::{
-  events::Event,
+  events::{Event, SDLK_TAB},
   init::InitFlags,
   video::{CreateWinArgs, GlContextFlags, GlProfile, GlSwapInterval},
   *,
@@ -19,7 +19,10 @@ use core::{
   ptr::null,
 };
 use learn::{
-  null_str, Buffer, BufferType, Shader, ShaderProgram, ShaderType, VertexArray,
+  debug_draw::{DebugDraw, DebugQueue, DebugStyle},
+  null_str,
+  state::{DepthState, RenderState, StateCache},
+  Buffer, BufferType, Shader, ShaderProgram, ShaderType, VertexArray,
 };
 use learn_opengl as learn;
 use ogl33::*;
@@ -149,8 +152,8 @@ fn main() {
   let win = sdl
     .create_gl_window(CreateWinArgs {
       title: WINDOW_TITLE,
-      width: 800,
-      height: 600,
+      width: WINDOW_WIDTH as i32,
+      height: WINDOW_HEIGHT as i32,
       ..Default::default()
     })
     .expect("couldn't make a window and context");
@@ -158,8 +161,6 @@ fn main() {
 
   unsafe {
     load_gl_with(|f_name| win.get_proc_address(f_name.cast()));
-
-    glEnable(GL_DEPTH_TEST);
   }
 
   learn::clear_color(0.2, 0.3, 0.3, 1.0);
@@ -283,11 +284,28 @@ fn main() {
   const MOUSE_SENSITIVITY: f32 = 0.2;
   sdl.set_relative_mouse_mode(true).unwrap();
 
+  // Tab turns on debug drawing: a floor grid, the world axes, a sphere
+  // around each cube, and the frustum the camera had when Tab was pressed,
+  // so you can look around and see where it was pointing.
+  let mut debug_draw = DebugDraw::new().unwrap();
+  let mut debug_queue = DebugQueue::new();
+  let mut frozen_frustum: Option<Mat4> = None;
+  let mut toggle_debug = false;
+  let mut cache = StateCache::new();
+  let scene_state = RenderState {
+    depth: DepthState { test: true, ..DepthState::default() },
+    ..RenderState::default()
+  };
+  let mut last_time = 0.0;
+
   'main_loop: loop {
     // handle events this frame
     while let Some((event, _timestamp)) = sdl.poll_events() {
       match event {
         Event::Quit => break 'main_loop,
+        Event::Key { pressed: true, repeat: 0, keycode: SDLK_TAB, .. } => {
+          toggle_debug = true;
+        }
         Event::MouseMotion { x_delta, y_delta, .. } => {
           view_pitch += (y_delta as f32) * MOUSE_SENSITIVITY;
           view_pitch = view_pitch.max(-89.0).min(89.0);
@@ -307,8 +325,34 @@ fn main() {
       view_pitch.to_radians(),
       view_yaw.to_radians(),
     ) * Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
+    let seconds = sdl.get_ticks() as f32 / 1_000.0;
+    let delta_time = seconds - last_time;
+    last_time = seconds;
+
+    if toggle_debug {
+      toggle_debug = false;
+      frozen_frustum = match frozen_frustum {
+        Some(_) => None,
+        None => Some(projection * view),
+      };
+    }
+    if let Some(frustum) = frozen_frustum {
+      let gray = DebugStyle::rgb(0.6, 0.6, 0.6);
+      debug_queue.grid(Vec3::new(0.0, -4.0, 0.0), 20, 1.0, gray);
+      debug_queue.axes(Mat4::identity(), 1.0, DebugStyle::default().overlay());
+      for position in CUBE_POSITIONS.iter().copied() {
+        // big enough for the cube's corners, however it's turned
+        let yellow = DebugStyle::rgb(1.0, 0.9, 0.2);
+        debug_queue.sphere(position, 0.87, yellow);
+      }
+      debug_queue.frustum(frustum, DebugStyle::rgb(1.0, 0.3, 0.3).overlay());
+    }
 
-    // and then draw!
+    // and then draw! The debug lines change the state, program, and VAO,
+    // so those get set again each frame.
+    cache.apply(&scene_state);
+    shader_program.use_program();
+    vao.bind();
     unsafe {
       glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
 
@@ -325,6 +369,8 @@ fn main() {
         glDrawArrays(GL_TRIANGLES, 0, 36);
       }
     }
+    let view_projection = projection * view;
+    debug_draw.flush(&mut debug_queue, view_projection, delta_time, &mut cache);
     win.swap_window();
   }
 }
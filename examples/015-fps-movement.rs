@@ -6,7 +6,14 @@
 
 const WINDOW_TITLE: &str = "Movement";
 const WINDOW_WIDTH: u32 = 800;
-const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_HEIGHT: u32 = 600;
+
+/// Fonts to try for the tweak UI when one isn't given on the command line.
+const UI_FONTS: &[&str] = &[
+  "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+  "C:\\Windows\\Fonts\\arial.ttf",
+  "/System/Library/Fonts/Supplemental/Arial.ttf",
+];
 
 use beryllium::{
   events::{
@@ -22,7 +29,13 @@ use core::{
   ptr::null,
 };
 use learn::{
-  null_str, Buffer, BufferType, Shader, ShaderProgram, ShaderType, VertexArray,
+  input::{InputEvent, Key, MouseButton},
+  null_str,
+  state::{DepthState, RenderState, StateCache},
+  text::{BmFontFile, Font},
+  truetype::TrueType,
+  ui::{Ui, UiRenderer, UiStyle},
+  Buffer, BufferType, Shader, ShaderProgram, ShaderType, VertexArray,
 };
 use learn_opengl as learn;
 use ogl33::*;
@@ -153,8 +166,8 @@ fn main() {
   let win = sdl
     .create_gl_window(CreateWinArgs {
       title: WINDOW_TITLE,
-      width: 800,
-      height: 600,
+      width: WINDOW_WIDTH as i32,
+      height: WINDOW_HEIGHT as i32,
       ..Default::default()
     })
     .expect("couldn't make a window and context");
@@ -162,8 +175,6 @@ fn main() {
 
   unsafe {
     load_gl_with(|f_name| win.get_proc_address(f_name.cast()));
-
-    glEnable(GL_DEPTH_TEST);
   }
 
   learn::clear_color(0.2, 0.3, 0.3, 1.0);
@@ -179,8 +190,9 @@ fn main() {
     GL_STATIC_DRAW,
   );
 
+  let mut logo_texture = 0;
+  let mut garris_texture = 0;
   unsafe {
-    let mut logo_texture = 0;
     glGenTextures(1, &mut logo_texture);
     glActiveTexture(GL_TEXTURE0);
     glBindTexture(GL_TEXTURE_2D, logo_texture);
@@ -203,7 +215,6 @@ fn main() {
   }
 
   unsafe {
-    let mut garris_texture = 0;
     glGenTextures(1, &mut garris_texture);
     glActiveTexture(GL_TEXTURE1);
     glBindTexture(GL_TEXTURE_2D, garris_texture);
@@ -284,23 +295,66 @@ fn main() {
 
   let mut camera =
     EulerFPSCamera::at_position(Vec3 { x: 0.0, y: 0.0, z: -3.0 });
-  let camera_speed = 100.0;
+  let mut camera_speed = 100.0;
+  let mut sensitivity = 0.1;
   sdl.set_relative_mouse_mode(true).unwrap();
   let mut keys_held = HashSet::new();
   let mut last_time = 0.0;
 
+  // Tab shows a UI to tweak the camera with. While it's up the mouse is
+  // free, and you look around by holding the right button.
+  let font = load_ui_font();
+  let mut ui = Ui::new(UiStyle::for_font(&font, 1.0));
+  let mut ui_renderer = UiRenderer::new().unwrap();
+  let mut show_ui = false;
+  let mut looking = false;
+  let mut cache = StateCache::new();
+  let scene_state = RenderState {
+    depth: DepthState { test: true, ..DepthState::default() },
+    ..RenderState::default()
+  };
+
   'main_loop: loop {
-    // handle events this frame
+    ui.begin_frame();
+
+    // handle events this frame. The UI gets each one first, and anything it
+    // uses doesn't also move the camera.
     while let Some((event, _timestamp)) = sdl.poll_events() {
       match event {
         Event::Quit => break 'main_loop,
-        Event::MouseMotion { x_delta, y_delta, .. } => {
-          let d_yaw_deg = -x_delta as f32 * 0.1;
-          let d_pitch_deg = -y_delta as f32 * 0.1;
-          camera.update_orientation(d_pitch_deg, d_yaw_deg);
+        Event::MouseMotion { x_win, y_win, x_delta, y_delta, .. } => {
+          let motion = InputEvent::MouseMotion {
+            x: x_win,
+            y: y_win,
+            dx: x_delta,
+            dy: y_delta,
+          };
+          let used = show_ui && ui.handle(&motion);
+          if !used && (!show_ui || looking) {
+            let d_yaw_deg = -x_delta as f32 * sensitivity;
+            let d_pitch_deg = -y_delta as f32 * sensitivity;
+            camera.update_orientation(d_pitch_deg, d_yaw_deg);
+          }
+        }
+        Event::MouseButton { button, pressed, .. } => {
+          let button = MouseButton(button);
+          let click = InputEvent::MouseButton { button, pressed };
+          let used = show_ui && ui.handle(&click);
+          if !used && button == MouseButton::RIGHT {
+            looking = pressed;
+          }
         }
         Event::Key { pressed, keycode, .. } => {
+          let key = InputEvent::Key { key: Key(keycode.0), pressed };
+          if show_ui && ui.handle(&key) {
+            continue;
+          }
           if pressed {
+            if Key(keycode.0) == Key::TAB && !keys_held.contains(&keycode) {
+              show_ui = !show_ui;
+              looking = false;
+              sdl.set_relative_mouse_mode(!show_ui).unwrap();
+            }
             keys_held.insert(keycode);
           } else {
             keys_held.remove(&keycode);
@@ -311,6 +365,14 @@ fn main() {
     }
     // now the events are clear.
 
+    if show_ui {
+      ui.window("Tweaks", Vec2::new(10.0, 10.0), 280.0, |ui| {
+        ui.slider_f32("camera speed", &mut camera_speed, 1.0, 500.0);
+        ui.slider_f32("sensitivity", &mut sensitivity, 0.01, 1.0);
+        ui.label("hold right click to look");
+      });
+    }
+
     // update the "world state".
     let time = sdl.get_ticks() as f32 / 10_000.0_f32;
     let delta_time = time - last_time;
@@ -320,8 +382,14 @@ fn main() {
 
     let view: Mat4 = camera.make_view_matrix();
 
-    // and then draw!
+    // and then draw! The UI changes the program, VAO, and texture unit 0,
+    // so those get set again each frame.
+    cache.apply(&scene_state);
+    shader_program.use_program();
+    vao.bind();
     unsafe {
+      glActiveTexture(GL_TEXTURE0);
+      glBindTexture(GL_TEXTURE_2D, logo_texture);
       glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
 
       glUniformMatrix4fv(view_loc, 1, GL_FALSE, view.as_ptr());
@@ -337,10 +405,33 @@ fn main() {
         glDrawArrays(GL_TRIANGLES, 0, 36);
       }
     }
+    ui_renderer.draw(&ui, &font, WINDOW_WIDTH, WINDOW_HEIGHT, &mut cache);
     win.swap_window();
   }
 }
 
+/// Renders the printable ASCII characters of a TrueType font for the UI.
+///
+/// The font file can be given as the first argument, otherwise a few usual
+/// system fonts are tried. If none of them work the UI still shows up, just
+/// without any text.
+fn load_ui_font() -> Font {
+  let given = std::env::args().nth(1);
+  let paths = given.iter().map(String::as_str).chain(UI_FONTS.iter().copied());
+  for path in paths {
+    if let Ok(bytes) = std::fs::read(path) {
+      let ttf = TrueType::parse(bytes);
+      match ttf.and_then(|ttf| Font::from_truetype(&ttf, 16.0, ' '..='~')) {
+        Ok(font) => return font,
+        Err(e) => eprintln!("Couldn't use the font {}: {}", path, e),
+      }
+    }
+  }
+  eprintln!("No font found, so the UI won't have any text.");
+  let no_glyphs = BmFontFile::parse("common lineHeight=16 base=12").unwrap();
+  Font::from_bmfont(&no_glyphs, &[]).unwrap()
+}
+
 /// Acts like a normal "FPS" camera, capped at +/- 89 degrees, no roll.
 #[derive(Debug, Clone, Copy)]
 pub struct EulerFPSCamera {
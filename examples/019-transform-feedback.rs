@@ -0,0 +1,126 @@
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+#![allow(clippy::single_match)]
+
+const WINDOW_TITLE: &str = "Transform Feedback";
+const VALUE_COUNT: usize = 1024;
+
+use beryllium::{
+  events::Event,
+  init::InitFlags,
+  video::{CreateWinArgs, GlContextFlags, GlProfile, GlSwapInterval},
+  *,
+};
+use core::mem::size_of;
+use learn::{
+  feedback::{
+    feedback_program, FeedbackMode, FeedbackPrimitive, TransformFeedback,
+  },
+  Buffer, BufferType, VertexArray,
+};
+use learn_opengl as learn;
+use ogl33::*;
+
+// the shader math that we want to check against the CPU.
+const VERT_SHADER: &str = r#"#version 330 core
+  layout (location = 0) in float value;
+
+  out float squared;
+  out float wave;
+
+  void main() {
+    squared = value * value;
+    wave = sin(value * 0.01);
+  }
+"#;
+
+fn main() {
+  let sdl = Sdl::init(InitFlags::EVERYTHING);
+  sdl.set_gl_context_major_version(3).unwrap();
+  sdl.set_gl_context_minor_version(3).unwrap();
+  sdl.set_gl_profile(GlProfile::Core).unwrap();
+  let mut flags = GlContextFlags::default();
+  if cfg!(target_os = "macos") {
+    flags |= GlContextFlags::FORWARD_COMPATIBLE;
+  }
+  if cfg!(debug_asserts) {
+    flags |= GlContextFlags::DEBUG;
+  }
+  sdl.set_gl_context_flags(flags).unwrap();
+
+  let win = sdl
+    .create_gl_window(CreateWinArgs {
+      title: WINDOW_TITLE,
+      width: 400,
+      height: 300,
+      ..Default::default()
+    })
+    .expect("couldn't make a window and context");
+  win.set_swap_interval(GlSwapInterval::Vsync).unwrap();
+
+  unsafe {
+    load_gl_with(|f_name| win.get_proc_address(f_name.cast()));
+  }
+
+  // the inputs are just the numbers 0 to 1023.
+  let values: Vec<f32> = (0..VALUE_COUNT).map(|i| i as f32).collect();
+  let vao = VertexArray::new().expect("Couldn't make a VAO");
+  vao.bind();
+  let vbo = Buffer::new().expect("Couldn't make the vertex buffer");
+  vbo.bind(BufferType::Array);
+  learn::buffer_data(
+    BufferType::Array,
+    bytemuck::cast_slice(&values),
+    GL_STATIC_DRAW,
+  );
+  unsafe {
+    glVertexAttribPointer(
+      0,
+      1,
+      GL_FLOAT,
+      GL_FALSE,
+      size_of::<f32>() as GLsizei,
+      core::ptr::null(),
+    );
+    glEnableVertexAttribArray(0);
+  }
+
+  // capture both outputs, interleaved into one buffer.
+  let program = feedback_program(
+    VERT_SHADER,
+    &["squared", "wave"],
+    FeedbackMode::Interleaved,
+  )
+  .unwrap();
+  let mut feedback =
+    TransformFeedback::new(&[VALUE_COUNT * size_of::<[f32; 2]>()]).unwrap();
+  program.use_program();
+  feedback.begin(FeedbackPrimitive::Points);
+  unsafe { glDrawArrays(GL_POINTS, 0, VALUE_COUNT as GLsizei) };
+  feedback.end();
+
+  // now check each captured vertex against the same math done on the CPU.
+  let captured: Vec<[f32; 2]> = feedback.read_captured(0);
+  assert_eq!(captured.len(), VALUE_COUNT);
+  for (value, [squared, wave]) in values.iter().zip(captured.iter()) {
+    assert_eq!(*squared, value * value, "{} squared", value);
+    let expected = (value * 0.01).sin();
+    assert!((wave - expected).abs() < 1e-3, "sin({} * 0.01)", value);
+  }
+  feedback.delete();
+  program.delete();
+  vbo.delete();
+  vao.delete();
+
+  // if we got here everything matched, so show a green window.
+  learn::clear_color(0.2, 0.7, 0.3, 1.0);
+  'main_loop: loop {
+    while let Some((event, _timestamp)) = sdl.poll_events() {
+      match event {
+        Event::Quit => break 'main_loop,
+        _ => (),
+      }
+    }
+    unsafe { glClear(GL_COLOR_BUFFER_BIT) };
+    win.swap_window();
+  }
+}
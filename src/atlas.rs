@@ -0,0 +1,476 @@
+//! Packing lots of small images into one big texture.
+//!
+//! Binding a texture is one of the more expensive state changes, so drawing a
+//! few hundred sprites (or text glyphs) that each have their own texture is
+//! slow. Put them all in one _atlas_ texture instead, and each sprite just
+//! uses its own little rectangle of UV space.
+//!
+//! The packing is done on the CPU with a simple "shelf" packer: images are
+//! placed left to right along a shelf, and when one doesn't fit a new shelf
+//! is started below. Sorting the images tallest first before packing (which
+//! [`AtlasBuilder`] does) keeps the wasted space low.
+
+use crate::{
+  image::{Image, ImageData},
+  texture::{
+    self, MagFilter, MinFilter, Texture, TextureFormat, TextureTarget,
+    TextureWrap,
+  },
+};
+use ultraviolet::Vec2;
+
+/// A rectangle of pixels within an atlas, with the origin at the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AtlasRect {
+  /// Left edge.
+  pub x: u32,
+  /// Top edge.
+  pub y: u32,
+  /// Width in pixels.
+  pub width: u32,
+  /// Height in pixels.
+  pub height: u32,
+}
+
+/// A rectangle of texture coordinates.
+///
+/// These are normal GL texture coordinates, so `min` is the bottom left
+/// corner and `max` is the top right.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UvRect {
+  /// Bottom left corner.
+  pub min: Vec2,
+  /// Top right corner.
+  pub max: Vec2,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Shelf {
+  y: u32,
+  height: u32,
+  used: u32,
+}
+
+/// Places rectangles into a fixed size area, one at a time.
+///
+/// Each rectangle gets `padding` empty pixels around it, so that filtering
+/// doesn't bleed neighbors into each other.
+#[derive(Debug, Clone)]
+pub struct ShelfPacker {
+  width: u32,
+  height: u32,
+  padding: u32,
+  shelves: Vec<Shelf>,
+}
+impl ShelfPacker {
+  /// Makes an empty packer.
+  pub fn new(width: u32, height: u32, padding: u32) -> Self {
+    Self { width, height, padding, shelves: Vec::new() }
+  }
+
+  /// The size of the area being packed.
+  pub fn size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  /// Finds a spot for a `width` by `height` rectangle, or `None` if it doesn't
+  /// fit anywhere.
+  ///
+  /// The shortest shelf that's tall enough and has room is used, otherwise a
+  /// new shelf is started.
+  pub fn insert(&mut self, width: u32, height: u32) -> Option<AtlasRect> {
+    let pad = self.padding.checked_mul(2)?;
+    let padded_w = width.checked_add(pad)?;
+    let padded_h = height.checked_add(pad)?;
+    let area_width = self.width;
+    let best = self
+      .shelves
+      .iter()
+      .enumerate()
+      .filter(|(_, s)| s.height >= padded_h && area_width - s.used >= padded_w)
+      .min_by_key(|(_, s)| s.height)
+      .map(|(i, _)| i);
+    let index = match best {
+      Some(i) => i,
+      None => {
+        let y = self.shelves.last().map(|s| s.y + s.height).unwrap_or(0);
+        if padded_w > self.width || padded_h > self.height - y {
+          return None;
+        }
+        self.shelves.push(Shelf { y, height: padded_h, used: 0 });
+        self.shelves.len() - 1
+      }
+    };
+    let shelf = &mut self.shelves[index];
+    let rect = AtlasRect {
+      x: shelf.used + self.padding,
+      y: shelf.y + self.padding,
+      width,
+      height,
+    };
+    shelf.used += padded_w;
+    Some(rect)
+  }
+
+  /// Forgets everything that's been packed.
+  pub fn clear(&mut self) {
+    self.shelves.clear();
+  }
+}
+
+/// Collects images and packs them into an atlas.
+///
+/// ```no_run
+/// # use learn_opengl::{atlas::AtlasBuilder, image::Image};
+/// let mut builder = AtlasBuilder::new();
+/// let player = builder.add(Image::load("player.png").unwrap());
+/// let enemy = builder.add(Image::load("enemy.png").unwrap());
+/// let atlas = builder.build().unwrap();
+/// let player_uv = atlas.uv(player);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+  /// Empty pixels around each image. Defaults to `1`.
+  pub padding: u32,
+  /// The biggest the atlas can grow, on each side. Defaults to `4096`.
+  pub max_size: u32,
+  /// If the atlas should be an sRGB texture. Defaults to `false`.
+  pub srgb: bool,
+  images: Vec<Image>,
+}
+impl Default for AtlasBuilder {
+  fn default() -> Self {
+    Self { padding: 1, max_size: 4096, srgb: false, images: Vec::new() }
+  }
+}
+impl AtlasBuilder {
+  /// Makes an empty builder with the default settings.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds an image, returning its index in the finished atlas.
+  ///
+  /// The image must have 8-bit data, this is checked when packing.
+  pub fn add(&mut self, image: Image) -> usize {
+    self.images.push(image);
+    self.images.len() - 1
+  }
+
+  /// Adds raw RGBA8 pixels, rows from top to bottom.
+  pub fn add_rgba8(&mut self, width: u32, height: u32, rgba: Vec<u8>) -> usize {
+    self.add(Image { width, height, data: ImageData::Rgba8(rgba), srgb: false })
+  }
+
+  /// The number of images added so far.
+  pub fn len(&self) -> usize {
+    self.images.len()
+  }
+
+  /// If no images have been added.
+  pub fn is_empty(&self) -> bool {
+    self.images.is_empty()
+  }
+
+  /// Packs the images on the CPU, without making a texture.
+  ///
+  /// The atlas starts at the smallest power of two that could hold all the
+  /// images, and doubles (width first, then height) until they fit. The
+  /// output image has rows from top to bottom, and the rects are in the same
+  /// order the images were added.
+  pub fn pack(&self) -> Result<(Image, Vec<AtlasRect>), String> {
+    for (i, image) in self.images.iter().enumerate() {
+      let expected = rgba_len(image.width, image.height);
+      match (&image.data, expected) {
+        (ImageData::Rgba8(v), Some(n)) if v.len() >= n => (),
+        (ImageData::Rgba8(_), _) => {
+          return Err(format!("Atlas image {} has too little data", i))
+        }
+        (ImageData::RgbF32(_), _) => {
+          return Err(format!("Atlas image {} isn't an 8-bit image", i))
+        }
+      }
+    }
+    let too_big =
+      || format!("Images don't fit in a {0}x{0} atlas", self.max_size);
+    let pad = u64::from(self.padding) * 2;
+    let area: u64 = self
+      .images
+      .iter()
+      .map(|i| (u64::from(i.width) + pad) * (u64::from(i.height) + pad))
+      .sum();
+    let widest = self.images.iter().map(|i| u64::from(i.width) + pad).max();
+    let tallest = self.images.iter().map(|i| u64::from(i.height) + pad).max();
+    let start = |side: Option<u64>| {
+      let side = side.unwrap_or(1).next_power_of_two();
+      core::convert::TryFrom::try_from(side).ok()
+    };
+    let mut width: u32 = start(widest).ok_or_else(too_big)?;
+    let mut height: u32 = start(tallest).ok_or_else(too_big)?;
+    while u64::from(width) * u64::from(height) < area {
+      let (w, h) = grow(width, height).ok_or_else(too_big)?;
+      width = w;
+      height = h;
+    }
+
+    let mut order: Vec<usize> = (0..self.images.len()).collect();
+    order.sort_by_key(|&i| core::cmp::Reverse(self.images[i].height));
+    loop {
+      if width > self.max_size || height > self.max_size {
+        return Err(too_big());
+      }
+      if let Some(rects) = self.try_pack(width, height, &order) {
+        let len = rgba_len(width, height).ok_or_else(too_big)?;
+        let mut pixels = vec![0_u8; len];
+        for (image, rect) in self.images.iter().zip(rects.iter()) {
+          if let ImageData::Rgba8(src) = &image.data {
+            blit(&mut pixels, width, src, rect);
+          }
+        }
+        let data = ImageData::Rgba8(pixels);
+        let image = Image { width, height, data, srgb: self.srgb };
+        return Ok((image, rects));
+      }
+      let (w, h) = grow(width, height).ok_or_else(too_big)?;
+      width = w;
+      height = h;
+    }
+  }
+
+  fn try_pack(
+    &self,
+    width: u32,
+    height: u32,
+    order: &[usize],
+  ) -> Option<Vec<AtlasRect>> {
+    let mut packer = ShelfPacker::new(width, height, self.padding);
+    let mut rects = vec![AtlasRect::default(); self.images.len()];
+    for &i in order {
+      let image = &self.images[i];
+      rects[i] = packer.insert(image.width, image.height)?;
+    }
+    Some(rects)
+  }
+
+  /// Packs the images and uploads the atlas as a texture.
+  ///
+  /// The texture uses linear filtering without mipmaps (which would blur
+  /// neighboring images together) and clamps to the edge.
+  pub fn build(&self) -> Result<Atlas, String> {
+    let (image, rects) = self.pack()?;
+    let format = image.texture_format();
+    let mut flipped = image;
+    flipped.flip_vertical();
+    let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+    tex.bind(TextureTarget::Texture2D);
+    if let ImageData::Rgba8(pixels) = &flipped.data {
+      texture::tex_image_2d(
+        format,
+        0,
+        flipped.width,
+        flipped.height,
+        Some(pixels),
+      );
+    }
+    texture::set_filters(
+      TextureTarget::Texture2D,
+      MinFilter::Linear,
+      MagFilter::Linear,
+    );
+    texture::set_wrap(TextureTarget::Texture2D, TextureWrap::ClampToEdge);
+    Texture::clear_binding(TextureTarget::Texture2D);
+    Ok(Atlas {
+      texture: tex,
+      width: flipped.width,
+      height: flipped.height,
+      rects,
+    })
+  }
+}
+
+/// Doubles the atlas size, width first, or `None` if that overflows.
+fn grow(width: u32, height: u32) -> Option<(u32, u32)> {
+  if width <= height {
+    Some((width.checked_mul(2)?, height))
+  } else {
+    Some((width, height.checked_mul(2)?))
+  }
+}
+
+/// The bytes in an RGBA8 image, if that fits in a `usize`.
+fn rgba_len(width: u32, height: u32) -> Option<usize> {
+  (width as usize).checked_mul(height as usize)?.checked_mul(4)
+}
+
+/// Copies an RGBA8 image into the atlas pixels, both top row first.
+///
+/// The rect must be inside the atlas, which the packer makes sure of, so all
+/// of the offsets here are less than `dst.len()`.
+fn blit(dst: &mut [u8], dst_width: u32, src: &[u8], rect: &AtlasRect) {
+  let row_bytes = rect.width as usize * 4;
+  let dst_stride = dst_width as usize * 4;
+  for y in 0..rect.height as usize {
+    let from = y * row_bytes;
+    let to = (rect.y as usize + y) * dst_stride + rect.x as usize * 4;
+    dst[to..to + row_bytes].copy_from_slice(&src[from..from + row_bytes]);
+  }
+}
+
+/// A packed atlas texture, and where each image ended up in it.
+pub struct Atlas {
+  /// The texture object.
+  pub texture: Texture,
+  width: u32,
+  height: u32,
+  rects: Vec<AtlasRect>,
+}
+impl Atlas {
+  /// The size of the whole atlas texture.
+  pub fn size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  /// The number of images in the atlas.
+  pub fn len(&self) -> usize {
+    self.rects.len()
+  }
+
+  /// If the atlas has no images.
+  pub fn is_empty(&self) -> bool {
+    self.rects.is_empty()
+  }
+
+  /// Where an image is in the atlas, in pixels.
+  ///
+  /// ## Panics
+  /// * If the index is out of bounds.
+  pub fn rect(&self, index: usize) -> AtlasRect {
+    self.rects[index]
+  }
+
+  /// Where an image is in the atlas, in texture coordinates.
+  ///
+  /// ## Panics
+  /// * If the index is out of bounds.
+  pub fn uv(&self, index: usize) -> UvRect {
+    let r = self.rects[index];
+    let (w, h) = (self.width as f32, self.height as f32);
+    UvRect {
+      min: Vec2::new(r.x as f32 / w, 1.0 - (r.y + r.height) as f32 / h),
+      max: Vec2::new((r.x + r.width) as f32 / w, 1.0 - r.y as f32 / h),
+    }
+  }
+
+  /// Binds the atlas texture to the given texture unit.
+  pub fn bind(&self, unit: u32) {
+    texture::active_texture(unit);
+    self.texture.bind(TextureTarget::Texture2D);
+  }
+
+  /// Deletes the texture.
+  pub fn delete(self) {
+    self.texture.delete()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn overlaps(a: &AtlasRect, b: &AtlasRect, pad: u32) -> bool {
+    a.x < b.x + b.width + pad
+      && b.x < a.x + a.width + pad
+      && a.y < b.y + b.height + pad
+      && b.y < a.y + a.height + pad
+  }
+
+  fn solid(builder: &mut AtlasBuilder, w: u32, h: u32, v: u8) -> usize {
+    builder.add_rgba8(w, h, vec![v; (w * h * 4) as usize])
+  }
+
+  #[test]
+  fn packed_rects_are_padded_and_apart() {
+    let mut packer = ShelfPacker::new(64, 64, 2);
+    let sizes = [(10, 12), (20, 5), (7, 7), (30, 12), (1, 1), (16, 9)];
+    let rects: Vec<AtlasRect> =
+      sizes.iter().map(|&(w, h)| packer.insert(w, h).unwrap()).collect();
+    for (rect, &(w, h)) in rects.iter().zip(sizes.iter()) {
+      assert_eq!((rect.width, rect.height), (w, h));
+      assert!(rect.x >= 2 && rect.y >= 2);
+      assert!(rect.x + rect.width + 2 <= 64);
+      assert!(rect.y + rect.height + 2 <= 64);
+    }
+    for (i, a) in rects.iter().enumerate() {
+      for b in &rects[i + 1..] {
+        assert!(!overlaps(a, b, 4), "{:?} is too close to {:?}", a, b);
+      }
+    }
+  }
+
+  #[test]
+  fn packer_says_when_its_full() {
+    let mut packer = ShelfPacker::new(16, 16, 0);
+    assert!(packer.insert(17, 1).is_none());
+    assert!(packer.insert(16, 16).is_some());
+    assert!(packer.insert(1, 1).is_none());
+    assert!(packer.insert(u32::MAX, 1).is_none());
+    packer.clear();
+    assert!(packer.insert(1, 1).is_some());
+  }
+
+  #[test]
+  fn atlas_grows_until_images_fit() {
+    // three 9x9 images fit in 16x16 by area, but not on shelves.
+    let mut builder = AtlasBuilder::new();
+    builder.padding = 0;
+    for v in 1..=3 {
+      solid(&mut builder, 9, 9, v);
+    }
+    let (image, rects) = builder.pack().unwrap();
+    assert_eq!((image.width, image.height), (32, 16));
+    assert_eq!(rects.len(), 3);
+  }
+
+  #[test]
+  fn atlas_too_big_is_an_error() {
+    let mut builder = AtlasBuilder::new();
+    builder.max_size = 16;
+    solid(&mut builder, 15, 1, 1);
+    assert!(builder.pack().is_err());
+
+    let mut builder = AtlasBuilder::new();
+    builder.add_rgba8(4, 4, vec![0; 63]);
+    assert!(builder.pack().is_err());
+  }
+
+  #[test]
+  fn blit_puts_pixels_in_their_rects() {
+    let mut builder = AtlasBuilder::new();
+    let red = builder.add_rgba8(2, 2, [255, 0, 0, 255].repeat(4));
+    let blue = builder.add_rgba8(3, 1, [0, 0, 255, 255].repeat(3));
+    let (image, rects) = builder.pack().unwrap();
+    let pixels = match &image.data {
+      ImageData::Rgba8(p) => p,
+      _ => panic!("the atlas should be RGBA8"),
+    };
+    let pixel = |x: u32, y: u32| {
+      let at = ((y * image.width + x) * 4) as usize;
+      &pixels[at..at + 4]
+    };
+    for &(index, color) in
+      [(red, [255, 0, 0, 255]), (blue, [0, 0, 255, 255])].iter()
+    {
+      let r = rects[index];
+      for y in r.y..r.y + r.height {
+        for x in r.x..r.x + r.width {
+          assert_eq!(pixel(x, y), color);
+        }
+      }
+      // the padding around each rect stays empty.
+      assert_eq!(pixel(r.x - 1, r.y), [0; 4]);
+      assert_eq!(pixel(r.x + r.width, r.y), [0; 4]);
+      assert_eq!(pixel(r.x, r.y - 1), [0; 4]);
+      assert_eq!(pixel(r.x, r.y + r.height), [0; 4]);
+    }
+  }
+}
@@ -0,0 +1,745 @@
+//! Cameras, projections, and some controllers to move cameras around.
+//!
+//! None of this knows anything about SDL or any other input library. Each
+//! frame you fill out a [`CameraInput`] from whatever input you've got and
+//! hand that to a controller. This keeps the camera math separate from the
+//! event loop, which makes both of them easier to think about.
+//!
+//! All cameras here use the usual OpenGL convention: with no rotation the
+//! camera looks down the `-Z` axis, with `+Y` as up and `+X` to the right.
+
+use ultraviolet::*;
+
+/// The projection part of a camera.
+///
+/// The view matrix says where the camera is, this says how the camera's view
+/// volume gets squished into clip space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+  /// A perspective projection, far away things look smaller.
+  Perspective {
+    /// The vertical field of view, in degrees.
+    fov_y_deg: f32,
+    /// Width divided by height.
+    aspect_ratio: f32,
+    /// Distance to the near plane.
+    z_near: f32,
+    /// Distance to the far plane.
+    z_far: f32,
+  },
+  /// An orthographic projection, things are the same size at any distance.
+  Orthographic {
+    /// The height of the view volume, in world units.
+    ///
+    /// The width is derived from the aspect ratio.
+    height: f32,
+    /// Width divided by height.
+    aspect_ratio: f32,
+    /// Distance to the near plane.
+    z_near: f32,
+    /// Distance to the far plane.
+    z_far: f32,
+  },
+}
+impl Projection {
+  /// The smallest field of view that zooming will take a perspective
+  /// projection to.
+  pub const MIN_FOV_DEG: f32 = 1.0;
+
+  /// The largest field of view that zooming will take a perspective projection
+  /// to.
+  pub const MAX_FOV_DEG: f32 = 90.0;
+
+  /// The smallest height that zooming will take an orthographic projection
+  /// to.
+  pub const MIN_ORTHO_HEIGHT: f32 = 0.01;
+
+  /// The largest height that zooming will take an orthographic projection
+  /// to.
+  pub const MAX_ORTHO_HEIGHT: f32 = 10_000.0;
+
+  /// Makes a perspective projection.
+  pub const fn perspective(
+    fov_y_deg: f32,
+    aspect_ratio: f32,
+    z_near: f32,
+    z_far: f32,
+  ) -> Self {
+    Projection::Perspective { fov_y_deg, aspect_ratio, z_near, z_far }
+  }
+
+  /// Makes an orthographic projection.
+  pub const fn orthographic(
+    height: f32,
+    aspect_ratio: f32,
+    z_near: f32,
+    z_far: f32,
+  ) -> Self {
+    Projection::Orthographic { height, aspect_ratio, z_near, z_far }
+  }
+
+  /// The current aspect ratio (width / height).
+  pub fn aspect_ratio(&self) -> f32 {
+    match *self {
+      Projection::Perspective { aspect_ratio, .. }
+      | Projection::Orthographic { aspect_ratio, .. } => aspect_ratio,
+    }
+  }
+
+  /// Sets a new aspect ratio (width / height).
+  ///
+  /// Call this when the window changes size or things will look stretched.
+  pub fn set_aspect_ratio(&mut self, new_ratio: f32) {
+    match self {
+      Projection::Perspective { aspect_ratio, .. }
+      | Projection::Orthographic { aspect_ratio, .. } => {
+        *aspect_ratio = new_ratio
+      }
+    }
+  }
+
+  /// Zooms the projection in (positive) or out (negative) by some number of
+  /// steps.
+  ///
+  /// One step is one "click" of a mouse wheel. For perspective this narrows
+  /// the field of view by 1 degree per step, within
+  /// [`MIN_FOV_DEG`](Self::MIN_FOV_DEG) and
+  /// [`MAX_FOV_DEG`](Self::MAX_FOV_DEG). For orthographic the view height
+  /// shrinks by 10% per step, within
+  /// [`MIN_ORTHO_HEIGHT`](Self::MIN_ORTHO_HEIGHT) and
+  /// [`MAX_ORTHO_HEIGHT`](Self::MAX_ORTHO_HEIGHT).
+  pub fn zoom(&mut self, steps: f32) {
+    match self {
+      Projection::Perspective { fov_y_deg, .. } => {
+        *fov_y_deg =
+          (*fov_y_deg - steps).clamp(Self::MIN_FOV_DEG, Self::MAX_FOV_DEG);
+      }
+      Projection::Orthographic { height, .. } => {
+        *height = (*height * 0.9_f32.powf(steps))
+          .clamp(Self::MIN_ORTHO_HEIGHT, Self::MAX_ORTHO_HEIGHT);
+      }
+    }
+  }
+
+  /// Distances to the near and far planes.
+  pub fn near_far(&self) -> (f32, f32) {
+    match *self {
+      Projection::Perspective { z_near, z_far, .. }
+      | Projection::Orthographic { z_near, z_far, .. } => (z_near, z_far),
+    }
+  }
+
+  /// The same projection, but with different near and far planes.
+  ///
+  /// Handy for cutting the view volume up into slices.
+  pub fn with_near_far(mut self, near: f32, far: f32) -> Self {
+    match &mut self {
+      Projection::Perspective { z_near, z_far, .. }
+      | Projection::Orthographic { z_near, z_far, .. } => {
+        *z_near = near;
+        *z_far = far;
+      }
+    }
+    self
+  }
+
+  /// Makes the projection matrix.
+  pub fn matrix(&self) -> Mat4 {
+    match *self {
+      Projection::Perspective { fov_y_deg, aspect_ratio, z_near, z_far } => {
+        projection::perspective_gl(
+          fov_y_deg.to_radians(),
+          aspect_ratio,
+          z_near,
+          z_far,
+        )
+      }
+      Projection::Orthographic { height, aspect_ratio, z_near, z_far } => {
+        let half_h = height / 2.0;
+        let half_w = half_h * aspect_ratio;
+        projection::orthographic_gl(
+          -half_w, half_w, -half_h, half_h, z_near, z_far,
+        )
+      }
+    }
+  }
+}
+
+/// One frame's worth of input for a camera controller.
+///
+/// Each controller interprets the fields in its own way, but the general idea
+/// is the same for all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CameraInput {
+  /// Requested movement in camera space: `x` is right, `y` is up, `z` is
+  /// forward.
+  ///
+  /// Each axis should be within `-1.0 ..= 1.0`. It's normalized before use,
+  /// so moving diagonally isn't any faster.
+  pub movement: Vec3,
+  /// Yaw change in degrees, positive turns left.
+  pub d_yaw_deg: f32,
+  /// Pitch change in degrees, positive looks up.
+  pub d_pitch_deg: f32,
+  /// Roll change in degrees, positive tilts to the right.
+  pub d_roll_deg: f32,
+  /// Zoom steps, positive zooms in. Usually from the mouse wheel.
+  pub zoom: f32,
+}
+
+/// Something that can turn [`CameraInput`] into a view matrix.
+pub trait CameraController {
+  /// Applies a frame of input, `delta_time` is in seconds.
+  fn update(&mut self, input: &CameraInput, delta_time: f32);
+
+  /// Makes the view matrix for the camera's current state.
+  fn view_matrix(&self) -> Mat4;
+
+  /// The camera's current position in world space.
+  fn position(&self) -> Vec3;
+
+  /// The direction the camera is currently looking, in world space.
+  fn forward(&self) -> Vec3;
+}
+
+/// A camera paired with its projection.
+///
+/// Zoom input goes to the projection, everything else goes to the
+/// controller.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera<C> {
+  /// The projection in use.
+  pub projection: Projection,
+  /// The controller that moves the camera.
+  pub controller: C,
+}
+impl<C: CameraController> Camera<C> {
+  /// Pairs a projection with a controller.
+  pub const fn new(projection: Projection, controller: C) -> Self {
+    Self { projection, controller }
+  }
+
+  /// Applies a frame of input.
+  pub fn update(&mut self, input: &CameraInput, delta_time: f32) {
+    if input.zoom != 0.0 {
+      self.projection.zoom(input.zoom);
+    }
+    self.controller.update(input, delta_time);
+  }
+
+  /// The view matrix of the controller.
+  pub fn view_matrix(&self) -> Mat4 {
+    self.controller.view_matrix()
+  }
+
+  /// The projection matrix.
+  pub fn projection_matrix(&self) -> Mat4 {
+    self.projection.matrix()
+  }
+
+  /// Projection times view, all in one.
+  pub fn view_projection_matrix(&self) -> Mat4 {
+    self.projection.matrix() * self.controller.view_matrix()
+  }
+}
+
+/// Gets a unit vector for the input's movement, or zero if there's no
+/// movement.
+fn normalized_movement(input: &CameraInput) -> Vec3 {
+  let m = input.movement;
+  if m.x == 0.0 && m.y == 0.0 && m.z == 0.0 {
+    Vec3::zero()
+  } else {
+    m.normalized()
+  }
+}
+
+/// The forward vector for a given yaw and pitch.
+///
+/// With both at zero this points down `-Z`.
+fn forward_from_yaw_pitch(yaw_deg: f32, pitch_deg: f32) -> Vec3 {
+  let pitch_rad = pitch_deg.to_radians();
+  let yaw_rad = yaw_deg.to_radians();
+  Vec3 {
+    x: -yaw_rad.sin() * pitch_rad.cos(),
+    y: pitch_rad.sin(),
+    z: -yaw_rad.cos() * pitch_rad.cos(),
+  }
+}
+
+/// Acts like a normal "FPS" camera, capped at +/- 89 degrees, no roll.
+///
+/// Moving up and down is always along the world's `+Y` axis, and forward /
+/// back goes where you're looking.
+#[derive(Debug, Clone, Copy)]
+pub struct FpsCamera {
+  /// Camera position, feel free to directly update at any time.
+  pub position: Vec3,
+  /// Movement speed in world units per second.
+  pub speed: f32,
+  pitch_deg: f32,
+  yaw_deg: f32,
+}
+impl FpsCamera {
+  /// The world up direction.
+  pub const UP: Vec3 = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
+
+  /// The most that pitch can be in either direction.
+  pub const PITCH_LIMIT_DEG: f32 = 89.0;
+
+  /// Makes a new camera at the position specified, looking down `-Z`.
+  pub const fn at_position(position: Vec3) -> Self {
+    Self { position, speed: 2.5, pitch_deg: 0.0, yaw_deg: 0.0 }
+  }
+
+  /// Current pitch in degrees.
+  pub fn pitch_deg(&self) -> f32 {
+    self.pitch_deg
+  }
+
+  /// Current yaw in degrees.
+  pub fn yaw_deg(&self) -> f32 {
+    self.yaw_deg
+  }
+
+  /// Adjusts the camera's orientation.
+  ///
+  /// Input deltas should be in _degrees_, pitch is capped at
+  /// [`PITCH_LIMIT_DEG`](Self::PITCH_LIMIT_DEG).
+  pub fn update_orientation(&mut self, d_pitch_deg: f32, d_yaw_deg: f32) {
+    self.pitch_deg = (self.pitch_deg + d_pitch_deg)
+      .clamp(-Self::PITCH_LIMIT_DEG, Self::PITCH_LIMIT_DEG);
+    self.yaw_deg = (self.yaw_deg + d_yaw_deg) % 360.0;
+  }
+
+  /// The camera's right vector, always level with the ground.
+  pub fn right(&self) -> Vec3 {
+    self.forward().cross(Self::UP).normalized()
+  }
+}
+impl CameraController for FpsCamera {
+  fn update(&mut self, input: &CameraInput, delta_time: f32) {
+    self.update_orientation(input.d_pitch_deg, input.d_yaw_deg);
+    let m = normalized_movement(input);
+    let v = self.right() * m.x + Self::UP * m.y + self.forward() * m.z;
+    self.position += v * (self.speed * delta_time);
+  }
+
+  fn view_matrix(&self) -> Mat4 {
+    Mat4::look_at(self.position, self.position + self.forward(), Self::UP)
+  }
+
+  fn position(&self) -> Vec3 {
+    self.position
+  }
+
+  fn forward(&self) -> Vec3 {
+    forward_from_yaw_pitch(self.yaw_deg, self.pitch_deg)
+  }
+}
+
+/// A free flying camera, it can turn any direction and also roll.
+///
+/// The orientation is kept as a rotor instead of as euler angles, so there's
+/// no gimbal lock and no pitch limit. All rotations are relative to the
+/// camera's current orientation, like the controls of a space ship.
+#[derive(Debug, Clone, Copy)]
+pub struct FreeCamera {
+  /// Camera position, feel free to directly update at any time.
+  pub position: Vec3,
+  /// Movement speed in world units per second.
+  pub speed: f32,
+  /// Camera orientation, relative to looking down `-Z` with `+Y` up.
+  pub orientation: Rotor3,
+}
+impl FreeCamera {
+  /// Makes a new camera at the position specified, looking down `-Z`.
+  pub fn at_position(position: Vec3) -> Self {
+    Self { position, speed: 2.5, orientation: Rotor3::identity() }
+  }
+
+  /// Rotates the camera relative to its current orientation.
+  ///
+  /// All inputs are in degrees.
+  pub fn rotate_local(
+    &mut self,
+    d_pitch_deg: f32,
+    d_yaw_deg: f32,
+    d_roll_deg: f32,
+  ) {
+    // These are all in camera space, so applying the change before the
+    // current orientation makes them relative to the current orientation.
+    let yaw = Rotor3::from_rotation_xz(-d_yaw_deg.to_radians());
+    let pitch = Rotor3::from_rotation_yz(d_pitch_deg.to_radians());
+    let roll = Rotor3::from_rotation_xy(-d_roll_deg.to_radians());
+    self.orientation = (self.orientation * yaw * pitch * roll).normalized();
+  }
+
+  /// The camera's up vector.
+  pub fn up(&self) -> Vec3 {
+    self.orientation * Vec3::unit_y()
+  }
+
+  /// The camera's right vector.
+  pub fn right(&self) -> Vec3 {
+    self.orientation * Vec3::unit_x()
+  }
+}
+impl CameraController for FreeCamera {
+  fn update(&mut self, input: &CameraInput, delta_time: f32) {
+    self.rotate_local(input.d_pitch_deg, input.d_yaw_deg, input.d_roll_deg);
+    let m = normalized_movement(input);
+    let v = self.right() * m.x + self.up() * m.y + self.forward() * m.z;
+    self.position += v * (self.speed * delta_time);
+  }
+
+  fn view_matrix(&self) -> Mat4 {
+    Mat4::look_at(self.position, self.position + self.forward(), self.up())
+  }
+
+  fn position(&self) -> Vec3 {
+    self.position
+  }
+
+  fn forward(&self) -> Vec3 {
+    self.orientation * -Vec3::unit_z()
+  }
+}
+
+/// Orbits around a target point, always looking at it.
+///
+/// Yaw and pitch swing the camera around the target, zooming moves closer or
+/// farther away, and movement pans the target itself around.
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitCamera {
+  /// The point being looked at.
+  pub target: Vec3,
+  /// Distance from the target.
+  pub distance: f32,
+  /// Panning speed in world units per second.
+  pub speed: f32,
+  /// The closest the camera will zoom to the target.
+  pub min_distance: f32,
+  pitch_deg: f32,
+  yaw_deg: f32,
+}
+impl OrbitCamera {
+  /// Makes a new camera looking at the target from the given distance, from
+  /// the `+Z` side.
+  pub const fn looking_at(target: Vec3, distance: f32) -> Self {
+    Self {
+      target,
+      distance,
+      speed: 2.5,
+      min_distance: 0.1,
+      pitch_deg: 0.0,
+      yaw_deg: 0.0,
+    }
+  }
+
+  /// Swings the camera around the target.
+  ///
+  /// Inputs are in degrees, pitch is capped at
+  /// [`FpsCamera::PITCH_LIMIT_DEG`].
+  pub fn orbit(&mut self, d_pitch_deg: f32, d_yaw_deg: f32) {
+    self.pitch_deg = (self.pitch_deg + d_pitch_deg)
+      .clamp(-FpsCamera::PITCH_LIMIT_DEG, FpsCamera::PITCH_LIMIT_DEG);
+    self.yaw_deg = (self.yaw_deg + d_yaw_deg) % 360.0;
+  }
+
+  /// Moves toward (positive) or away from (negative) the target.
+  ///
+  /// Each step is 10% of the current distance, so it feels the same at any
+  /// range.
+  pub fn dolly(&mut self, steps: f32) {
+    self.distance =
+      (self.distance * 0.9_f32.powf(steps)).max(self.min_distance);
+  }
+}
+impl CameraController for OrbitCamera {
+  /// Zoom input is used as a dolly here, so don't also send it to the
+  /// projection unless you want both effects.
+  fn update(&mut self, input: &CameraInput, delta_time: f32) {
+    self.orbit(input.d_pitch_deg, input.d_yaw_deg);
+    self.dolly(input.zoom);
+    let m = normalized_movement(input);
+    let forward = self.forward();
+    let right = forward.cross(FpsCamera::UP).normalized();
+    let up = right.cross(forward);
+    let v = right * m.x + up * m.y + forward * m.z;
+    self.target += v * (self.speed * delta_time);
+  }
+
+  fn view_matrix(&self) -> Mat4 {
+    Mat4::look_at(self.position(), self.target, FpsCamera::UP)
+  }
+
+  fn position(&self) -> Vec3 {
+    self.target - self.forward() * self.distance
+  }
+
+  fn forward(&self) -> Vec3 {
+    forward_from_yaw_pitch(self.yaw_deg, self.pitch_deg)
+  }
+}
+
+/// An arcball camera, dragging the mouse "rolls" a ball around the target.
+///
+/// Unlike [`OrbitCamera`] there's no fixed up direction, so you can spin all
+/// the way over the top.
+#[derive(Debug, Clone, Copy)]
+pub struct ArcballCamera {
+  /// The point being looked at.
+  pub target: Vec3,
+  /// Distance from the target.
+  pub distance: f32,
+  /// The closest the camera will zoom to the target.
+  pub min_distance: f32,
+  /// Camera orientation, relative to looking down `-Z` with `+Y` up.
+  pub orientation: Rotor3,
+}
+impl ArcballCamera {
+  /// Makes a new camera looking at the target from the given distance, from
+  /// the `+Z` side.
+  pub fn looking_at(target: Vec3, distance: f32) -> Self {
+    Self {
+      target,
+      distance,
+      min_distance: 0.1,
+      orientation: Rotor3::identity(),
+    }
+  }
+
+  /// Maps a point in normalized device coordinates onto the arcball.
+  ///
+  /// Points inside the unit circle go onto the front of the sphere, points
+  /// outside of it go onto a hyperbolic sheet so that drags stay smooth.
+  pub fn project_to_sphere(ndc: Vec2) -> Vec3 {
+    let d_sq = ndc.mag_sq();
+    if d_sq <= 0.5 {
+      Vec3::new(ndc.x, ndc.y, (1.0 - d_sq).sqrt())
+    } else {
+      Vec3::new(ndc.x, ndc.y, 0.5 / d_sq.sqrt()).normalized()
+    }
+  }
+
+  /// Drags the arcball from one point to another.
+  ///
+  /// Both points are in normalized device coordinates (`-1.0 ..= 1.0` on both
+  /// axes, `+Y` up).
+  pub fn drag(&mut self, from_ndc: Vec2, to_ndc: Vec2) {
+    let from = Self::project_to_sphere(from_ndc);
+    let to = Self::project_to_sphere(to_ndc);
+    if from == to {
+      return;
+    }
+    // dragging turns the ball, which means the camera turns the other way.
+    let ball = Rotor3::from_rotation_between(from, to);
+    self.orientation = (self.orientation * ball.reversed()).normalized();
+  }
+
+  /// Moves toward (positive) or away from (negative) the target.
+  pub fn dolly(&mut self, steps: f32) {
+    self.distance =
+      (self.distance * 0.9_f32.powf(steps)).max(self.min_distance);
+  }
+
+  /// The camera's up vector.
+  pub fn up(&self) -> Vec3 {
+    self.orientation * Vec3::unit_y()
+  }
+}
+impl CameraController for ArcballCamera {
+  /// Yaw and pitch input turns the ball as if you had dragged across the
+  /// screen by that many degrees. Zoom input is used as a dolly.
+  fn update(&mut self, input: &CameraInput, _delta_time: f32) {
+    let yaw = Rotor3::from_rotation_xz(-input.d_yaw_deg.to_radians());
+    let pitch = Rotor3::from_rotation_yz(input.d_pitch_deg.to_radians());
+    self.orientation = (self.orientation * yaw * pitch).normalized();
+    self.dolly(input.zoom);
+  }
+
+  fn view_matrix(&self) -> Mat4 {
+    Mat4::look_at(self.position(), self.target, self.up())
+  }
+
+  fn position(&self) -> Vec3 {
+    self.target - self.forward() * self.distance
+  }
+
+  fn forward(&self) -> Vec3 {
+    self.orientation * -Vec3::unit_z()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: Vec3, b: Vec3) -> bool {
+    (a - b).mag() < 1e-4
+  }
+
+  fn turn(d_pitch_deg: f32, d_yaw_deg: f32) -> CameraInput {
+    CameraInput { d_pitch_deg, d_yaw_deg, ..CameraInput::default() }
+  }
+
+  #[test]
+  fn yaw_and_pitch_directions() {
+    let x = Vec3::unit_x();
+    let y = Vec3::unit_y();
+    let z = Vec3::unit_z();
+    assert!(close(forward_from_yaw_pitch(0.0, 0.0), -z));
+    // positive yaw turns left, positive pitch looks up
+    assert!(close(forward_from_yaw_pitch(90.0, 0.0), -x));
+    assert!(close(forward_from_yaw_pitch(-90.0, 0.0), x));
+    assert!(close(forward_from_yaw_pitch(0.0, 90.0), y));
+    assert!(close(forward_from_yaw_pitch(0.0, -90.0), -y));
+    assert!(close(forward_from_yaw_pitch(180.0, 0.0), z));
+  }
+
+  #[test]
+  fn fps_camera_turns_moves_and_clamps() {
+    let mut camera = FpsCamera::at_position(Vec3::zero());
+    assert!(close(camera.forward(), -Vec3::unit_z()));
+    assert!(close(camera.right(), Vec3::unit_x()));
+    camera.update(&turn(0.0, 90.0), 0.0);
+    assert!(close(camera.forward(), -Vec3::unit_x()));
+    assert!(close(camera.right(), -Vec3::unit_z()));
+    camera.update_orientation(100.0, 0.0);
+    assert_eq!(camera.pitch_deg(), FpsCamera::PITCH_LIMIT_DEG);
+    camera.update_orientation(-300.0, 0.0);
+    assert_eq!(camera.pitch_deg(), -FpsCamera::PITCH_LIMIT_DEG);
+    camera.update_orientation(0.0, 300.0);
+    assert_eq!(camera.yaw_deg(), 30.0);
+
+    // forward along the view, and up along the world's up
+    let mut camera = FpsCamera::at_position(Vec3::zero());
+    camera.update_orientation(45.0, 0.0);
+    let input = CameraInput {
+      movement: Vec3::new(0.0, 0.0, 1.0),
+      ..CameraInput::default()
+    };
+    camera.update(&input, 2.0);
+    let expected = camera.forward() * camera.speed * 2.0;
+    assert!(close(camera.position, expected));
+    assert!(camera.position.y > 0.0);
+  }
+
+  #[test]
+  fn free_camera_has_no_pitch_limit() {
+    let mut camera = FreeCamera::at_position(Vec3::zero());
+    camera.update(&turn(0.0, 90.0), 0.0);
+    assert!(close(camera.forward(), -Vec3::unit_x()));
+    let mut camera = FreeCamera::at_position(Vec3::zero());
+    camera.update(&turn(90.0, 0.0), 0.0);
+    assert!(close(camera.forward(), Vec3::unit_y()));
+    assert!(close(camera.up(), Vec3::unit_z()));
+    // past straight up, and over onto its back
+    camera.update(&turn(90.0, 0.0), 0.0);
+    assert!(close(camera.forward(), Vec3::unit_z()));
+    assert!(close(camera.up(), -Vec3::unit_y()));
+    // rolling keeps looking the same way
+    let mut camera = FreeCamera::at_position(Vec3::zero());
+    camera.rotate_local(0.0, 0.0, 90.0);
+    assert!(close(camera.forward(), -Vec3::unit_z()));
+    assert!(close(camera.up(), Vec3::unit_x()), "{:?}", camera.up());
+  }
+
+  #[test]
+  fn orbit_camera_distance_limits() {
+    let target = Vec3::new(1.0, 2.0, 3.0);
+    let mut camera = OrbitCamera::looking_at(target, 5.0);
+    assert!(close(camera.position(), target + Vec3::new(0.0, 0.0, 5.0)));
+    camera.orbit(0.0, 90.0);
+    assert!(close(camera.position(), target + Vec3::new(5.0, 0.0, 0.0)));
+    camera.orbit(200.0, 0.0);
+    assert!(close(camera.forward(), forward_from_yaw_pitch(90.0, 89.0)));
+    assert!(((camera.position() - target).mag() - 5.0).abs() < 1e-4);
+
+    camera.dolly(1.0);
+    assert!((camera.distance - 4.5).abs() < 1e-4);
+    camera.dolly(1000.0);
+    assert_eq!(camera.distance, camera.min_distance);
+    camera.update(&CameraInput { zoom: -2.0, ..CameraInput::default() }, 0.0);
+    assert!((camera.distance - camera.min_distance / 0.81).abs() < 1e-4);
+  }
+
+  #[test]
+  fn arcball_keeps_its_distance() {
+    let target = Vec3::new(0.0, 1.0, 0.0);
+    let mut camera = ArcballCamera::looking_at(target, 4.0);
+    let drags = [
+      (Vec2::new(0.0, 0.0), Vec2::new(0.5, 0.0)),
+      (Vec2::new(-0.3, 0.2), Vec2::new(0.9, -0.8)),
+      (Vec2::new(2.0, 2.0), Vec2::new(-3.0, 1.0)),
+    ];
+    for &(from, to) in drags.iter() {
+      camera.drag(from, to);
+      assert!(((camera.position() - target).mag() - 4.0).abs() < 1e-3);
+      assert!((camera.forward().mag() - 1.0).abs() < 1e-4);
+      assert!(camera.forward().dot(camera.up()).abs() < 1e-4);
+    }
+    // dragging right turns the ball right, so the camera goes left
+    let mut camera = ArcballCamera::looking_at(Vec3::zero(), 1.0);
+    camera.drag(Vec2::new(0.0, 0.0), Vec2::new(0.5, 0.0));
+    assert!(camera.position().x < 0.0);
+    // and dragging back puts it where it was
+    camera.drag(Vec2::new(0.5, 0.0), Vec2::new(0.0, 0.0));
+    assert!(close(camera.position(), Vec3::unit_z()));
+  }
+
+  #[test]
+  fn arcball_sphere_is_smooth() {
+    for &d in [0.0, 0.3, 0.5_f32.sqrt(), 1.0, 5.0].iter() {
+      let p = ArcballCamera::project_to_sphere(Vec2::new(d, 0.0));
+      assert!((p.mag() - 1.0).abs() < 1e-4, "{}", d);
+      assert!(p.z >= 0.0);
+    }
+    let edge = 0.5_f32.sqrt();
+    let inside = ArcballCamera::project_to_sphere(Vec2::new(edge - 1e-4, 0.0));
+    let outside = ArcballCamera::project_to_sphere(Vec2::new(edge + 1e-4, 0.0));
+    assert!((inside - outside).mag() < 1e-3);
+  }
+
+  #[test]
+  fn projection_zoom_is_clamped() {
+    let mut perspective = Projection::perspective(45.0, 1.0, 0.1, 100.0);
+    perspective.zoom(100.0);
+    assert_eq!(perspective, Projection::perspective(1.0, 1.0, 0.1, 100.0));
+    perspective.zoom(-1000.0);
+    assert_eq!(perspective, Projection::perspective(90.0, 1.0, 0.1, 100.0));
+
+    let mut ortho = Projection::orthographic(10.0, 2.0, 0.1, 100.0);
+    ortho.zoom(1.0);
+    match ortho {
+      Projection::Orthographic { height, .. } => {
+        assert!((height - 9.0).abs() < 1e-4)
+      }
+      _ => unreachable!(),
+    }
+    ortho.zoom(10_000.0);
+    let min = Projection::MIN_ORTHO_HEIGHT;
+    assert_eq!(ortho, Projection::orthographic(min, 2.0, 0.1, 100.0));
+    ortho.zoom(-10_000.0);
+    let max = Projection::MAX_ORTHO_HEIGHT;
+    assert_eq!(ortho, Projection::orthographic(max, 2.0, 0.1, 100.0));
+  }
+
+  #[test]
+  fn projection_matrices() {
+    let ortho = Projection::orthographic(4.0, 2.0, 1.0, 11.0);
+    let corner = ortho.matrix() * Vec4::new(4.0, 2.0, -1.0, 1.0);
+    assert!(close(corner.truncated(), Vec3::new(1.0, 1.0, -1.0)));
+    let far = ortho.matrix() * Vec4::new(-4.0, -2.0, -11.0, 1.0);
+    assert!(close(far.truncated(), Vec3::new(-1.0, -1.0, 1.0)));
+
+    let mut perspective = Projection::perspective(90.0, 1.0, 1.0, 10.0);
+    let clip = perspective.matrix() * Vec4::new(10.0, 10.0, -10.0, 1.0);
+    assert!(close(clip.truncated() / clip.w, Vec3::new(1.0, 1.0, 1.0)));
+    perspective.set_aspect_ratio(2.0);
+    assert_eq!(perspective.aspect_ratio(), 2.0);
+    let sliced = perspective.with_near_far(2.0, 3.0);
+    assert_eq!(sliced.near_far(), (2.0, 3.0));
+  }
+}
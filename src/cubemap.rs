@@ -0,0 +1,325 @@
+//! Cube map textures, loaded from image files.
+//!
+//! A cube map is six square images, one for each side of a cube. You sample
+//! it with a _direction_ instead of a texture coordinate, which makes it
+//! great for things like skyboxes and environment reflections.
+//!
+//! Unlike normal 2D textures, cube map faces are _not_ flipped when loading.
+//! GL's cube map convention has the origin of each face at the top left,
+//! which happens to be how image files store them already.
+
+use crate::image::{Image, ImageData};
+use crate::texture::{
+  self, CubeFace, MagFilter, MinFilter, Texture, TextureFormat, TextureTarget,
+  TextureWrap,
+};
+use std::path::Path;
+
+/// How the six faces are laid out within a single image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeLayout {
+  /// A 4:3 image with the faces in a sideways cross:
+  /// ```text
+  ///       +Y
+  ///   -X  +Z  +X  -Z
+  ///       -Y
+  /// ```
+  HorizontalCross,
+  /// A 6:1 image with the faces left to right in GL order:
+  /// `+X -X +Y -Y +Z -Z`.
+  HorizontalStrip,
+  /// A 1:6 image with the faces top to bottom in GL order.
+  VerticalStrip,
+}
+impl CubeLayout {
+  /// Guesses the layout from an image's size.
+  ///
+  /// Empty images don't have a layout.
+  pub fn detect(width: u32, height: u32) -> Option<Self> {
+    let (width, height) = (u64::from(width), u64::from(height));
+    if width == 0 || height == 0 {
+      None
+    } else if width * 3 == height * 4 {
+      Some(CubeLayout::HorizontalCross)
+    } else if width == height * 6 {
+      Some(CubeLayout::HorizontalStrip)
+    } else if width * 6 == height {
+      Some(CubeLayout::VerticalStrip)
+    } else {
+      None
+    }
+  }
+
+  /// The edge length of each face, given the full image's width.
+  pub const fn face_size(self, width: u32) -> u32 {
+    match self {
+      CubeLayout::HorizontalCross => width / 4,
+      CubeLayout::HorizontalStrip => width / 6,
+      CubeLayout::VerticalStrip => width,
+    }
+  }
+
+  /// The cell `(column, row)` of each face, in GL face order.
+  const fn cells(self) -> [(u32, u32); 6] {
+    match self {
+      CubeLayout::HorizontalCross => {
+        [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)]
+      }
+      CubeLayout::HorizontalStrip => {
+        [(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0)]
+      }
+      CubeLayout::VerticalStrip => {
+        [(0, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5)]
+      }
+    }
+  }
+}
+
+/// A cube map texture.
+pub struct TextureCube {
+  /// The texture object.
+  pub texture: Texture,
+  size: u32,
+}
+impl TextureCube {
+  /// Makes a cube map from six faces of pixel data, in GL face order.
+  ///
+  /// Each face must be `size` by `size` pixels. The texture gets linear
+  /// filtering, mipmaps, and edge clamping, and this turns on
+  /// [seamless](texture::enable_seamless_cube_maps) cube map filtering.
+  pub fn from_faces(
+    format: TextureFormat,
+    size: u32,
+    faces: [&[u8]; 6],
+  ) -> Result<Self, String> {
+    let needed = texture::bytes_per_pixel(format) * (size * size) as usize;
+    if let Some(i) = faces.iter().position(|f| f.len() < needed) {
+      return Err(format!(
+        "Cube face {} is too small for {}x{}",
+        i, size, size
+      ));
+    }
+    texture::enable_seamless_cube_maps();
+    let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+    tex.bind(TextureTarget::CubeMap);
+    for (face, data) in CubeFace::ALL.iter().zip(faces.iter()) {
+      texture::tex_image_cube_face(*face, format, 0, size, Some(data));
+    }
+    texture::set_filters(
+      TextureTarget::CubeMap,
+      MinFilter::LinearMipmapLinear,
+      MagFilter::Linear,
+    );
+    texture::set_wrap(TextureTarget::CubeMap, TextureWrap::ClampToEdge);
+    texture::generate_mipmap(TextureTarget::CubeMap);
+    Ok(Self { texture: tex, size })
+  }
+
+  /// Makes a cube map with no contents yet, for rendering into.
+  ///
+  /// With `mipmapped` the full mip chain is allocated too, and the texture
+  /// uses trilinear filtering. Either way it clamps to the edge, and seamless
+  /// filtering gets turned on like with [`from_faces`](Self::from_faces).
+  pub fn empty(
+    format: TextureFormat,
+    size: u32,
+    mipmapped: bool,
+  ) -> Result<Self, String> {
+    texture::enable_seamless_cube_maps();
+    let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+    tex.bind(TextureTarget::CubeMap);
+    for face in CubeFace::ALL.iter() {
+      texture::tex_image_cube_face(*face, format, 0, size, None);
+    }
+    let min =
+      if mipmapped { MinFilter::LinearMipmapLinear } else { MinFilter::Linear };
+    texture::set_filters(TextureTarget::CubeMap, min, MagFilter::Linear);
+    texture::set_wrap(TextureTarget::CubeMap, TextureWrap::ClampToEdge);
+    if mipmapped {
+      texture::generate_mipmap(TextureTarget::CubeMap);
+    }
+    Ok(Self { texture: tex, size })
+  }
+
+  /// Loads a cube map from six image files, in GL face order
+  /// (`+X -X +Y -Y +Z -Z`).
+  ///
+  /// If `srgb` is set the color data is treated as sRGB.
+  pub fn load_faces<P: AsRef<Path>>(
+    paths: [P; 6],
+    srgb: bool,
+  ) -> Result<Self, String> {
+    let mut size = None;
+    let mut faces: Vec<Vec<u8>> = Vec::with_capacity(6);
+    for path in paths.iter() {
+      let (w, h, pixels) = load_rgba8(path.as_ref())?;
+      if w != h {
+        return Err(format!(
+          "{}: cube faces must be square, got {}x{}",
+          path.as_ref().display(),
+          w,
+          h
+        ));
+      }
+      if *size.get_or_insert(w) != w {
+        return Err(format!(
+          "{}: all cube faces must be the same size",
+          path.as_ref().display()
+        ));
+      }
+      faces.push(pixels);
+    }
+    let size = size.unwrap_or(0);
+    Self::from_faces(
+      rgba_format(srgb),
+      size,
+      [&faces[0], &faces[1], &faces[2], &faces[3], &faces[4], &faces[5]],
+    )
+  }
+
+  /// Loads a cube map from a single image holding all six faces.
+  ///
+  /// The [`CubeLayout`] is picked from the image's size.
+  pub fn load_single<P: AsRef<Path>>(
+    path: P,
+    srgb: bool,
+  ) -> Result<Self, String> {
+    let path = path.as_ref();
+    let (w, h, pixels) = load_rgba8(path)?;
+    let layout = CubeLayout::detect(w, h).ok_or_else(|| {
+      format!("{}: {}x{} isn't a cube map layout", path.display(), w, h)
+    })?;
+    let size = layout.face_size(w);
+    let faces = layout
+      .cells()
+      .iter()
+      .map(|&(col, row)| extract_face(&pixels, w, size, col, row))
+      .collect::<Option<Vec<Vec<u8>>>>()
+      .ok_or_else(|| {
+        format!("{}: the image is smaller than {}x{}", path.display(), w, h)
+      })?;
+    Self::from_faces(
+      rgba_format(srgb),
+      size,
+      [&faces[0], &faces[1], &faces[2], &faces[3], &faces[4], &faces[5]],
+    )
+  }
+
+  /// The edge length of each face, in pixels.
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  /// Binds the cube map to the given texture unit.
+  pub fn bind(&self, unit: u32) {
+    texture::active_texture(unit);
+    self.texture.bind(TextureTarget::CubeMap);
+  }
+
+  /// Deletes the texture.
+  pub fn delete(self) {
+    self.texture.delete()
+  }
+}
+
+fn rgba_format(srgb: bool) -> TextureFormat {
+  if srgb {
+    TextureFormat::SRGB8Alpha8
+  } else {
+    TextureFormat::RGBA8
+  }
+}
+
+fn load_rgba8(path: &Path) -> Result<(u32, u32, Vec<u8>), String> {
+  let image =
+    Image::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  match image.data {
+    ImageData::Rgba8(pixels) => Ok((image.width, image.height, pixels)),
+    ImageData::RgbF32(_) => {
+      Err(format!("{}: cube faces must be 8-bit images", path.display()))
+    }
+  }
+}
+
+/// Copies out the RGBA8 face in cell `(col, row)`, or `None` if it's past
+/// the end of `pixels`.
+fn extract_face(
+  pixels: &[u8],
+  image_width: u32,
+  size: u32,
+  col: u32,
+  row: u32,
+) -> Option<Vec<u8>> {
+  let (image_width, size) = (image_width as usize, size as usize);
+  let (col, row) = (col as usize, row as usize);
+  let row_bytes = size.checked_mul(4)?;
+  let mut out = Vec::with_capacity(row_bytes.checked_mul(size)?);
+  for y in 0..size {
+    let start = row
+      .checked_mul(size)?
+      .checked_add(y)?
+      .checked_mul(image_width)?
+      .checked_add(col.checked_mul(size)?)?
+      .checked_mul(4)?;
+    out.extend_from_slice(pixels.get(start..start.checked_add(row_bytes)?)?);
+  }
+  Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn detect_layouts() {
+    assert_eq!(CubeLayout::detect(4, 3), Some(CubeLayout::HorizontalCross));
+    assert_eq!(
+      CubeLayout::detect(1024, 768),
+      Some(CubeLayout::HorizontalCross)
+    );
+    assert_eq!(CubeLayout::detect(6, 1), Some(CubeLayout::HorizontalStrip));
+    assert_eq!(CubeLayout::detect(1, 6), Some(CubeLayout::VerticalStrip));
+    assert_eq!(CubeLayout::detect(5, 5), None);
+    assert_eq!(CubeLayout::detect(0, 0), None);
+    assert_eq!(CubeLayout::detect(0, 1), None);
+    // these would wrap around in u32 math
+    assert_eq!(CubeLayout::detect(u32::MAX, u32::MAX), None);
+    assert_eq!(
+      CubeLayout::detect(0x8000_0000, 0x6000_0000),
+      Some(CubeLayout::HorizontalCross)
+    );
+    assert_eq!(CubeLayout::detect(2, 0x2AAA_AAAB), None);
+  }
+
+  #[test]
+  fn extract_cross_faces() {
+    // a 2 pixel face cross, each pixel is (x, y, 0, 255)
+    let (w, h) = (8_u32, 6_u32);
+    let pixels: Vec<u8> = (0..h)
+      .flat_map(|y| {
+        (0..w).flat_map(move |x| [x as u8, y as u8, 0, 255].to_vec())
+      })
+      .collect();
+    let layout = CubeLayout::detect(w, h).unwrap();
+    let size = layout.face_size(w);
+    assert_eq!(size, 2);
+    let faces: Vec<Vec<u8>> = layout
+      .cells()
+      .iter()
+      .map(|&(col, row)| extract_face(&pixels, w, size, col, row).unwrap())
+      .collect();
+    // +X is cell (2, 1), so its corner is pixel (4, 2)
+    assert_eq!(
+      faces[0],
+      [4, 2, 0, 255, 5, 2, 0, 255, 4, 3, 0, 255, 5, 3, 0, 255]
+    );
+    // -Y is cell (1, 2), the bottom of the cross
+    assert_eq!(&faces[3][..4], &[2, 4, 0, 255]);
+    assert_eq!(&faces[3][12..], &[3, 5, 0, 255]);
+
+    // missing pixels are None, not a panic
+    assert_eq!(extract_face(&pixels[..pixels.len() - 1], w, size, 3, 2), None);
+    assert_eq!(extract_face(&pixels, w, size, 0, 3), None);
+    assert_eq!(extract_face(&pixels, u32::MAX, u32::MAX, 3, 2), None);
+  }
+}
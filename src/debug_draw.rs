@@ -0,0 +1,563 @@
+//! Quick lines for seeing what's going on: boxes, spheres, frustums, grids,
+//! and axes.
+//!
+//! Shapes are queued up in a [`DebugQueue`] from wherever is handy, and a
+//! [`DebugDraw`] turns the whole queue into lines in one buffer and draws it
+//! once per frame. Nothing here is fast or pretty, it's for checking where
+//! things are (eg: if the camera's frustum points the way you think it does).
+//!
+//! Each shape has a [`DebugStyle`]: a color, how long it stays around, and if
+//! it's hidden by the scene's depth or drawn over everything.
+//!
+//! If passing a queue around is a bother, there's also one per thread, see
+//! [`with_queue`].
+//!
+//! The `014-mouse-look` example turns some of these on with Tab, including
+//! the frustum the camera had at the time.
+
+use crate::{
+  buffer_data,
+  state::{BlendState, CompareFunc, DepthState, RenderState, StateCache},
+  Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{cell::RefCell, convert::TryInto, mem::size_of};
+use ogl33::*;
+use ultraviolet::{Mat4, Vec3, Vec4};
+
+const VERT_SHADER: &str = r#"#version 330 core
+  layout (location = 0) in vec3 pos;
+  layout (location = 1) in vec4 color;
+
+  uniform mat4 view_projection;
+
+  out vec4 line_color;
+
+  void main() {
+    line_color = color;
+    gl_Position = view_projection * vec4(pos, 1.0);
+  }
+"#;
+
+const FRAG_SHADER: &str = r#"#version 330 core
+  in vec4 line_color;
+
+  out vec4 final_color;
+
+  void main() {
+    final_color = line_color;
+  }
+"#;
+
+/// The number of segments used for each circle of a sphere.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// If debug shapes are hidden behind the scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+  /// Tested against the depth buffer, so the scene can cover them.
+  Tested,
+  /// Drawn over everything.
+  Overlay,
+}
+
+/// How a debug shape is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugStyle {
+  /// Line color, with alpha.
+  pub color: Vec4,
+  /// How many seconds the shape stays around. `0.0` means just the next
+  /// frame.
+  pub duration: f32,
+  /// If the shape is depth tested.
+  pub depth: DepthMode,
+}
+impl Default for DebugStyle {
+  /// White, one frame, depth tested.
+  fn default() -> Self {
+    Self { color: Vec4::one(), duration: 0.0, depth: DepthMode::Tested }
+  }
+}
+impl DebugStyle {
+  /// An opaque color, for one frame, depth tested.
+  pub fn rgb(r: f32, g: f32, b: f32) -> Self {
+    Self { color: Vec4::new(r, g, b, 1.0), ..Self::default() }
+  }
+
+  /// Changes how long the shape stays around.
+  pub fn for_seconds(self, duration: f32) -> Self {
+    Self { duration, ..self }
+  }
+
+  /// Draws the shape over everything.
+  pub fn overlay(self) -> Self {
+    Self { depth: DepthMode::Overlay, ..self }
+  }
+}
+
+/// One queued line.
+#[derive(Debug, Clone, Copy)]
+struct DebugLine {
+  a: Vec3,
+  b: Vec3,
+  color: Vec4,
+  /// Seconds left, the line goes away once this drops below zero.
+  remaining: f32,
+  depth: DepthMode,
+}
+
+/// Shapes waiting to be drawn.
+///
+/// Everything becomes lines as soon as it's queued, so this doesn't need GL
+/// at all.
+#[derive(Debug, Clone, Default)]
+pub struct DebugQueue {
+  lines: Vec<DebugLine>,
+}
+impl DebugQueue {
+  /// Makes an empty queue.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The number of lines waiting.
+  pub fn len(&self) -> usize {
+    self.lines.len()
+  }
+
+  /// If nothing is waiting.
+  pub fn is_empty(&self) -> bool {
+    self.lines.is_empty()
+  }
+
+  /// Throws away everything, even shapes that have time left.
+  pub fn clear(&mut self) {
+    self.lines.clear();
+  }
+
+  /// Counts down the time left on each shape by `delta_time` seconds, and
+  /// removes the shapes that are done.
+  ///
+  /// [`DebugDraw::flush`] calls this after drawing.
+  pub fn age(&mut self, delta_time: f32) {
+    self.lines.retain(|l| l.remaining - delta_time > 0.0);
+    self.lines.iter_mut().for_each(|l| l.remaining -= delta_time);
+  }
+
+  /// A line from `a` to `b`.
+  pub fn line(&mut self, a: Vec3, b: Vec3, style: DebugStyle) {
+    self.lines.push(DebugLine {
+      a,
+      b,
+      color: style.color,
+      remaining: style.duration,
+      depth: style.depth,
+    });
+  }
+
+  /// A small 3D cross marking a point.
+  pub fn point(&mut self, p: Vec3, size: f32, style: DebugStyle) {
+    let h = size * 0.5;
+    self.line(p - Vec3::unit_x() * h, p + Vec3::unit_x() * h, style);
+    self.line(p - Vec3::unit_y() * h, p + Vec3::unit_y() * h, style);
+    self.line(p - Vec3::unit_z() * h, p + Vec3::unit_z() * h, style);
+  }
+
+  /// The edges of an axis-aligned box.
+  pub fn aabb(&mut self, min: Vec3, max: Vec3, style: DebugStyle) {
+    let corners = [
+      Vec3::new(min.x, min.y, min.z),
+      Vec3::new(max.x, min.y, min.z),
+      Vec3::new(max.x, max.y, min.z),
+      Vec3::new(min.x, max.y, min.z),
+      Vec3::new(min.x, min.y, max.z),
+      Vec3::new(max.x, min.y, max.z),
+      Vec3::new(max.x, max.y, max.z),
+      Vec3::new(min.x, max.y, max.z),
+    ];
+    self.box_edges(&corners, style);
+  }
+
+  /// The edges of a box given by 8 corners: the near face counter-clockwise,
+  /// then the far face in the same order.
+  fn box_edges(&mut self, c: &[Vec3; 8], style: DebugStyle) {
+    for i in 0..4 {
+      let j = (i + 1) % 4;
+      self.line(c[i], c[j], style);
+      self.line(c[i + 4], c[j + 4], style);
+      self.line(c[i], c[i + 4], style);
+    }
+  }
+
+  /// A circle around `normal`.
+  pub fn circle(
+    &mut self,
+    center: Vec3,
+    normal: Vec3,
+    radius: f32,
+    style: DebugStyle,
+  ) {
+    let normal = normal.normalized();
+    // any vector that isn't parallel to the normal will do
+    let other =
+      if normal.x.abs() < 0.9 { Vec3::unit_x() } else { Vec3::unit_y() };
+    let u = normal.cross(other).normalized() * radius;
+    let v = normal.cross(u);
+    let at = |i: usize| {
+      let t = i as f32 / CIRCLE_SEGMENTS as f32 * core::f32::consts::TAU;
+      center + u * t.cos() + v * t.sin()
+    };
+    for i in 0..CIRCLE_SEGMENTS {
+      self.line(at(i), at(i + 1), style);
+    }
+  }
+
+  /// A sphere, drawn as three circles.
+  pub fn sphere(&mut self, center: Vec3, radius: f32, style: DebugStyle) {
+    self.circle(center, Vec3::unit_x(), radius, style);
+    self.circle(center, Vec3::unit_y(), radius, style);
+    self.circle(center, Vec3::unit_z(), radius, style);
+  }
+
+  /// The edges of the volume that a camera can see.
+  ///
+  /// Pass the _camera's_ view-projection matrix, not the one you're drawing
+  /// with, or the frustum will be exactly the edges of the screen.
+  pub fn frustum(&mut self, view_projection: Mat4, style: DebugStyle) {
+    let inverse = view_projection.inversed();
+    let corner = |x: f32, y: f32, z: f32| {
+      Vec3::from_homogeneous_point(inverse * Vec4::new(x, y, z, 1.0))
+    };
+    let corners = [
+      corner(-1.0, -1.0, -1.0),
+      corner(1.0, -1.0, -1.0),
+      corner(1.0, 1.0, -1.0),
+      corner(-1.0, 1.0, -1.0),
+      corner(-1.0, -1.0, 1.0),
+      corner(1.0, -1.0, 1.0),
+      corner(1.0, 1.0, 1.0),
+      corner(-1.0, 1.0, 1.0),
+    ];
+    self.box_edges(&corners, style);
+  }
+
+  /// A square grid on the XZ plane, with `cells` cells of `spacing` along
+  /// each side.
+  pub fn grid(
+    &mut self,
+    center: Vec3,
+    cells: u32,
+    spacing: f32,
+    style: DebugStyle,
+  ) {
+    let half = cells as f32 * spacing * 0.5;
+    for i in 0..=cells {
+      let offset = i as f32 * spacing - half;
+      self.line(
+        center + Vec3::new(offset, 0.0, -half),
+        center + Vec3::new(offset, 0.0, half),
+        style,
+      );
+      self.line(
+        center + Vec3::new(-half, 0.0, offset),
+        center + Vec3::new(half, 0.0, offset),
+        style,
+      );
+    }
+  }
+
+  /// The X, Y, and Z axes of a transform, in red, green, and blue.
+  ///
+  /// The style's color is ignored, but its alpha is kept.
+  pub fn axes(&mut self, transform: Mat4, length: f32, style: DebugStyle) {
+    let origin = transform.cols[3].truncated();
+    let alpha = style.color.w;
+    for (axis, color) in [
+      (0, Vec4::new(1.0, 0.0, 0.0, alpha)),
+      (1, Vec4::new(0.0, 1.0, 0.0, alpha)),
+      (2, Vec4::new(0.0, 0.0, 1.0, alpha)),
+    ]
+    .iter()
+    {
+      let dir = transform.cols[*axis].truncated().normalized();
+      self.line(
+        origin,
+        origin + dir * length,
+        DebugStyle { color: *color, ..style },
+      );
+    }
+  }
+}
+
+thread_local! {
+  static QUEUE: RefCell<DebugQueue> = RefCell::new(DebugQueue::new());
+}
+
+/// Uses this thread's debug queue.
+///
+/// This way any code can queue up shapes without being handed a queue. Draw
+/// them with [`DebugDraw::flush_thread_queue`].
+///
+/// ## Panics
+/// * If called again from inside `op`.
+pub fn with_queue<R>(op: impl FnOnce(&mut DebugQueue) -> R) -> R {
+  QUEUE.with(|q| op(&mut q.borrow_mut()))
+}
+
+/// Draws a [`DebugQueue`].
+pub struct DebugDraw {
+  vao: VertexArray,
+  vbo: Buffer,
+  program: ShaderProgram,
+  view_projection_loc: GLint,
+  vertices: Vec<[f32; 7]>,
+}
+impl DebugDraw {
+  /// Makes the line buffer and compiles the line shader.
+  pub fn new() -> Result<Self, String> {
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    vao.bind();
+    let vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    vbo.bind(BufferType::Array);
+    let stride: GLsizei = size_of::<[f32; 7]>().try_into().unwrap();
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        3,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+      glVertexAttribPointer(
+        1,
+        4,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        size_of::<[f32; 3]>() as *const _,
+      );
+      glEnableVertexAttribArray(1);
+    }
+    VertexArray::clear_binding();
+    Buffer::clear_binding(BufferType::Array);
+
+    let program = ShaderProgram::from_vert_frag(VERT_SHADER, FRAG_SHADER)?;
+    let view_projection_loc = program
+      .uniform_location("view_projection")
+      .ok_or("Debug draw shader is missing `view_projection`")?;
+    Ok(Self { vao, vbo, program, view_projection_loc, vertices: Vec::new() })
+  }
+
+  /// The state that depth tested shapes draw with: tested with `LessEqual`
+  /// so lines on top of surfaces still show, but not written, and blended.
+  pub fn tested_state() -> RenderState {
+    RenderState {
+      depth: DepthState {
+        test: true,
+        func: CompareFunc::LessEqual,
+        write: false,
+      },
+      blend: BlendState::ALPHA,
+      ..RenderState::default()
+    }
+  }
+
+  /// Draws everything in the queue, then ages it by `delta_time` seconds.
+  ///
+  /// Depth tested shapes go first, then the overlay shapes on top. Call this
+  /// after the scene is drawn, with the depth buffer still around. This
+  /// leaves the debug program in use.
+  pub fn flush(
+    &mut self,
+    queue: &mut DebugQueue,
+    view_projection: Mat4,
+    delta_time: f32,
+    cache: &mut StateCache,
+  ) {
+    if queue.is_empty() {
+      return;
+    }
+    self.vertices.clear();
+    let mut tested = 0;
+    for mode in [DepthMode::Tested, DepthMode::Overlay].iter() {
+      for line in queue.lines.iter().filter(|l| l.depth == *mode) {
+        let c = line.color;
+        for p in [line.a, line.b].iter() {
+          self.vertices.push([p.x, p.y, p.z, c.x, c.y, c.z, c.w]);
+        }
+      }
+      if *mode == DepthMode::Tested {
+        tested = self.vertices.len();
+      }
+    }
+    let total = self.vertices.len();
+
+    self.vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&self.vertices),
+      GL_STREAM_DRAW,
+    );
+    Buffer::clear_binding(BufferType::Array);
+    self.program.use_program();
+    unsafe {
+      glUniformMatrix4fv(
+        self.view_projection_loc,
+        1,
+        GL_FALSE,
+        view_projection.as_ptr(),
+      )
+    };
+    self.vao.bind();
+    if tested > 0 {
+      cache.apply(&Self::tested_state());
+      unsafe { glDrawArrays(GL_LINES, 0, tested as GLsizei) };
+    }
+    if total > tested {
+      cache.apply(&RenderState::overlay());
+      unsafe {
+        glDrawArrays(GL_LINES, tested as GLsizei, (total - tested) as GLsizei)
+      };
+    }
+    VertexArray::clear_binding();
+    queue.age(delta_time);
+  }
+
+  /// Like [`flush`](Self::flush), using this thread's queue (see
+  /// [`with_queue`]).
+  pub fn flush_thread_queue(
+    &mut self,
+    view_projection: Mat4,
+    delta_time: f32,
+    cache: &mut StateCache,
+  ) {
+    with_queue(|q| self.flush(q, view_projection, delta_time, cache))
+  }
+
+  /// Deletes the GL objects.
+  pub fn delete(self) {
+    self.vao.delete();
+    self.vbo.delete();
+    self.program.delete();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: Vec3, b: Vec3) -> bool {
+    (a - b).mag() < 1e-4
+  }
+
+  #[test]
+  fn zero_duration_lasts_one_frame() {
+    let mut queue = DebugQueue::new();
+    queue.line(Vec3::zero(), Vec3::one(), DebugStyle::default());
+    queue.line(
+      Vec3::zero(),
+      Vec3::one(),
+      DebugStyle::default().for_seconds(1.0),
+    );
+    assert_eq!(queue.len(), 2);
+    // what `flush` does after drawing the first frame
+    queue.age(0.016);
+    assert_eq!(queue.len(), 1);
+    queue.age(0.5);
+    assert_eq!(queue.len(), 1);
+    queue.age(0.5);
+    assert!(queue.is_empty());
+    // even a frame that took no time
+    queue.point(Vec3::zero(), 1.0, DebugStyle::default());
+    queue.age(0.0);
+    assert!(queue.is_empty());
+  }
+
+  #[test]
+  fn shape_line_counts() {
+    let style = DebugStyle::default();
+    let count = |shape: &dyn Fn(&mut DebugQueue)| {
+      let mut queue = DebugQueue::new();
+      shape(&mut queue);
+      queue.len()
+    };
+    assert_eq!(count(&|q| q.point(Vec3::zero(), 1.0, style)), 3);
+    assert_eq!(count(&|q| q.aabb(Vec3::zero(), Vec3::one(), style)), 12);
+    assert_eq!(count(&|q| q.grid(Vec3::zero(), 4, 1.0, style)), 10);
+    assert_eq!(count(&|q| q.grid(Vec3::zero(), 0, 1.0, style)), 2);
+    assert_eq!(
+      count(&|q| q.sphere(Vec3::zero(), 1.0, style)),
+      3 * CIRCLE_SEGMENTS
+    );
+    assert_eq!(count(&|q| q.axes(Mat4::identity(), 1.0, style)), 3);
+    assert_eq!(count(&|q| q.frustum(Mat4::identity(), style)), 12);
+  }
+
+  #[test]
+  fn grid_and_sphere_positions() {
+    let mut queue = DebugQueue::new();
+    queue.grid(Vec3::new(0.0, 1.0, 0.0), 2, 3.0, DebugStyle::default());
+    for line in &queue.lines {
+      for p in [line.a, line.b].iter() {
+        assert_eq!(p.y, 1.0);
+        assert!(p.x.abs() <= 3.0 && p.z.abs() <= 3.0);
+      }
+    }
+    queue.clear();
+    let center = Vec3::new(1.0, 2.0, 3.0);
+    queue.sphere(center, 2.0, DebugStyle::default());
+    for line in &queue.lines {
+      assert!(((line.a - center).mag() - 2.0).abs() < 1e-4);
+    }
+  }
+
+  #[test]
+  fn frustum_corners() {
+    // 90 degrees wide and square, so each corner is as far out as it is deep
+    let projection = ultraviolet::projection::perspective_gl(
+      90_f32.to_radians(),
+      1.0,
+      1.0,
+      10.0,
+    );
+    let mut queue = DebugQueue::new();
+    queue.frustum(projection, DebugStyle::default());
+    let mut corners: Vec<Vec3> = Vec::new();
+    for line in &queue.lines {
+      for p in [line.a, line.b].iter() {
+        if !corners.iter().any(|c| close(*c, *p)) {
+          corners.push(*p);
+        }
+      }
+    }
+    assert_eq!(corners.len(), 8);
+    for &(x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+      let near = Vec3::new(x, y, -1.0);
+      let far = Vec3::new(x, y, -1.0) * 10.0;
+      assert!(corners.iter().any(|c| close(*c, near)), "{:?}", near);
+      assert!(corners.iter().any(|c| close(*c, far)), "{:?}", far);
+    }
+    // the first edge goes along the bottom of the near plane
+    assert!(close(queue.lines[0].a, Vec3::new(-1.0, -1.0, -1.0)));
+    assert!(close(queue.lines[0].b, Vec3::new(1.0, -1.0, -1.0)));
+  }
+
+  #[test]
+  fn axes_keep_alpha() {
+    let mut queue = DebugQueue::new();
+    let style = DebugStyle {
+      color: Vec4::new(0.5, 0.5, 0.5, 0.25),
+      ..DebugStyle::default()
+    };
+    let transform =
+      Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0)) * Mat4::from_scale(2.0);
+    queue.axes(transform, 1.0, style.overlay());
+    let x = queue.lines[0];
+    assert_eq!(x.color, Vec4::new(1.0, 0.0, 0.0, 0.25));
+    assert_eq!(x.depth, DepthMode::Overlay);
+    assert!(close(x.a, Vec3::new(1.0, 0.0, 0.0)));
+    // the scale doesn't change the length
+    assert!(close(x.b, Vec3::new(2.0, 0.0, 0.0)));
+  }
+}
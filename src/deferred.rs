@@ -0,0 +1,572 @@
+//! Deferred shading, for scenes with lots of lights.
+//!
+//! With forward rendering every object is lit by every light, all inside the
+//! object's own shader. Deferred shading splits that in two:
+//!
+//! 1. The _geometry pass_ draws every object once, but instead of a color it
+//!    writes the surface info (position, normal, albedo, specular) into a
+//!    [`GBuffer`] with several color attachments at once.
+//! 2. The _lighting pass_ reads that back. Each point light is drawn as a
+//!    sphere covering the area it can reach (its "light volume"), so each
+//!    light only costs the pixels it actually touches.
+//!
+//! Your geometry pass shaders write their outputs with the
+//! [`GBUFFER_GLSL`] helper. Everything after that is handled here.
+//!
+//! ```ignore
+//! renderer.begin_geometry(&mut state_cache);
+//! draw_scene_with_gbuffer_shaders();
+//! Framebuffer::clear_binding(FramebufferTarget::Both);
+//! resizer.reset_viewport();
+//! clear_the_window();
+//! renderer.light(camera_pos, view_projection, &lights, &mut state_cache);
+//! ```
+//!
+//! Transparent things don't fit in a G-buffer (there's only one surface per
+//! pixel), so draw them with forward rendering afterwards.
+
+use crate::{
+  buffer_data,
+  framebuffer::{BlitMask, DepthBuffer, RenderTarget, RenderTargetDesc},
+  postprocess::{FullscreenTriangle, FULLSCREEN_VERT},
+  resize::Resize,
+  state::{BlendState, CullFace, CullState, RenderState, StateCache},
+  texture::{self, Texture, TextureFormat, TextureTarget},
+  Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{convert::TryInto, mem::size_of};
+use ogl33::*;
+use ultraviolet::{Mat4, Vec3};
+
+/// GLSL outputs for a geometry pass fragment shader.
+///
+/// Paste this in after the `#version` line, then call
+/// `write_gbuffer(world_pos, normal, albedo, specular, shininess)` once in
+/// `main`. `specular` is the strength of the highlight (`0.0 ..= 1.0`) and
+/// `shininess` is the Blinn-Phong exponent.
+pub const GBUFFER_GLSL: &str = r#"
+  layout (location = 0) out vec4 g_position;
+  layout (location = 1) out vec4 g_normal;
+  layout (location = 2) out vec4 g_albedo_spec;
+
+  void write_gbuffer(
+    vec3 world_pos, vec3 normal, vec3 albedo, float specular, float shininess
+  ) {
+    g_position = vec4(world_pos, 1.0);
+    g_normal = vec4(normalize(normal), shininess);
+    g_albedo_spec = vec4(albedo, specular);
+  }
+"#;
+
+const AMBIENT_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D g_position;
+  uniform sampler2D g_albedo_spec;
+  uniform vec3 ambient;
+  out vec4 final_color;
+
+  void main() {
+    if (texture(g_position, uv).w == 0.0) {
+      discard;
+    }
+    final_color = vec4(texture(g_albedo_spec, uv).rgb * ambient, 1.0);
+  }
+"#;
+
+const LIGHT_VERT: &str = r#"#version 330 core
+  layout (location = 0) in vec3 pos;
+  layout (location = 1) in vec4 light_pos_radius;
+  layout (location = 2) in vec4 light_color;
+
+  uniform mat4 view_projection;
+
+  flat out vec4 v_light_pos_radius;
+  flat out vec3 v_light_color;
+
+  void main() {
+    vec3 world = light_pos_radius.xyz + pos * light_pos_radius.w;
+    gl_Position = view_projection * vec4(world, 1.0);
+    v_light_pos_radius = light_pos_radius;
+    v_light_color = light_color.rgb;
+  }
+"#;
+
+const LIGHT_FRAG: &str = r#"#version 330 core
+  flat in vec4 v_light_pos_radius;
+  flat in vec3 v_light_color;
+
+  uniform sampler2D g_position;
+  uniform sampler2D g_normal;
+  uniform sampler2D g_albedo_spec;
+  uniform vec2 screen_size;
+  uniform vec3 view_position;
+
+  out vec4 final_color;
+
+  void main() {
+    vec2 uv = gl_FragCoord.xy / screen_size;
+    vec4 p = texture(g_position, uv);
+    if (p.w == 0.0) {
+      discard;
+    }
+    vec3 to_light = v_light_pos_radius.xyz - p.xyz;
+    float d = length(to_light);
+    float radius = v_light_pos_radius.w;
+    if (d > radius) {
+      discard;
+    }
+    vec4 n = texture(g_normal, uv);
+    vec4 albedo_spec = texture(g_albedo_spec, uv);
+    vec3 normal = normalize(n.xyz);
+    vec3 l = to_light / d;
+    vec3 v = normalize(view_position - p.xyz);
+    vec3 h = normalize(l + v);
+
+    float diffuse = max(dot(normal, l), 0.0);
+    float specular = pow(max(dot(normal, h), 0.0), n.w) * albedo_spec.a;
+    float x = d / radius;
+    float window = clamp(1.0 - x * x, 0.0, 1.0);
+    float attenuation = window * window / (d * d + 1.0);
+
+    vec3 c = (diffuse * albedo_spec.rgb + specular) * v_light_color;
+    final_color = vec4(c * attenuation, 1.0);
+  }
+"#;
+
+const DEBUG_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D g_position;
+  uniform sampler2D g_normal;
+  uniform sampler2D g_albedo_spec;
+  uniform sampler2D g_depth;
+  uniform int channel;
+  uniform float near;
+  uniform float far;
+  out vec4 final_color;
+
+  void main() {
+    vec3 c;
+    if (channel == 0) {
+      c = texture(g_position, uv).xyz;
+    } else if (channel == 1) {
+      c = texture(g_normal, uv).xyz * 0.5 + 0.5;
+    } else if (channel == 2) {
+      c = texture(g_albedo_spec, uv).rgb;
+    } else if (channel == 3) {
+      c = vec3(texture(g_albedo_spec, uv).a);
+    } else {
+      float z = texture(g_depth, uv).r * 2.0 - 1.0;
+      float linear = (2.0 * near * far) / (far + near - z * (far - near));
+      c = vec3(linear / far);
+    }
+    final_color = vec4(c, 1.0);
+  }
+"#;
+
+/// One part of the G-buffer, to look at with
+/// [`DeferredRenderer::draw_debug`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GBufferView {
+  /// World position, as a color.
+  Position,
+  /// Normals, mapped from `-1.0 ..= 1.0` to `0.0 ..= 1.0`.
+  Normal,
+  /// Albedo color.
+  Albedo,
+  /// Specular strength, in gray.
+  Specular,
+  /// Depth, made linear so that it's not all white.
+  Depth {
+    /// The camera's near plane.
+    near: f32,
+    /// The camera's far plane.
+    far: f32,
+  },
+}
+
+/// The render target for a geometry pass.
+///
+/// * Color `0`: world position (`RGBA16F`), with `w` set to `1.0` where
+///   there's geometry and `0.0` where there's not.
+/// * Color `1`: normal and shininess (`RGBA16F`).
+/// * Color `2`: albedo and specular strength (`RGBA8`).
+/// * Depth: a 24-bit depth texture.
+pub struct GBuffer {
+  target: RenderTarget,
+}
+impl GBuffer {
+  /// Makes a G-buffer of the given size.
+  pub fn new(width: u32, height: u32) -> Result<Self, String> {
+    let desc = RenderTargetDesc {
+      color: vec![
+        TextureFormat::RGBA16F,
+        TextureFormat::RGBA16F,
+        TextureFormat::RGBA8,
+      ],
+      depth: Some(TextureFormat::Depth24),
+      depth_as_texture: true,
+      samples: 0,
+    };
+    Ok(Self { target: RenderTarget::new(width, height, desc)? })
+  }
+
+  /// The render target.
+  pub fn target(&self) -> &RenderTarget {
+    &self.target
+  }
+
+  /// The position texture.
+  pub fn position(&self) -> &Texture {
+    &self.target.color[0]
+  }
+
+  /// The normal and shininess texture.
+  pub fn normal(&self) -> &Texture {
+    &self.target.color[1]
+  }
+
+  /// The albedo and specular texture.
+  pub fn albedo_spec(&self) -> &Texture {
+    &self.target.color[2]
+  }
+
+  /// The depth texture.
+  pub fn depth(&self) -> &Texture {
+    match &self.target.depth {
+      Some(DepthBuffer::Texture(tex)) => tex,
+      _ => unreachable!("the G-buffer always has a depth texture"),
+    }
+  }
+
+  /// Binds the G-buffer and clears it, so the geometry pass can start.
+  ///
+  /// All the color attachments are cleared to zero, no matter what the
+  /// clear color is.
+  pub fn begin(&self) {
+    self.target.bind();
+    let zero = [0.0_f32; 4];
+    unsafe {
+      for i in 0..self.target.color.len() {
+        glClearBufferfv(GL_COLOR, i.try_into().unwrap(), zero.as_ptr());
+      }
+      glClear(GL_DEPTH_BUFFER_BIT);
+    }
+  }
+
+  /// Copies the depth into `dst` (or the window with `None`).
+  ///
+  /// Do this before a forward pass, so that things drawn after the lighting
+  /// still get hidden behind the G-buffer's geometry. The depth formats have
+  /// to match, so the window needs a 24-bit depth buffer.
+  pub fn copy_depth_to(&self, dst: Option<&RenderTarget>) {
+    self.target.resolve(dst, BlitMask::DEPTH);
+  }
+
+  /// Binds the position, normal, albedo, and depth textures to units
+  /// `first_unit` and up, in that order.
+  pub fn bind_textures(&self, first_unit: u32) {
+    let textures = [self.position(), self.normal(), self.albedo_spec()];
+    for (i, tex) in textures.iter().enumerate() {
+      texture::active_texture(first_unit + i as u32);
+      tex.bind(TextureTarget::Texture2D);
+    }
+    texture::active_texture(first_unit + 3);
+    self.depth().bind(TextureTarget::Texture2D);
+  }
+
+  /// Deletes the framebuffer and all the textures.
+  pub fn delete(self) {
+    self.target.delete()
+  }
+}
+impl Resize for GBuffer {
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    self.target.resize(width, height)
+  }
+}
+
+/// A point light for the deferred lighting pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+  /// Where the light is, in world space.
+  pub position: Vec3,
+  /// The light's color. This can go over `1.0` for brighter lights.
+  pub color: Vec3,
+  /// How far the light reaches. The light fades out to zero at this
+  /// distance, and nothing past it is touched at all.
+  pub radius: f32,
+}
+
+const SPHERE_RINGS: u16 = 8;
+const SPHERE_SEGMENTS: u16 = 12;
+
+/// A low-poly unit sphere, as positions and triangle indexes.
+///
+/// The vertices are pushed out a bit so that the flat faces are all still
+/// outside the true sphere, otherwise the light volume would clip the edge
+/// of the light's area.
+fn light_volume_sphere() -> (Vec<[f32; 3]>, Vec<u16>) {
+  use core::f32::consts::PI;
+  let scale = 1.0
+    / ((PI / SPHERE_SEGMENTS as f32).cos() * (PI / SPHERE_RINGS as f32).cos());
+  let mut vertices = Vec::new();
+  for r in 0..=SPHERE_RINGS {
+    let theta = PI * r as f32 / SPHERE_RINGS as f32;
+    for s in 0..=SPHERE_SEGMENTS {
+      let phi = 2.0 * PI * s as f32 / SPHERE_SEGMENTS as f32;
+      vertices.push([
+        theta.sin() * phi.cos() * scale,
+        theta.cos() * scale,
+        theta.sin() * phi.sin() * scale,
+      ]);
+    }
+  }
+  let mut indexes = Vec::new();
+  for r in 0..SPHERE_RINGS {
+    for s in 0..SPHERE_SEGMENTS {
+      let a = r * (SPHERE_SEGMENTS + 1) + s;
+      let b = a + SPHERE_SEGMENTS + 1;
+      indexes.extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+    }
+  }
+  (vertices, indexes)
+}
+
+fn set_sampler(program: &ShaderProgram, name: &str, unit: i32) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform1i(loc, unit) }
+  }
+}
+
+/// A G-buffer plus the lighting and debug passes that read it.
+pub struct DeferredRenderer {
+  /// The G-buffer.
+  pub gbuffer: GBuffer,
+  /// Light that reaches everything, multiplied by the albedo.
+  pub ambient: Vec3,
+  ambient_program: ShaderProgram,
+  light_program: ShaderProgram,
+  debug_program: ShaderProgram,
+  triangle: FullscreenTriangle,
+  sphere_vao: VertexArray,
+  sphere_vbo: Buffer,
+  sphere_ebo: Buffer,
+  instance_vbo: Buffer,
+  index_count: i32,
+}
+impl DeferredRenderer {
+  /// Makes the G-buffer, the light volume mesh, and the shaders.
+  pub fn new(width: u32, height: u32) -> Result<Self, String> {
+    let gbuffer = GBuffer::new(width, height)?;
+    let ambient_program =
+      ShaderProgram::from_vert_frag(FULLSCREEN_VERT, AMBIENT_FRAG)?;
+    let light_program = ShaderProgram::from_vert_frag(LIGHT_VERT, LIGHT_FRAG)?;
+    let debug_program =
+      ShaderProgram::from_vert_frag(FULLSCREEN_VERT, DEBUG_FRAG)?;
+    for program in [&ambient_program, &light_program, &debug_program].iter() {
+      program.use_program();
+      set_sampler(program, "g_position", 0);
+      set_sampler(program, "g_normal", 1);
+      set_sampler(program, "g_albedo_spec", 2);
+      set_sampler(program, "g_depth", 3);
+    }
+
+    let (vertices, indexes) = light_volume_sphere();
+    let sphere_vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    sphere_vao.bind();
+    let sphere_vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    sphere_vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&vertices),
+      GL_STATIC_DRAW,
+    );
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        3,
+        GL_FLOAT,
+        GL_FALSE,
+        size_of::<[f32; 3]>().try_into().unwrap(),
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+    }
+    let sphere_ebo = Buffer::new().ok_or("Couldn't make an EBO")?;
+    sphere_ebo.bind(BufferType::ElementArray);
+    buffer_data(
+      BufferType::ElementArray,
+      bytemuck::cast_slice(&indexes),
+      GL_STATIC_DRAW,
+    );
+    let instance_vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    instance_vbo.bind(BufferType::Array);
+    let stride = size_of::<[f32; 8]>().try_into().unwrap();
+    unsafe {
+      glVertexAttribPointer(
+        1,
+        4,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(1);
+      glVertexAttribDivisor(1, 1);
+      glVertexAttribPointer(
+        2,
+        4,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        size_of::<[f32; 4]>() as *const _,
+      );
+      glEnableVertexAttribArray(2);
+      glVertexAttribDivisor(2, 1);
+    }
+    VertexArray::clear_binding();
+
+    Ok(Self {
+      gbuffer,
+      ambient: Vec3::broadcast(0.05),
+      ambient_program,
+      light_program,
+      debug_program,
+      triangle: FullscreenTriangle::new()?,
+      sphere_vao,
+      sphere_vbo,
+      sphere_ebo,
+      instance_vbo,
+      index_count: indexes.len().try_into().unwrap(),
+    })
+  }
+
+  /// Starts the geometry pass: binds and clears the G-buffer and applies
+  /// [`RenderState::opaque`].
+  pub fn begin_geometry(&self, cache: &mut StateCache) {
+    cache.apply(&RenderState::opaque());
+    self.gbuffer.begin();
+  }
+
+  /// Lights the G-buffer into whatever framebuffer is bound.
+  ///
+  /// First the ambient light is drawn (replacing what's there, except where
+  /// there's no geometry), then each light volume is added on top. The bound
+  /// framebuffer should be the same size as the G-buffer.
+  pub fn light(
+    &self,
+    view_position: Vec3,
+    view_projection: Mat4,
+    lights: &[PointLight],
+    cache: &mut StateCache,
+  ) {
+    self.gbuffer.bind_textures(0);
+
+    cache.apply(&RenderState::default());
+    self.ambient_program.use_program();
+    if let Some(loc) = self.ambient_program.uniform_location("ambient") {
+      unsafe {
+        glUniform3f(loc, self.ambient.x, self.ambient.y, self.ambient.z)
+      }
+    }
+    self.triangle.draw();
+
+    if lights.is_empty() {
+      return;
+    }
+    // Culling the front faces means we draw the far side of each sphere,
+    // which still works when the camera is inside the light's volume.
+    cache.apply(&RenderState {
+      blend: BlendState::ADDITIVE,
+      cull: CullState {
+        enabled: true,
+        face: CullFace::Front,
+        ..CullState::default()
+      },
+      ..RenderState::default()
+    });
+    let instances: Vec<[f32; 8]> = lights
+      .iter()
+      .map(|l| {
+        let (p, c) = (l.position, l.color);
+        [p.x, p.y, p.z, l.radius, c.x, c.y, c.z, 0.0]
+      })
+      .collect();
+    self.instance_vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&instances),
+      GL_STREAM_DRAW,
+    );
+    Buffer::clear_binding(BufferType::Array);
+
+    let p = &self.light_program;
+    p.use_program();
+    let (w, h) = self.gbuffer.target().size();
+    unsafe {
+      if let Some(loc) = p.uniform_location("view_projection") {
+        glUniformMatrix4fv(loc, 1, GL_FALSE, view_projection.as_ptr());
+      }
+      if let Some(loc) = p.uniform_location("screen_size") {
+        glUniform2f(loc, w as f32, h as f32);
+      }
+      if let Some(loc) = p.uniform_location("view_position") {
+        glUniform3f(loc, view_position.x, view_position.y, view_position.z);
+      }
+      self.sphere_vao.bind();
+      glDrawElementsInstanced(
+        GL_TRIANGLES,
+        self.index_count,
+        GL_UNSIGNED_SHORT,
+        core::ptr::null(),
+        instances.len().try_into().unwrap(),
+      );
+    }
+    VertexArray::clear_binding();
+  }
+
+  /// Shows one part of the G-buffer in whatever framebuffer is bound.
+  pub fn draw_debug(&self, view: GBufferView, cache: &mut StateCache) {
+    self.gbuffer.bind_textures(0);
+    cache.apply(&RenderState::default());
+    let p = &self.debug_program;
+    p.use_program();
+    let (channel, near, far) = match view {
+      GBufferView::Position => (0, 0.0, 1.0),
+      GBufferView::Normal => (1, 0.0, 1.0),
+      GBufferView::Albedo => (2, 0.0, 1.0),
+      GBufferView::Specular => (3, 0.0, 1.0),
+      GBufferView::Depth { near, far } => (4, near, far),
+    };
+    unsafe {
+      if let Some(loc) = p.uniform_location("channel") {
+        glUniform1i(loc, channel);
+      }
+      if let Some(loc) = p.uniform_location("near") {
+        glUniform1f(loc, near);
+      }
+      if let Some(loc) = p.uniform_location("far") {
+        glUniform1f(loc, far);
+      }
+    }
+    self.triangle.draw();
+  }
+
+  /// Deletes everything.
+  pub fn delete(self) {
+    self.gbuffer.delete();
+    self.ambient_program.delete();
+    self.light_program.delete();
+    self.debug_program.delete();
+    self.triangle.delete();
+    self.sphere_vao.delete();
+    self.sphere_vbo.delete();
+    self.sphere_ebo.delete();
+    self.instance_vbo.delete();
+  }
+}
+impl Resize for DeferredRenderer {
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    self.gbuffer.resize(width, height)
+  }
+}
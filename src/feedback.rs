@@ -0,0 +1,311 @@
+//! Capturing vertex shader output with [Transform
+//! Feedback](https://www.khronos.org/opengl/wiki/Transform_Feedback).
+//!
+//! Normally vertex shader outputs go on to the rasterizer and are gone after
+//! the draw. With transform feedback, the outputs you pick are also written
+//! into buffers. That gives you a way to do work on the GPU and keep the
+//! results, such as stepping a particle simulation forward (draw last frame's
+//! particles, capture this frame's), or checking shader math against the
+//! same math on the CPU. The `019-transform-feedback` example does the
+//! second one.
+//!
+//! The steps are:
+//! 1. Pick the varyings with
+//!    [`ShaderProgram::set_feedback_varyings`] _before_ linking, or just use
+//!    [`feedback_program`].
+//! 2. Make a [`TransformFeedback`] with room for the output.
+//! 3. [`begin`](TransformFeedback::begin), draw,
+//!    [`end`](TransformFeedback::end).
+//! 4. Use the buffers as vertex input for the next draw, or
+//!    [`read`](TransformFeedback::read) them back.
+//!
+//! ```ignore
+//! let program = feedback_program(
+//!   SQUARE_VERT,
+//!   &["out_value"],
+//!   FeedbackMode::Interleaved,
+//! )?;
+//! let mut feedback = TransformFeedback::new(&[4 * 1024])?;
+//! program.use_program();
+//! feedback.begin(FeedbackPrimitive::Points);
+//! unsafe { glDrawArrays(GL_POINTS, 0, 1024) };
+//! feedback.end();
+//! let squares: Vec<f32> = feedback.read_captured(0);
+//! ```
+
+use crate::{
+  query::{Query, QueryKind},
+  Buffer, BufferType, ShaderProgram, ShaderType,
+};
+use bytemuck::Pod;
+use ogl33::*;
+
+/// How the captured varyings are laid out in the buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackMode {
+  /// All the varyings go into one buffer, one vertex after another.
+  Interleaved = GL_INTERLEAVED_ATTRIBS as isize,
+  /// Each varying goes into its own buffer, in the order they were listed.
+  Separate = GL_SEPARATE_ATTRIBS as isize,
+}
+
+/// The kind of primitive that's captured.
+///
+/// This has to match the draws made while capturing: `Points` for
+/// `GL_POINTS`, `Lines` for any of the line modes, and `Triangles` for any of
+/// the triangle modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackPrimitive {
+  /// One vertex per primitive.
+  Points = GL_POINTS as isize,
+  /// Two vertices per primitive.
+  Lines = GL_LINES as isize,
+  /// Three vertices per primitive.
+  Triangles = GL_TRIANGLES as isize,
+}
+impl FeedbackPrimitive {
+  /// The number of vertices captured for each primitive.
+  pub fn vertices(self) -> u32 {
+    match self {
+      Self::Points => 1,
+      Self::Lines => 2,
+      Self::Triangles => 3,
+    }
+  }
+}
+
+/// The most varyings that can be captured in separate mode, which is also
+/// the most buffers a [`TransformFeedback`] can have.
+pub fn max_separate_buffers() -> u32 {
+  let mut max = 0;
+  unsafe {
+    glGetIntegerv(GL_MAX_TRANSFORM_FEEDBACK_SEPARATE_ATTRIBS, &mut max)
+  };
+  max.max(0) as u32
+}
+
+/// Makes a program with only a vertex shader, capturing the given varyings.
+///
+/// With no fragment shader nothing useful can be drawn, so this is for
+/// programs that are only run for their captured output.
+pub fn feedback_program(
+  vert: &str,
+  varyings: &[&str],
+  mode: FeedbackMode,
+) -> Result<ShaderProgram, String> {
+  ShaderProgram::from_shaders(
+    &[(ShaderType::Vertex, vert)],
+    Some(&|p: &ShaderProgram| p.set_feedback_varyings(varyings, mode)),
+  )
+}
+
+/// The buffers that transform feedback writes into, and a query that counts
+/// what got written.
+///
+/// GL 3.3 doesn't have transform feedback _objects_ (those are GL 4.0), so
+/// this just keeps the buffers together and binds them all at
+/// [`begin`](Self::begin).
+pub struct TransformFeedback {
+  buffers: Vec<(Buffer, usize)>,
+  query: Query,
+  active: Option<FeedbackPrimitive>,
+  last: Option<FeedbackPrimitive>,
+  /// If rasterizing is turned off while capturing. Defaults to `true`, since
+  /// usually you only want the captured output. Turn it off to capture and
+  /// draw in the same pass.
+  pub discard_rasterizer: bool,
+}
+impl TransformFeedback {
+  /// Makes one buffer for each given size in bytes.
+  ///
+  /// Interleaved capture uses one buffer, separate capture needs a buffer for
+  /// each varying.
+  pub fn new(sizes: &[usize]) -> Result<Self, String> {
+    if sizes.is_empty() {
+      return Err("Transform feedback needs at least one buffer".to_string());
+    }
+    let max = max_separate_buffers() as usize;
+    if sizes.len() > max {
+      return Err(format!(
+        "{} feedback buffers is more than the limit of {}",
+        sizes.len(),
+        max
+      ));
+    }
+    let mut buffers = Vec::with_capacity(sizes.len());
+    for &size in sizes {
+      let buffer = Buffer::new().ok_or("Couldn't make a feedback buffer")?;
+      buffer.bind(BufferType::TransformFeedback);
+      unsafe {
+        glBufferData(
+          GL_TRANSFORM_FEEDBACK_BUFFER,
+          size as GLsizeiptr,
+          core::ptr::null(),
+          GL_DYNAMIC_COPY,
+        )
+      };
+      buffers.push((buffer, size));
+    }
+    Buffer::clear_binding(BufferType::TransformFeedback);
+    let query = match Query::new() {
+      Some(query) => query,
+      None => {
+        buffers.into_iter().for_each(|(b, _)| b.delete());
+        return Err("Couldn't make a query object".to_string());
+      }
+    };
+    Ok(Self {
+      buffers,
+      query,
+      active: None,
+      last: None,
+      discard_rasterizer: true,
+    })
+  }
+
+  /// The number of buffers.
+  pub fn len(&self) -> usize {
+    self.buffers.len()
+  }
+
+  /// If there are no buffers (which never happens).
+  pub fn is_empty(&self) -> bool {
+    self.buffers.is_empty()
+  }
+
+  /// A buffer, to use as vertex input or fill with starting data.
+  ///
+  /// ## Panics
+  /// * If the index is out of bounds.
+  pub fn buffer(&self, index: usize) -> &Buffer {
+    &self.buffers[index].0
+  }
+
+  /// The size of a buffer, in bytes.
+  ///
+  /// ## Panics
+  /// * If the index is out of bounds.
+  pub fn buffer_size(&self, index: usize) -> usize {
+    self.buffers[index].1
+  }
+
+  /// If capturing is going on right now.
+  pub fn is_active(&self) -> bool {
+    self.active.is_some()
+  }
+
+  /// Starts capturing.
+  ///
+  /// The program that's in use must have been linked with feedback varyings,
+  /// and every draw until [`end`](Self::end) must match `primitive`. Capture
+  /// starts at the beginning of each buffer, overwriting what was there.
+  ///
+  /// ## Panics
+  /// * If capturing is already going on.
+  pub fn begin(&mut self, primitive: FeedbackPrimitive) {
+    assert!(self.active.is_none(), "transform feedback is already active");
+    for (index, (buffer, _)) in self.buffers.iter().enumerate() {
+      unsafe {
+        glBindBufferBase(
+          GL_TRANSFORM_FEEDBACK_BUFFER,
+          index as GLuint,
+          buffer.0,
+        )
+      };
+    }
+    unsafe {
+      if self.discard_rasterizer {
+        glEnable(GL_RASTERIZER_DISCARD);
+      }
+      self.query.begin(QueryKind::FeedbackPrimitives);
+      glBeginTransformFeedback(primitive as GLenum);
+    }
+    self.active = Some(primitive);
+  }
+
+  /// Stops capturing.
+  ///
+  /// This unbinds the buffers from the indexed binding points, so that they
+  /// can be used as vertex input right away.
+  ///
+  /// ## Panics
+  /// * If capturing wasn't going on.
+  pub fn end(&mut self) {
+    let primitive = self.active.take().expect("transform feedback not active");
+    unsafe {
+      glEndTransformFeedback();
+      Query::end(QueryKind::FeedbackPrimitives);
+      if self.discard_rasterizer {
+        glDisable(GL_RASTERIZER_DISCARD);
+      }
+      for index in 0..self.buffers.len() {
+        glBindBufferBase(GL_TRANSFORM_FEEDBACK_BUFFER, index as GLuint, 0);
+      }
+    }
+    self.last = Some(primitive);
+  }
+
+  /// The number of primitives written during the last capture.
+  ///
+  /// Primitives that didn't fit in the buffers aren't counted. This waits
+  /// for the GPU to finish the capture.
+  pub fn primitives_written(&self) -> u32 {
+    if self.last.is_none() {
+      return 0;
+    }
+    self.query.result() as u32
+  }
+
+  /// The number of vertices written during the last capture.
+  ///
+  /// This waits for the GPU to finish the capture.
+  pub fn vertices_written(&self) -> u32 {
+    match self.last {
+      Some(primitive) => self.primitives_written() * primitive.vertices(),
+      None => 0,
+    }
+  }
+
+  /// Reads values back from the start of a buffer.
+  ///
+  /// This gives at most `count` values, fewer if the buffer isn't that big.
+  ///
+  /// ## Panics
+  /// * If the index is out of bounds.
+  /// * If capturing is going on.
+  pub fn read<T: Pod>(&self, index: usize, count: usize) -> Vec<T> {
+    assert!(self.active.is_none(), "can't read during transform feedback");
+    let (buffer, size) = &self.buffers[index];
+    let count = count.min(size / core::mem::size_of::<T>().max(1));
+    let mut out = vec![T::zeroed(); count];
+    let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut out);
+    unsafe {
+      glBindBuffer(GL_COPY_READ_BUFFER, buffer.0);
+      glGetBufferSubData(
+        GL_COPY_READ_BUFFER,
+        0,
+        bytes.len() as GLsizeiptr,
+        bytes.as_mut_ptr().cast(),
+      );
+      glBindBuffer(GL_COPY_READ_BUFFER, 0);
+    }
+    out
+  }
+
+  /// Reads back one value per vertex from the last capture.
+  ///
+  /// With interleaved capture `T` should be a struct of all the varyings (in
+  /// order, and with no padding), with separate capture it's the type of that
+  /// buffer's varying. This waits for the GPU to finish the capture.
+  pub fn read_captured<T: Pod>(&self, index: usize) -> Vec<T> {
+    self.read(index, self.vertices_written() as usize)
+  }
+
+  /// Deletes the buffers and the query.
+  pub fn delete(self) {
+    self.query.delete();
+    for (buffer, _) in self.buffers {
+      buffer.delete();
+    }
+  }
+}
@@ -0,0 +1,632 @@
+//! Wrappers for [Framebuffer](https://www.khronos.org/opengl/wiki/Framebuffer_Object)
+//! and [Renderbuffer](https://www.khronos.org/opengl/wiki/Renderbuffer_Object)
+//! objects, so that you can draw somewhere other than the window.
+
+use crate::texture::{
+  self, CubeFace, MagFilter, MinFilter, Texture, TextureFormat, TextureTarget,
+  TextureWrap,
+};
+use core::convert::TryInto;
+use ogl33::*;
+
+/// The framebuffer binding points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferTarget {
+  /// Both the read and draw binding at once.
+  Both = GL_FRAMEBUFFER as isize,
+  /// Where reads (`glReadPixels`, blit sources) come from.
+  Read = GL_READ_FRAMEBUFFER as isize,
+  /// Where drawing goes.
+  Draw = GL_DRAW_FRAMEBUFFER as isize,
+}
+
+/// The places that an image can be attached to a framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attachment {
+  /// Color attachment `N`.
+  Color(u32),
+  /// The depth buffer.
+  Depth,
+  /// The stencil buffer.
+  Stencil,
+  /// A combined depth and stencil buffer.
+  DepthStencil,
+}
+impl Attachment {
+  /// The matching `GL_*_ATTACHMENT` value.
+  pub const fn gl_enum(self) -> GLenum {
+    match self {
+      Attachment::Color(n) => GL_COLOR_ATTACHMENT0 + n,
+      Attachment::Depth => GL_DEPTH_ATTACHMENT,
+      Attachment::Stencil => GL_STENCIL_ATTACHMENT,
+      Attachment::DepthStencil => GL_DEPTH_STENCIL_ATTACHMENT,
+    }
+  }
+
+  /// The attachment point that a depth format should use.
+  pub const fn for_depth_format(format: TextureFormat) -> Self {
+    if format.has_stencil() {
+      Attachment::DepthStencil
+    } else {
+      Attachment::Depth
+    }
+  }
+}
+
+/// Basic wrapper for a [Framebuffer
+/// Object](https://www.khronos.org/opengl/wiki/Framebuffer_Object).
+pub struct Framebuffer(pub GLuint);
+impl Framebuffer {
+  /// Makes a new framebuffer.
+  pub fn new() -> Option<Self> {
+    let mut fbo = 0;
+    unsafe { glGenFramebuffers(1, &mut fbo) };
+    if fbo != 0 {
+      Some(Self(fbo))
+    } else {
+      None
+    }
+  }
+
+  /// Bind this framebuffer to the given target.
+  pub fn bind(&self, target: FramebufferTarget) {
+    unsafe { glBindFramebuffer(target as GLenum, self.0) }
+  }
+
+  /// Go back to drawing to (and reading from) the window's framebuffer.
+  pub fn clear_binding(target: FramebufferTarget) {
+    unsafe { glBindFramebuffer(target as GLenum, 0) }
+  }
+
+  /// Deletes the framebuffer.
+  ///
+  /// Attached images are _not_ deleted.
+  pub fn delete(self) {
+    unsafe { glDeleteFramebuffers(1, &self.0) }
+  }
+}
+
+/// Attaches mip level `level` of a 2D texture to the framebuffer bound to
+/// `target`.
+pub fn attach_texture_2d(
+  target: FramebufferTarget,
+  attachment: Attachment,
+  tex: &Texture,
+  level: u32,
+) {
+  unsafe {
+    glFramebufferTexture2D(
+      target as GLenum,
+      attachment.gl_enum(),
+      GL_TEXTURE_2D,
+      tex.0,
+      level.try_into().unwrap(),
+    )
+  }
+}
+
+/// Attaches mip level `level` of one face of a cube map to the framebuffer
+/// bound to `target`.
+pub fn attach_cube_face(
+  target: FramebufferTarget,
+  attachment: Attachment,
+  tex: &Texture,
+  face: CubeFace,
+  level: u32,
+) {
+  unsafe {
+    glFramebufferTexture2D(
+      target as GLenum,
+      attachment.gl_enum(),
+      face as GLenum,
+      tex.0,
+      level.try_into().unwrap(),
+    )
+  }
+}
+
+/// Attaches a multisample texture to the framebuffer bound to `target`.
+pub fn attach_texture_2d_multisample(
+  target: FramebufferTarget,
+  attachment: Attachment,
+  tex: &Texture,
+) {
+  unsafe {
+    glFramebufferTexture2D(
+      target as GLenum,
+      attachment.gl_enum(),
+      GL_TEXTURE_2D_MULTISAMPLE,
+      tex.0,
+      0,
+    )
+  }
+}
+
+/// Attaches a renderbuffer to the framebuffer bound to `target`.
+pub fn attach_renderbuffer(
+  target: FramebufferTarget,
+  attachment: Attachment,
+  rbo: &Renderbuffer,
+) {
+  unsafe {
+    glFramebufferRenderbuffer(
+      target as GLenum,
+      attachment.gl_enum(),
+      GL_RENDERBUFFER,
+      rbo.0,
+    )
+  }
+}
+
+/// Sets which color attachments the fragment shader outputs go to.
+///
+/// Output `0` goes to the first attachment listed, output `1` to the second,
+/// and so on. An empty list means there's no color output at all, which is
+/// what depth-only passes want.
+pub fn draw_buffers(attachments: &[u32]) {
+  if attachments.is_empty() {
+    unsafe { glDrawBuffer(GL_NONE) };
+    return;
+  }
+  let bufs: Vec<GLenum> =
+    attachments.iter().map(|&n| GL_COLOR_ATTACHMENT0 + n).collect();
+  unsafe { glDrawBuffers(bufs.len().try_into().unwrap(), bufs.as_ptr()) };
+}
+
+/// Checks that the framebuffer bound to `target` is complete.
+///
+/// The error message names the reason, if it's one we know.
+pub fn check_status(target: FramebufferTarget) -> Result<(), String> {
+  let status = unsafe { glCheckFramebufferStatus(target as GLenum) };
+  match status {
+    GL_FRAMEBUFFER_COMPLETE => Ok(()),
+    GL_FRAMEBUFFER_UNDEFINED => Err("Framebuffer Undefined".to_string()),
+    GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT => {
+      Err("Framebuffer Incomplete Attachment".to_string())
+    }
+    GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+      Err("Framebuffer Incomplete: Missing Attachment".to_string())
+    }
+    GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => {
+      Err("Framebuffer Incomplete: Draw Buffer".to_string())
+    }
+    GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER => {
+      Err("Framebuffer Incomplete: Read Buffer".to_string())
+    }
+    GL_FRAMEBUFFER_UNSUPPORTED => Err("Framebuffer Unsupported".to_string()),
+    GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => {
+      Err("Framebuffer Incomplete: Multisample".to_string())
+    }
+    other => Err(format!("Framebuffer Incomplete: 0x{:X}", other)),
+  }
+}
+
+/// Basic wrapper for a [Renderbuffer
+/// Object](https://www.khronos.org/opengl/wiki/Renderbuffer_Object).
+///
+/// Renderbuffers are images you can draw to but can't sample from in a
+/// shader. They're the usual choice for depth buffers you won't read later.
+pub struct Renderbuffer(pub GLuint);
+impl Renderbuffer {
+  /// Makes a new renderbuffer.
+  pub fn new() -> Option<Self> {
+    let mut rbo = 0;
+    unsafe { glGenRenderbuffers(1, &mut rbo) };
+    if rbo != 0 {
+      Some(Self(rbo))
+    } else {
+      None
+    }
+  }
+
+  /// Bind this as the current renderbuffer.
+  pub fn bind(&self) {
+    unsafe { glBindRenderbuffer(GL_RENDERBUFFER, self.0) }
+  }
+
+  /// Clear the renderbuffer binding.
+  pub fn clear_binding() {
+    unsafe { glBindRenderbuffer(GL_RENDERBUFFER, 0) }
+  }
+
+  /// Deletes the renderbuffer.
+  pub fn delete(self) {
+    unsafe { glDeleteRenderbuffers(1, &self.0) }
+  }
+}
+
+/// Allocates storage for the currently bound renderbuffer.
+pub fn renderbuffer_storage(format: TextureFormat, width: u32, height: u32) {
+  unsafe {
+    glRenderbufferStorage(
+      GL_RENDERBUFFER,
+      format.internal_format(),
+      width.try_into().unwrap(),
+      height.try_into().unwrap(),
+    )
+  }
+}
+
+/// Allocates multisample storage for the currently bound renderbuffer.
+///
+/// `samples` should already be clamped with [`clamp_samples`].
+pub fn renderbuffer_storage_multisample(
+  format: TextureFormat,
+  samples: u32,
+  width: u32,
+  height: u32,
+) {
+  unsafe {
+    glRenderbufferStorageMultisample(
+      GL_RENDERBUFFER,
+      samples.try_into().unwrap(),
+      format.internal_format(),
+      width.try_into().unwrap(),
+      height.try_into().unwrap(),
+    )
+  }
+}
+
+/// The most samples per pixel that this GL supports for MSAA.
+pub fn max_samples() -> u32 {
+  let mut max = 0;
+  unsafe { glGetIntegerv(GL_MAX_SAMPLES, &mut max) };
+  max.max(0) as u32
+}
+
+/// Clamps a requested sample count to what the GL supports.
+///
+/// `0` and `1` both mean "no multisampling", and stay as they are.
+pub fn clamp_samples(requested: u32) -> u32 {
+  if requested <= 1 {
+    requested
+  } else {
+    requested.min(max_samples().max(1))
+  }
+}
+
+/// The samples per pixel of the framebuffer bound for drawing.
+///
+/// For the window's framebuffer this is how much MSAA the window really got,
+/// which can be less than what you asked for (or `0`).
+pub fn framebuffer_samples() -> u32 {
+  let mut samples = 0;
+  unsafe { glGetIntegerv(GL_SAMPLES, &mut samples) };
+  samples.max(0) as u32
+}
+
+/// Turns multisample rasterization on or off.
+///
+/// It's on by default, but drawing to a multisample framebuffer with it off
+/// gives aliased edges just like a normal framebuffer.
+pub fn set_multisample(enabled: bool) {
+  unsafe {
+    if enabled {
+      glEnable(GL_MULTISAMPLE)
+    } else {
+      glDisable(GL_MULTISAMPLE)
+    }
+  }
+}
+
+/// Which buffers a blit copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlitMask(pub GLbitfield);
+impl BlitMask {
+  /// The color buffer.
+  pub const COLOR: Self = Self(GL_COLOR_BUFFER_BIT);
+  /// The depth buffer.
+  pub const DEPTH: Self = Self(GL_DEPTH_BUFFER_BIT);
+  /// The stencil buffer.
+  pub const STENCIL: Self = Self(GL_STENCIL_BUFFER_BIT);
+}
+impl core::ops::BitOr for BlitMask {
+  type Output = Self;
+  fn bitor(self, rhs: Self) -> Self {
+    Self(self.0 | rhs.0)
+  }
+}
+
+/// Copies a rectangle from the read framebuffer to the draw framebuffer.
+///
+/// Rectangles are `[x0, y0, x1, y1]` in pixels. If the read framebuffer is
+/// multisampled this also _resolves_ it, averaging the samples of each pixel,
+/// but then both rectangles must be the same size. Depth and stencil must use
+/// [`MagFilter::Nearest`].
+pub fn blit_framebuffer(
+  src: [i32; 4],
+  dst: [i32; 4],
+  mask: BlitMask,
+  filter: MagFilter,
+) {
+  unsafe {
+    glBlitFramebuffer(
+      src[0],
+      src[1],
+      src[2],
+      src[3],
+      dst[0],
+      dst[1],
+      dst[2],
+      dst[3],
+      mask.0,
+      filter as GLenum,
+    )
+  }
+}
+
+/// The depth buffer of a [`RenderTarget`].
+pub enum DepthBuffer {
+  /// A renderbuffer, when you don't need to read the depth later.
+  Renderbuffer(Renderbuffer),
+  /// A texture, when you do (eg: shadow maps).
+  Texture(Texture),
+}
+
+/// Describes the images that a [`RenderTarget`] should have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderTargetDesc {
+  /// The formats of each color attachment, in attachment order.
+  pub color: Vec<TextureFormat>,
+  /// The depth format, if any.
+  pub depth: Option<TextureFormat>,
+  /// Make the depth buffer a texture instead of a renderbuffer.
+  pub depth_as_texture: bool,
+  /// Samples per pixel for MSAA. `0` or `1` means no multisampling.
+  ///
+  /// With more than one sample the color images (and a depth texture) are
+  /// multisample textures, which can't be sampled normally. Draw the scene
+  /// into this, then [`resolve`](RenderTarget::resolve) it into a normal
+  /// target (or the window).
+  pub samples: u32,
+}
+impl Default for RenderTargetDesc {
+  /// One `RGBA8` color target and a 24-bit depth renderbuffer.
+  fn default() -> Self {
+    Self {
+      color: vec![TextureFormat::RGBA8],
+      depth: Some(TextureFormat::Depth24),
+      depth_as_texture: false,
+      samples: 0,
+    }
+  }
+}
+
+/// A framebuffer along with the images attached to it.
+///
+/// Unlike the other wrappers this owns its attachments, so that it can
+/// reallocate them all when the size changes.
+pub struct RenderTarget {
+  /// The framebuffer.
+  pub fbo: Framebuffer,
+  /// The color textures, in attachment order.
+  pub color: Vec<Texture>,
+  /// The depth buffer, if there is one.
+  pub depth: Option<DepthBuffer>,
+  desc: RenderTargetDesc,
+  width: u32,
+  height: u32,
+}
+impl RenderTarget {
+  /// Makes a new render target of the given size.
+  ///
+  /// Color textures use linear filtering and clamp to the edge. The sample
+  /// count in `desc` is clamped to what the GL supports. This leaves the
+  /// window's framebuffer bound when it's done.
+  pub fn new(
+    width: u32,
+    height: u32,
+    mut desc: RenderTargetDesc,
+  ) -> Result<Self, String> {
+    desc.samples = clamp_samples(desc.samples);
+    let fbo = Framebuffer::new()
+      .ok_or_else(|| "Couldn't make a framebuffer".to_string())?;
+    let mut color = Vec::with_capacity(desc.color.len());
+    for _ in desc.color.iter() {
+      color.push(
+        Texture::new().ok_or_else(|| "Couldn't make a texture".to_string())?,
+      );
+    }
+    let depth = match desc.depth {
+      None => None,
+      Some(_) if desc.depth_as_texture => Some(DepthBuffer::Texture(
+        Texture::new().ok_or_else(|| "Couldn't make a texture".to_string())?,
+      )),
+      Some(_) => Some(DepthBuffer::Renderbuffer(
+        Renderbuffer::new()
+          .ok_or_else(|| "Couldn't make a renderbuffer".to_string())?,
+      )),
+    };
+    let mut out = Self { fbo, color, depth, desc, width: 0, height: 0 };
+    out.allocate(width, height)?;
+    Ok(out)
+  }
+
+  /// (Re)allocates all the attachments at the given size and checks that the
+  /// framebuffer is complete.
+  fn allocate(&mut self, width: u32, height: u32) -> Result<(), String> {
+    self.width = width.max(1);
+    self.height = height.max(1);
+    let (w, h) = (self.width, self.height);
+    let samples = self.samples();
+    self.fbo.bind(FramebufferTarget::Both);
+    for (i, (tex, format)) in
+      self.color.iter().zip(self.desc.color.iter()).enumerate()
+    {
+      let attachment = Attachment::Color(i as u32);
+      if samples > 1 {
+        tex.bind(TextureTarget::Texture2DMultisample);
+        texture::tex_image_2d_multisample(*format, samples, w, h);
+        attach_texture_2d_multisample(FramebufferTarget::Both, attachment, tex);
+      } else {
+        tex.bind(TextureTarget::Texture2D);
+        texture::tex_image_2d(*format, 0, w, h, None);
+        texture::set_filters(
+          TextureTarget::Texture2D,
+          MinFilter::Linear,
+          MagFilter::Linear,
+        );
+        texture::set_wrap(TextureTarget::Texture2D, TextureWrap::ClampToEdge);
+        attach_texture_2d(FramebufferTarget::Both, attachment, tex, 0);
+      }
+    }
+    Texture::clear_binding(TextureTarget::Texture2D);
+    Texture::clear_binding(TextureTarget::Texture2DMultisample);
+    let indexes: Vec<u32> = (0..self.color.len() as u32).collect();
+    draw_buffers(&indexes);
+    if indexes.is_empty() {
+      unsafe { glReadBuffer(GL_NONE) };
+    }
+    if let (Some(depth), Some(format)) = (&self.depth, self.desc.depth) {
+      let attachment = Attachment::for_depth_format(format);
+      match depth {
+        DepthBuffer::Renderbuffer(rbo) => {
+          rbo.bind();
+          if samples > 1 {
+            renderbuffer_storage_multisample(format, samples, w, h);
+          } else {
+            renderbuffer_storage(format, w, h);
+          }
+          Renderbuffer::clear_binding();
+          attach_renderbuffer(FramebufferTarget::Both, attachment, rbo);
+        }
+        DepthBuffer::Texture(tex) if samples > 1 => {
+          tex.bind(TextureTarget::Texture2DMultisample);
+          texture::tex_image_2d_multisample(format, samples, w, h);
+          Texture::clear_binding(TextureTarget::Texture2DMultisample);
+          attach_texture_2d_multisample(
+            FramebufferTarget::Both,
+            attachment,
+            tex,
+          );
+        }
+        DepthBuffer::Texture(tex) => {
+          tex.bind(TextureTarget::Texture2D);
+          texture::tex_image_2d(format, 0, w, h, None);
+          texture::set_filters(
+            TextureTarget::Texture2D,
+            MinFilter::Nearest,
+            MagFilter::Nearest,
+          );
+          texture::set_wrap(TextureTarget::Texture2D, TextureWrap::ClampToEdge);
+          Texture::clear_binding(TextureTarget::Texture2D);
+          attach_texture_2d(FramebufferTarget::Both, attachment, tex, 0);
+        }
+      }
+    }
+    let status = check_status(FramebufferTarget::Both);
+    Framebuffer::clear_binding(FramebufferTarget::Both);
+    status
+  }
+
+  /// Changes the size of all the attachments.
+  ///
+  /// The old contents are lost. Does nothing if the size is the same.
+  pub fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    if (width.max(1), height.max(1)) == (self.width, self.height) {
+      return Ok(());
+    }
+    self.allocate(width, height)
+  }
+
+  /// The current size.
+  pub fn size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  /// The description this target was made with.
+  ///
+  /// The sample count in here is the clamped one that's really in use.
+  pub fn desc(&self) -> &RenderTargetDesc {
+    &self.desc
+  }
+
+  /// Samples per pixel, `0` or `1` if this isn't multisampled.
+  pub fn samples(&self) -> u32 {
+    self.desc.samples
+  }
+
+  /// If this has more than one sample per pixel.
+  pub fn is_multisampled(&self) -> bool {
+    self.desc.samples > 1
+  }
+
+  /// Copies this target into `dst`, or into the window with `None`.
+  ///
+  /// If this is multisampled, this is the step that resolves it. The
+  /// destination must then be the same size as this target (for the window,
+  /// that means this target has to track the window size). Each color
+  /// attachment is copied to the destination's attachment with the same
+  /// index. The window only has one color buffer, so it gets attachment `0`.
+  ///
+  /// This leaves the window's framebuffer bound.
+  pub fn resolve(&self, dst: Option<&RenderTarget>, mask: BlitMask) {
+    let src_rect = [0, 0, self.width as i32, self.height as i32];
+    let dst_rect = match dst {
+      Some(t) => [0, 0, t.width as i32, t.height as i32],
+      None => src_rect,
+    };
+    let filter = if src_rect == dst_rect || mask != BlitMask::COLOR {
+      MagFilter::Nearest
+    } else {
+      MagFilter::Linear
+    };
+    self.fbo.bind(FramebufferTarget::Read);
+    match dst {
+      Some(t) => t.fbo.bind(FramebufferTarget::Draw),
+      None => Framebuffer::clear_binding(FramebufferTarget::Draw),
+    }
+    let color_count = match dst {
+      Some(t) => self.color.len().min(t.color.len()),
+      None => self.color.len().min(1),
+    };
+    let copies_color = mask.0 & GL_COLOR_BUFFER_BIT != 0;
+    let other_bits = BlitMask(mask.0 & !GL_COLOR_BUFFER_BIT);
+    if copies_color {
+      for i in 0..color_count as u32 {
+        unsafe {
+          glReadBuffer(GL_COLOR_ATTACHMENT0 + i);
+          match dst {
+            Some(_) => glDrawBuffer(GL_COLOR_ATTACHMENT0 + i),
+            None => glDrawBuffer(GL_BACK),
+          }
+        }
+        let bits = if i == 0 { mask } else { BlitMask::COLOR };
+        blit_framebuffer(src_rect, dst_rect, bits, filter);
+      }
+    }
+    if (!copies_color || color_count == 0) && other_bits.0 != 0 {
+      blit_framebuffer(src_rect, dst_rect, other_bits, MagFilter::Nearest);
+    }
+    // put the draw buffers back the way `allocate` set them
+    if let Some(t) = dst {
+      let indexes: Vec<u32> = (0..t.color.len() as u32).collect();
+      draw_buffers(&indexes);
+    } else {
+      unsafe { glDrawBuffer(GL_BACK) };
+    }
+    if !self.color.is_empty() {
+      unsafe { glReadBuffer(GL_COLOR_ATTACHMENT0) };
+    }
+    Framebuffer::clear_binding(FramebufferTarget::Both);
+  }
+
+  /// Binds the framebuffer for drawing and sets the viewport to cover it.
+  pub fn bind(&self) {
+    self.fbo.bind(FramebufferTarget::Both);
+    crate::viewport(0, 0, self.width, self.height);
+  }
+
+  /// Deletes the framebuffer and all of the attachments.
+  pub fn delete(self) {
+    self.fbo.delete();
+    for tex in self.color {
+      tex.delete();
+    }
+    match self.depth {
+      Some(DepthBuffer::Renderbuffer(rbo)) => rbo.delete(),
+      Some(DepthBuffer::Texture(tex)) => tex.delete(),
+      None => (),
+    }
+  }
+}
@@ -0,0 +1,745 @@
+//! Loading image files: PNG, BMP, TGA, and Radiance HDR.
+//!
+//! PNG decoding is done by `imagine`, the others are simple enough that they
+//! just get parsed here. Only the common flavors of each format are handled:
+//!
+//! * **BMP**: uncompressed 8-bit (palette), 24-bit, and 32-bit images.
+//! * **TGA**: true color and grayscale, both raw and RLE compressed.
+//! * **HDR**: the usual `32-bit_rle_rgbe` data with a `-Y h +X w` size line.
+//!
+//! An [`Image`] keeps its rows in the order the file shows them, top to
+//! bottom. GL wants the bottom row first, so [`Image::to_texture`] flips the
+//! rows as it uploads.
+
+use crate::texture::{
+  self, MagFilter, MinFilter, Texture, TextureFormat, TextureTarget,
+  TextureWrap,
+};
+use core::{convert::TryInto, fmt};
+use imagine::png::{parse_png_rgba8, PngError};
+use std::path::Path;
+
+/// The file formats that can be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+  /// Portable Network Graphics.
+  Png,
+  /// Windows bitmap.
+  Bmp,
+  /// Truevision TGA.
+  Tga,
+  /// Radiance RGBE, a high dynamic range format.
+  Hdr,
+}
+impl ImageFormat {
+  /// Guesses the format from a file's first few bytes.
+  ///
+  /// TGA files don't have any magic bytes, so they're never detected here.
+  pub fn detect(bytes: &[u8]) -> Option<Self> {
+    if bytes.starts_with(b"\x89PNG") {
+      Some(ImageFormat::Png)
+    } else if bytes.starts_with(b"BM") {
+      Some(ImageFormat::Bmp)
+    } else if bytes.starts_with(b"#?") {
+      Some(ImageFormat::Hdr)
+    } else {
+      None
+    }
+  }
+
+  /// Guesses the format from a file extension (without the dot).
+  pub fn from_extension(ext: &str) -> Option<Self> {
+    match ext.to_ascii_lowercase().as_str() {
+      "png" => Some(ImageFormat::Png),
+      "bmp" | "dib" => Some(ImageFormat::Bmp),
+      "tga" | "targa" => Some(ImageFormat::Tga),
+      "hdr" | "rgbe" | "pic" => Some(ImageFormat::Hdr),
+      _ => None,
+    }
+  }
+}
+
+/// Things that can go wrong loading an image.
+#[derive(Debug)]
+pub enum ImageError {
+  /// The file couldn't be read.
+  Io(std::io::Error),
+  /// The format couldn't be worked out from the data or file extension.
+  UnknownFormat,
+  /// The PNG decoder gave an error.
+  Png(PngError),
+  /// The data is broken, or cut off early.
+  Malformed(ImageFormat, &'static str),
+  /// The data is valid, but uses a feature that isn't supported here.
+  Unsupported(ImageFormat, &'static str),
+}
+impl fmt::Display for ImageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ImageError::Io(e) => write!(f, "{}", e),
+      ImageError::UnknownFormat => write!(f, "unknown image format"),
+      ImageError::Png(e) => write!(f, "bad PNG: {:?}", e),
+      ImageError::Malformed(format, why) => {
+        write!(f, "bad {:?} image: {}", format, why)
+      }
+      ImageError::Unsupported(format, what) => {
+        write!(f, "unsupported {:?} image: {}", format, what)
+      }
+    }
+  }
+}
+impl std::error::Error for ImageError {}
+impl From<std::io::Error> for ImageError {
+  fn from(e: std::io::Error) -> Self {
+    ImageError::Io(e)
+  }
+}
+impl From<PngError> for ImageError {
+  fn from(e: PngError) -> Self {
+    ImageError::Png(e)
+  }
+}
+impl From<ImageError> for String {
+  fn from(e: ImageError) -> Self {
+    e.to_string()
+  }
+}
+
+/// The pixels of an [`Image`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageData {
+  /// Four bytes per pixel: red, green, blue, alpha.
+  Rgba8(Vec<u8>),
+  /// Three linear floats per pixel: red, green, blue.
+  RgbF32(Vec<f32>),
+}
+
+/// A decoded image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+  /// Width in pixels.
+  pub width: u32,
+  /// Height in pixels.
+  pub height: u32,
+  /// The pixels, rows from top to bottom.
+  pub data: ImageData,
+  /// If 8-bit color should be treated as sRGB when uploaded. This is
+  /// ignored for float images, which are always linear.
+  pub srgb: bool,
+}
+impl Image {
+  /// Loads an image file.
+  ///
+  /// The format is detected from the data, or from the extension if that
+  /// doesn't work (which is always the case for TGA).
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    let format = ImageFormat::detect(&bytes)
+      .or_else(|| {
+        path
+          .extension()
+          .and_then(|ext| ext.to_str())
+          .and_then(ImageFormat::from_extension)
+      })
+      .ok_or(ImageError::UnknownFormat)?;
+    Self::decode(&bytes, format)
+  }
+
+  /// Decodes an image already in memory.
+  pub fn decode(bytes: &[u8], format: ImageFormat) -> Result<Self, ImageError> {
+    match format {
+      ImageFormat::Png => decode_png(bytes),
+      ImageFormat::Bmp => decode_bmp(bytes),
+      ImageFormat::Tga => decode_tga(bytes),
+      ImageFormat::Hdr => decode_hdr(bytes),
+    }
+  }
+
+  /// Sets the sRGB flag.
+  pub fn with_srgb(self, srgb: bool) -> Self {
+    Self { srgb, ..self }
+  }
+
+  /// If this has float data.
+  pub fn is_hdr(&self) -> bool {
+    matches!(self.data, ImageData::RgbF32(_))
+  }
+
+  /// The texture format that best fits this image.
+  ///
+  /// 8-bit images use `RGBA8` or `SRGB8Alpha8`, depending on the sRGB flag.
+  /// Float images use `RGB16F`, which is plenty of precision for color at
+  /// half the memory. Use [`to_texture_as`](Self::to_texture_as) to pick
+  /// `RGB32F` instead.
+  pub fn texture_format(&self) -> TextureFormat {
+    match self.data {
+      ImageData::Rgba8(_) if self.srgb => TextureFormat::SRGB8Alpha8,
+      ImageData::Rgba8(_) => TextureFormat::RGBA8,
+      ImageData::RgbF32(_) => TextureFormat::RGB16F,
+    }
+  }
+
+  /// Flips the rows, top to bottom.
+  pub fn flip_vertical(&mut self) {
+    let height = self.height as usize;
+    match &mut self.data {
+      ImageData::Rgba8(v) => flip_rows(v, height),
+      ImageData::RgbF32(v) => flip_rows(v, height),
+    }
+  }
+
+  /// The pixels as linear RGBA floats, rows from top to bottom.
+  ///
+  /// 8-bit data is converted to `0.0 ..= 1.0`, and decoded from sRGB if the
+  /// flag is set. Float data gets an alpha of `1.0`.
+  pub fn to_rgba_f32(&self) -> Vec<f32> {
+    match &self.data {
+      ImageData::Rgba8(v) => v
+        .chunks_exact(4)
+        .flat_map(|p| {
+          let c = |b: u8| {
+            let f = b as f32 / 255.0;
+            if self.srgb {
+              srgb_to_linear(f)
+            } else {
+              f
+            }
+          };
+          [c(p[0]), c(p[1]), c(p[2]), p[3] as f32 / 255.0]
+        })
+        .collect(),
+      ImageData::RgbF32(v) => {
+        v.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 1.0]).collect()
+      }
+    }
+  }
+
+  /// Makes a texture from the image using [`texture_format`].
+  ///
+  /// The rows are flipped so that the bottom left of the image is UV
+  /// `(0, 0)`. The texture gets mipmaps, trilinear filtering, and repeat
+  /// wrapping. The `Texture2D` binding is cleared afterwards.
+  ///
+  /// [`texture_format`]: Self::texture_format
+  pub fn to_texture(&self) -> Result<Texture, String> {
+    self.to_texture_as(self.texture_format())
+  }
+
+  /// Like [`to_texture`](Self::to_texture), with a chosen format.
+  ///
+  /// 8-bit images can use `RGBA8` or `SRGB8Alpha8`, float images can use
+  /// `RGB16F` or `RGB32F`.
+  pub fn to_texture_as(
+    &self,
+    format: TextureFormat,
+  ) -> Result<Texture, String> {
+    let mut flipped = self.clone();
+    flipped.flip_vertical();
+    let bytes: &[u8] = match (&flipped.data, format) {
+      (
+        ImageData::Rgba8(v),
+        TextureFormat::RGBA8 | TextureFormat::SRGB8Alpha8,
+      ) => v,
+      (ImageData::RgbF32(v), TextureFormat::RGB16F | TextureFormat::RGB32F) => {
+        bytemuck::cast_slice(v)
+      }
+      _ => {
+        return Err(format!("Can't upload this image as {:?}", format));
+      }
+    };
+    let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+    tex.bind(TextureTarget::Texture2D);
+    texture::tex_image_2d(format, 0, self.width, self.height, Some(bytes));
+    texture::set_wrap(TextureTarget::Texture2D, TextureWrap::Repeat);
+    texture::set_filters(
+      TextureTarget::Texture2D,
+      MinFilter::LinearMipmapLinear,
+      MagFilter::Linear,
+    );
+    texture::generate_mipmap(TextureTarget::Texture2D);
+    Texture::clear_binding(TextureTarget::Texture2D);
+    Ok(tex)
+  }
+}
+
+fn flip_rows<T>(pixels: &mut [T], height: usize) {
+  if height == 0 {
+    return;
+  }
+  let row_len = pixels.len() / height;
+  for y in 0..height / 2 {
+    let (top, bottom) = pixels.split_at_mut((height - 1 - y) * row_len);
+    top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+  }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+  if c <= 0.04045 {
+    c / 12.92
+  } else {
+    ((c + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+fn size_u32(
+  format: ImageFormat,
+  width: usize,
+  height: usize,
+) -> Result<(u32, u32), ImageError> {
+  let too_big = |_| ImageError::Unsupported(format, "image is too big");
+  Ok((width.try_into().map_err(too_big)?, height.try_into().map_err(too_big)?))
+}
+
+/// Multiplies out a buffer length from header values, which can be anything
+/// in a bad file.
+fn buffer_len(
+  format: ImageFormat,
+  factors: &[usize],
+) -> Result<usize, ImageError> {
+  factors
+    .iter()
+    .try_fold(1_usize, |len, &f| len.checked_mul(f))
+    .ok_or(ImageError::Malformed(format, "image is too big"))
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Image, ImageError> {
+  let bitmap = parse_png_rgba8(bytes)?.bitmap;
+  let (width, height) =
+    size_u32(ImageFormat::Png, bitmap.width(), bitmap.height())?;
+  Ok(Image {
+    width,
+    height,
+    data: ImageData::Rgba8(bytemuck::cast_slice(bitmap.pixels()).to_vec()),
+    srgb: false,
+  })
+}
+
+/// Little endian reads that report running off the end as a bad image.
+struct Reader<'a> {
+  bytes: &'a [u8],
+  format: ImageFormat,
+}
+impl<'a> Reader<'a> {
+  fn short(&self) -> ImageError {
+    ImageError::Malformed(self.format, "unexpected end of data")
+  }
+  fn slice(&self, start: usize, len: usize) -> Result<&'a [u8], ImageError> {
+    let end = start.checked_add(len).ok_or_else(|| self.short())?;
+    self.bytes.get(start..end).ok_or_else(|| self.short())
+  }
+  fn u8(&self, at: usize) -> Result<u8, ImageError> {
+    Ok(self.slice(at, 1)?[0])
+  }
+  fn u16(&self, at: usize) -> Result<u16, ImageError> {
+    let s = self.slice(at, 2)?;
+    Ok(u16::from_le_bytes([s[0], s[1]]))
+  }
+  fn u32(&self, at: usize) -> Result<u32, ImageError> {
+    let s = self.slice(at, 4)?;
+    Ok(u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+  }
+}
+
+fn decode_bmp(bytes: &[u8]) -> Result<Image, ImageError> {
+  const F: ImageFormat = ImageFormat::Bmp;
+  let r = Reader { bytes, format: F };
+  if r.slice(0, 2)? != b"BM" {
+    return Err(ImageError::Malformed(F, "missing `BM` signature"));
+  }
+  let data_offset = r.u32(10)? as usize;
+  let header_size = r.u32(14)? as usize;
+  if header_size < 40 {
+    return Err(ImageError::Unsupported(F, "old style BMP header"));
+  }
+  let width = r.u32(18)? as i32;
+  let raw_height = r.u32(22)? as i32;
+  let bits = r.u16(28)?;
+  let compression = r.u32(30)?;
+  if width <= 0 || raw_height == 0 {
+    return Err(ImageError::Malformed(F, "image has no pixels"));
+  }
+  let width = width as usize;
+  let height = raw_height.unsigned_abs() as usize;
+  let bottom_up = raw_height > 0;
+
+  // BI_RGB is plain data, BI_BITFIELDS is plain data with channel masks.
+  const BI_RGB: u32 = 0;
+  const BI_BITFIELDS: u32 = 3;
+  let masks = match (compression, bits) {
+    (BI_RGB, 32) => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000],
+    (BI_BITFIELDS, 32) => {
+      // the masks follow a 40 byte header, or are part of a bigger header.
+      let alpha = if header_size >= 56 { r.u32(66)? } else { 0 };
+      [r.u32(54)?, r.u32(58)?, r.u32(62)?, alpha]
+    }
+    (BI_RGB, 8) | (BI_RGB, 24) => [0; 4],
+    (BI_RGB, _) => {
+      return Err(ImageError::Unsupported(F, "only 8, 24, and 32 bit color"))
+    }
+    _ => return Err(ImageError::Unsupported(F, "compressed data")),
+  };
+  let palette = if bits == 8 {
+    let colors = match r.u32(46)? {
+      0 => 256,
+      n => n as usize,
+    };
+    r.slice(14 + header_size, colors.min(256) * 4)?
+  } else {
+    &[]
+  };
+
+  let row_bytes = (buffer_len(F, &[width, bits as usize])? / 8 + 3) & !3;
+  // check that all the rows are there before making room for them
+  r.slice(data_offset, buffer_len(F, &[row_bytes, height])?)?;
+  let mut out = Vec::with_capacity(buffer_len(F, &[width, height, 4])?);
+  for y in 0..height {
+    let file_row = if bottom_up { height - 1 - y } else { y };
+    let row = r.slice(data_offset + file_row * row_bytes, row_bytes)?;
+    for x in 0..width {
+      let rgba = match bits {
+        8 => {
+          let i = row[x] as usize * 4;
+          let p = palette
+            .get(i..i + 3)
+            .ok_or(ImageError::Malformed(F, "palette index out of range"))?;
+          [p[2], p[1], p[0], 255]
+        }
+        24 => {
+          let p = &row[x * 3..x * 3 + 3];
+          [p[2], p[1], p[0], 255]
+        }
+        _ => {
+          let p = &row[x * 4..x * 4 + 4];
+          let v = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+          let [red, green, blue, alpha] = masks;
+          let alpha = if alpha == 0 { 255 } else { masked(v, alpha) };
+          [masked(v, red), masked(v, green), masked(v, blue), alpha]
+        }
+      };
+      out.extend_from_slice(&rgba);
+    }
+  }
+  let (width, height) = size_u32(F, width, height)?;
+  Ok(Image { width, height, data: ImageData::Rgba8(out), srgb: false })
+}
+
+/// Pulls a channel out of a BMP bitfield pixel, scaled to 8 bits.
+fn masked(v: u32, mask: u32) -> u8 {
+  if mask == 0 {
+    return 0;
+  }
+  let shifted = u64::from((v & mask) >> mask.trailing_zeros());
+  let max = u64::from(mask >> mask.trailing_zeros());
+  ((shifted * 255 + max / 2) / max) as u8
+}
+
+fn decode_tga(bytes: &[u8]) -> Result<Image, ImageError> {
+  const F: ImageFormat = ImageFormat::Tga;
+  let r = Reader { bytes, format: F };
+  let id_len = r.u8(0)? as usize;
+  let color_map_type = r.u8(1)?;
+  let image_type = r.u8(2)?;
+  let color_map_len = r.u16(5)? as usize;
+  let color_map_bits = r.u8(7)? as usize;
+  let width = r.u16(12)? as usize;
+  let height = r.u16(14)? as usize;
+  let bits = r.u8(16)?;
+  let descriptor = r.u8(17)?;
+  if width == 0 || height == 0 {
+    return Err(ImageError::Malformed(F, "image has no pixels"));
+  }
+
+  // 2 and 3 are raw true color and grayscale, 10 and 11 are the RLE versions.
+  let (gray, rle) = match image_type {
+    2 => (false, false),
+    3 => (true, false),
+    10 => (false, true),
+    11 => (true, true),
+    1 | 9 => return Err(ImageError::Unsupported(F, "color mapped images")),
+    _ => return Err(ImageError::Malformed(F, "unknown image type")),
+  };
+  let pixel_size = match (gray, bits) {
+    (true, 8) => 1,
+    (false, 24) => 3,
+    (false, 32) => 4,
+    _ => {
+      return Err(ImageError::Unsupported(F, "only 8, 24, and 32 bit color"))
+    }
+  };
+  if descriptor & 0x10 != 0 {
+    return Err(ImageError::Unsupported(F, "right to left pixel order"));
+  }
+  let top_down = descriptor & 0x20 != 0;
+
+  let mut at = 18 + id_len;
+  if color_map_type != 0 {
+    at += color_map_len * color_map_bits.div_ceil(8);
+  }
+  let count = buffer_len(F, &[width, height])?;
+  let total = buffer_len(F, &[count, pixel_size])?;
+  let pixels = if rle {
+    // each packet is at least `1 + pixel_size` bytes, and makes at most 128
+    // pixels
+    let packets = bytes.len().saturating_sub(at) / (1 + pixel_size);
+    if packets.saturating_mul(128) < count {
+      return Err(r.short());
+    }
+    let mut pixels = Vec::with_capacity(total);
+    while pixels.len() < total {
+      let header = r.u8(at)?;
+      at += 1;
+      let run = (header & 0x7F) as usize + 1;
+      if header & 0x80 != 0 {
+        let p = r.slice(at, pixel_size)?;
+        at += pixel_size;
+        for _ in 0..run {
+          pixels.extend_from_slice(p);
+        }
+      } else {
+        pixels.extend_from_slice(r.slice(at, run * pixel_size)?);
+        at += run * pixel_size;
+      }
+    }
+    pixels.truncate(total);
+    pixels
+  } else {
+    r.slice(at, total)?.to_vec()
+  };
+
+  let mut out = Vec::with_capacity(buffer_len(F, &[count, 4])?);
+  for y in 0..height {
+    let file_row = if top_down { y } else { height - 1 - y };
+    let row = &pixels[file_row * width * pixel_size..][..width * pixel_size];
+    for p in row.chunks_exact(pixel_size) {
+      let rgba = match pixel_size {
+        1 => [p[0], p[0], p[0], 255],
+        3 => [p[2], p[1], p[0], 255],
+        _ => [p[2], p[1], p[0], p[3]],
+      };
+      out.extend_from_slice(&rgba);
+    }
+  }
+  let (width, height) = size_u32(F, width, height)?;
+  Ok(Image { width, height, data: ImageData::Rgba8(out), srgb: false })
+}
+
+fn decode_hdr(bytes: &[u8]) -> Result<Image, ImageError> {
+  const F: ImageFormat = ImageFormat::Hdr;
+  let short = || ImageError::Malformed(F, "unexpected end of data");
+
+  // text header lines, ending with a blank line and then the size line.
+  let mut at = 0;
+  let mut next_line = || -> Result<&[u8], ImageError> {
+    let rest = bytes.get(at..).ok_or_else(short)?;
+    let len = rest.iter().position(|&b| b == b'\n').ok_or_else(short)?;
+    at += len + 1;
+    Ok(&rest[..len])
+  };
+  if !next_line()?.starts_with(b"#?") {
+    return Err(ImageError::Malformed(F, "missing `#?` signature"));
+  }
+  loop {
+    let line = next_line()?;
+    if line.is_empty() {
+      break;
+    }
+    if let Some(format) = line.strip_prefix(b"FORMAT=") {
+      if format != b"32-bit_rle_rgbe" {
+        return Err(ImageError::Unsupported(F, "only RGBE pixel data"));
+      }
+    }
+  }
+  let size_line = core::str::from_utf8(next_line()?)
+    .map_err(|_| ImageError::Malformed(F, "bad size line"))?;
+  let (height, width) = match size_line.split_whitespace().collect::<Vec<_>>()[..]
+  {
+    ["-Y", h, "+X", w] => (
+      h.parse::<usize>().map_err(|_| ImageError::Malformed(F, "bad height"))?,
+      w.parse::<usize>().map_err(|_| ImageError::Malformed(F, "bad width"))?,
+    ),
+    [_, _, _, _] => {
+      return Err(ImageError::Unsupported(F, "only `-Y h +X w` orientation"))
+    }
+    _ => return Err(ImageError::Malformed(F, "bad size line")),
+  };
+  if width == 0 || height == 0 {
+    return Err(ImageError::Malformed(F, "image has no pixels"));
+  }
+
+  let mut data = bytes.get(at..).ok_or_else(short)?;
+  // a flat scanline is 4 bytes a pixel, and an RLE one is 4 bytes of header
+  // then at least 2 bytes per channel for each run of up to 127 pixels
+  let line_bytes = buffer_len(F, &[width, 4])?;
+  let min_line = line_bytes.min(4 + 8 * width.div_ceil(127));
+  if buffer_len(F, &[height, min_line])? > data.len() {
+    return Err(short());
+  }
+  let mut out = Vec::with_capacity(buffer_len(F, &[width, height, 3])?);
+  let mut scanline = vec![0_u8; line_bytes];
+  for _ in 0..height {
+    data = read_hdr_scanline(data, &mut scanline)?;
+    for rgbe in scanline.chunks_exact(4) {
+      out.extend_from_slice(&rgbe_to_rgb(rgbe));
+    }
+  }
+  let (width, height) = size_u32(F, width, height)?;
+  Ok(Image { width, height, data: ImageData::RgbF32(out), srgb: false })
+}
+
+/// Reads one scanline of RGBE pixels into `out`, returning the rest of the
+/// data.
+///
+/// New style RLE lines start with `2, 2` and then the width. Each channel is
+/// stored separately, as runs (count > 128) or literal bytes. Anything else is
+/// read as flat RGBE values.
+fn read_hdr_scanline<'a>(
+  data: &'a [u8],
+  out: &mut [u8],
+) -> Result<&'a [u8], ImageError> {
+  const F: ImageFormat = ImageFormat::Hdr;
+  let short = || ImageError::Malformed(F, "unexpected end of data");
+  let width = out.len() / 4;
+  let rle = (8..0x8000).contains(&width)
+    && data.len() >= 4
+    && data[0] == 2
+    && data[1] == 2
+    && data[2] & 0x80 == 0;
+  if !rle {
+    let flat = data.get(..out.len()).ok_or_else(short)?;
+    out.copy_from_slice(flat);
+    return Ok(&data[out.len()..]);
+  }
+  if ((data[2] as usize) << 8 | data[3] as usize) != width {
+    return Err(ImageError::Malformed(F, "scanline width mismatch"));
+  }
+  let mut at = 4;
+  for channel in 0..4 {
+    let mut x = 0;
+    while x < width {
+      let count = *data.get(at).ok_or_else(short)? as usize;
+      at += 1;
+      if count > 128 {
+        let run = count - 128;
+        let value = *data.get(at).ok_or_else(short)?;
+        at += 1;
+        if x + run > width {
+          return Err(ImageError::Malformed(F, "run past end of scanline"));
+        }
+        for i in x..x + run {
+          out[i * 4 + channel] = value;
+        }
+        x += run;
+      } else {
+        if count == 0 || x + count > width {
+          return Err(ImageError::Malformed(F, "bad literal run"));
+        }
+        let values = data.get(at..at + count).ok_or_else(short)?;
+        at += count;
+        for (i, value) in values.iter().enumerate() {
+          out[(x + i) * 4 + channel] = *value;
+        }
+        x += count;
+      }
+    }
+  }
+  Ok(&data[at..])
+}
+
+fn rgbe_to_rgb(rgbe: &[u8]) -> [f32; 3] {
+  if rgbe[3] == 0 {
+    return [0.0; 3];
+  }
+  let scale = 2.0_f32.powi(rgbe[3] as i32 - (128 + 8));
+  [rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tga_header(kind: u8, width: u16, height: u16, bits: u8) -> Vec<u8> {
+    let mut out = vec![0, 0, kind, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&[bits, 0x20]);
+    out
+  }
+
+  fn bmp_header(width: i32, height: i32, bits: u16) -> Vec<u8> {
+    let mut out = b"BM".to_vec();
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&54_u32.to_le_bytes());
+    out.extend_from_slice(&40_u32.to_le_bytes());
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&1_u16.to_le_bytes());
+    out.extend_from_slice(&bits.to_le_bytes());
+    out.extend_from_slice(&[0; 24]);
+    out
+  }
+
+  fn is_malformed(result: Result<Image, ImageError>) -> bool {
+    matches!(result, Err(ImageError::Malformed(..)))
+  }
+
+  #[test]
+  fn small_tga() {
+    let mut bytes = tga_header(2, 2, 1, 24);
+    bytes.extend_from_slice(&[0, 0, 255, 255, 0, 0]);
+    let image = Image::decode(&bytes, ImageFormat::Tga).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(
+      image.data,
+      ImageData::Rgba8(vec![255, 0, 0, 255, 0, 0, 255, 255])
+    );
+  }
+
+  #[test]
+  fn small_bmp() {
+    // one row of two pixels, padded out to 8 bytes
+    let mut bytes = bmp_header(2, 1, 24);
+    bytes.extend_from_slice(&[0, 255, 0, 10, 20, 30, 0, 0]);
+    let image = Image::decode(&bytes, ImageFormat::Bmp).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(
+      image.data,
+      ImageData::Rgba8(vec![0, 255, 0, 255, 30, 20, 10, 255])
+    );
+  }
+
+  #[test]
+  fn huge_headers_are_errors() {
+    let tga = tga_header(2, u16::MAX, u16::MAX, 32);
+    assert!(is_malformed(Image::decode(&tga, ImageFormat::Tga)));
+    let tga_rle = tga_header(10, u16::MAX, u16::MAX, 32);
+    assert!(is_malformed(Image::decode(&tga_rle, ImageFormat::Tga)));
+    let bmp = bmp_header(i32::MAX, i32::MIN + 1, 32);
+    assert!(is_malformed(Image::decode(&bmp, ImageFormat::Bmp)));
+    let hdr = b"#?RADIANCE\n\n-Y 99999999999 +X 99999999999\n";
+    assert!(is_malformed(Image::decode(hdr, ImageFormat::Hdr)));
+    let hdr = b"#?RADIANCE\n\n-Y 1000000 +X 1000000\n\x02\x02";
+    assert!(is_malformed(Image::decode(hdr, ImageFormat::Hdr)));
+  }
+
+  #[test]
+  fn small_hdr() {
+    let mut bytes =
+      b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+    bytes.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+    let image = Image::decode(&bytes, ImageFormat::Hdr).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(
+      image.data,
+      ImageData::RgbF32(vec![1.0, 0.5, 0.0, 0.0, 0.0, 0.0])
+    );
+  }
+
+  #[test]
+  fn wide_bitfield_masks() {
+    assert_eq!(masked(u32::MAX, u32::MAX), 255);
+    assert_eq!(masked(0x8000_0000, 0xFFFF_FFFF), 128);
+    assert_eq!(masked(0x00FF_0000, 0x00FF_0000), 255);
+    assert_eq!(masked(0x0010_0000, 0x00F0_0000), 17);
+  }
+}
@@ -0,0 +1,737 @@
+//! Tracks input state and maps it to named actions.
+//!
+//! Like the [`camera`](crate::camera) module, this doesn't depend on SDL. You
+//! turn each window event into an [`InputEvent`] and feed it to an
+//! [`InputState`], then ask an [`ActionMap`] if an action is happening. Key
+//! values match SDL's keycodes, so converting an SDL event is just a matter
+//! of copying the fields over.
+//!
+//! Since an [`InputState`] only ever sees [`InputEvent`] values, you can also
+//! feed it a made up list of events and check what comes out the other side.
+
+use crate::camera::CameraInput;
+use core::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+use ultraviolet::Vec3;
+
+/// A keyboard key.
+///
+/// The inner value is an SDL keycode, so with `beryllium` you can just use
+/// `Key(keycode.0)`. Letter and number keys are their lowercase ASCII value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key(pub i32);
+impl Key {
+  /// SDL sets this bit for keys that don't have a character.
+  const SCANCODE_MASK: i32 = 1 << 30;
+
+  /// Backspace key.
+  pub const BACKSPACE: Self = Self(8);
+  /// Tab key.
+  pub const TAB: Self = Self(9);
+  /// Enter / Return key.
+  pub const RETURN: Self = Self(13);
+  /// Escape key.
+  pub const ESCAPE: Self = Self(27);
+  /// Space bar.
+  pub const SPACE: Self = Self(32);
+  /// Delete key.
+  pub const DELETE: Self = Self(127);
+  /// Right arrow.
+  pub const RIGHT: Self = Self(79 | Self::SCANCODE_MASK);
+  /// Left arrow.
+  pub const LEFT: Self = Self(80 | Self::SCANCODE_MASK);
+  /// Down arrow.
+  pub const DOWN: Self = Self(81 | Self::SCANCODE_MASK);
+  /// Up arrow.
+  pub const UP: Self = Self(82 | Self::SCANCODE_MASK);
+  /// Left control.
+  pub const LCTRL: Self = Self(224 | Self::SCANCODE_MASK);
+  /// Left shift.
+  pub const LSHIFT: Self = Self(225 | Self::SCANCODE_MASK);
+  /// Left alt.
+  pub const LALT: Self = Self(226 | Self::SCANCODE_MASK);
+  /// Right control.
+  pub const RCTRL: Self = Self(228 | Self::SCANCODE_MASK);
+  /// Right shift.
+  pub const RSHIFT: Self = Self(229 | Self::SCANCODE_MASK);
+  /// Right alt.
+  pub const RALT: Self = Self(230 | Self::SCANCODE_MASK);
+
+  /// The named keys, for config files.
+  const NAMED: &'static [(&'static str, Key)] = &[
+    ("backspace", Key::BACKSPACE),
+    ("tab", Key::TAB),
+    ("return", Key::RETURN),
+    ("escape", Key::ESCAPE),
+    ("space", Key::SPACE),
+    ("delete", Key::DELETE),
+    ("right", Key::RIGHT),
+    ("left", Key::LEFT),
+    ("down", Key::DOWN),
+    ("up", Key::UP),
+    ("lctrl", Key::LCTRL),
+    ("lshift", Key::LSHIFT),
+    ("lalt", Key::LALT),
+    ("rctrl", Key::RCTRL),
+    ("rshift", Key::RSHIFT),
+    ("ralt", Key::RALT),
+  ];
+
+  /// The key for a printable ASCII character, such as `'w'` or `'1'`.
+  ///
+  /// Letters are always the lowercase version, because that's what SDL
+  /// reports no matter if shift is held.
+  pub const fn from_char(ch: char) -> Self {
+    Self(ch.to_ascii_lowercase() as i32)
+  }
+
+  /// The function key `F1` through `F12`.
+  pub fn function(n: u8) -> Option<Self> {
+    if (1..=12).contains(&n) {
+      Some(Self((57 + n as i32) | Self::SCANCODE_MASK))
+    } else {
+      None
+    }
+  }
+
+  /// Parses a key name as used in config files.
+  ///
+  /// This is either a single printable character (`w`, `1`, `/`), a function
+  /// key (`f1` to `f12`), or one of the named keys such as `space`, `lshift`,
+  /// or `up`. Case doesn't matter.
+  pub fn from_name(name: &str) -> Option<Self> {
+    let lower = name.to_ascii_lowercase();
+    if let Some(&(_, key)) = Self::NAMED.iter().find(|(n, _)| *n == lower) {
+      return Some(key);
+    }
+    let mut chars = lower.chars();
+    match (chars.next(), chars.next()) {
+      (Some(ch), None) if ch.is_ascii_graphic() => Some(Self::from_char(ch)),
+      _ => lower.strip_prefix('f')?.parse().ok().and_then(Self::function),
+    }
+  }
+
+  /// The name of this key, as used in config files.
+  ///
+  /// Keys without a name are written as `#` followed by the keycode number,
+  /// which [`from_name`](Self::from_name) doesn't accept, but
+  /// [`Binding::parse`] does. So are `#` and `,`, since in a config file
+  /// those already mean "keycode" and "next binding", and uppercase letters,
+  /// which would read back in as lowercase.
+  pub fn name(self) -> String {
+    if let Some(&(n, _)) = Self::NAMED.iter().find(|(_, k)| *k == self) {
+      return n.to_string();
+    }
+    let f1 = 58 | Self::SCANCODE_MASK;
+    if (f1..f1 + 12).contains(&self.0) {
+      return format!("f{}", self.0 - f1 + 1);
+    }
+    match u8::try_from(self.0) {
+      Ok(b)
+        if b.is_ascii_graphic()
+          && !b.is_ascii_uppercase()
+          && b != b'#'
+          && b != b',' =>
+      {
+        char::from(b).to_string()
+      }
+      _ => format!("#{}", self.0),
+    }
+  }
+}
+
+/// A mouse button.
+///
+/// The numbers are the same as SDL uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MouseButton(pub u8);
+impl MouseButton {
+  /// The left button.
+  pub const LEFT: Self = Self(1);
+  /// The middle button, usually the wheel.
+  pub const MIDDLE: Self = Self(2);
+  /// The right button.
+  pub const RIGHT: Self = Self(3);
+  /// The first extra button, usually "back".
+  pub const X1: Self = Self(4);
+  /// The second extra button, usually "forward".
+  pub const X2: Self = Self(5);
+
+  const NAMED: &'static [(&'static str, MouseButton)] = &[
+    ("left", MouseButton::LEFT),
+    ("middle", MouseButton::MIDDLE),
+    ("right", MouseButton::RIGHT),
+    ("x1", MouseButton::X1),
+    ("x2", MouseButton::X2),
+  ];
+
+  /// Parses a button name (`left`, `middle`, `right`, `x1`, `x2`) or number.
+  pub fn from_name(name: &str) -> Option<Self> {
+    let lower = name.to_ascii_lowercase();
+    match Self::NAMED.iter().find(|(n, _)| *n == lower) {
+      Some(&(_, button)) => Some(button),
+      None => lower.parse().ok().map(Self),
+    }
+  }
+
+  /// The name of this button, as used in config files.
+  pub fn name(self) -> String {
+    match Self::NAMED.iter().find(|(_, b)| *b == self) {
+      Some(&(n, _)) => n.to_string(),
+      None => self.0.to_string(),
+    }
+  }
+}
+
+/// The input events that we track.
+///
+/// These are a small subset of what a windowing library will give you. Other
+/// events (such as `Quit`) should be handled by the event loop directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+  /// A key went down or came up.
+  Key {
+    /// Which key.
+    key: Key,
+    /// If it's now down.
+    pressed: bool,
+  },
+  /// A mouse button went down or came up.
+  MouseButton {
+    /// Which button.
+    button: MouseButton,
+    /// If it's now down.
+    pressed: bool,
+  },
+  /// The mouse moved.
+  MouseMotion {
+    /// New window position, X.
+    x: i32,
+    /// New window position, Y (down is positive).
+    y: i32,
+    /// Change in X since the last motion.
+    dx: i32,
+    /// Change in Y since the last motion (down is positive).
+    dy: i32,
+  },
+  /// The mouse wheel turned.
+  MouseWheel {
+    /// Horizontal clicks, positive to the right.
+    x: i32,
+    /// Vertical clicks, positive is away from the user.
+    y: i32,
+  },
+}
+
+/// The current state of the keyboard and mouse.
+///
+/// Call [`begin_frame`](Self::begin_frame) at the start of each frame, then
+/// [`handle`](Self::handle) each event as it comes in. After that the
+/// "pressed" and "released" queries tell you what changed during this frame,
+/// and the "held" queries tell you what's down right now.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+  keys_held: HashSet<Key>,
+  keys_pressed: HashSet<Key>,
+  keys_released: HashSet<Key>,
+  buttons_held: HashSet<MouseButton>,
+  buttons_pressed: HashSet<MouseButton>,
+  buttons_released: HashSet<MouseButton>,
+  mouse_position: (i32, i32),
+  mouse_delta: (i32, i32),
+  wheel_delta: (i32, i32),
+}
+impl InputState {
+  /// Makes a new state with nothing held.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Clears out the per-frame info (edges, motion, wheel).
+  ///
+  /// Anything held stays held.
+  pub fn begin_frame(&mut self) {
+    self.keys_pressed.clear();
+    self.keys_released.clear();
+    self.buttons_pressed.clear();
+    self.buttons_released.clear();
+    self.mouse_delta = (0, 0);
+    self.wheel_delta = (0, 0);
+  }
+
+  /// Updates the state with an event.
+  ///
+  /// Key repeats are ignored, a key is only "pressed" on the frame that it
+  /// actually goes down.
+  pub fn handle(&mut self, event: &InputEvent) {
+    match *event {
+      InputEvent::Key { key, pressed } => update_held(
+        key,
+        pressed,
+        &mut self.keys_held,
+        &mut self.keys_pressed,
+        &mut self.keys_released,
+      ),
+      InputEvent::MouseButton { button, pressed } => update_held(
+        button,
+        pressed,
+        &mut self.buttons_held,
+        &mut self.buttons_pressed,
+        &mut self.buttons_released,
+      ),
+      InputEvent::MouseMotion { x, y, dx, dy } => {
+        self.mouse_position = (x, y);
+        self.mouse_delta.0 += dx;
+        self.mouse_delta.1 += dy;
+      }
+      InputEvent::MouseWheel { x, y } => {
+        self.wheel_delta.0 += x;
+        self.wheel_delta.1 += y;
+      }
+    }
+  }
+
+  /// Is the key down right now?
+  pub fn key_held(&self, key: Key) -> bool {
+    self.keys_held.contains(&key)
+  }
+
+  /// Did the key go down this frame?
+  pub fn key_pressed(&self, key: Key) -> bool {
+    self.keys_pressed.contains(&key)
+  }
+
+  /// Did the key come up this frame?
+  pub fn key_released(&self, key: Key) -> bool {
+    self.keys_released.contains(&key)
+  }
+
+  /// Is the mouse button down right now?
+  pub fn button_held(&self, button: MouseButton) -> bool {
+    self.buttons_held.contains(&button)
+  }
+
+  /// Did the mouse button go down this frame?
+  pub fn button_pressed(&self, button: MouseButton) -> bool {
+    self.buttons_pressed.contains(&button)
+  }
+
+  /// Did the mouse button come up this frame?
+  pub fn button_released(&self, button: MouseButton) -> bool {
+    self.buttons_released.contains(&button)
+  }
+
+  /// The last known mouse position within the window.
+  pub fn mouse_position(&self) -> (i32, i32) {
+    self.mouse_position
+  }
+
+  /// The total mouse motion this frame.
+  pub fn mouse_delta(&self) -> (i32, i32) {
+    self.mouse_delta
+  }
+
+  /// The total wheel motion this frame.
+  pub fn wheel_delta(&self) -> (i32, i32) {
+    self.wheel_delta
+  }
+}
+
+fn update_held<T: Copy + Eq + core::hash::Hash>(
+  t: T,
+  pressed: bool,
+  held: &mut HashSet<T>,
+  pressed_set: &mut HashSet<T>,
+  released_set: &mut HashSet<T>,
+) {
+  if pressed {
+    if held.insert(t) {
+      pressed_set.insert(t);
+    }
+  } else if held.remove(&t) {
+    released_set.insert(t);
+  }
+}
+
+/// Something that can trigger an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Binding {
+  /// A keyboard key.
+  Key(Key),
+  /// A mouse button.
+  MouseButton(MouseButton),
+}
+impl Binding {
+  /// Parses a binding as written in a config file.
+  ///
+  /// * `key:NAME` is a key, see [`Key::from_name`]. `key:#N` uses keycode `N`.
+  /// * `mouse:NAME` is a mouse button, see [`MouseButton::from_name`].
+  pub fn parse(s: &str) -> Result<Self, String> {
+    let s = s.trim();
+    let (kind, name) = match s.find(':') {
+      Some(i) => (&s[..i], &s[i + 1..]),
+      None => return Err(format!("Binding `{}` has no `key:` or `mouse:`", s)),
+    };
+    match kind {
+      "key" => match name.strip_prefix('#') {
+        Some(num) => num.parse().ok().map(Key),
+        None => Key::from_name(name),
+      }
+      .map(Binding::Key)
+      .ok_or_else(|| format!("Unknown key `{}`", name)),
+      "mouse" => MouseButton::from_name(name)
+        .map(Binding::MouseButton)
+        .ok_or_else(|| format!("Unknown mouse button `{}`", name)),
+      other => Err(format!("Unknown binding kind `{}`", other)),
+    }
+  }
+
+  /// Is this binding down right now?
+  pub fn held(self, input: &InputState) -> bool {
+    match self {
+      Binding::Key(k) => input.key_held(k),
+      Binding::MouseButton(b) => input.button_held(b),
+    }
+  }
+
+  /// Did this binding go down this frame?
+  pub fn pressed(self, input: &InputState) -> bool {
+    match self {
+      Binding::Key(k) => input.key_pressed(k),
+      Binding::MouseButton(b) => input.button_pressed(b),
+    }
+  }
+
+  /// Did this binding come up this frame?
+  pub fn released(self, input: &InputState) -> bool {
+    match self {
+      Binding::Key(k) => input.key_released(k),
+      Binding::MouseButton(b) => input.button_released(b),
+    }
+  }
+}
+impl core::fmt::Display for Binding {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      Binding::Key(k) => write!(f, "key:{}", k.name()),
+      Binding::MouseButton(b) => write!(f, "mouse:{}", b.name()),
+    }
+  }
+}
+
+/// Maps action names to the bindings that trigger them.
+///
+/// Any number of bindings can trigger an action, and an action is "held" if
+/// _any_ of its bindings are held.
+///
+/// The config format is one action per line, and lines starting with `#` are
+/// comments:
+///
+/// ```text
+/// # movement
+/// move_forward = key:w, key:up
+/// move_back = key:s, key:down
+/// fire = mouse:left
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionMap {
+  bindings: HashMap<String, Vec<Binding>>,
+}
+impl ActionMap {
+  /// An action map with no actions in it.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Default bindings for the actions used by [`camera_input`](Self::camera_input).
+  ///
+  /// WASD moves, E and Q go up and down, Z and C roll.
+  pub fn default_camera() -> Self {
+    let mut map = Self::new();
+    let key = |ch| Binding::Key(Key::from_char(ch));
+    map.bind(actions::MOVE_FORWARD, key('w'));
+    map.bind(actions::MOVE_BACK, key('s'));
+    map.bind(actions::MOVE_LEFT, key('a'));
+    map.bind(actions::MOVE_RIGHT, key('d'));
+    map.bind(actions::MOVE_UP, key('e'));
+    map.bind(actions::MOVE_DOWN, key('q'));
+    map.bind(actions::ROLL_LEFT, key('z'));
+    map.bind(actions::ROLL_RIGHT, key('c'));
+    map
+  }
+
+  /// Parses an action map from config text.
+  pub fn from_config_str(config: &str) -> Result<Self, String> {
+    let mut map = Self::new();
+    for (i, line) in config.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let eq = line.find('=').ok_or_else(|| {
+        format!("Line {}: expected `action = bindings`", i + 1)
+      })?;
+      let action = line[..eq].trim();
+      if action.is_empty() {
+        return Err(format!("Line {}: missing action name", i + 1));
+      }
+      map.bindings.entry(action.to_string()).or_default();
+      for binding in line[eq + 1..].split(',').filter(|b| !b.trim().is_empty())
+      {
+        let binding = Binding::parse(binding)
+          .map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        map.bind(action, binding);
+      }
+    }
+    Ok(map)
+  }
+
+  /// Loads an action map from a config file.
+  pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+      .map_err(|e| format!("Couldn't read `{}`: {}", path.display(), e))?;
+    Self::from_config_str(&text)
+  }
+
+  /// Writes the action map out in the config format.
+  ///
+  /// Actions are sorted by name so that the output is stable.
+  pub fn to_config_string(&self) -> String {
+    let mut names: Vec<&String> = self.bindings.keys().collect();
+    names.sort();
+    let mut out = String::new();
+    for name in names {
+      let list: Vec<String> =
+        self.bindings[name].iter().map(|b| b.to_string()).collect();
+      out.push_str(&format!("{} = {}\n", name, list.join(", ")));
+    }
+    out
+  }
+
+  /// Saves the action map to a config file.
+  pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::write(path, self.to_config_string())
+      .map_err(|e| format!("Couldn't write `{}`: {}", path.display(), e))
+  }
+
+  /// Adds a binding to an action.
+  ///
+  /// Binding the same thing twice does nothing.
+  pub fn bind(&mut self, action: &str, binding: Binding) {
+    let list = self.bindings.entry(action.to_string()).or_default();
+    if !list.contains(&binding) {
+      list.push(binding);
+    }
+  }
+
+  /// Removes one binding from an action.
+  pub fn unbind(&mut self, action: &str, binding: Binding) {
+    if let Some(list) = self.bindings.get_mut(action) {
+      list.retain(|b| *b != binding);
+    }
+  }
+
+  /// Replaces all of an action's bindings with just the one given.
+  pub fn rebind(&mut self, action: &str, binding: Binding) {
+    self.bindings.insert(action.to_string(), vec![binding]);
+  }
+
+  /// The bindings of an action.
+  pub fn bindings(&self, action: &str) -> &[Binding] {
+    self.bindings.get(action).map(Vec::as_slice).unwrap_or(&[])
+  }
+
+  /// All action names in the map, in no particular order.
+  pub fn actions(&self) -> impl Iterator<Item = &str> {
+    self.bindings.keys().map(String::as_str)
+  }
+
+  /// Is any binding of this action held?
+  pub fn held(&self, input: &InputState, action: &str) -> bool {
+    self.bindings(action).iter().any(|b| b.held(input))
+  }
+
+  /// Did any binding of this action go down this frame?
+  pub fn pressed(&self, input: &InputState, action: &str) -> bool {
+    self.bindings(action).iter().any(|b| b.pressed(input))
+  }
+
+  /// Did any binding of this action come up this frame?
+  pub fn released(&self, input: &InputState, action: &str) -> bool {
+    self.bindings(action).iter().any(|b| b.released(input))
+  }
+
+  /// Gets `-1.0`, `0.0`, or `1.0` depending on which of two opposing actions
+  /// are held.
+  pub fn axis(
+    &self,
+    input: &InputState,
+    negative: &str,
+    positive: &str,
+  ) -> f32 {
+    let mut out = 0.0;
+    if self.held(input, negative) {
+      out -= 1.0;
+    }
+    if self.held(input, positive) {
+      out += 1.0;
+    }
+    out
+  }
+
+  /// Builds a frame of camera input.
+  ///
+  /// Uses the actions in the [`actions`] module for movement and roll. Mouse
+  /// motion is turned into yaw and pitch at `look_sensitivity` degrees per
+  /// pixel, the mouse wheel zooms, and roll goes at `roll_speed_deg` degrees
+  /// per second.
+  pub fn camera_input(
+    &self,
+    input: &InputState,
+    look_sensitivity: f32,
+    roll_speed_deg: f32,
+    delta_time: f32,
+  ) -> CameraInput {
+    use actions::*;
+    let (dx, dy) = input.mouse_delta();
+    CameraInput {
+      movement: Vec3 {
+        x: self.axis(input, MOVE_LEFT, MOVE_RIGHT),
+        y: self.axis(input, MOVE_DOWN, MOVE_UP),
+        z: self.axis(input, MOVE_BACK, MOVE_FORWARD),
+      },
+      d_yaw_deg: -dx as f32 * look_sensitivity,
+      d_pitch_deg: -dy as f32 * look_sensitivity,
+      d_roll_deg: self.axis(input, ROLL_LEFT, ROLL_RIGHT)
+        * roll_speed_deg
+        * delta_time,
+      zoom: input.wheel_delta().1 as f32,
+    }
+  }
+}
+
+/// Names of the actions used by [`ActionMap::camera_input`].
+pub mod actions {
+  /// Move forward.
+  pub const MOVE_FORWARD: &str = "move_forward";
+  /// Move backward.
+  pub const MOVE_BACK: &str = "move_back";
+  /// Move (strafe) left.
+  pub const MOVE_LEFT: &str = "move_left";
+  /// Move (strafe) right.
+  pub const MOVE_RIGHT: &str = "move_right";
+  /// Move up.
+  pub const MOVE_UP: &str = "move_up";
+  /// Move down.
+  pub const MOVE_DOWN: &str = "move_down";
+  /// Roll to the left.
+  pub const ROLL_LEFT: &str = "roll_left";
+  /// Roll to the right.
+  pub const ROLL_RIGHT: &str = "roll_right";
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn key(ch: char, pressed: bool) -> InputEvent {
+    InputEvent::Key { key: Key::from_char(ch), pressed }
+  }
+
+  #[test]
+  fn edges_only_last_one_frame() {
+    let w = Key::from_char('w');
+    let mut input = InputState::new();
+    input.begin_frame();
+    input.handle(&key('w', true));
+    assert!(input.key_pressed(w) && input.key_held(w));
+    assert!(!input.key_released(w));
+
+    input.begin_frame();
+    // a key repeat isn't a new press
+    input.handle(&key('w', true));
+    assert!(!input.key_pressed(w) && input.key_held(w));
+
+    input.begin_frame();
+    input.handle(&key('w', false));
+    assert!(input.key_released(w) && !input.key_held(w));
+
+    input.begin_frame();
+    assert!(!input.key_released(w) && !input.key_held(w));
+  }
+
+  #[test]
+  fn mouse_motion_adds_up_within_a_frame() {
+    let mut input = InputState::new();
+    input.handle(&InputEvent::MouseMotion { x: 5, y: 6, dx: 2, dy: -1 });
+    input.handle(&InputEvent::MouseMotion { x: 8, y: 4, dx: 3, dy: -2 });
+    input.handle(&InputEvent::MouseWheel { x: 0, y: 1 });
+    assert_eq!(input.mouse_position(), (8, 4));
+    assert_eq!(input.mouse_delta(), (5, -3));
+    assert_eq!(input.wheel_delta(), (0, 1));
+    input.begin_frame();
+    assert_eq!(input.mouse_position(), (8, 4));
+    assert_eq!(input.mouse_delta(), (0, 0));
+    assert_eq!(input.wheel_delta(), (0, 0));
+  }
+
+  #[test]
+  fn config_round_trip() {
+    let config = "\
+      # comment\n\
+      fire = mouse:left, key:space\n\
+      hash = key:#35\n\
+      comma = key:#44\n\
+      odd = key:#1234, key:f5, key:/\n\
+      empty =\n";
+    let map = ActionMap::from_config_str(config).unwrap();
+    assert_eq!(
+      map.bindings("fire"),
+      &[Binding::MouseButton(MouseButton::LEFT), Binding::Key(Key::SPACE)]
+    );
+    assert_eq!(map.bindings("hash"), &[Binding::Key(Key::from_char('#'))]);
+    assert_eq!(map.bindings("comma"), &[Binding::Key(Key::from_char(','))]);
+    assert!(map.bindings("empty").is_empty());
+    let written = map.to_config_string();
+    assert_eq!(ActionMap::from_config_str(&written).unwrap(), map);
+  }
+
+  #[test]
+  fn every_key_name_parses_back() {
+    let keys = (0..128)
+      .map(Key)
+      .chain((1..=12).filter_map(Key::function))
+      .chain(Key::NAMED.iter().map(|&(_, k)| k));
+    for k in keys {
+      let binding = Binding::Key(k);
+      assert_eq!(Binding::parse(&binding.to_string()), Ok(binding));
+    }
+  }
+
+  #[test]
+  fn bad_bindings_are_errors() {
+    assert!(Binding::parse("w").is_err());
+    assert!(Binding::parse("key:nope").is_err());
+    assert!(Binding::parse("pad:a").is_err());
+    assert!(ActionMap::from_config_str("no equals sign").is_err());
+    assert!(ActionMap::from_config_str(" = key:w").is_err());
+  }
+
+  #[test]
+  fn axis_values() {
+    let map = ActionMap::default_camera();
+    let mut input = InputState::new();
+    let axis = |input: &InputState| {
+      map.axis(input, actions::MOVE_LEFT, actions::MOVE_RIGHT)
+    };
+    assert_eq!(axis(&input), 0.0);
+    input.handle(&key('a', true));
+    assert_eq!(axis(&input), -1.0);
+    input.handle(&key('d', true));
+    assert_eq!(axis(&input), 0.0);
+    input.handle(&key('a', false));
+    assert_eq!(axis(&input), 1.0);
+  }
+}
@@ -0,0 +1,231 @@
+//! Textures with more than one layer: 2D arrays and 3D textures.
+//!
+//! A [`TextureArray`] is a stack of same sized images behind one texture
+//! binding. The shader picks the layer with the third texture coordinate, so
+//! you can draw things that use different images without rebinding anything.
+//!
+//! A [`Texture3D`] is a true volume, filtering blends between slices as well
+//! as within them. They're good for things like color grading lookup tables
+//! and volume data.
+
+use crate::{
+  image::{Image, ImageData},
+  texture::{
+    self, MagFilter, MinFilter, Texture, TextureFormat, TextureTarget,
+    TextureWrap,
+  },
+};
+
+/// A 2D array texture.
+pub struct TextureArray {
+  /// The texture object.
+  pub texture: Texture,
+  format: TextureFormat,
+  width: u32,
+  height: u32,
+  layers: u32,
+}
+impl TextureArray {
+  /// Makes an array texture with no contents yet.
+  ///
+  /// With `mipmapped` the texture uses trilinear filtering, call
+  /// [`generate_mipmaps`](Self::generate_mipmaps) after uploading the layers.
+  /// It repeats on both axes.
+  pub fn new(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    layers: u32,
+    mipmapped: bool,
+  ) -> Result<Self, String> {
+    if layers == 0 {
+      return Err("Texture arrays need at least one layer".to_string());
+    }
+    let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+    tex.bind(TextureTarget::Texture2DArray);
+    texture::tex_image_3d(
+      TextureTarget::Texture2DArray,
+      format,
+      0,
+      [width, height, layers],
+      None,
+    );
+    let min =
+      if mipmapped { MinFilter::LinearMipmapLinear } else { MinFilter::Linear };
+    texture::set_filters(TextureTarget::Texture2DArray, min, MagFilter::Linear);
+    texture::set_wrap(TextureTarget::Texture2DArray, TextureWrap::Repeat);
+    Ok(Self { texture: tex, format, width, height, layers })
+  }
+
+  /// Makes an array texture from a list of images, one per layer.
+  ///
+  /// All the images must be the same size. 8-bit images use `RGBA8` (or
+  /// `SRGB8Alpha8` if the first image has the sRGB flag), and float images
+  /// use `RGB16F`. Like [`Image::to_texture`] the rows are flipped and the
+  /// texture gets mipmaps.
+  pub fn from_images(images: &[Image]) -> Result<Self, String> {
+    let first = images.first().ok_or("No images given")?;
+    let format = first.texture_format();
+    let (width, height) = (first.width, first.height);
+    if let Some(i) = images.iter().position(|image| {
+      image.width != width
+        || image.height != height
+        || image.is_hdr() != first.is_hdr()
+    }) {
+      return Err(format!(
+        "Layer {} doesn't match the first layer's size and format",
+        i
+      ));
+    }
+    let layers = images.len() as u32;
+    let out = Self::new(format, width, height, layers, true)?;
+    for (layer, image) in images.iter().enumerate() {
+      let mut flipped = image.clone();
+      flipped.flip_vertical();
+      match &flipped.data {
+        ImageData::Rgba8(v) => out.upload_layer(layer as u32, v),
+        ImageData::RgbF32(v) => {
+          out.upload_layer(layer as u32, bytemuck::cast_slice(v))
+        }
+      }
+    }
+    out.generate_mipmaps();
+    Ok(out)
+  }
+
+  /// Replaces the contents of one layer's base mip level.
+  ///
+  /// The data is in the array's format, bottom row first.
+  ///
+  /// ## Panics
+  /// * If the layer is out of bounds.
+  /// * If `data` is too small for one layer.
+  pub fn upload_layer(&self, layer: u32, data: &[u8]) {
+    assert!(layer < self.layers, "layer {} of {}", layer, self.layers);
+    self.texture.bind(TextureTarget::Texture2DArray);
+    texture::tex_sub_image_3d(
+      TextureTarget::Texture2DArray,
+      self.format,
+      0,
+      [0, 0, layer],
+      [self.width, self.height, 1],
+      data,
+    );
+  }
+
+  /// Rebuilds the mip levels from the base level.
+  ///
+  /// Each layer gets its own mips, they never blend into each other.
+  pub fn generate_mipmaps(&self) {
+    self.texture.bind(TextureTarget::Texture2DArray);
+    texture::generate_mipmap(TextureTarget::Texture2DArray);
+  }
+
+  /// The format of the texels.
+  pub fn format(&self) -> TextureFormat {
+    self.format
+  }
+
+  /// The width and height of each layer.
+  pub fn size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  /// The number of layers.
+  pub fn layers(&self) -> u32 {
+    self.layers
+  }
+
+  /// Binds the array to the given texture unit.
+  pub fn bind(&self, unit: u32) {
+    texture::active_texture(unit);
+    self.texture.bind(TextureTarget::Texture2DArray);
+  }
+
+  /// Deletes the texture.
+  pub fn delete(self) {
+    self.texture.delete()
+  }
+}
+
+/// A 3D texture.
+pub struct Texture3D {
+  /// The texture object.
+  pub texture: Texture,
+  format: TextureFormat,
+  size: [u32; 3],
+}
+impl Texture3D {
+  /// Makes a 3D texture, with optional starting contents.
+  ///
+  /// The data goes a row at a time, then a slice at a time. The texture gets
+  /// linear filtering and clamps to the edge on all three axes, which is
+  /// what lookup tables want.
+  pub fn new(
+    format: TextureFormat,
+    size: [u32; 3],
+    data: Option<&[u8]>,
+  ) -> Result<Self, String> {
+    if let Some(d) = data {
+      let len =
+        size.iter().try_fold(texture::bytes_per_pixel(format), |n, &d| {
+          n.checked_mul(d as usize)
+        });
+      if !matches!(len, Some(len) if d.len() >= len) {
+        return Err(format!(
+          "Data is too small for {}x{}x{}",
+          size[0], size[1], size[2]
+        ));
+      }
+    }
+    let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+    tex.bind(TextureTarget::Texture3D);
+    texture::tex_image_3d(TextureTarget::Texture3D, format, 0, size, data);
+    texture::set_filters(
+      TextureTarget::Texture3D,
+      MinFilter::Linear,
+      MagFilter::Linear,
+    );
+    texture::set_wrap(TextureTarget::Texture3D, TextureWrap::ClampToEdge);
+    Ok(Self { texture: tex, format, size })
+  }
+
+  /// Replaces the contents of one slice (at depth `z`) of the base mip level.
+  ///
+  /// ## Panics
+  /// * If the slice is out of bounds.
+  /// * If `data` is too small for one slice.
+  pub fn upload_slice(&self, z: u32, data: &[u8]) {
+    assert!(z < self.size[2], "slice {} of {}", z, self.size[2]);
+    self.texture.bind(TextureTarget::Texture3D);
+    texture::tex_sub_image_3d(
+      TextureTarget::Texture3D,
+      self.format,
+      0,
+      [0, 0, z],
+      [self.size[0], self.size[1], 1],
+      data,
+    );
+  }
+
+  /// The format of the texels.
+  pub fn format(&self) -> TextureFormat {
+    self.format
+  }
+
+  /// The width, height, and depth.
+  pub fn size(&self) -> [u32; 3] {
+    self.size
+  }
+
+  /// Binds the texture to the given texture unit.
+  pub fn bind(&self, unit: u32) {
+    texture::active_texture(unit);
+    self.texture.bind(TextureTarget::Texture3D);
+  }
+
+  /// Deletes the texture.
+  pub fn delete(self) {
+    self.texture.delete()
+  }
+}
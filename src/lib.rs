@@ -24,8 +24,6 @@ texture id variables should go inside the block because we don't use them later.
 
 TODO:
 
-016 mouse-wheel Zoom on the camera
-017 Free Camera? (allowing roll)
 --- end of arc 1
 
 */
@@ -33,6 +31,36 @@ TODO:
 use core::convert::{TryFrom, TryInto};
 use ogl33::*;
 
+pub mod atlas;
+pub mod camera;
+pub mod cubemap;
+pub mod debug_draw;
+pub mod deferred;
+pub mod feedback;
+pub mod framebuffer;
+pub mod image;
+pub mod input;
+pub mod layered;
+pub mod pbr;
+pub mod postprocess;
+pub mod program_cache;
+pub mod query;
+pub mod reflect;
+pub mod replay;
+pub mod resize;
+pub mod sampler;
+pub mod shadow;
+pub mod skybox;
+pub mod sprite;
+pub mod state;
+pub mod sync;
+pub mod text;
+pub mod texture;
+pub mod truetype;
+pub mod ui;
+pub mod uniform;
+pub mod units;
+
 /// Takes a string literal and concatenates a null byte onto the end.
 #[macro_export]
 macro_rules! null_str {
@@ -48,6 +76,28 @@ pub fn clear_color(r: f32, g: f32, b: f32, a: f32) {
   unsafe { glClearColor(r, g, b, a) }
 }
 
+/// Sets the area of the framebuffer that drawing goes to, in pixels.
+///
+/// `(0, 0)` is the bottom left.
+pub fn viewport(x: i32, y: i32, width: u32, height: u32) {
+  unsafe {
+    glViewport(x, y, width.try_into().unwrap(), height.try_into().unwrap())
+  }
+}
+
+/// Checks if the GL context supports an extension, eg:
+/// `"GL_EXT_texture_filter_anisotropic"`.
+pub fn has_extension(name: &str) -> bool {
+  let mut count = 0;
+  unsafe { glGetIntegerv(GL_NUM_EXTENSIONS, &mut count) };
+  (0..count.max(0) as GLuint).any(|i| {
+    let ptr = unsafe { glGetStringi(GL_EXTENSIONS, i) };
+    !ptr.is_null()
+      && unsafe { core::ffi::CStr::from_ptr(ptr.cast()) }.to_bytes()
+        == name.as_bytes()
+  })
+}
+
 /// Basic wrapper for a [Vertex Array
 /// Object](https://www.khronos.org/opengl/wiki/Vertex_Specification#Vertex_Array_Object).
 pub struct VertexArray(pub GLuint);
@@ -72,6 +122,11 @@ impl VertexArray {
   pub fn clear_binding() {
     unsafe { glBindVertexArray(0) }
   }
+
+  /// Deletes the vertex array object.
+  pub fn delete(self) {
+    unsafe { glDeleteVertexArrays(1, &self.0) }
+  }
 }
 
 /// The types of buffer object that you can have.
@@ -81,6 +136,12 @@ pub enum BufferType {
   Array = GL_ARRAY_BUFFER as isize,
   /// Element Array Buffers hold indexes of what vertexes to use for drawing.
   ElementArray = GL_ELEMENT_ARRAY_BUFFER as isize,
+  /// Uniform Buffers hold the values of uniform blocks.
+  Uniform = GL_UNIFORM_BUFFER as isize,
+  /// Transform Feedback Buffers receive vertex shader output.
+  TransformFeedback = GL_TRANSFORM_FEEDBACK_BUFFER as isize,
+  /// Pixel Pack Buffers receive pixels read back from GL.
+  PixelPack = GL_PIXEL_PACK_BUFFER as isize,
 }
 
 /// Basic wrapper for a [Buffer
@@ -109,6 +170,11 @@ impl Buffer {
   pub fn clear_binding(ty: BufferType) {
     unsafe { glBindBuffer(ty as GLenum, 0) }
   }
+
+  /// Deletes the buffer.
+  pub fn delete(self) {
+    unsafe { glDeleteBuffers(1, &self.0) }
+  }
 }
 
 /// Places a slice of data into a previously-bound buffer.
@@ -124,6 +190,7 @@ pub fn buffer_data(ty: BufferType, data: &[u8], usage: GLenum) {
 }
 
 /// The types of shader object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShaderType {
   /// Vertex shaders determine the position of geometry within the screen.
   Vertex = GL_VERTEX_SHADER as isize,
@@ -228,6 +295,9 @@ impl Shader {
   }
 }
 
+/// Called by [`ShaderProgram::from_shaders`] just before linking.
+pub type LinkHook<'a> = &'a dyn Fn(&ShaderProgram) -> Result<(), String>;
+
 /// A handle to a [Program
 /// Object](https://www.khronos.org/opengl/wiki/GLSL_Object#Program_objects)
 pub struct ShaderProgram(pub GLuint);
@@ -288,6 +358,74 @@ impl ShaderProgram {
     unsafe { glUseProgram(self.0) };
   }
 
+  /// Gets the location of a uniform.
+  ///
+  /// Gives `None` if there's no active uniform by that name. Uniforms that
+  /// aren't used by the shader get optimized out, so they count as not active.
+  pub fn uniform_location(&self, name: &str) -> Option<GLint> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let loc = unsafe { glGetUniformLocation(self.0, name.as_ptr().cast()) };
+    if loc != -1 {
+      Some(loc)
+    } else {
+      None
+    }
+  }
+
+  /// Gets the index of a uniform block.
+  ///
+  /// Gives `None` if there's no active block by that name.
+  pub fn uniform_block_index(&self, name: &str) -> Option<GLuint> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let index = unsafe { glGetUniformBlockIndex(self.0, name.as_ptr().cast()) };
+    if index != GL_INVALID_INDEX {
+      Some(index)
+    } else {
+      None
+    }
+  }
+
+  /// Connects a uniform block to a uniform buffer binding point.
+  ///
+  /// Gives `false` (and does nothing) if there's no active block by that
+  /// name.
+  pub fn bind_uniform_block(&self, name: &str, binding: u32) -> bool {
+    match self.uniform_block_index(name) {
+      Some(index) => {
+        unsafe { glUniformBlockBinding(self.0, index, binding) };
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Sets which vertex shader outputs get captured by transform feedback.
+  ///
+  /// This only takes effect at the next [`link_program`](Self::link_program),
+  /// so call it before linking. See the [`feedback`] module.
+  pub fn set_feedback_varyings(
+    &self,
+    names: &[&str],
+    mode: feedback::FeedbackMode,
+  ) -> Result<(), String> {
+    let names = names
+      .iter()
+      .map(|name| std::ffi::CString::new(*name))
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| format!("Bad varying name: {}", e))?;
+    let ptrs: Vec<*const GLchar> =
+      names.iter().map(|name| name.as_ptr().cast()).collect();
+    unsafe {
+      glTransformFeedbackVaryings(
+        self.0,
+        ptrs.len() as GLsizei,
+        ptrs.as_ptr(),
+        mode as GLenum,
+      )
+    };
+    Ok(())
+  }
+
   /// Marks the program for deletion.
   ///
   /// Note: This _does not_ immediately delete the program. If the program is
@@ -303,24 +441,71 @@ impl ShaderProgram {
   /// This is the preferred way to create a simple shader program in the common
   /// case. It's just less error prone than doing all the steps yourself.
   pub fn from_vert_frag(vert: &str, frag: &str) -> Result<Self, String> {
+    Self::from_shaders(
+      &[(ShaderType::Vertex, vert), (ShaderType::Fragment, frag)],
+      None,
+    )
+  }
+
+  /// Compiles and attaches each shader, calls `before_link` (if any), and
+  /// then links the program.
+  ///
+  /// The hook is for settings that only take effect when the program is
+  /// linked, such as [feedback varyings](Self::set_feedback_varyings).
+  pub fn from_shaders(
+    sources: &[(ShaderType, &str)],
+    before_link: Option<LinkHook<'_>>,
+  ) -> Result<Self, String> {
     let p =
       Self::new().ok_or_else(|| "Couldn't allocate a program".to_string())?;
-    let v = Shader::from_source(ShaderType::Vertex, vert)
-      .map_err(|e| format!("Vertex Compile Error: {}", e))?;
-    let f = Shader::from_source(ShaderType::Fragment, frag)
-      .map_err(|e| format!("Fragment Compile Error: {}", e))?;
-    p.attach_shader(&v);
-    p.attach_shader(&f);
-    p.link_program();
-    v.delete();
-    f.delete();
-    if p.link_success() {
-      Ok(p)
-    } else {
-      let out = format!("Program Link Error: {}", p.info_log());
-      p.delete();
-      Err(out)
+    let mut shaders = Vec::with_capacity(sources.len());
+    let mut result = Ok(());
+    for &(ty, source) in sources {
+      match Shader::from_source(ty, source) {
+        Ok(shader) => {
+          p.attach_shader(&shader);
+          shaders.push(shader);
+        }
+        Err(e) => {
+          let stage = match ty {
+            ShaderType::Vertex => "Vertex",
+            ShaderType::Fragment => "Fragment",
+          };
+          result = Err(format!("{} Compile Error: {}", stage, e));
+          break;
+        }
+      }
+    }
+    if let (Ok(()), Some(hook)) = (&result, before_link) {
+      result = hook(&p);
     }
+    if result.is_ok() {
+      p.link_program();
+      if !p.link_success() {
+        result = Err(format!("Program Link Error: {}", p.info_log()));
+      }
+    }
+    for shader in shaders {
+      shader.delete();
+    }
+    match result {
+      Ok(()) => Ok(p),
+      Err(e) => {
+        p.delete();
+        Err(e)
+      }
+    }
+  }
+
+  /// Like [`from_vert_frag`](Self::from_vert_frag), but goes through a
+  /// [`ProgramCache`](program_cache::ProgramCache) so that the linked program
+  /// can be reloaded from disk next time.
+  pub fn from_vert_frag_cached(
+    vert: &str,
+    frag: &str,
+    cache: &program_cache::ProgramCache,
+  ) -> Result<Self, String> {
+    cache.from_vert_frag(vert, frag)
   }
 }
 
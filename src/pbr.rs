@@ -0,0 +1,877 @@
+//! Physically based rendering, with the metallic-roughness model that glTF
+//! uses, and image based lighting.
+//!
+//! The lighting math is Cook-Torrance with the GGX distribution, the Smith
+//! (Schlick-GGX) geometry term, and Schlick's Fresnel. It's all in
+//! [`PBR_GLSL`], to paste into your own shaders after the `#version` line,
+//! along with [`MATERIAL_GLSL`] to read a [`PbrMaterial`]'s textures.
+//!
+//! _Image based lighting_ (IBL) lights the scene with an environment image
+//! instead of (or as well as) individual lights. Doing that properly would
+//! mean integrating the whole image for every pixel, so the integrals are
+//! worked out once ahead of time by [`IblMaps::from_equirect`]:
+//!
+//! * An _irradiance_ cube map holds the diffuse light from every direction.
+//! * A _prefiltered_ cube map holds the specular reflection, blurrier at each
+//!   mip level to match rougher surfaces.
+//! * The _BRDF LUT_ is a 2D table of the rest of the specular integral, by
+//!   view angle and roughness. It doesn't depend on the environment at all.
+
+use crate::{
+  buffer_data,
+  cubemap::TextureCube,
+  framebuffer::{
+    self, Attachment, Framebuffer, FramebufferTarget, Renderbuffer,
+  },
+  postprocess::{FullscreenTriangle, FULLSCREEN_VERT},
+  skybox::CUBE_VERTICES,
+  state::{RenderState, StateCache},
+  texture::{
+    self, CubeFace, MagFilter, MinFilter, Texture, TextureFormat,
+    TextureTarget, TextureWrap,
+  },
+  Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{convert::TryInto, mem::size_of};
+use ogl33::*;
+use ultraviolet::{projection, Mat4, Vec3, Vec4};
+
+/// GLSL for Cook-Torrance GGX shading.
+///
+/// * `vec3 pbr_direct(PbrSurface s, vec3 v, vec3 l, vec3 radiance)`: the
+///   light reflected towards `v` (pointing at the eye) from light arriving
+///   along `l` (pointing at the light) with the given radiance.
+/// * `vec3 pbr_point_light(PbrSurface s, vec3 world_pos, vec3 v, vec3
+///   light_pos, vec3 light_color)`: the same, with inverse square falloff.
+/// * `vec3 pbr_ambient_ibl(PbrSurface s, vec3 v)`: the image based lighting,
+///   using the uniforms that [`IblMaps::set_uniforms`] fills in.
+///
+/// The `PbrSurface` struct is declared here too, [`MATERIAL_GLSL`] fills one
+/// in from a material.
+pub const PBR_GLSL: &str = r#"
+  const float PI = 3.14159265359;
+
+  struct PbrSurface {
+    vec3 albedo;
+    float alpha;
+    float metallic;
+    float roughness;
+    float ao;
+    vec3 emissive;
+    vec3 normal;
+  };
+
+  uniform samplerCube irradiance_map;
+  uniform samplerCube prefilter_map;
+  uniform sampler2D brdf_lut;
+  uniform float prefilter_max_lod;
+
+  float distribution_ggx(vec3 n, vec3 h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float n_dot_h = max(dot(n, h), 0.0);
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+  }
+
+  float geometry_schlick_ggx(float n_dot_v, float roughness) {
+    float r = roughness + 1.0;
+    float k = (r * r) / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+  }
+
+  float geometry_smith(vec3 n, vec3 v, vec3 l, float roughness) {
+    float n_dot_v = max(dot(n, v), 0.0);
+    float n_dot_l = max(dot(n, l), 0.0);
+    return geometry_schlick_ggx(n_dot_v, roughness)
+      * geometry_schlick_ggx(n_dot_l, roughness);
+  }
+
+  vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+  }
+
+  vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness) {
+    vec3 r = max(vec3(1.0 - roughness), f0);
+    return f0 + (r - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+  }
+
+  vec3 pbr_f0(PbrSurface s) {
+    return mix(vec3(0.04), s.albedo, s.metallic);
+  }
+
+  vec3 pbr_direct(PbrSurface s, vec3 v, vec3 l, vec3 radiance) {
+    vec3 n = s.normal;
+    vec3 h = normalize(v + l);
+    vec3 f = fresnel_schlick(max(dot(h, v), 0.0), pbr_f0(s));
+    float ndf = distribution_ggx(n, h, s.roughness);
+    float g = geometry_smith(n, v, l, s.roughness);
+    float n_dot_l = max(dot(n, l), 0.0);
+    vec3 specular = (ndf * g * f)
+      / (4.0 * max(dot(n, v), 0.0) * n_dot_l + 0.0001);
+    vec3 k_d = (vec3(1.0) - f) * (1.0 - s.metallic);
+    return (k_d * s.albedo / PI + specular) * radiance * n_dot_l;
+  }
+
+  vec3 pbr_point_light(
+    PbrSurface s, vec3 world_pos, vec3 v, vec3 light_pos, vec3 light_color
+  ) {
+    vec3 to_light = light_pos - world_pos;
+    float d2 = max(dot(to_light, to_light), 0.0001);
+    return pbr_direct(s, v, to_light * inversesqrt(d2), light_color / d2);
+  }
+
+  vec3 pbr_ambient_ibl(PbrSurface s, vec3 v) {
+    vec3 n = s.normal;
+    float n_dot_v = max(dot(n, v), 0.0);
+    vec3 f = fresnel_schlick_roughness(n_dot_v, pbr_f0(s), s.roughness);
+    vec3 k_d = (1.0 - f) * (1.0 - s.metallic);
+    vec3 diffuse = texture(irradiance_map, n).rgb * s.albedo;
+
+    vec3 r = reflect(-v, n);
+    float lod = s.roughness * prefilter_max_lod;
+    vec3 prefiltered = textureLod(prefilter_map, r, lod).rgb;
+    vec2 brdf = texture(brdf_lut, vec2(n_dot_v, s.roughness)).rg;
+    vec3 specular = prefiltered * (f * brdf.x + brdf.y);
+
+    return (k_d * diffuse + specular) * s.ao;
+  }
+"#;
+
+/// GLSL for reading a [`PbrMaterial`], using the uniforms that
+/// [`PbrMaterial::set_uniforms`] fills in.
+///
+/// Paste this in after [`PBR_GLSL`], then call `PbrSurface
+/// sample_material(vec2 uv, vec3 world_pos, vec3 normal)` with the
+/// interpolated vertex values.
+///
+/// Normal maps are applied without needing tangents: the tangent frame is
+/// worked out from screen space derivatives of the position and UV.
+pub const MATERIAL_GLSL: &str = r#"
+  uniform vec4 material_base_color;
+  uniform float material_metallic;
+  uniform float material_roughness;
+  uniform vec3 material_emissive;
+  uniform float material_normal_scale;
+  uniform float material_occlusion_strength;
+
+  uniform sampler2D material_albedo_map;
+  uniform sampler2D material_normal_map;
+  uniform sampler2D material_metallic_roughness_map;
+  uniform sampler2D material_occlusion_map;
+  uniform sampler2D material_emissive_map;
+  uniform bool material_has_albedo_map;
+  uniform bool material_has_normal_map;
+  uniform bool material_has_metallic_roughness_map;
+  uniform bool material_has_occlusion_map;
+  uniform bool material_has_emissive_map;
+
+  vec3 perturb_normal(vec3 n, vec3 world_pos, vec2 uv, vec3 map_normal) {
+    vec3 dp1 = dFdx(world_pos);
+    vec3 dp2 = dFdy(world_pos);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+    float inv_max = inversesqrt(max(dot(t, t), dot(b, b)));
+    mat3 tbn = mat3(t * inv_max, b * inv_max, n);
+    return normalize(tbn * map_normal);
+  }
+
+  PbrSurface sample_material(vec2 uv, vec3 world_pos, vec3 normal) {
+    PbrSurface s;
+    vec4 base = material_base_color;
+    if (material_has_albedo_map) {
+      base *= texture(material_albedo_map, uv);
+    }
+    s.albedo = base.rgb;
+    s.alpha = base.a;
+
+    s.metallic = material_metallic;
+    s.roughness = material_roughness;
+    if (material_has_metallic_roughness_map) {
+      vec4 mr = texture(material_metallic_roughness_map, uv);
+      s.roughness *= mr.g;
+      s.metallic *= mr.b;
+    }
+    s.roughness = clamp(s.roughness, 0.04, 1.0);
+
+    s.ao = 1.0;
+    if (material_has_occlusion_map) {
+      float ao = texture(material_occlusion_map, uv).r;
+      s.ao = mix(1.0, ao, material_occlusion_strength);
+    }
+
+    s.emissive = material_emissive;
+    if (material_has_emissive_map) {
+      s.emissive *= texture(material_emissive_map, uv).rgb;
+    }
+
+    vec3 n = normalize(normal);
+    if (material_has_normal_map) {
+      vec3 m = texture(material_normal_map, uv).xyz * 2.0 - 1.0;
+      m.xy *= material_normal_scale;
+      n = perturb_normal(n, world_pos, uv, normalize(m));
+    }
+    s.normal = n;
+    return s;
+  }
+"#;
+
+/// The textures and factors of a metallic-roughness material.
+///
+/// These work just like glTF materials: each factor is multiplied by its
+/// texture (if there is one). The albedo and emissive maps should be loaded
+/// as sRGB, the others as linear.
+pub struct PbrMaterial {
+  /// Base color and alpha.
+  pub base_color: Vec4,
+  /// `0.0` for dielectrics (plastic, wood, etc) up to `1.0` for metals.
+  pub metallic: f32,
+  /// `0.0` for mirror smooth up to `1.0` for fully rough.
+  pub roughness: f32,
+  /// Light given off by the surface itself.
+  pub emissive: Vec3,
+  /// Scales the X and Y of the normal map.
+  pub normal_scale: f32,
+  /// How much the occlusion map applies.
+  pub occlusion_strength: f32,
+  /// Base color texture.
+  pub albedo_map: Option<Texture>,
+  /// Tangent space normal map.
+  pub normal_map: Option<Texture>,
+  /// Roughness in green and metallic in blue.
+  pub metallic_roughness_map: Option<Texture>,
+  /// Ambient occlusion in red.
+  pub occlusion_map: Option<Texture>,
+  /// Emissive color texture.
+  pub emissive_map: Option<Texture>,
+}
+impl Default for PbrMaterial {
+  /// The glTF defaults: white, fully metallic, fully rough, no textures.
+  fn default() -> Self {
+    Self {
+      base_color: Vec4::one(),
+      metallic: 1.0,
+      roughness: 1.0,
+      emissive: Vec3::zero(),
+      normal_scale: 1.0,
+      occlusion_strength: 1.0,
+      albedo_map: None,
+      normal_map: None,
+      metallic_roughness_map: None,
+      occlusion_map: None,
+      emissive_map: None,
+    }
+  }
+}
+impl PbrMaterial {
+  /// The number of texture units that [`set_uniforms`](Self::set_uniforms)
+  /// uses.
+  pub const TEXTURE_UNITS: u32 = 5;
+
+  /// Binds the textures to units `first_unit` and up, and sets the
+  /// `material_*` uniforms of a program using [`MATERIAL_GLSL`].
+  ///
+  /// The program must be in use.
+  pub fn set_uniforms(&self, program: &ShaderProgram, first_unit: u32) {
+    set_vec4(program, "material_base_color", self.base_color);
+    set_f32(program, "material_metallic", self.metallic);
+    set_f32(program, "material_roughness", self.roughness);
+    set_vec3(program, "material_emissive", self.emissive);
+    set_f32(program, "material_normal_scale", self.normal_scale);
+    set_f32(program, "material_occlusion_strength", self.occlusion_strength);
+    let maps = [
+      ("albedo", &self.albedo_map),
+      ("normal", &self.normal_map),
+      ("metallic_roughness", &self.metallic_roughness_map),
+      ("occlusion", &self.occlusion_map),
+      ("emissive", &self.emissive_map),
+    ];
+    for (i, (name, map)) in maps.iter().enumerate() {
+      let unit = first_unit + i as u32;
+      let has = format!("material_has_{}_map", name);
+      set_i32(program, &has, map.is_some() as i32);
+      set_i32(program, &format!("material_{}_map", name), unit as i32);
+      if let Some(tex) = map {
+        texture::active_texture(unit);
+        tex.bind(TextureTarget::Texture2D);
+      }
+    }
+  }
+
+  /// Deletes all the textures.
+  pub fn delete(self) {
+    let maps = vec![
+      self.albedo_map,
+      self.normal_map,
+      self.metallic_roughness_map,
+      self.occlusion_map,
+      self.emissive_map,
+    ];
+    for tex in maps.into_iter().flatten() {
+      tex.delete();
+    }
+  }
+}
+
+fn set_f32(program: &ShaderProgram, name: &str, v: f32) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform1f(loc, v) }
+  }
+}
+
+fn set_i32(program: &ShaderProgram, name: &str, v: i32) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform1i(loc, v) }
+  }
+}
+
+fn set_vec3(program: &ShaderProgram, name: &str, v: Vec3) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform3f(loc, v.x, v.y, v.z) }
+  }
+}
+
+fn set_vec4(program: &ShaderProgram, name: &str, v: Vec4) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform4f(loc, v.x, v.y, v.z, v.w) }
+  }
+}
+
+fn set_mat4(program: &ShaderProgram, name: &str, m: &Mat4) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniformMatrix4fv(loc, 1, GL_FALSE, m.as_ptr()) }
+  }
+}
+
+const CAPTURE_VERT: &str = r#"#version 330 core
+  layout (location = 0) in vec3 pos;
+  uniform mat4 view_projection;
+  out vec3 local_pos;
+
+  void main() {
+    local_pos = pos;
+    gl_Position = view_projection * vec4(pos, 1.0);
+  }
+"#;
+
+const EQUIRECT_FRAG: &str = r#"#version 330 core
+  in vec3 local_pos;
+  uniform sampler2D equirect;
+  out vec4 final_color;
+
+  const vec2 INV_ATAN = vec2(0.1591, 0.3183);
+
+  void main() {
+    vec3 v = normalize(local_pos);
+    vec2 uv = vec2(atan(v.z, v.x), asin(v.y)) * INV_ATAN + 0.5;
+    final_color = vec4(texture(equirect, uv).rgb, 1.0);
+  }
+"#;
+
+const IRRADIANCE_FRAG: &str = r#"#version 330 core
+  in vec3 local_pos;
+  uniform samplerCube environment;
+  out vec4 final_color;
+
+  const float PI = 3.14159265359;
+
+  void main() {
+    vec3 n = normalize(local_pos);
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 right = normalize(cross(up, n));
+    up = normalize(cross(n, right));
+
+    vec3 sum = vec3(0.0);
+    float count = 0.0;
+    const float STEP = 0.025;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+      for (float theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+        vec3 t = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+        vec3 dir = t.x * right + t.y * up + t.z * n;
+        sum += texture(environment, dir).rgb * cos(theta) * sin(theta);
+        count += 1.0;
+      }
+    }
+    final_color = vec4(PI * sum / count, 1.0);
+  }
+"#;
+
+/// Shared by the prefilter and BRDF shaders.
+const IMPORTANCE_SAMPLE_GLSL: &str = r#"
+  const float PI = 3.14159265359;
+
+  float radical_inverse(uint bits) {
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10;
+  }
+
+  vec2 hammersley(uint i, uint n) {
+    return vec2(float(i) / float(n), radical_inverse(i));
+  }
+
+  vec3 importance_sample_ggx(vec2 xi, vec3 n, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, n));
+    vec3 bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+  }
+"#;
+
+const PREFILTER_FRAG: &str = r#"
+  in vec3 local_pos;
+  uniform samplerCube environment;
+  uniform float roughness;
+  uniform float environment_size;
+  out vec4 final_color;
+
+  const uint SAMPLES = 1024u;
+
+  float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+  }
+
+  void main() {
+    vec3 n = normalize(local_pos);
+    vec3 v = n;
+    vec3 sum = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SAMPLES; ++i) {
+      vec2 xi = hammersley(i, SAMPLES);
+      vec3 h = importance_sample_ggx(xi, n, roughness);
+      vec3 l = normalize(2.0 * dot(v, h) * h - v);
+      float n_dot_l = max(dot(n, l), 0.0);
+      if (n_dot_l > 0.0) {
+        // sample a blurrier mip when each sample covers more of the sphere,
+        // which stops bright spots from turning into dotted patterns.
+        float n_dot_h = max(dot(n, h), 0.0);
+        float h_dot_v = max(dot(h, v), 0.0);
+        float pdf = distribution_ggx(n_dot_h, roughness) * n_dot_h
+          / (4.0 * h_dot_v) + 0.0001;
+        float texel_solid_angle =
+          4.0 * PI / (6.0 * environment_size * environment_size);
+        float sample_solid_angle = 1.0 / (float(SAMPLES) * pdf + 0.0001);
+        float mip = roughness == 0.0
+          ? 0.0
+          : 0.5 * log2(sample_solid_angle / texel_solid_angle);
+        sum += textureLod(environment, l, mip).rgb * n_dot_l;
+        weight += n_dot_l;
+      }
+    }
+    final_color = vec4(sum / weight, 1.0);
+  }
+"#;
+
+const BRDF_FRAG: &str = r#"
+  in vec2 uv;
+  out vec2 final_color;
+
+  const uint SAMPLES = 1024u;
+
+  float geometry_schlick_ggx_ibl(float n_dot_v, float roughness) {
+    float k = (roughness * roughness) / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+  }
+
+  void main() {
+    float n_dot_v = max(uv.x, 0.001);
+    float roughness = uv.y;
+    vec3 v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    vec3 n = vec3(0.0, 0.0, 1.0);
+    float a = 0.0;
+    float b = 0.0;
+    for (uint i = 0u; i < SAMPLES; ++i) {
+      vec2 xi = hammersley(i, SAMPLES);
+      vec3 h = importance_sample_ggx(xi, n, roughness);
+      vec3 l = normalize(2.0 * dot(v, h) * h - v);
+      float n_dot_l = max(l.z, 0.0);
+      float n_dot_h = max(h.z, 0.0);
+      float v_dot_h = max(dot(v, h), 0.0);
+      if (n_dot_l > 0.0) {
+        float g = geometry_schlick_ggx_ibl(n_dot_v, roughness)
+          * geometry_schlick_ggx_ibl(n_dot_l, roughness);
+        float g_vis = (g * v_dot_h) / (n_dot_h * n_dot_v);
+        float fc = pow(1.0 - v_dot_h, 5.0);
+        a += (1.0 - fc) * g_vis;
+        b += fc * g_vis;
+      }
+    }
+    final_color = vec2(a, b) / float(SAMPLES);
+  }
+"#;
+
+/// The sizes used when making [`IblMaps`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IblSettings {
+  /// Edge size of the environment cube map made from the equirect image.
+  pub environment_size: u32,
+  /// Edge size of the irradiance cube map. It's very blurry, so this can be
+  /// tiny.
+  pub irradiance_size: u32,
+  /// Edge size of the base level of the prefiltered cube map.
+  pub prefilter_size: u32,
+  /// Mip levels of the prefiltered map, from roughness `0.0` at the base up
+  /// to roughness `1.0` at the last level.
+  ///
+  /// This is capped at the length of the mip chain for `prefilter_size`.
+  pub prefilter_mips: u32,
+  /// Width and height of the BRDF LUT.
+  pub brdf_lut_size: u32,
+}
+impl Default for IblSettings {
+  fn default() -> Self {
+    Self {
+      environment_size: 512,
+      irradiance_size: 32,
+      prefilter_size: 128,
+      prefilter_mips: 5,
+      brdf_lut_size: 512,
+    }
+  }
+}
+
+/// The precomputed maps for image based lighting.
+pub struct IblMaps {
+  /// The environment itself, as a cube map. Draw this with the
+  /// [`Skybox`](crate::skybox::Skybox) for the background.
+  pub environment: TextureCube,
+  /// Diffuse irradiance.
+  pub irradiance: TextureCube,
+  /// Specular reflection, blurred more at each mip level.
+  pub prefiltered: TextureCube,
+  /// The split-sum BRDF table, `RG16F`.
+  pub brdf_lut: Texture,
+  prefilter_mips: u32,
+}
+impl IblMaps {
+  /// The number of texture units that [`set_uniforms`](Self::set_uniforms)
+  /// uses.
+  pub const TEXTURE_UNITS: u32 = 3;
+
+  /// Runs all the precomputation passes on an equirectangular HDR image.
+  ///
+  /// `rgba` is linear color, four floats per pixel, with the rows going from
+  /// the bottom of the image to the top (like other GL texture uploads).
+  ///
+  /// This takes a moment, since the irradiance and prefilter passes take a
+  /// lot of samples per pixel. It leaves the window's framebuffer bound, but
+  /// the viewport will need to be reset.
+  pub fn from_equirect(
+    width: u32,
+    height: u32,
+    rgba: &[f32],
+    settings: IblSettings,
+    cache: &mut StateCache,
+  ) -> Result<Self, String> {
+    let needed = (width as usize)
+      .checked_mul(height as usize)
+      .and_then(|pixels| pixels.checked_mul(4))
+      .ok_or_else(|| {
+        format!("Equirect size {}x{} is too big", width, height)
+      })?;
+    if rgba.len() < needed {
+      return Err(format!(
+        "Equirect data is too small for {}x{}",
+        width, height
+      ));
+    }
+    let equirect = Texture::new().ok_or("Couldn't allocate a texture")?;
+    equirect.bind(TextureTarget::Texture2D);
+    texture::tex_image_2d(
+      TextureFormat::RGBA32F,
+      0,
+      width,
+      height,
+      Some(bytemuck::cast_slice(&rgba[..needed])),
+    );
+    texture::set_filters(
+      TextureTarget::Texture2D,
+      MinFilter::Linear,
+      MagFilter::Linear,
+    );
+    texture::set_wrap(TextureTarget::Texture2D, TextureWrap::ClampToEdge);
+
+    let capture = Capture::new()?;
+    cache.apply(&RenderState::default());
+    let out = capture.run(&equirect, settings);
+    capture.delete();
+    equirect.delete();
+    Framebuffer::clear_binding(FramebufferTarget::Both);
+    out
+  }
+
+  /// Binds the maps to units `first_unit` and up, and sets the uniforms used
+  /// by `pbr_ambient_ibl` in [`PBR_GLSL`].
+  ///
+  /// The program must be in use.
+  pub fn set_uniforms(&self, program: &ShaderProgram, first_unit: u32) {
+    self.irradiance.bind(first_unit);
+    self.prefiltered.bind(first_unit + 1);
+    texture::active_texture(first_unit + 2);
+    self.brdf_lut.bind(TextureTarget::Texture2D);
+    set_i32(program, "irradiance_map", first_unit as i32);
+    set_i32(program, "prefilter_map", first_unit as i32 + 1);
+    set_i32(program, "brdf_lut", first_unit as i32 + 2);
+    let max_lod = self.prefilter_mips.saturating_sub(1) as f32;
+    set_f32(program, "prefilter_max_lod", max_lod);
+  }
+
+  /// Deletes all the textures.
+  pub fn delete(self) {
+    self.environment.delete();
+    self.irradiance.delete();
+    self.prefiltered.delete();
+    self.brdf_lut.delete();
+  }
+}
+
+/// The GL objects for rendering into cube map faces.
+struct Capture {
+  fbo: Framebuffer,
+  rbo: Renderbuffer,
+  vao: VertexArray,
+  vbo: Buffer,
+  triangle: FullscreenTriangle,
+  equirect: ShaderProgram,
+  irradiance: ShaderProgram,
+  prefilter: ShaderProgram,
+  brdf: ShaderProgram,
+}
+impl Capture {
+  fn new() -> Result<Self, String> {
+    let fbo = Framebuffer::new().ok_or("Couldn't make a framebuffer")?;
+    let rbo = Renderbuffer::new().ok_or("Couldn't make a renderbuffer")?;
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    vao.bind();
+    let vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&CUBE_VERTICES),
+      GL_STATIC_DRAW,
+    );
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        3,
+        GL_FLOAT,
+        GL_FALSE,
+        size_of::<[f32; 3]>().try_into().unwrap(),
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+    }
+    VertexArray::clear_binding();
+    let with_sampling = |frag: &str| {
+      format!("#version 330 core\n{}{}", IMPORTANCE_SAMPLE_GLSL, frag)
+    };
+    Ok(Self {
+      fbo,
+      rbo,
+      vao,
+      vbo,
+      triangle: FullscreenTriangle::new()?,
+      equirect: ShaderProgram::from_vert_frag(CAPTURE_VERT, EQUIRECT_FRAG)?,
+      irradiance: ShaderProgram::from_vert_frag(CAPTURE_VERT, IRRADIANCE_FRAG)?,
+      prefilter: ShaderProgram::from_vert_frag(
+        CAPTURE_VERT,
+        &with_sampling(PREFILTER_FRAG),
+      )?,
+      brdf: ShaderProgram::from_vert_frag(
+        FULLSCREEN_VERT,
+        &with_sampling(BRDF_FRAG),
+      )?,
+    })
+  }
+
+  /// The view-projection for looking at each face, from the inside, in GL
+  /// face order.
+  fn face_matrices() -> [Mat4; 6] {
+    let proj =
+      projection::perspective_gl(90.0_f32.to_radians(), 1.0, 0.1, 10.0);
+    let look =
+      |dir: Vec3, up: Vec3| proj * Mat4::look_at(Vec3::zero(), dir, up);
+    [
+      look(Vec3::unit_x(), -Vec3::unit_y()),
+      look(-Vec3::unit_x(), -Vec3::unit_y()),
+      look(Vec3::unit_y(), Vec3::unit_z()),
+      look(-Vec3::unit_y(), -Vec3::unit_z()),
+      look(Vec3::unit_z(), -Vec3::unit_y()),
+      look(-Vec3::unit_z(), -Vec3::unit_y()),
+    ]
+  }
+
+  /// Draws the cube once into each face of mip `level` of `target`.
+  fn render_faces(
+    &self,
+    program: &ShaderProgram,
+    target: &TextureCube,
+    level: u32,
+  ) {
+    let size = (target.size() >> level).max(1);
+    self.fbo.bind(FramebufferTarget::Both);
+    self.rbo.bind();
+    framebuffer::renderbuffer_storage(TextureFormat::Depth24, size, size);
+    framebuffer::attach_renderbuffer(
+      FramebufferTarget::Both,
+      Attachment::Depth,
+      &self.rbo,
+    );
+    crate::viewport(0, 0, size, size);
+    self.vao.bind();
+    for (face, matrix) in CubeFace::ALL.iter().zip(Self::face_matrices().iter())
+    {
+      framebuffer::attach_cube_face(
+        FramebufferTarget::Both,
+        Attachment::Color(0),
+        &target.texture,
+        *face,
+        level,
+      );
+      set_mat4(program, "view_projection", matrix);
+      unsafe {
+        glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+        glDrawArrays(GL_TRIANGLES, 0, CUBE_VERTICES.len() as GLsizei);
+      }
+    }
+    VertexArray::clear_binding();
+  }
+
+  fn run(
+    &self,
+    equirect: &Texture,
+    settings: IblSettings,
+  ) -> Result<IblMaps, String> {
+    // equirect to cube map
+    let environment = TextureCube::empty(
+      TextureFormat::RGBA16F,
+      settings.environment_size,
+      true,
+    )?;
+    self.equirect.use_program();
+    set_i32(&self.equirect, "equirect", 0);
+    texture::active_texture(0);
+    equirect.bind(TextureTarget::Texture2D);
+    self.render_faces(&self.equirect, &environment, 0);
+    environment.bind(0);
+    texture::generate_mipmap(TextureTarget::CubeMap);
+
+    // diffuse irradiance
+    let irradiance = TextureCube::empty(
+      TextureFormat::RGBA16F,
+      settings.irradiance_size,
+      false,
+    )?;
+    self.irradiance.use_program();
+    set_i32(&self.irradiance, "environment", 0);
+    environment.bind(0);
+    self.render_faces(&self.irradiance, &irradiance, 0);
+
+    // prefiltered specular, one roughness per mip
+    let prefiltered = TextureCube::empty(
+      TextureFormat::RGBA16F,
+      settings.prefilter_size,
+      true,
+    )?;
+    self.prefilter.use_program();
+    set_i32(&self.prefilter, "environment", 0);
+    set_f32(
+      &self.prefilter,
+      "environment_size",
+      settings.environment_size as f32,
+    );
+    environment.bind(0);
+    // a 128 pixel cube only has 8 levels, down to 1x1
+    let chain = 32 - settings.prefilter_size.max(1).leading_zeros();
+    let mips = settings.prefilter_mips.clamp(1, chain);
+    for level in 0..mips {
+      let roughness =
+        if mips == 1 { 0.0 } else { level as f32 / (mips - 1) as f32 };
+      set_f32(&self.prefilter, "roughness", roughness);
+      self.render_faces(&self.prefilter, &prefiltered, level);
+    }
+    prefiltered.bind(0);
+    unsafe {
+      glTexParameteri(
+        GL_TEXTURE_CUBE_MAP,
+        GL_TEXTURE_MAX_LEVEL,
+        (mips - 1).try_into().unwrap(),
+      );
+    }
+
+    // BRDF lookup table
+    let brdf_lut = Texture::new().ok_or("Couldn't allocate a texture")?;
+    brdf_lut.bind(TextureTarget::Texture2D);
+    let lut_size = settings.brdf_lut_size;
+    texture::tex_image_2d(TextureFormat::RG16F, 0, lut_size, lut_size, None);
+    texture::set_filters(
+      TextureTarget::Texture2D,
+      MinFilter::Linear,
+      MagFilter::Linear,
+    );
+    texture::set_wrap(TextureTarget::Texture2D, TextureWrap::ClampToEdge);
+    self.fbo.bind(FramebufferTarget::Both);
+    framebuffer::attach_texture_2d(
+      FramebufferTarget::Both,
+      Attachment::Color(0),
+      &brdf_lut,
+      0,
+    );
+    self.rbo.bind();
+    framebuffer::renderbuffer_storage(
+      TextureFormat::Depth24,
+      lut_size,
+      lut_size,
+    );
+    crate::viewport(0, 0, lut_size, lut_size);
+    self.brdf.use_program();
+    unsafe { glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT) };
+    self.triangle.draw();
+    let status = framebuffer::check_status(FramebufferTarget::Both);
+    Renderbuffer::clear_binding();
+    Texture::clear_binding(TextureTarget::Texture2D);
+    Texture::clear_binding(TextureTarget::CubeMap);
+
+    let out = IblMaps {
+      environment,
+      irradiance,
+      prefiltered,
+      brdf_lut,
+      prefilter_mips: mips,
+    };
+    match status {
+      Ok(()) => Ok(out),
+      Err(e) => {
+        out.delete();
+        Err(e)
+      }
+    }
+  }
+
+  fn delete(self) {
+    self.fbo.delete();
+    self.rbo.delete();
+    self.vao.delete();
+    self.vbo.delete();
+    self.triangle.delete();
+    self.equirect.delete();
+    self.irradiance.delete();
+    self.prefilter.delete();
+    self.brdf.delete();
+  }
+}
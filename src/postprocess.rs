@@ -0,0 +1,638 @@
+//! Post-processing: effects that run over the whole image after the scene is
+//! drawn.
+//!
+//! The scene gets drawn into an HDR (16-bit float) render target instead of
+//! the window. Then each [`PostEffect`] in the list runs as a fullscreen
+//! pass, reading the previous result and writing to one of two "ping-pong"
+//! targets. The last effect writes to the window.
+//!
+//! The order of the effects matters. Things that work on light values (like
+//! [`Bloom`](PostEffect::Bloom)) go before
+//! [`ToneMap`](PostEffect::ToneMap), which squishes HDR values into
+//! `0.0 ..= 1.0`. [`Gamma`](PostEffect::Gamma) goes after that, and
+//! [`Fxaa`](PostEffect::Fxaa) works best on the final gamma-corrected image.
+//!
+//! ```ignore
+//! let mut post = PostProcess::new(w, h)?;
+//! post.effects = vec![
+//!   PostEffect::Bloom { threshold: 1.0, intensity: 0.5, blur_passes: 4 },
+//!   PostEffect::ToneMap { exposure: 1.0, operator: ToneMapOperator::Aces },
+//!   PostEffect::Gamma { gamma: 2.2 },
+//!   PostEffect::Fxaa { span_max: 8.0 },
+//! ];
+//! // each frame
+//! post.begin();
+//! draw_scene();
+//! post.finish(&mut state_cache);
+//! ```
+
+use crate::{
+  framebuffer::{
+    Framebuffer, FramebufferTarget, RenderTarget, RenderTargetDesc,
+  },
+  resize::Resize,
+  state::{RenderState, StateCache},
+  texture::{self, Texture, TextureFormat, TextureTarget},
+  ShaderProgram, VertexArray,
+};
+use ogl33::*;
+
+/// A vertex shader for a fullscreen triangle, see [`FullscreenTriangle`].
+///
+/// It outputs `uv`, the texture coordinate of the screen (`0.0 ..= 1.0` over
+/// the visible part).
+pub const FULLSCREEN_VERT: &str = r#"#version 330 core
+  out vec2 uv;
+
+  void main() {
+    vec2 pos = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    uv = pos;
+    gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+  }
+"#;
+
+/// Draws one big triangle that covers the whole viewport.
+///
+/// A single triangle instead of a quad avoids the diagonal seam down the
+/// middle, where pixels get shaded twice. The positions are made up in
+/// [`FULLSCREEN_VERT`] from `gl_VertexID`, so there's no vertex buffer, but
+/// core GL still needs _some_ VAO bound to draw.
+pub struct FullscreenTriangle {
+  vao: VertexArray,
+}
+impl FullscreenTriangle {
+  /// Makes the (empty) VAO.
+  pub fn new() -> Result<Self, String> {
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    Ok(Self { vao })
+  }
+
+  /// Draws the triangle with whatever program is in use.
+  pub fn draw(&self) {
+    self.vao.bind();
+    unsafe { glDrawArrays(GL_TRIANGLES, 0, 3) };
+    VertexArray::clear_binding();
+  }
+
+  /// Deletes the VAO.
+  pub fn delete(self) {
+    self.vao.delete()
+  }
+}
+
+const COPY_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  out vec4 final_color;
+
+  void main() {
+    final_color = texture(src, uv);
+  }
+"#;
+
+const TONE_MAP_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform float exposure;
+  uniform int tone_operator;
+  out vec4 final_color;
+
+  vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+  }
+
+  void main() {
+    vec4 hdr = texture(src, uv);
+    vec3 c = hdr.rgb * exposure;
+    if (tone_operator == 0) {
+      c = c / (c + vec3(1.0));
+    } else {
+      c = aces(c);
+    }
+    final_color = vec4(c, hdr.a);
+  }
+"#;
+
+const GAMMA_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform float gamma;
+  out vec4 final_color;
+
+  void main() {
+    vec4 c = texture(src, uv);
+    final_color = vec4(pow(c.rgb, vec3(1.0 / gamma)), c.a);
+  }
+"#;
+
+const FXAA_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform vec2 texel_size;
+  uniform float span_max;
+  out vec4 final_color;
+
+  const float REDUCE_MIN = 1.0 / 128.0;
+  const float REDUCE_MUL = 1.0 / 8.0;
+  const vec3 LUMA = vec3(0.299, 0.587, 0.114);
+
+  void main() {
+    float nw = dot(texture(src, uv + vec2(-1.0, -1.0) * texel_size).rgb, LUMA);
+    float ne = dot(texture(src, uv + vec2(1.0, -1.0) * texel_size).rgb, LUMA);
+    float sw = dot(texture(src, uv + vec2(-1.0, 1.0) * texel_size).rgb, LUMA);
+    float se = dot(texture(src, uv + vec2(1.0, 1.0) * texel_size).rgb, LUMA);
+    vec4 center = texture(src, uv);
+    float m = dot(center.rgb, LUMA);
+    float luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    float luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    vec2 dir = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    float reduce = max((nw + ne + sw + se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * rcp_dir_min, -span_max, span_max) * texel_size;
+
+    vec3 a = 0.5 * (
+      texture(src, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+      texture(src, uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 b = a * 0.5 + 0.25 * (
+      texture(src, uv + dir * -0.5).rgb +
+      texture(src, uv + dir * 0.5).rgb);
+    float luma_b = dot(b, LUMA);
+    if (luma_b < luma_min || luma_b > luma_max) {
+      final_color = vec4(a, center.a);
+    } else {
+      final_color = vec4(b, center.a);
+    }
+  }
+"#;
+
+const BRIGHT_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform float threshold;
+  out vec4 final_color;
+
+  void main() {
+    vec3 c = texture(src, uv).rgb;
+    float brightness = max(c.r, max(c.g, c.b));
+    float keep = max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+    final_color = vec4(c * keep, 1.0);
+  }
+"#;
+
+const BLUR_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform vec2 direction;
+  out vec4 final_color;
+
+  const float WEIGHTS[5] = float[](
+    0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216
+  );
+
+  void main() {
+    vec2 texel_step = direction / vec2(textureSize(src, 0));
+    vec3 sum = texture(src, uv).rgb * WEIGHTS[0];
+    for (int i = 1; i < 5; ++i) {
+      sum += texture(src, uv + texel_step * float(i)).rgb * WEIGHTS[i];
+      sum += texture(src, uv - texel_step * float(i)).rgb * WEIGHTS[i];
+    }
+    final_color = vec4(sum, 1.0);
+  }
+"#;
+
+const BLOOM_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform sampler2D bloom;
+  uniform float intensity;
+  out vec4 final_color;
+
+  void main() {
+    vec4 c = texture(src, uv);
+    final_color = vec4(c.rgb + texture(bloom, uv).rgb * intensity, c.a);
+  }
+"#;
+
+const VIGNETTE_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform float strength;
+  uniform float radius;
+  uniform float softness;
+  out vec4 final_color;
+
+  void main() {
+    vec4 c = texture(src, uv);
+    float d = length(uv - vec2(0.5)) * 1.41421356;
+    float v = 1.0 - smoothstep(radius - softness, radius, d);
+    final_color = vec4(c.rgb * mix(1.0, v, strength), c.a);
+  }
+"#;
+
+const GRAYSCALE_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform float amount;
+  out vec4 final_color;
+
+  void main() {
+    vec4 c = texture(src, uv);
+    float gray = dot(c.rgb, vec3(0.2126, 0.7152, 0.0722));
+    final_color = vec4(mix(c.rgb, vec3(gray), amount), c.a);
+  }
+"#;
+
+const KERNEL_FRAG: &str = r#"#version 330 core
+  in vec2 uv;
+  uniform sampler2D src;
+  uniform vec2 texel_size;
+  uniform float kernel[9];
+  out vec4 final_color;
+
+  void main() {
+    vec3 sum = vec3(0.0);
+    for (int y = 0; y < 3; ++y) {
+      for (int x = 0; x < 3; ++x) {
+        vec2 offset = vec2(float(x - 1), float(1 - y)) * texel_size;
+        sum += texture(src, uv + offset).rgb * kernel[y * 3 + x];
+      }
+    }
+    final_color = vec4(sum, texture(src, uv).a);
+  }
+"#;
+
+/// The curve that [`PostEffect::ToneMap`] uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+  /// `c / (c + 1)`. Simple, but it washes out bright colors.
+  Reinhard,
+  /// A fit of the ACES filmic curve. More contrast, nicer highlights.
+  Aces,
+}
+
+/// One fullscreen pass of a [`PostProcess`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+  /// Maps HDR color down to `0.0 ..= 1.0`.
+  ToneMap {
+    /// Color is multiplied by this first, like a camera's exposure.
+    exposure: f32,
+    /// The curve to use.
+    operator: ToneMapOperator,
+  },
+  /// Converts linear color for display, raising it to `1.0 / gamma`.
+  ///
+  /// Skip this if the window's framebuffer is sRGB.
+  Gamma {
+    /// Usually `2.2`.
+    gamma: f32,
+  },
+  /// Fast approximate anti-aliasing, which blurs along detected edges.
+  Fxaa {
+    /// How far to search along an edge, in pixels. Usually `8.0`.
+    span_max: f32,
+  },
+  /// Makes bright areas glow.
+  ///
+  /// Parts brighter than the threshold are copied to a half size target,
+  /// blurred, and added back on top.
+  Bloom {
+    /// How bright a pixel must be to glow. With HDR, `1.0` is a good start.
+    threshold: f32,
+    /// How strongly the glow is added back.
+    intensity: f32,
+    /// Rounds of blurring (one horizontal plus one vertical each). More is
+    /// wider.
+    blur_passes: u32,
+  },
+  /// Darkens the corners.
+  Vignette {
+    /// `0.0` for no effect up to `1.0` for fully black corners.
+    strength: f32,
+    /// Where the darkening is complete, as a fraction of the way to the
+    /// corner.
+    radius: f32,
+    /// How wide the fade is, in the same units as `radius`.
+    softness: f32,
+  },
+  /// Removes color.
+  Grayscale {
+    /// `0.0` for no effect up to `1.0` for fully gray.
+    amount: f32,
+  },
+  /// A 3x3 convolution, with the kernel in rows from top to bottom.
+  Kernel {
+    /// The weights.
+    kernel: [f32; 9],
+  },
+}
+impl PostEffect {
+  /// A sharpening kernel.
+  pub const fn sharpen() -> Self {
+    PostEffect::Kernel {
+      kernel: [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0],
+    }
+  }
+
+  /// A small blurring kernel.
+  pub const fn blur() -> Self {
+    let a = 1.0 / 16.0;
+    let b = 2.0 / 16.0;
+    let c = 4.0 / 16.0;
+    PostEffect::Kernel { kernel: [a, b, a, b, c, b, a, b, a] }
+  }
+
+  /// An edge detection kernel.
+  pub const fn edge_detect() -> Self {
+    PostEffect::Kernel {
+      kernel: [1.0, 1.0, 1.0, 1.0, -8.0, 1.0, 1.0, 1.0, 1.0],
+    }
+  }
+}
+
+struct Programs {
+  copy: ShaderProgram,
+  tone_map: ShaderProgram,
+  gamma: ShaderProgram,
+  fxaa: ShaderProgram,
+  bright: ShaderProgram,
+  blur: ShaderProgram,
+  bloom: ShaderProgram,
+  vignette: ShaderProgram,
+  grayscale: ShaderProgram,
+  kernel: ShaderProgram,
+}
+impl Programs {
+  fn new() -> Result<Self, String> {
+    let make =
+      |frag: &str| ShaderProgram::from_vert_frag(FULLSCREEN_VERT, frag);
+    Ok(Self {
+      copy: make(COPY_FRAG)?,
+      tone_map: make(TONE_MAP_FRAG)?,
+      gamma: make(GAMMA_FRAG)?,
+      fxaa: make(FXAA_FRAG)?,
+      bright: make(BRIGHT_FRAG)?,
+      blur: make(BLUR_FRAG)?,
+      bloom: make(BLOOM_FRAG)?,
+      vignette: make(VIGNETTE_FRAG)?,
+      grayscale: make(GRAYSCALE_FRAG)?,
+      kernel: make(KERNEL_FRAG)?,
+    })
+  }
+
+  fn delete(self) {
+    self.copy.delete();
+    self.tone_map.delete();
+    self.gamma.delete();
+    self.fxaa.delete();
+    self.bright.delete();
+    self.blur.delete();
+    self.bloom.delete();
+    self.vignette.delete();
+    self.grayscale.delete();
+    self.kernel.delete();
+  }
+}
+
+fn set_f32(program: &ShaderProgram, name: &str, v: f32) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform1f(loc, v) }
+  }
+}
+
+fn set_i32(program: &ShaderProgram, name: &str, v: i32) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform1i(loc, v) }
+  }
+}
+
+fn set_vec2(program: &ShaderProgram, name: &str, x: f32, y: f32) {
+  if let Some(loc) = program.uniform_location(name) {
+    unsafe { glUniform2f(loc, x, y) }
+  }
+}
+
+fn bind_input(tex: &Texture, unit: u32) {
+  texture::active_texture(unit);
+  tex.bind(TextureTarget::Texture2D);
+}
+
+fn hdr_target(width: u32, height: u32) -> Result<RenderTarget, String> {
+  let desc = RenderTargetDesc {
+    color: vec![TextureFormat::RGBA16F],
+    depth: None,
+    depth_as_texture: false,
+    samples: 0,
+  };
+  RenderTarget::new(width, height, desc)
+}
+
+/// The scene target, the effect list, and everything needed to run it.
+pub struct PostProcess {
+  /// The effects, run in order.
+  pub effects: Vec<PostEffect>,
+  scene: RenderTarget,
+  ping_pong: [RenderTarget; 2],
+  bloom: [RenderTarget; 2],
+  programs: Programs,
+  triangle: FullscreenTriangle,
+  width: u32,
+  height: u32,
+}
+impl PostProcess {
+  /// Makes the targets and compiles the effect shaders.
+  ///
+  /// The scene target is `RGBA16F` with a 24-bit depth buffer.
+  pub fn new(width: u32, height: u32) -> Result<Self, String> {
+    let scene_desc = RenderTargetDesc {
+      color: vec![TextureFormat::RGBA16F],
+      depth: Some(TextureFormat::Depth24),
+      depth_as_texture: false,
+      samples: 0,
+    };
+    let (half_w, half_h) = ((width / 2).max(1), (height / 2).max(1));
+    Ok(Self {
+      effects: Vec::new(),
+      scene: RenderTarget::new(width, height, scene_desc)?,
+      ping_pong: [hdr_target(width, height)?, hdr_target(width, height)?],
+      bloom: [hdr_target(half_w, half_h)?, hdr_target(half_w, half_h)?],
+      programs: Programs::new()?,
+      triangle: FullscreenTriangle::new()?,
+      width,
+      height,
+    })
+  }
+
+  /// The target that the scene is drawn into.
+  pub fn scene_target(&self) -> &RenderTarget {
+    &self.scene
+  }
+
+  /// Binds the scene target, so that the scene draws into it.
+  ///
+  /// You still need to clear it.
+  pub fn begin(&self) {
+    self.scene.bind();
+  }
+
+  /// Runs all the effects, with the last one drawing to the window.
+  ///
+  /// With no effects the scene is just copied over. This leaves the window's
+  /// framebuffer bound, with the viewport covering it.
+  pub fn finish(&self, cache: &mut StateCache) {
+    cache.apply(&RenderState::default());
+    let mut input = &self.scene.color[0];
+    if self.effects.is_empty() {
+      self.bind_window();
+      self.programs.copy.use_program();
+      bind_input(input, 0);
+      set_i32(&self.programs.copy, "src", 0);
+      self.triangle.draw();
+      return;
+    }
+    let last = self.effects.len() - 1;
+    for (i, effect) in self.effects.iter().enumerate() {
+      let output = if i == last { None } else { Some(&self.ping_pong[i % 2]) };
+      self.run(effect, input, output);
+      if let Some(target) = output {
+        input = &target.color[0];
+      }
+    }
+  }
+
+  /// Deletes all of the targets and shaders.
+  pub fn delete(self) {
+    self.scene.delete();
+    let [a, b] = self.ping_pong;
+    a.delete();
+    b.delete();
+    let [a, b] = self.bloom;
+    a.delete();
+    b.delete();
+    self.programs.delete();
+    self.triangle.delete();
+  }
+
+  fn bind_window(&self) {
+    Framebuffer::clear_binding(FramebufferTarget::Both);
+    crate::viewport(0, 0, self.width, self.height);
+  }
+
+  fn bind_output(&self, output: Option<&RenderTarget>) {
+    match output {
+      Some(target) => target.bind(),
+      None => self.bind_window(),
+    }
+  }
+
+  fn run(
+    &self,
+    effect: &PostEffect,
+    input: &Texture,
+    output: Option<&RenderTarget>,
+  ) {
+    let texel = (1.0 / self.width as f32, 1.0 / self.height as f32);
+    let p = &self.programs;
+    let program = match *effect {
+      PostEffect::ToneMap { exposure, operator } => {
+        p.tone_map.use_program();
+        set_f32(&p.tone_map, "exposure", exposure);
+        let op = match operator {
+          ToneMapOperator::Reinhard => 0,
+          ToneMapOperator::Aces => 1,
+        };
+        set_i32(&p.tone_map, "tone_operator", op);
+        &p.tone_map
+      }
+      PostEffect::Gamma { gamma } => {
+        p.gamma.use_program();
+        set_f32(&p.gamma, "gamma", gamma);
+        &p.gamma
+      }
+      PostEffect::Fxaa { span_max } => {
+        p.fxaa.use_program();
+        set_f32(&p.fxaa, "span_max", span_max);
+        set_vec2(&p.fxaa, "texel_size", texel.0, texel.1);
+        &p.fxaa
+      }
+      PostEffect::Bloom { threshold, intensity, blur_passes } => {
+        self.blur_bright_parts(input, threshold, blur_passes);
+        p.bloom.use_program();
+        set_f32(&p.bloom, "intensity", intensity);
+        set_i32(&p.bloom, "bloom", 1);
+        bind_input(&self.bloom[0].color[0], 1);
+        &p.bloom
+      }
+      PostEffect::Vignette { strength, radius, softness } => {
+        p.vignette.use_program();
+        set_f32(&p.vignette, "strength", strength);
+        set_f32(&p.vignette, "radius", radius);
+        set_f32(&p.vignette, "softness", softness);
+        &p.vignette
+      }
+      PostEffect::Grayscale { amount } => {
+        p.grayscale.use_program();
+        set_f32(&p.grayscale, "amount", amount);
+        &p.grayscale
+      }
+      PostEffect::Kernel { kernel } => {
+        p.kernel.use_program();
+        set_vec2(&p.kernel, "texel_size", texel.0, texel.1);
+        if let Some(loc) = p.kernel.uniform_location("kernel") {
+          unsafe { glUniform1fv(loc, 9, kernel.as_ptr()) };
+        }
+        &p.kernel
+      }
+    };
+    set_i32(program, "src", 0);
+    bind_input(input, 0);
+    self.bind_output(output);
+    self.triangle.draw();
+  }
+
+  /// Leaves the blurred bright parts of `input` in `self.bloom[0]`.
+  fn blur_bright_parts(&self, input: &Texture, threshold: f32, passes: u32) {
+    let p = &self.programs;
+    self.bloom[0].bind();
+    p.bright.use_program();
+    set_i32(&p.bright, "src", 0);
+    set_f32(&p.bright, "threshold", threshold);
+    bind_input(input, 0);
+    self.triangle.draw();
+
+    p.blur.use_program();
+    set_i32(&p.blur, "src", 0);
+    for _ in 0..passes {
+      self.bloom[1].bind();
+      set_vec2(&p.blur, "direction", 1.0, 0.0);
+      bind_input(&self.bloom[0].color[0], 0);
+      self.triangle.draw();
+
+      self.bloom[0].bind();
+      set_vec2(&p.blur, "direction", 0.0, 1.0);
+      bind_input(&self.bloom[1].color[0], 0);
+      self.triangle.draw();
+    }
+  }
+}
+impl Resize for PostProcess {
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    self.width = width.max(1);
+    self.height = height.max(1);
+    let (half_w, half_h) = ((width / 2).max(1), (height / 2).max(1));
+    self.scene.resize(width, height)?;
+    for target in self.ping_pong.iter_mut() {
+      target.resize(width, height)?;
+    }
+    for target in self.bloom.iter_mut() {
+      target.resize(half_w, half_h)?;
+    }
+    Ok(())
+  }
+}
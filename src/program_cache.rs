@@ -0,0 +1,283 @@
+//! Saving linked programs to disk, so they don't need compiling next time.
+//!
+//! Compiling and linking a big set of shaders can take a noticeable moment
+//! at startup. With `GL_ARB_get_program_binary` (core in GL 4.1) the driver
+//! can hand over a linked program as an opaque blob, and take it back later.
+//! [`ProgramCache`] keeps those blobs in a directory, keyed by a hash of the
+//! shader sources _and_ the driver's vendor, renderer, and version strings,
+//! since a blob only works on the exact driver that made it.
+//!
+//! A blob can still be rejected (eg: after a driver update that kept the
+//! same version string). Then the program is just compiled from source like
+//! normal and the cache file gets replaced.
+//!
+//! The binary functions aren't part of GL 3.3, so `ogl33` doesn't load them.
+//! Call [`load_program_binary_with`] right after `load_gl_with`, using the
+//! same loader.
+
+use crate::{ShaderProgram, ShaderType};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use ogl33::*;
+use std::{
+  ffi::CStr,
+  path::{Path, PathBuf},
+};
+
+const GL_PROGRAM_BINARY_RETRIEVABLE_HINT: GLenum = 0x8257;
+const GL_PROGRAM_BINARY_LENGTH: GLenum = 0x8741;
+const GL_NUM_PROGRAM_BINARY_FORMATS: GLenum = 0x87FE;
+
+type GetProgramBinaryFn = unsafe extern "system" fn(
+  program: GLuint,
+  buf_size: GLsizei,
+  length: *mut GLsizei,
+  binary_format: *mut GLenum,
+  binary: *mut c_void,
+);
+type ProgramBinaryFn = unsafe extern "system" fn(
+  program: GLuint,
+  binary_format: GLenum,
+  binary: *const c_void,
+  length: GLsizei,
+);
+type ProgramParameteriFn =
+  unsafe extern "system" fn(program: GLuint, pname: GLenum, value: GLint);
+
+static GET_PROGRAM_BINARY: AtomicPtr<c_void> =
+  AtomicPtr::new(core::ptr::null_mut());
+static PROGRAM_BINARY: AtomicPtr<c_void> =
+  AtomicPtr::new(core::ptr::null_mut());
+static PROGRAM_PARAMETERI: AtomicPtr<c_void> =
+  AtomicPtr::new(core::ptr::null_mut());
+
+/// Loads the program binary functions, using the same kind of loader as
+/// `ogl33::load_gl_with`.
+///
+/// ## Safety
+/// * The loader must give the right function (or null) for each name.
+pub unsafe fn load_program_binary_with<F>(mut load_fn: F)
+where
+  F: FnMut(*const c_char) -> *const c_void,
+{
+  let mut load = |name: &[u8]| load_fn(name.as_ptr().cast()) as *mut c_void;
+  GET_PROGRAM_BINARY.store(load(b"glGetProgramBinary\0"), Ordering::Relaxed);
+  PROGRAM_BINARY.store(load(b"glProgramBinary\0"), Ordering::Relaxed);
+  PROGRAM_PARAMETERI.store(load(b"glProgramParameteri\0"), Ordering::Relaxed);
+}
+
+fn get_program_binary() -> Option<GetProgramBinaryFn> {
+  let p = GET_PROGRAM_BINARY.load(Ordering::Relaxed);
+  // Safety: only ever set from the loader, with this function's address.
+  (!p.is_null()).then(|| unsafe { core::mem::transmute(p) })
+}
+
+fn program_binary() -> Option<ProgramBinaryFn> {
+  let p = PROGRAM_BINARY.load(Ordering::Relaxed);
+  (!p.is_null()).then(|| unsafe { core::mem::transmute(p) })
+}
+
+fn program_parameteri() -> Option<ProgramParameteriFn> {
+  let p = PROGRAM_PARAMETERI.load(Ordering::Relaxed);
+  (!p.is_null()).then(|| unsafe { core::mem::transmute(p) })
+}
+
+/// 64-bit FNV-1a, which is plenty for telling shader sources apart.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+  bytes
+    .iter()
+    .fold(hash, |h, &b| (h ^ u64::from(b)).wrapping_mul(0x100_0000_01B3))
+}
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+
+fn gl_string(name: GLenum) -> String {
+  let ptr = unsafe { glGetString(name) };
+  if ptr.is_null() {
+    String::new()
+  } else {
+    unsafe { CStr::from_ptr(ptr.cast()) }.to_string_lossy().into_owned()
+  }
+}
+
+/// Marks the start of each cache file, so that junk files are never handed
+/// to the driver.
+const FILE_MAGIC: &[u8; 8] = b"LOGLPB01";
+
+/// A directory of linked program binaries.
+#[derive(Debug, Clone)]
+pub struct ProgramCache {
+  dir: PathBuf,
+  driver_hash: u64,
+  supported: bool,
+}
+impl ProgramCache {
+  /// Uses the given directory for the cache. It's made when the first
+  /// program is saved.
+  ///
+  /// This checks if the driver can give out program binaries at all (GL 4.1
+  /// or `GL_ARB_get_program_binary`, and at least one binary format). If
+  /// not, the cache still works, it just always compiles from source.
+  pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+    let mut driver_hash = FNV_OFFSET;
+    for name in [GL_VENDOR, GL_RENDERER, GL_VERSION].iter() {
+      driver_hash = fnv1a(driver_hash, gl_string(*name).as_bytes());
+      driver_hash = fnv1a(driver_hash, &[0]);
+    }
+    // the format count is an invalid enum without program binary support
+    let supported = context_has_program_binary()
+      && get_program_binary().is_some()
+      && program_binary().is_some()
+      && {
+        let mut formats = 0;
+        unsafe { glGetIntegerv(GL_NUM_PROGRAM_BINARY_FORMATS, &mut formats) };
+        formats > 0
+      };
+    Self { dir: dir.as_ref().to_path_buf(), driver_hash, supported }
+  }
+
+  /// If program binaries are being saved and loaded.
+  pub fn is_supported(&self) -> bool {
+    self.supported
+  }
+
+  /// The file that a program with these sources is cached in.
+  pub fn path_for(&self, vert: &str, frag: &str) -> PathBuf {
+    let mut hash = fnv1a(self.driver_hash, vert.as_bytes());
+    hash = fnv1a(hash, &[0]);
+    hash = fnv1a(hash, frag.as_bytes());
+    self.dir.join(format!("{:016x}.bin", hash))
+  }
+
+  /// Like [`ShaderProgram::from_vert_frag`], but loads the linked program
+  /// from the cache when it can, and saves it to the cache when it can't.
+  ///
+  /// Problems with the cache itself (a missing directory, a rejected binary,
+  /// a failed write) are never errors, only compile and link errors are.
+  pub fn from_vert_frag(
+    &self,
+    vert: &str,
+    frag: &str,
+  ) -> Result<ShaderProgram, String> {
+    if !self.supported {
+      return ShaderProgram::from_vert_frag(vert, frag);
+    }
+    let path = self.path_for(vert, frag);
+    if let Some(program) = load_binary(&path) {
+      return Ok(program);
+    }
+    let program = compile_retrievable(vert, frag)?;
+    save_binary(&program, &path);
+    Ok(program)
+  }
+
+  /// Deletes every cached binary.
+  pub fn clear(&self) -> std::io::Result<()> {
+    if !self.dir.exists() {
+      return Ok(());
+    }
+    for entry in std::fs::read_dir(&self.dir)? {
+      let path = entry?.path();
+      if path.extension().map(|e| e == "bin").unwrap_or(false) {
+        std::fs::remove_file(path)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// If the context is GL 4.1 or newer, or has the program binary extension.
+fn context_has_program_binary() -> bool {
+  let (mut major, mut minor) = (0, 0);
+  unsafe {
+    glGetIntegerv(GL_MAJOR_VERSION, &mut major);
+    glGetIntegerv(GL_MINOR_VERSION, &mut minor);
+  }
+  (major, minor) >= (4, 1) || crate::has_extension("GL_ARB_get_program_binary")
+}
+
+/// Tries to make a program from a cache file, removing the file if the
+/// driver rejects it.
+fn load_binary(path: &Path) -> Option<ShaderProgram> {
+  let program_binary = program_binary()?;
+  let bytes = std::fs::read(path).ok()?;
+  let rest = bytes.strip_prefix(&FILE_MAGIC[..])?;
+  if rest.len() < 4 {
+    return None;
+  }
+  let (format, blob) = rest.split_at(4);
+  let format = u32::from_le_bytes([format[0], format[1], format[2], format[3]]);
+  let program = ShaderProgram::new()?;
+  unsafe {
+    program_binary(
+      program.0,
+      format,
+      blob.as_ptr().cast(),
+      blob.len() as GLsizei,
+    )
+  };
+  if program.link_success() {
+    Some(program)
+  } else {
+    program.delete();
+    let _ = std::fs::remove_file(path);
+    None
+  }
+}
+
+/// Compiles and links from source, asking the driver to keep the binary
+/// around for [`save_binary`].
+fn compile_retrievable(
+  vert: &str,
+  frag: &str,
+) -> Result<ShaderProgram, String> {
+  let hint = |p: &ShaderProgram| {
+    if let Some(program_parameteri) = program_parameteri() {
+      let hint = GL_TRUE as GLint;
+      unsafe {
+        program_parameteri(p.0, GL_PROGRAM_BINARY_RETRIEVABLE_HINT, hint)
+      };
+    }
+    Ok(())
+  };
+  ShaderProgram::from_shaders(
+    &[(ShaderType::Vertex, vert), (ShaderType::Fragment, frag)],
+    Some(&hint),
+  )
+}
+
+/// Writes a linked program's binary to a cache file, if the driver gives
+/// one out.
+fn save_binary(program: &ShaderProgram, path: &Path) {
+  let get_program_binary = match get_program_binary() {
+    Some(f) => f,
+    None => return,
+  };
+  let mut len = 0;
+  unsafe { glGetProgramiv(program.0, GL_PROGRAM_BINARY_LENGTH, &mut len) };
+  if len <= 0 {
+    return;
+  }
+  let mut blob = vec![0_u8; len as usize];
+  let mut written = 0;
+  let mut format = 0;
+  unsafe {
+    get_program_binary(
+      program.0,
+      len,
+      &mut written,
+      &mut format,
+      blob.as_mut_ptr().cast(),
+    )
+  };
+  if written <= 0 {
+    return;
+  }
+  blob.truncate(written as usize);
+  let mut bytes = Vec::with_capacity(FILE_MAGIC.len() + 4 + blob.len());
+  bytes.extend_from_slice(FILE_MAGIC);
+  bytes.extend_from_slice(&format.to_le_bytes());
+  bytes.extend_from_slice(&blob);
+  if let Some(dir) = path.parent() {
+    let _ = std::fs::create_dir_all(dir);
+  }
+  let _ = std::fs::write(path, bytes);
+}
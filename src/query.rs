@@ -0,0 +1,450 @@
+//! Wrappers for [Query](https://www.khronos.org/opengl/wiki/Query_Object)
+//! objects: GPU timing, occlusion, and primitive counts.
+//!
+//! A query asks the GPU to measure something while it works through the
+//! commands between [`begin`](Query::begin) and [`end`](Query::end). The GPU
+//! runs a frame or two behind the CPU, so asking for the answer right after
+//! `end` makes the CPU sit and wait. Instead, use a [`QueryRing`] and take
+//! whatever answers are ready, or a [`GpuProfiler`] for timing whole passes.
+//!
+//! An occlusion query can also decide if later draws happen at all, without
+//! reading it back, see [`Query::begin_conditional_render`].
+
+use ogl33::*;
+
+/// The things a query can measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryKind {
+  /// How long the commands took, in nanoseconds.
+  TimeElapsed = GL_TIME_ELAPSED as isize,
+  /// The GPU's clock, in nanoseconds, when all earlier commands finished.
+  /// Use [`Query::timestamp`] for this one, not `begin` and `end`.
+  Timestamp = GL_TIMESTAMP as isize,
+  /// How many samples passed the depth and stencil tests.
+  SamplesPassed = GL_SAMPLES_PASSED as isize,
+  /// If any samples passed the depth and stencil tests (`0` or `1`). This can
+  /// be faster than counting them.
+  AnySamplesPassed = GL_ANY_SAMPLES_PASSED as isize,
+  /// How many primitives came out of the vertex (or geometry) stage.
+  PrimitivesGenerated = GL_PRIMITIVES_GENERATED as isize,
+  /// How many primitives were written by transform feedback.
+  FeedbackPrimitives = GL_TRANSFORM_FEEDBACK_PRIMITIVES_WRITTEN as isize,
+}
+
+/// How conditional rendering handles a query that isn't done yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionalMode {
+  /// Wait for the query result.
+  Wait = GL_QUERY_WAIT as isize,
+  /// Don't wait, just draw if the result isn't ready.
+  NoWait = GL_QUERY_NO_WAIT as isize,
+  /// Like `Wait`, but the GPU may decide per screen region.
+  ByRegionWait = GL_QUERY_BY_REGION_WAIT as isize,
+  /// Like `NoWait`, but the GPU may decide per screen region.
+  ByRegionNoWait = GL_QUERY_BY_REGION_NO_WAIT as isize,
+}
+
+/// The number of bits in a query's counter. Zero means that kind of query
+/// isn't supported at all.
+pub fn counter_bits(kind: QueryKind) -> u32 {
+  let mut bits = 0;
+  unsafe { glGetQueryiv(kind as GLenum, GL_QUERY_COUNTER_BITS, &mut bits) };
+  bits.max(0) as u32
+}
+
+/// The GPU's clock right now, in nanoseconds, without waiting for any
+/// commands to finish.
+///
+/// Comparing this with a [`Query::timestamp`] result shows how far behind the
+/// CPU the GPU is running.
+pub fn gpu_time_now() -> u64 {
+  let mut time = 0;
+  unsafe { glGetInteger64v(GL_TIMESTAMP, &mut time) };
+  time.max(0) as u64
+}
+
+/// Basic wrapper for a Query Object.
+pub struct Query(pub GLuint);
+impl Query {
+  /// Makes a new query.
+  pub fn new() -> Option<Self> {
+    let mut query = 0;
+    unsafe { glGenQueries(1, &mut query) };
+    if query != 0 {
+      Some(Self(query))
+    } else {
+      None
+    }
+  }
+
+  /// Starts measuring.
+  ///
+  /// Only one query of each kind can be going on at once.
+  pub fn begin(&self, kind: QueryKind) {
+    unsafe { glBeginQuery(kind as GLenum, self.0) }
+  }
+
+  /// Stops whatever query of this kind is going on.
+  pub fn end(kind: QueryKind) {
+    unsafe { glEndQuery(kind as GLenum) }
+  }
+
+  /// Records the GPU's clock once all earlier commands are done.
+  pub fn timestamp(&self) {
+    unsafe { glQueryCounter(self.0, GL_TIMESTAMP) }
+  }
+
+  /// If the result can be read without waiting.
+  pub fn is_available(&self) -> bool {
+    let mut available = 0;
+    unsafe {
+      glGetQueryObjectuiv(self.0, GL_QUERY_RESULT_AVAILABLE, &mut available)
+    };
+    available != 0
+  }
+
+  /// Gets the result, waiting for the GPU if it's not ready yet.
+  pub fn result(&self) -> u64 {
+    let mut result = 0;
+    unsafe { glGetQueryObjectui64v(self.0, GL_QUERY_RESULT, &mut result) };
+    result
+  }
+
+  /// Gets the result, if it's ready.
+  pub fn try_result(&self) -> Option<u64> {
+    if self.is_available() {
+      Some(self.result())
+    } else {
+      None
+    }
+  }
+
+  /// Draws made until
+  /// [`end_conditional_render`](Self::end_conditional_render) only happen if
+  /// this occlusion query passed some samples.
+  ///
+  /// The query must have been a [`SamplesPassed`](QueryKind::SamplesPassed)
+  /// or [`AnySamplesPassed`](QueryKind::AnySamplesPassed) query. This is the
+  /// usual way to skip drawing a detailed object when its bounding box was
+  /// hidden, since the CPU never needs the answer.
+  pub fn begin_conditional_render(&self, mode: ConditionalMode) {
+    unsafe { glBeginConditionalRender(self.0, mode as GLenum) }
+  }
+
+  /// Ends conditional rendering.
+  pub fn end_conditional_render() {
+    unsafe { glEndConditionalRender() }
+  }
+
+  /// Deletes the query.
+  pub fn delete(self) {
+    unsafe { glDeleteQueries(1, &self.0) }
+  }
+}
+
+/// A few queries of one kind, used in turn over several frames so that
+/// results can be read without waiting.
+///
+/// Each frame, [`begin`](Self::begin) and [`end`](Self::end) once, then
+/// [`poll`](Self::poll) for the newest result that's ready.
+pub struct QueryRing {
+  kind: QueryKind,
+  queries: Vec<Query>,
+  /// Which queries have been ended but not read yet.
+  pending: Vec<bool>,
+  next: usize,
+  active: bool,
+  latest: Option<u64>,
+}
+impl QueryRing {
+  /// Makes a ring of `frames` queries. Three is usually enough for the
+  /// results to be ready by the time the query comes around again.
+  pub fn new(kind: QueryKind, frames: usize) -> Result<Self, String> {
+    let frames = frames.max(1);
+    let mut queries = Vec::with_capacity(frames);
+    for _ in 0..frames {
+      match Query::new() {
+        Some(q) => queries.push(q),
+        None => {
+          queries.into_iter().for_each(Query::delete);
+          return Err("Couldn't make a query object".to_string());
+        }
+      }
+    }
+    Ok(Self {
+      kind,
+      queries,
+      pending: vec![false; frames],
+      next: 0,
+      active: false,
+      latest: None,
+    })
+  }
+
+  /// The kind of the queries.
+  pub fn kind(&self) -> QueryKind {
+    self.kind
+  }
+
+  /// Starts the next query in the ring.
+  ///
+  /// If that query's last result was never read it's thrown away. For a
+  /// [`Timestamp`](QueryKind::Timestamp) ring this records the timestamp, and
+  /// [`end`](Self::end) does nothing.
+  ///
+  /// ## Panics
+  /// * If the last query wasn't ended.
+  pub fn begin(&mut self) {
+    assert!(!self.active, "the last query in the ring wasn't ended");
+    let query = &self.queries[self.next];
+    if self.kind == QueryKind::Timestamp {
+      query.timestamp();
+    } else {
+      query.begin(self.kind);
+    }
+    self.pending[self.next] = false;
+    self.active = true;
+  }
+
+  /// Ends the query started by [`begin`](Self::begin).
+  ///
+  /// ## Panics
+  /// * If there's no query going on.
+  pub fn end(&mut self) {
+    assert!(self.active, "no query in the ring was started");
+    if self.kind != QueryKind::Timestamp {
+      Query::end(self.kind);
+    }
+    self.pending[self.next] = true;
+    self.next = (self.next + 1) % self.queries.len();
+    self.active = false;
+  }
+
+  /// Reads every result that's ready without waiting, and gives the newest
+  /// result seen so far.
+  pub fn poll(&mut self) -> Option<u64> {
+    let len = self.queries.len();
+    // oldest first, so the newest ready one is kept last
+    for offset in 0..len {
+      let i = (self.next + offset) % len;
+      if self.pending[i] {
+        match self.queries[i].try_result() {
+          Some(result) => {
+            self.latest = Some(result);
+            self.pending[i] = false;
+          }
+          None => break,
+        }
+      }
+    }
+    self.latest
+  }
+
+  /// The newest result read by [`poll`](Self::poll).
+  pub fn latest(&self) -> Option<u64> {
+    self.latest
+  }
+
+  /// Deletes the queries.
+  pub fn delete(self) {
+    self.queries.into_iter().for_each(Query::delete);
+  }
+}
+
+/// The GPU time one pass took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTime {
+  /// The name given to [`GpuProfiler::begin_pass`].
+  pub name: String,
+  /// How many passes this one was inside of.
+  pub depth: u32,
+  /// How long it took, in milliseconds.
+  pub ms: f64,
+}
+
+/// One frame's worth of timestamp pairs.
+#[derive(Default)]
+struct ProfilerFrame {
+  /// Reused between frames, `passes[..used]` are this frame's.
+  queries: Vec<(Query, Query)>,
+  passes: Vec<(String, u32)>,
+  /// The pair whose end timestamp was issued last. With nested passes that's
+  /// an outer pass, not the last one started.
+  last_end: Option<usize>,
+  pending: bool,
+}
+
+/// Times named passes on the GPU, reading the results a few frames later so
+/// that nothing ever waits.
+///
+/// Passes can be nested. Each one takes a pair of timestamp queries, so
+/// unlike [`TimeElapsed`](QueryKind::TimeElapsed) queries they don't get in
+/// each other's way.
+///
+/// ```ignore
+/// profiler.begin_frame();
+/// profiler.begin_pass("shadows");
+/// draw_shadows();
+/// profiler.end_pass();
+/// profiler.begin_pass("lighting");
+/// draw_lighting();
+/// profiler.end_pass();
+/// profiler.end_frame();
+/// for pass in profiler.latest() {
+///   let indent = pass.depth as usize * 2;
+///   println!("{:>1$}{}: {:.2}ms", "", indent, pass.name, pass.ms);
+/// }
+/// ```
+pub struct GpuProfiler {
+  frames: Vec<ProfilerFrame>,
+  current: usize,
+  in_frame: bool,
+  /// Indexes into the current frame's passes that haven't ended yet.
+  open: Vec<usize>,
+  latest: Vec<PassTime>,
+  dropped: u64,
+}
+impl GpuProfiler {
+  /// Makes a profiler that keeps `frames` frames of queries going at once.
+  pub fn new(frames: usize) -> Self {
+    let frames = frames.max(1);
+    Self {
+      frames: (0..frames).map(|_| ProfilerFrame::default()).collect(),
+      current: 0,
+      in_frame: false,
+      open: Vec::new(),
+      latest: Vec::new(),
+      dropped: 0,
+    }
+  }
+
+  /// Starts a new frame, after reading any finished frames.
+  ///
+  /// ## Panics
+  /// * If the last frame wasn't ended.
+  pub fn begin_frame(&mut self) {
+    assert!(!self.in_frame, "the last profiler frame wasn't ended");
+    self.poll();
+    self.current = (self.current + 1) % self.frames.len();
+    let frame = &mut self.frames[self.current];
+    if frame.pending {
+      // the GPU is more than a full ring behind, so skip this result
+      frame.pending = false;
+      self.dropped += 1;
+    }
+    frame.passes.clear();
+    frame.last_end = None;
+    self.in_frame = true;
+  }
+
+  /// Starts timing a pass. Passes inside of this one count towards its time
+  /// too.
+  ///
+  /// ## Panics
+  /// * If there's no frame going on.
+  pub fn begin_pass(&mut self, name: &str) {
+    assert!(self.in_frame, "passes must be inside a profiler frame");
+    let depth = self.open.len() as u32;
+    let frame = &mut self.frames[self.current];
+    let index = frame.passes.len();
+    if index == frame.queries.len() {
+      match (Query::new(), Query::new()) {
+        (Some(start), Some(end)) => frame.queries.push((start, end)),
+        (start, end) => {
+          // out of queries, so this pass just isn't timed
+          if let Some(query) = start {
+            query.delete();
+          }
+          if let Some(query) = end {
+            query.delete();
+          }
+          self.open.push(usize::MAX);
+          return;
+        }
+      }
+    }
+    frame.queries[index].0.timestamp();
+    frame.passes.push((name.to_string(), depth));
+    self.open.push(index);
+  }
+
+  /// Stops timing the most recently started pass.
+  ///
+  /// ## Panics
+  /// * If there's no pass going on.
+  pub fn end_pass(&mut self) {
+    let index = self.open.pop().expect("no profiler pass was started");
+    let frame = &mut self.frames[self.current];
+    if let Some((_, end)) = frame.queries.get(index) {
+      end.timestamp();
+      frame.last_end = Some(index);
+    }
+  }
+
+  /// Ends the frame.
+  ///
+  /// ## Panics
+  /// * If a pass is still going on.
+  pub fn end_frame(&mut self) {
+    assert!(self.open.is_empty(), "a profiler pass wasn't ended");
+    assert!(self.in_frame, "no profiler frame was started");
+    let frame = &mut self.frames[self.current];
+    frame.pending = frame.last_end.is_some();
+    self.in_frame = false;
+  }
+
+  /// Reads every finished frame without waiting, oldest first, keeping the
+  /// newest one's times.
+  pub fn poll(&mut self) {
+    let len = self.frames.len();
+    for offset in 1..=len {
+      let frame = &mut self.frames[(self.current + offset) % len];
+      if !frame.pending {
+        continue;
+      }
+      // timestamps finish in the order they were issued, so the last one
+      // being ready means they all are
+      let last = match frame.last_end {
+        Some(index) => &frame.queries[index].1,
+        None => continue,
+      };
+      if !last.is_available() {
+        break;
+      }
+      self.latest.clear();
+      for ((name, depth), (start, end)) in
+        frame.passes.iter().zip(frame.queries.iter())
+      {
+        let ns = end.result().saturating_sub(start.result());
+        self.latest.push(PassTime {
+          name: name.clone(),
+          depth: *depth,
+          ms: ns as f64 / 1_000_000.0,
+        });
+      }
+      frame.pending = false;
+    }
+  }
+
+  /// The pass times of the newest finished frame, in the order the passes
+  /// started.
+  pub fn latest(&self) -> &[PassTime] {
+    &self.latest
+  }
+
+  /// How many frames were thrown away because their results weren't ready
+  /// in time. If this keeps going up, use more frames.
+  pub fn dropped_frames(&self) -> u64 {
+    self.dropped
+  }
+
+  /// Deletes all the queries.
+  pub fn delete(self) {
+    for frame in self.frames {
+      for (start, end) in frame.queries {
+        start.delete();
+        end.delete();
+      }
+    }
+  }
+}
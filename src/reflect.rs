@@ -0,0 +1,440 @@
+//! Asking a linked program what inputs it actually has.
+//!
+//! The GLSL compiler removes anything that doesn't affect the output, so the
+//! program's _active_ attributes and uniforms can be fewer than what's in
+//! the source. [`ProgramInfo`] lists what's really there, which is handy for
+//! checking that a vertex layout, a uniform buffer struct, or a material's
+//! textures actually match the shader.
+
+use crate::ShaderProgram;
+use core::fmt;
+use ogl33::*;
+
+/// The GL type of an attribute or uniform, eg: `GL_FLOAT_VEC3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlslType(pub GLenum);
+impl GlslType {
+  /// `float`
+  pub const FLOAT: Self = Self(GL_FLOAT);
+  /// `vec2`
+  pub const VEC2: Self = Self(GL_FLOAT_VEC2);
+  /// `vec3`
+  pub const VEC3: Self = Self(GL_FLOAT_VEC3);
+  /// `vec4`
+  pub const VEC4: Self = Self(GL_FLOAT_VEC4);
+  /// `int`
+  pub const INT: Self = Self(GL_INT);
+  /// `uint`
+  pub const UINT: Self = Self(GL_UNSIGNED_INT);
+  /// `bool`
+  pub const BOOL: Self = Self(GL_BOOL);
+  /// `mat3`
+  pub const MAT3: Self = Self(GL_FLOAT_MAT3);
+  /// `mat4`
+  pub const MAT4: Self = Self(GL_FLOAT_MAT4);
+  /// `sampler2D`
+  pub const SAMPLER_2D: Self = Self(GL_SAMPLER_2D);
+  /// `samplerCube`
+  pub const SAMPLER_CUBE: Self = Self(GL_SAMPLER_CUBE);
+
+  /// The GLSL spelling of the type, or `"unknown"`.
+  #[allow(non_upper_case_globals)]
+  pub fn name(self) -> &'static str {
+    match self.0 {
+      GL_FLOAT => "float",
+      GL_FLOAT_VEC2 => "vec2",
+      GL_FLOAT_VEC3 => "vec3",
+      GL_FLOAT_VEC4 => "vec4",
+      GL_INT => "int",
+      GL_INT_VEC2 => "ivec2",
+      GL_INT_VEC3 => "ivec3",
+      GL_INT_VEC4 => "ivec4",
+      GL_UNSIGNED_INT => "uint",
+      GL_UNSIGNED_INT_VEC2 => "uvec2",
+      GL_UNSIGNED_INT_VEC3 => "uvec3",
+      GL_UNSIGNED_INT_VEC4 => "uvec4",
+      GL_BOOL => "bool",
+      GL_BOOL_VEC2 => "bvec2",
+      GL_BOOL_VEC3 => "bvec3",
+      GL_BOOL_VEC4 => "bvec4",
+      GL_FLOAT_MAT2 => "mat2",
+      GL_FLOAT_MAT3 => "mat3",
+      GL_FLOAT_MAT4 => "mat4",
+      GL_FLOAT_MAT2x3 => "mat2x3",
+      GL_FLOAT_MAT2x4 => "mat2x4",
+      GL_FLOAT_MAT3x2 => "mat3x2",
+      GL_FLOAT_MAT3x4 => "mat3x4",
+      GL_FLOAT_MAT4x2 => "mat4x2",
+      GL_FLOAT_MAT4x3 => "mat4x3",
+      GL_SAMPLER_1D => "sampler1D",
+      GL_SAMPLER_2D => "sampler2D",
+      GL_SAMPLER_3D => "sampler3D",
+      GL_SAMPLER_CUBE => "samplerCube",
+      GL_SAMPLER_1D_SHADOW => "sampler1DShadow",
+      GL_SAMPLER_2D_SHADOW => "sampler2DShadow",
+      GL_SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+      GL_SAMPLER_1D_ARRAY => "sampler1DArray",
+      GL_SAMPLER_2D_ARRAY => "sampler2DArray",
+      GL_SAMPLER_1D_ARRAY_SHADOW => "sampler1DArrayShadow",
+      GL_SAMPLER_2D_ARRAY_SHADOW => "sampler2DArrayShadow",
+      GL_SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+      GL_SAMPLER_2D_MULTISAMPLE_ARRAY => "sampler2DMSArray",
+      GL_SAMPLER_2D_RECT => "sampler2DRect",
+      GL_SAMPLER_2D_RECT_SHADOW => "sampler2DRectShadow",
+      GL_SAMPLER_BUFFER => "samplerBuffer",
+      GL_INT_SAMPLER_1D => "isampler1D",
+      GL_INT_SAMPLER_2D => "isampler2D",
+      GL_INT_SAMPLER_3D => "isampler3D",
+      GL_INT_SAMPLER_CUBE => "isamplerCube",
+      GL_INT_SAMPLER_1D_ARRAY => "isampler1DArray",
+      GL_INT_SAMPLER_2D_ARRAY => "isampler2DArray",
+      GL_INT_SAMPLER_2D_MULTISAMPLE => "isampler2DMS",
+      GL_INT_SAMPLER_2D_MULTISAMPLE_ARRAY => "isampler2DMSArray",
+      GL_INT_SAMPLER_2D_RECT => "isampler2DRect",
+      GL_INT_SAMPLER_BUFFER => "isamplerBuffer",
+      GL_UNSIGNED_INT_SAMPLER_1D => "usampler1D",
+      GL_UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+      GL_UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+      GL_UNSIGNED_INT_SAMPLER_CUBE => "usamplerCube",
+      GL_UNSIGNED_INT_SAMPLER_1D_ARRAY => "usampler1DArray",
+      GL_UNSIGNED_INT_SAMPLER_2D_ARRAY => "usampler2DArray",
+      GL_UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE => "usampler2DMS",
+      GL_UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY => "usampler2DMSArray",
+      GL_UNSIGNED_INT_SAMPLER_2D_RECT => "usampler2DRect",
+      GL_UNSIGNED_INT_SAMPLER_BUFFER => "usamplerBuffer",
+      _ => "unknown",
+    }
+  }
+
+  /// If this is any kind of sampler (which are all spelled `*sampler*`).
+  pub fn is_sampler(self) -> bool {
+    self.name().contains("sampler")
+  }
+
+  /// If this is a depth comparison sampler.
+  pub fn is_shadow_sampler(self) -> bool {
+    self.name().ends_with("Shadow")
+  }
+}
+impl fmt::Display for GlslType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.name() {
+      "unknown" => write!(f, "unknown type 0x{:X}", self.0),
+      name => f.write_str(name),
+    }
+  }
+}
+
+/// An active vertex attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeInfo {
+  /// The name, as written in the shader.
+  pub name: String,
+  /// The attribute location.
+  pub location: GLint,
+  /// The type.
+  pub ty: GlslType,
+  /// The number of elements, `1` if it's not an array.
+  pub array_size: u32,
+}
+
+/// An active uniform, either on its own or as a member of a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformInfo {
+  /// The name, without any `[0]` on the end. Members of struct uniforms have
+  /// names like `light.color`.
+  pub name: String,
+  /// The type.
+  pub ty: GlslType,
+  /// The number of elements, `1` if it's not an array.
+  pub array_size: u32,
+  /// The uniform location, or `None` for block members.
+  pub location: Option<GLint>,
+  /// Byte offset within the block, for block members.
+  pub offset: Option<u32>,
+  /// Bytes between array elements, for block members that are arrays.
+  pub array_stride: Option<u32>,
+  /// Bytes between matrix columns, for block members that are matrices.
+  pub matrix_stride: Option<u32>,
+}
+
+/// An active uniform block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformBlockInfo {
+  /// The block name (not the instance name).
+  pub name: String,
+  /// The block index.
+  pub index: GLuint,
+  /// The binding point the block is currently connected to.
+  pub binding: u32,
+  /// The size of the buffer data the block needs.
+  pub data_size: u32,
+  /// The members, sorted by offset.
+  pub members: Vec<UniformInfo>,
+}
+
+/// An active sampler uniform, and the texture unit it reads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerInfo {
+  /// The name, without any `[0]` on the end.
+  pub name: String,
+  /// The uniform location.
+  pub location: GLint,
+  /// The sampler type.
+  pub ty: GlslType,
+  /// The number of elements, `1` if it's not an array.
+  pub array_size: u32,
+  /// The texture unit it's currently set to (of the first element, if it's
+  /// an array).
+  pub unit: i32,
+}
+
+/// Everything active in a linked program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramInfo {
+  /// Vertex attributes, sorted by location.
+  pub attributes: Vec<AttributeInfo>,
+  /// Uniforms outside of any block, including samplers, sorted by name.
+  pub uniforms: Vec<UniformInfo>,
+  /// Uniform blocks, sorted by index.
+  pub blocks: Vec<UniformBlockInfo>,
+  /// Sampler uniforms, sorted by name.
+  pub samplers: Vec<SamplerInfo>,
+}
+impl ProgramInfo {
+  /// Queries everything about a program. It must have linked successfully.
+  pub fn of(program: &ShaderProgram) -> Self {
+    let p = program.0;
+    let mut attributes: Vec<AttributeInfo> =
+      (0..program_iv(p, GL_ACTIVE_ATTRIBUTES))
+        .map(|i| {
+          let (name, array_size, ty) = active_attrib(p, i);
+          let location = match std::ffi::CString::new(name.clone()) {
+            Ok(c) => unsafe { glGetAttribLocation(p, c.as_ptr().cast()) },
+            Err(_) => -1,
+          };
+          AttributeInfo { name, location, ty, array_size }
+        })
+        .collect();
+    attributes.sort_by_key(|a| a.location);
+
+    let uniform_count = program_iv(p, GL_ACTIVE_UNIFORMS);
+    let indices: Vec<GLuint> = (0..uniform_count).collect();
+    let block_index = uniforms_iv(p, &indices, GL_UNIFORM_BLOCK_INDEX);
+    let offset = uniforms_iv(p, &indices, GL_UNIFORM_OFFSET);
+    let array_stride = uniforms_iv(p, &indices, GL_UNIFORM_ARRAY_STRIDE);
+    let matrix_stride = uniforms_iv(p, &indices, GL_UNIFORM_MATRIX_STRIDE);
+    let non_neg = |v: GLint| if v >= 0 { Some(v as u32) } else { None };
+    let positive = |v: GLint| if v > 0 { Some(v as u32) } else { None };
+
+    let mut uniforms = Vec::new();
+    let mut block_members: Vec<(GLint, UniformInfo)> = Vec::new();
+    for i in indices.iter().map(|&i| i as usize) {
+      let (name, array_size, ty) = active_uniform(p, i as GLuint);
+      let name = name.strip_suffix("[0]").unwrap_or(&name).to_string();
+      if block_index[i] >= 0 {
+        block_members.push((
+          block_index[i],
+          UniformInfo {
+            name,
+            ty,
+            array_size,
+            location: None,
+            offset: non_neg(offset[i]),
+            array_stride: positive(array_stride[i]),
+            matrix_stride: positive(matrix_stride[i]),
+          },
+        ));
+      } else {
+        let location = program.uniform_location(&name);
+        uniforms.push(UniformInfo {
+          name,
+          ty,
+          array_size,
+          location,
+          offset: None,
+          array_stride: None,
+          matrix_stride: None,
+        });
+      }
+    }
+    uniforms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let blocks = (0..program_iv(p, GL_ACTIVE_UNIFORM_BLOCKS))
+      .map(|index| {
+        let mut members: Vec<UniformInfo> = block_members
+          .iter()
+          .filter(|(b, _)| *b as GLuint == index)
+          .map(|(_, u)| u.clone())
+          .collect();
+        members.sort_by_key(|u| u.offset);
+        UniformBlockInfo {
+          name: block_name(p, index),
+          index,
+          binding: block_iv(p, index, GL_UNIFORM_BLOCK_BINDING) as u32,
+          data_size: block_iv(p, index, GL_UNIFORM_BLOCK_DATA_SIZE) as u32,
+          members,
+        }
+      })
+      .collect();
+
+    let samplers = uniforms
+      .iter()
+      .filter(|u| u.ty.is_sampler())
+      .filter_map(|u| {
+        let location = u.location?;
+        let mut unit = 0;
+        unsafe { glGetUniformiv(p, location, &mut unit) };
+        Some(SamplerInfo {
+          name: u.name.clone(),
+          location,
+          ty: u.ty,
+          array_size: u.array_size,
+          unit,
+        })
+      })
+      .collect();
+
+    Self { attributes, uniforms, blocks, samplers }
+  }
+
+  /// Looks up an attribute by name.
+  pub fn attribute(&self, name: &str) -> Option<&AttributeInfo> {
+    self.attributes.iter().find(|a| a.name == name)
+  }
+
+  /// Looks up a uniform (outside of any block) by name.
+  pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+    self.uniforms.iter().find(|u| u.name == name)
+  }
+
+  /// Looks up a uniform block by name.
+  pub fn block(&self, name: &str) -> Option<&UniformBlockInfo> {
+    self.blocks.iter().find(|b| b.name == name)
+  }
+
+  /// Looks up a sampler by name.
+  pub fn sampler(&self, name: &str) -> Option<&SamplerInfo> {
+    self.samplers.iter().find(|s| s.name == name)
+  }
+
+  /// Checks a vertex layout, given as `(location, type)` pairs, against the
+  /// program's attributes.
+  ///
+  /// Every active attribute has to be in the layout with the same type.
+  /// Extra entries in the layout are fine, the program just doesn't use them.
+  pub fn check_vertex_layout(
+    &self,
+    layout: &[(GLuint, GlslType)],
+  ) -> Result<(), String> {
+    for a in self.attributes.iter().filter(|a| a.location >= 0) {
+      match layout.iter().find(|(loc, _)| *loc as GLint == a.location) {
+        None => {
+          return Err(format!(
+            "Attribute `{}` (location {}) isn't in the vertex layout",
+            a.name, a.location
+          ))
+        }
+        Some((_, ty)) if *ty != a.ty => {
+          return Err(format!(
+            "Attribute `{}` is {} but the vertex layout gives {}",
+            a.name, a.ty, ty
+          ))
+        }
+        Some(_) => (),
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks that a uniform block needs exactly `size` bytes, such as the
+  /// [`Std140::SIZE`](crate::uniform::Std140::SIZE) of the struct that
+  /// fills it. A block the program doesn't have is fine.
+  pub fn check_block_size(
+    &self,
+    name: &str,
+    size: usize,
+  ) -> Result<(), String> {
+    match self.block(name) {
+      Some(b) if b.data_size as usize != size => Err(format!(
+        "Block `{}` is {} bytes but the data is {} bytes",
+        name, b.data_size, size
+      )),
+      _ => Ok(()),
+    }
+  }
+
+  /// Checks that every sampler in the program is in `names`, such as the
+  /// uniform names that a material binds.
+  pub fn check_samplers(&self, names: &[&str]) -> Result<(), String> {
+    match self.samplers.iter().find(|s| !names.contains(&s.name.as_str())) {
+      Some(s) => Err(format!("Sampler `{}` ({}) isn't bound", s.name, s.ty)),
+      None => Ok(()),
+    }
+  }
+}
+
+fn program_iv(p: GLuint, pname: GLenum) -> GLuint {
+  let mut out = 0;
+  unsafe { glGetProgramiv(p, pname, &mut out) };
+  out.max(0) as GLuint
+}
+
+fn block_iv(p: GLuint, index: GLuint, pname: GLenum) -> GLint {
+  let mut out = 0;
+  unsafe { glGetActiveUniformBlockiv(p, index, pname, &mut out) };
+  out
+}
+
+fn uniforms_iv(p: GLuint, indices: &[GLuint], pname: GLenum) -> Vec<GLint> {
+  let mut out = vec![0; indices.len()];
+  if !indices.is_empty() {
+    unsafe {
+      glGetActiveUniformsiv(
+        p,
+        indices.len() as GLsizei,
+        indices.as_ptr(),
+        pname,
+        out.as_mut_ptr(),
+      )
+    };
+  }
+  out
+}
+
+/// Reads a name that GL writes into a buffer, given the max length query.
+fn read_name(
+  max_len: GLuint,
+  f: impl FnOnce(GLsizei, *mut GLsizei, *mut GLchar),
+) -> String {
+  let mut buf = vec![0_u8; max_len.max(1) as usize];
+  let mut len = 0;
+  f(buf.len() as GLsizei, &mut len, buf.as_mut_ptr().cast());
+  buf.truncate(len.max(0) as usize);
+  String::from_utf8_lossy(&buf).into_owned()
+}
+
+fn active_attrib(p: GLuint, i: GLuint) -> (String, u32, GlslType) {
+  let (mut size, mut ty) = (0, 0);
+  let max_len = program_iv(p, GL_ACTIVE_ATTRIBUTE_MAX_LENGTH);
+  let name = read_name(max_len, |cap, len, buf| unsafe {
+    glGetActiveAttrib(p, i, cap, len, &mut size, &mut ty, buf)
+  });
+  (name, size.max(0) as u32, GlslType(ty))
+}
+
+fn active_uniform(p: GLuint, i: GLuint) -> (String, u32, GlslType) {
+  let (mut size, mut ty) = (0, 0);
+  let max_len = program_iv(p, GL_ACTIVE_UNIFORM_MAX_LENGTH);
+  let name = read_name(max_len, |cap, len, buf| unsafe {
+    glGetActiveUniform(p, i, cap, len, &mut size, &mut ty, buf)
+  });
+  (name, size.max(0) as u32, GlslType(ty))
+}
+
+fn block_name(p: GLuint, index: GLuint) -> String {
+  let max_len = program_iv(p, GL_ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH);
+  read_name(max_len, |cap, len, buf| unsafe {
+    glGetActiveUniformBlockName(p, index, cap, len, buf)
+  })
+}
@@ -0,0 +1,519 @@
+//! Recording input to a file and playing it back later.
+//!
+//! Bugs in camera and movement code usually depend on exactly how the mouse
+//! moved, which is hard to do the same way twice by hand. Instead you can
+//! record a session and then replay it as many times as you like.
+//!
+//! A recording is a list of timestamped events, along with a `frame` marker
+//! at the time each frame's world update happened. On playback the frame
+//! markers act as a simulated clock, so each frame gets exactly the same
+//! events and the same delta time as it did live. None of this needs a
+//! window, so a replay can run in a test and check where the camera ended up.
+//!
+//! The file format is plain text, one event per line:
+//!
+//! ```text
+//! # learn-opengl recording v1
+//! 1520 key w down
+//! 1533 motion 400 300 -3 1
+//! 1541 wheel 0 1
+//! 1550 button left up
+//! 1551 resize 1024 768
+//! 1566 frame
+//! 2070 quit
+//! ```
+
+use crate::{
+  camera::{Camera, CameraController},
+  input::{ActionMap, Binding, InputEvent, InputState, MouseButton},
+};
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+};
+
+/// The first line of every recording.
+const HEADER: &str = "# learn-opengl recording v1";
+
+/// The events that go into a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordedEvent {
+  /// Keyboard and mouse input.
+  Input(InputEvent),
+  /// The window changed size.
+  Resized {
+    /// New width.
+    width: i32,
+    /// New height.
+    height: i32,
+  },
+  /// The user asked to quit.
+  Quit,
+}
+impl RecordedEvent {
+  /// Writes the event in the text format (without the timestamp).
+  fn to_line(self) -> String {
+    match self {
+      RecordedEvent::Input(InputEvent::Key { key, pressed }) => {
+        format!("key {} {}", key.name(), up_down(pressed))
+      }
+      RecordedEvent::Input(InputEvent::MouseButton { button, pressed }) => {
+        format!("button {} {}", button.name(), up_down(pressed))
+      }
+      RecordedEvent::Input(InputEvent::MouseMotion { x, y, dx, dy }) => {
+        format!("motion {} {} {} {}", x, y, dx, dy)
+      }
+      RecordedEvent::Input(InputEvent::MouseWheel { x, y }) => {
+        format!("wheel {} {}", x, y)
+      }
+      RecordedEvent::Resized { width, height } => {
+        format!("resize {} {}", width, height)
+      }
+      RecordedEvent::Quit => "quit".to_string(),
+    }
+  }
+}
+
+/// An event along with when it happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+  /// The clock time of the event, in milliseconds.
+  pub time_ms: u32,
+  /// The event.
+  pub event: RecordedEvent,
+}
+
+fn up_down(pressed: bool) -> &'static str {
+  if pressed {
+    "down"
+  } else {
+    "up"
+  }
+}
+
+/// One frame's worth of events.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frame {
+  /// The clock time of this frame's update, in milliseconds.
+  pub time_ms: u32,
+  /// The events in the order that they happened.
+  pub events: Vec<TimedEvent>,
+}
+impl Frame {
+  /// Adds an event that happened at `time_ms`.
+  pub fn push(&mut self, time_ms: u32, event: RecordedEvent) {
+    self.events.push(TimedEvent { time_ms, event });
+  }
+
+  /// Was a quit requested during this frame?
+  pub fn quit_requested(&self) -> bool {
+    self.events.iter().any(|e| e.event == RecordedEvent::Quit)
+  }
+
+  /// The last resize during this frame, if any.
+  pub fn last_resize(&self) -> Option<(i32, i32)> {
+    self.events.iter().rev().find_map(|e| match e.event {
+      RecordedEvent::Resized { width, height } => Some((width, height)),
+      _ => None,
+    })
+  }
+
+  /// Starts a new input frame and feeds all of this frame's input events in.
+  pub fn apply_to(&self, input: &mut InputState) {
+    input.begin_frame();
+    for event in self.events.iter() {
+      if let RecordedEvent::Input(i) = &event.event {
+        input.handle(i);
+      }
+    }
+  }
+}
+
+/// Writes events out to a recording.
+pub struct EventRecorder<W: Write> {
+  out: W,
+}
+impl EventRecorder<BufWriter<File>> {
+  /// Creates (or replaces) a recording file.
+  pub fn create(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let f = File::create(path)
+      .map_err(|e| format!("Couldn't create `{}`: {}", path.display(), e))?;
+    Self::new(BufWriter::new(f))
+  }
+}
+impl<W: Write> EventRecorder<W> {
+  /// Starts a recording into any writer.
+  pub fn new(mut out: W) -> Result<Self, String> {
+    writeln!(out, "{}", HEADER).map_err(|e| e.to_string())?;
+    Ok(Self { out })
+  }
+
+  /// Records one event.
+  pub fn record(
+    &mut self,
+    timestamp_ms: u32,
+    event: &RecordedEvent,
+  ) -> Result<(), String> {
+    writeln!(self.out, "{} {}", timestamp_ms, event.to_line())
+      .map_err(|e| e.to_string())
+  }
+
+  /// Records a whole frame, events first and then the frame marker.
+  pub fn record_frame(&mut self, frame: &Frame) -> Result<(), String> {
+    for event in frame.events.iter() {
+      self.record(event.time_ms, &event.event)?;
+    }
+    writeln!(self.out, "{} frame", frame.time_ms).map_err(|e| e.to_string())
+  }
+
+  /// Flushes and gives back the writer.
+  pub fn finish(mut self) -> Result<W, String> {
+    self.out.flush().map_err(|e| e.to_string())?;
+    Ok(self.out)
+  }
+}
+
+/// Plays back a recording one frame at a time.
+#[derive(Debug, Clone, Default)]
+pub struct EventReplayer {
+  frames: Vec<Frame>,
+  next: usize,
+}
+impl EventReplayer {
+  /// Loads a recording file.
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+      .map_err(|e| format!("Couldn't read `{}`: {}", path.display(), e))?;
+    Self::from_recording_str(&text)
+  }
+
+  /// Parses the text of a recording.
+  ///
+  /// Events after the last frame marker are put into one final frame with the
+  /// time of the last event.
+  pub fn from_recording_str(text: &str) -> Result<Self, String> {
+    let mut frames = Vec::new();
+    let mut pending = Frame::default();
+    for (i, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let err = |msg: &str| format!("Line {}: {}", i + 1, msg);
+      let mut words = line.split_whitespace();
+      let time_ms: u32 = words
+        .next()
+        .and_then(|w| w.parse().ok())
+        .ok_or_else(|| err("missing timestamp"))?;
+      let kind = words.next().ok_or_else(|| err("missing event"))?;
+      let args: Vec<&str> = words.collect();
+      pending.time_ms = time_ms;
+      if kind == "frame" {
+        frames.push(core::mem::take(&mut pending));
+        continue;
+      }
+      let event = parse_event(kind, &args).map_err(|e| err(&e))?;
+      pending.push(time_ms, event);
+    }
+    if !pending.events.is_empty() {
+      frames.push(pending);
+    }
+    Ok(Self { frames, next: 0 })
+  }
+
+  /// Gets the next frame, or `None` once the recording is done.
+  pub fn next_frame(&mut self) -> Option<&Frame> {
+    let frame = self.frames.get(self.next)?;
+    self.next += 1;
+    Some(frame)
+  }
+
+  /// All the frames in the recording.
+  pub fn frames(&self) -> &[Frame] {
+    &self.frames
+  }
+
+  /// Starts the playback over from the beginning.
+  pub fn rewind(&mut self) {
+    self.next = 0;
+  }
+}
+
+fn parse_event(kind: &str, args: &[&str]) -> Result<RecordedEvent, String> {
+  let int = |i: usize| -> Result<i32, String> {
+    args
+      .get(i)
+      .and_then(|a| a.parse().ok())
+      .ok_or_else(|| format!("`{}` needs a number as argument {}", kind, i + 1))
+  };
+  let pressed = || match args.get(1) {
+    Some(&"down") => Ok(true),
+    Some(&"up") => Ok(false),
+    _ => Err(format!("`{}` needs `down` or `up`", kind)),
+  };
+  Ok(match kind {
+    "key" => {
+      let name = args.first().ok_or("`key` needs a key name")?;
+      let key = match Binding::parse(&format!("key:{}", name))? {
+        Binding::Key(key) => key,
+        Binding::MouseButton(_) => unreachable!(),
+      };
+      RecordedEvent::Input(InputEvent::Key { key, pressed: pressed()? })
+    }
+    "button" => {
+      let name = args.first().ok_or("`button` needs a button name")?;
+      let button = MouseButton::from_name(name)
+        .ok_or_else(|| format!("Unknown mouse button `{}`", name))?;
+      RecordedEvent::Input(InputEvent::MouseButton {
+        button,
+        pressed: pressed()?,
+      })
+    }
+    "motion" => RecordedEvent::Input(InputEvent::MouseMotion {
+      x: int(0)?,
+      y: int(1)?,
+      dx: int(2)?,
+      dy: int(3)?,
+    }),
+    "wheel" => {
+      RecordedEvent::Input(InputEvent::MouseWheel { x: int(0)?, y: int(1)? })
+    }
+    "resize" => RecordedEvent::Resized { width: int(0)?, height: int(1)? },
+    "quit" => RecordedEvent::Quit,
+    other => return Err(format!("Unknown event `{}`", other)),
+  })
+}
+
+/// Where a program's events come from this run.
+///
+/// The event loop gathers up its live events each frame and hands them to
+/// [`frame`](Self::frame). What comes back is the frame that the program
+/// should actually use, which might be the live events or might be recorded
+/// ones.
+pub enum Session {
+  /// Just use live events.
+  Live,
+  /// Use live events, and also save them to a recording.
+  Recording(EventRecorder<BufWriter<File>>),
+  /// Ignore live events (other than quitting) and use a recording instead.
+  Replaying(EventReplayer),
+}
+impl Session {
+  /// Picks a session type from command line arguments.
+  ///
+  /// `--record PATH` records to a file and `--replay PATH` plays a file back.
+  /// With neither you get a live session.
+  pub fn from_args(
+    mut args: impl Iterator<Item = String>,
+  ) -> Result<Self, String> {
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--record" => {
+          let path = args.next().ok_or("`--record` needs a file path")?;
+          return EventRecorder::create(path).map(Session::Recording);
+        }
+        "--replay" => {
+          let path = args.next().ok_or("`--replay` needs a file path")?;
+          return EventReplayer::load(path).map(Session::Replaying);
+        }
+        _ => (),
+      }
+    }
+    Ok(Session::Live)
+  }
+
+  /// Turns this frame's live events into the frame to use.
+  ///
+  /// Gives `None` once a replay has run out of frames. A live quit event
+  /// still gets passed along during a replay, so you can always close the
+  /// window.
+  pub fn frame(&mut self, live: Frame) -> Result<Option<Frame>, String> {
+    match self {
+      Session::Live => Ok(Some(live)),
+      Session::Recording(recorder) => {
+        recorder.record_frame(&live)?;
+        Ok(Some(live))
+      }
+      Session::Replaying(replayer) => {
+        Ok(replayer.next_frame().cloned().map(|mut frame| {
+          if live.quit_requested() {
+            frame.push(live.time_ms, RecordedEvent::Quit);
+          }
+          frame
+        }))
+      }
+    }
+  }
+
+  /// Is this session playing back a recording?
+  pub fn is_replay(&self) -> bool {
+    matches!(self, Session::Replaying(_))
+  }
+
+  /// Flushes any recording out to disk.
+  pub fn finish(self) -> Result<(), String> {
+    match self {
+      Session::Recording(recorder) => recorder.finish().map(drop),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Drives a camera from frames of events.
+///
+/// Use this for both the live loop and the headless replay so that they
+/// always do exactly the same math.
+#[derive(Debug, Clone)]
+pub struct CameraDriver {
+  /// The input state being updated.
+  pub input: InputState,
+  /// The bindings to use.
+  pub actions: ActionMap,
+  /// Degrees of turning per pixel of mouse motion.
+  pub look_sensitivity: f32,
+  /// Degrees per second of roll.
+  pub roll_speed_deg: f32,
+  last_time_ms: Option<u32>,
+}
+impl CameraDriver {
+  /// Makes a driver with the given bindings.
+  pub fn new(actions: ActionMap) -> Self {
+    Self {
+      input: InputState::new(),
+      actions,
+      look_sensitivity: 0.1,
+      roll_speed_deg: 90.0,
+      last_time_ms: None,
+    }
+  }
+
+  /// Applies a frame to the input state and the camera.
+  ///
+  /// A resize updates the camera's aspect ratio. The first frame has a delta
+  /// time of zero.
+  pub fn step<C: CameraController>(
+    &mut self,
+    frame: &Frame,
+    camera: &mut Camera<C>,
+  ) {
+    frame.apply_to(&mut self.input);
+    if let Some((w, h)) = frame.last_resize() {
+      if w > 0 && h > 0 {
+        camera.projection.set_aspect_ratio(w as f32 / h as f32);
+      }
+    }
+    let delta_ms = match self.last_time_ms {
+      Some(last) => frame.time_ms.saturating_sub(last),
+      None => 0,
+    };
+    self.last_time_ms = Some(frame.time_ms);
+    let delta_time = delta_ms as f32 / 1_000.0;
+    let camera_input = self.actions.camera_input(
+      &self.input,
+      self.look_sensitivity,
+      self.roll_speed_deg,
+      delta_time,
+    );
+    camera.update(&camera_input, delta_time);
+  }
+
+  /// Runs a whole recording through the camera, with no window needed.
+  ///
+  /// Stops early at a quit event, just like the live loop would.
+  pub fn replay<C: CameraController>(
+    &mut self,
+    replayer: &mut EventReplayer,
+    camera: &mut Camera<C>,
+  ) {
+    while let Some(frame) = replayer.next_frame() {
+      if frame.quit_requested() {
+        break;
+      }
+      self.step(frame, camera);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    camera::{FpsCamera, Projection},
+    input::Key,
+  };
+  use ultraviolet::Vec3;
+
+  const RECORDING: &str = "\
+# learn-opengl recording v1
+1000 frame
+1000 key w down
+1500 frame
+1510 motion 400 300 -900 0
+1520 key w up
+1530 resize 1000 500
+2000 frame
+2010 key d down
+2100 motion 400 300 0 -200
+2500 frame
+2600 quit
+2700 key d up
+3000 frame
+";
+
+  fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+  }
+
+  #[test]
+  fn replay_moves_the_camera() {
+    let mut replayer = EventReplayer::from_recording_str(RECORDING).unwrap();
+    assert_eq!(replayer.frames().len(), 5);
+    let mut camera = Camera::new(
+      Projection::perspective(45.0, 4.0 / 3.0, 0.1, 100.0),
+      FpsCamera::at_position(Vec3::new(0.0, 0.0, 3.0)),
+    );
+    let mut driver = CameraDriver::new(ActionMap::default_camera());
+    driver.replay(&mut replayer, &mut camera);
+
+    // half a second forward down -Z, turn left 90 degrees and look up 20,
+    // then half a second strafing right, which is now -Z again.
+    let fps = &camera.controller;
+    assert!(close(fps.yaw_deg(), 90.0), "yaw {}", fps.yaw_deg());
+    assert!(close(fps.pitch_deg(), 20.0), "pitch {}", fps.pitch_deg());
+    let p = fps.position;
+    assert!(close(p.x, 0.0) && close(p.y, 0.0) && close(p.z, 0.5), "{:?}", p);
+    assert!(close(camera.projection.aspect_ratio(), 2.0));
+    // stopped at the quit, so the `d` release was never seen
+    assert!(driver.input.key_held(Key::from_char('d')));
+  }
+
+  #[test]
+  fn recording_round_trip_keeps_event_times() {
+    let mut frame = Frame { time_ms: 40, events: Vec::new() };
+    frame.push(12, RecordedEvent::Resized { width: 640, height: 480 });
+    frame.push(
+      31,
+      RecordedEvent::Input(InputEvent::Key {
+        key: Key::from_char('#'),
+        pressed: true,
+      }),
+    );
+    let mut recorder = EventRecorder::new(Vec::new()).unwrap();
+    recorder.record_frame(&frame).unwrap();
+    let text = String::from_utf8(recorder.finish().unwrap()).unwrap();
+    assert!(text.contains("12 resize 640 480\n31 key #35 down\n40 frame\n"));
+    let replayer = EventReplayer::from_recording_str(&text).unwrap();
+    assert_eq!(replayer.frames(), &[frame]);
+  }
+
+  #[test]
+  fn bad_lines_are_errors() {
+    assert!(EventReplayer::from_recording_str("frame").is_err());
+    assert!(EventReplayer::from_recording_str("10 key w sideways").is_err());
+    assert!(EventReplayer::from_recording_str("10 motion 1 2").is_err());
+    assert!(EventReplayer::from_recording_str("10 jump").is_err());
+  }
+}
@@ -0,0 +1,132 @@
+//! Keeping everything in sync with the window's size.
+//!
+//! When the window changes size a few things have to change with it: the
+//! viewport, the aspect ratio of any cameras, and the size of any off-screen
+//! render targets that are supposed to match the window. The
+//! [`ResizeHandler`] does all of that in one place.
+//!
+//! On a HiDPI display the window size (in "points") and the drawable size (in
+//! pixels) aren't the same. The viewport and render targets need the
+//! _drawable_ size, so always pass that along too. With `beryllium` that's
+//! `win.get_drawable_size()`.
+
+use crate::{
+  camera::{Camera, Projection},
+  framebuffer::RenderTarget,
+};
+
+/// Something that changes when the window's drawable size changes.
+pub trait Resize {
+  /// Called with the new drawable size, in pixels.
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String>;
+}
+
+impl Resize for Projection {
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    if width > 0 && height > 0 {
+      self.set_aspect_ratio(width as f32 / height as f32);
+    }
+    Ok(())
+  }
+}
+
+impl<C> Resize for Camera<C> {
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    self.projection.resize(width, height)
+  }
+}
+
+impl Resize for RenderTarget {
+  fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+    RenderTarget::resize(self, width, height)
+  }
+}
+
+/// Tracks the window's size and passes changes along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeHandler {
+  window: (u32, u32),
+  drawable: (u32, u32),
+}
+impl ResizeHandler {
+  /// Starts tracking with the window's current size and drawable size.
+  ///
+  /// This sets the viewport right away.
+  pub fn new(window_size: (i32, i32), drawable_size: (i32, i32)) -> Self {
+    let out = Self {
+      window: to_u32_pair(window_size),
+      drawable: to_u32_pair(drawable_size),
+    };
+    crate::viewport(0, 0, out.drawable.0, out.drawable.1);
+    out
+  }
+
+  /// The window's size, in points.
+  pub fn window_size(&self) -> (u32, u32) {
+    self.window
+  }
+
+  /// The window's drawable size, in pixels.
+  pub fn drawable_size(&self) -> (u32, u32) {
+    self.drawable
+  }
+
+  /// Drawable width over window width.
+  ///
+  /// This is `1.0` on a normal display, and usually `2.0` on a HiDPI display.
+  /// Mouse positions are in points, so multiply them by this to get pixels.
+  pub fn hidpi_scale(&self) -> f32 {
+    if self.window.0 == 0 {
+      1.0
+    } else {
+      self.drawable.0 as f32 / self.window.0 as f32
+    }
+  }
+
+  /// Drawable width over drawable height.
+  pub fn aspect_ratio(&self) -> f32 {
+    if self.drawable.1 == 0 {
+      1.0
+    } else {
+      self.drawable.0 as f32 / self.drawable.1 as f32
+    }
+  }
+
+  /// Call this after any window size event.
+  ///
+  /// If the drawable size changed this sets the viewport and resizes each
+  /// thing in `targets` (cameras, render targets, etc). A minimized window
+  /// can report a size of zero, and those updates are skipped so that nothing
+  /// ends up with a zero size.
+  ///
+  /// Returns if there was a change.
+  pub fn update(
+    &mut self,
+    window_size: (i32, i32),
+    drawable_size: (i32, i32),
+    targets: &mut [&mut dyn Resize],
+  ) -> Result<bool, String> {
+    self.window = to_u32_pair(window_size);
+    let drawable = to_u32_pair(drawable_size);
+    if drawable == self.drawable || drawable.0 == 0 || drawable.1 == 0 {
+      return Ok(false);
+    }
+    self.drawable = drawable;
+    crate::viewport(0, 0, drawable.0, drawable.1);
+    for target in targets.iter_mut() {
+      target.resize(drawable.0, drawable.1)?;
+    }
+    Ok(true)
+  }
+
+  /// Sets the viewport back to cover the whole window.
+  ///
+  /// Use this after drawing into a render target of some other size.
+  pub fn reset_viewport(&self) {
+    crate::viewport(0, 0, self.drawable.0, self.drawable.1);
+  }
+}
+
+fn to_u32_pair((w, h): (i32, i32)) -> (u32, u32) {
+  (w.max(0) as u32, h.max(0) as u32)
+}
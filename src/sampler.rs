@@ -0,0 +1,238 @@
+//! Wrapper for [Sampler](https://www.khronos.org/opengl/wiki/Sampler_Object)
+//! objects.
+//!
+//! Normally the filtering and wrapping settings are part of the texture
+//! itself. A sampler holds those same settings on its own, and when a sampler
+//! is bound to a texture unit its settings override the texture's. That lets
+//! one texture be read in different ways in different passes (eg: nearest for
+//! a pixel-exact debug view, linear for the normal view) without changing the
+//! texture.
+
+use crate::{
+  state::CompareFunc,
+  texture::{MagFilter, MinFilter, TextureWrap},
+};
+use ogl33::*;
+
+/// `GL_TEXTURE_MAX_ANISOTROPY`, from `GL_EXT_texture_filter_anisotropic`.
+const GL_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+/// `GL_MAX_TEXTURE_MAX_ANISOTROPY`, from `GL_EXT_texture_filter_anisotropic`.
+const GL_MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+/// The most anisotropic filtering the GPU can do.
+///
+/// Anisotropic filtering isn't part of core GL 3.3, but almost every driver
+/// has the extension for it. If it's missing this gives `1.0`, which means
+/// "off".
+pub fn max_anisotropy() -> f32 {
+  if !crate::has_extension("GL_EXT_texture_filter_anisotropic")
+    && !crate::has_extension("GL_ARB_texture_filter_anisotropic")
+  {
+    return 1.0;
+  }
+  let mut max = 1.0;
+  unsafe { glGetFloatv(GL_MAX_TEXTURE_MAX_ANISOTROPY, &mut max) };
+  max
+}
+
+/// All the settings of a sampler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerDesc {
+  /// Filter for when the texture is drawn smaller.
+  pub min_filter: MinFilter,
+  /// Filter for when the texture is drawn bigger.
+  pub mag_filter: MagFilter,
+  /// Wrapping on the `s`, `t`, and `r` axes.
+  pub wrap: [TextureWrap; 3],
+  /// Color used with [`TextureWrap::ClampToBorder`].
+  pub border_color: [f32; 4],
+  /// Depth comparison, for shadow samplers.
+  pub compare: Option<CompareFunc>,
+  /// The lowest mip level (most detailed) that can be used.
+  pub min_lod: f32,
+  /// The highest mip level (least detailed) that can be used.
+  pub max_lod: f32,
+  /// Added to the mip level that would normally be picked. Negative values
+  /// make things sharper, positive values make them blurrier.
+  pub lod_bias: f32,
+  /// Anisotropic filtering amount, `1.0` is off. This is clamped to
+  /// [`max_anisotropy`] when used.
+  pub anisotropy: f32,
+}
+impl Default for SamplerDesc {
+  /// The same settings that a new texture starts with.
+  fn default() -> Self {
+    Self {
+      min_filter: MinFilter::NearestMipmapLinear,
+      mag_filter: MagFilter::Linear,
+      wrap: [TextureWrap::Repeat; 3],
+      border_color: [0.0; 4],
+      compare: None,
+      min_lod: -1000.0,
+      max_lod: 1000.0,
+      lod_bias: 0.0,
+      anisotropy: 1.0,
+    }
+  }
+}
+impl SamplerDesc {
+  /// Nearest filtering without mipmaps, for a blocky "pixel art" look or
+  /// reading exact texels.
+  pub fn nearest() -> Self {
+    Self {
+      min_filter: MinFilter::Nearest,
+      mag_filter: MagFilter::Nearest,
+      ..Self::default()
+    }
+  }
+
+  /// Linear filtering without mipmaps.
+  pub fn linear() -> Self {
+    Self {
+      min_filter: MinFilter::Linear,
+      mag_filter: MagFilter::Linear,
+      ..Self::default()
+    }
+  }
+
+  /// Linear filtering that blends between mip levels too, with the given
+  /// anisotropy.
+  pub fn trilinear(anisotropy: f32) -> Self {
+    Self {
+      min_filter: MinFilter::LinearMipmapLinear,
+      mag_filter: MagFilter::Linear,
+      anisotropy,
+      ..Self::default()
+    }
+  }
+
+  /// Linear filtering with depth comparison, for reading shadow maps with
+  /// `sampler2DShadow`. Outside the map counts as fully lit.
+  pub fn shadow() -> Self {
+    Self {
+      min_filter: MinFilter::Linear,
+      mag_filter: MagFilter::Linear,
+      wrap: [TextureWrap::ClampToBorder; 3],
+      border_color: [1.0; 4],
+      compare: Some(CompareFunc::LessEqual),
+      ..Self::default()
+    }
+  }
+
+  /// Changes the wrapping on all axes.
+  pub fn with_wrap(self, wrap: TextureWrap) -> Self {
+    Self { wrap: [wrap; 3], ..self }
+  }
+}
+
+/// Basic wrapper for a Sampler Object.
+pub struct Sampler(pub GLuint);
+impl Sampler {
+  /// Makes a new sampler with the default settings.
+  pub fn new() -> Option<Self> {
+    let mut sampler = 0;
+    unsafe { glGenSamplers(1, &mut sampler) };
+    if sampler != 0 {
+      Some(Self(sampler))
+    } else {
+      None
+    }
+  }
+
+  /// Makes a new sampler with the given settings.
+  pub fn from_desc(desc: &SamplerDesc) -> Result<Self, String> {
+    let sampler = Self::new().ok_or("Couldn't make a sampler")?;
+    sampler.set_filters(desc.min_filter, desc.mag_filter);
+    sampler.set_wrap_axes(desc.wrap);
+    sampler.set_border_color(desc.border_color);
+    sampler.set_depth_compare(desc.compare);
+    sampler.set_lod(desc.min_lod, desc.max_lod, desc.lod_bias);
+    if desc.anisotropy > 1.0 {
+      sampler.set_anisotropy(desc.anisotropy);
+    }
+    Ok(sampler)
+  }
+
+  /// Binds this sampler to a texture unit.
+  ///
+  /// Whatever texture is bound to that unit is read with this sampler's
+  /// settings, until the sampler binding is cleared.
+  pub fn bind(&self, unit: u32) {
+    unsafe { glBindSampler(unit, self.0) }
+  }
+
+  /// Clears the sampler binding of a texture unit, so that the texture's own
+  /// settings are used again.
+  pub fn clear_binding(unit: u32) {
+    unsafe { glBindSampler(unit, 0) }
+  }
+
+  /// Sets the min and mag filters.
+  pub fn set_filters(&self, min: MinFilter, mag: MagFilter) {
+    unsafe {
+      glSamplerParameteri(self.0, GL_TEXTURE_MIN_FILTER, min as GLint);
+      glSamplerParameteri(self.0, GL_TEXTURE_MAG_FILTER, mag as GLint);
+    }
+  }
+
+  /// Sets the wrapping on all axes.
+  pub fn set_wrap(&self, wrap: TextureWrap) {
+    self.set_wrap_axes([wrap; 3])
+  }
+
+  /// Sets the wrapping of the `s`, `t`, and `r` axes separately.
+  pub fn set_wrap_axes(&self, [s, t, r]: [TextureWrap; 3]) {
+    unsafe {
+      glSamplerParameteri(self.0, GL_TEXTURE_WRAP_S, s as GLint);
+      glSamplerParameteri(self.0, GL_TEXTURE_WRAP_T, t as GLint);
+      glSamplerParameteri(self.0, GL_TEXTURE_WRAP_R, r as GLint);
+    }
+  }
+
+  /// Sets the border color used with [`TextureWrap::ClampToBorder`].
+  pub fn set_border_color(&self, color: [f32; 4]) {
+    unsafe {
+      glSamplerParameterfv(self.0, GL_TEXTURE_BORDER_COLOR, color.as_ptr())
+    }
+  }
+
+  /// Sets the depth comparison, see
+  /// [`texture::set_depth_compare`](crate::texture::set_depth_compare).
+  pub fn set_depth_compare(&self, func: Option<CompareFunc>) {
+    let (mode, func) = match func {
+      Some(func) => (GL_COMPARE_REF_TO_TEXTURE, func as GLenum),
+      None => (GL_NONE, GL_LEQUAL),
+    };
+    unsafe {
+      glSamplerParameteri(self.0, GL_TEXTURE_COMPARE_MODE, mode as GLint);
+      glSamplerParameteri(self.0, GL_TEXTURE_COMPARE_FUNC, func as GLint);
+    }
+  }
+
+  /// Sets the range of mip levels that can be used, and the bias added to
+  /// the level that would normally be picked.
+  pub fn set_lod(&self, min: f32, max: f32, bias: f32) {
+    unsafe {
+      glSamplerParameterf(self.0, GL_TEXTURE_MIN_LOD, min);
+      glSamplerParameterf(self.0, GL_TEXTURE_MAX_LOD, max);
+      glSamplerParameterf(self.0, GL_TEXTURE_LOD_BIAS, bias);
+    }
+  }
+
+  /// Sets the anisotropic filtering amount.
+  ///
+  /// This is clamped to [`max_anisotropy`], and does nothing if the driver
+  /// doesn't support anisotropic filtering.
+  pub fn set_anisotropy(&self, amount: f32) {
+    let max = max_anisotropy();
+    if max > 1.0 {
+      let amount = amount.max(1.0).min(max);
+      unsafe { glSamplerParameterf(self.0, GL_TEXTURE_MAX_ANISOTROPY, amount) }
+    }
+  }
+
+  /// Deletes the sampler.
+  pub fn delete(self) {
+    unsafe { glDeleteSamplers(1, &self.0) }
+  }
+}
@@ -0,0 +1,540 @@
+//! Shadow maps for directional lights and spot lights.
+//!
+//! A shadow map is the scene's depth as seen from the light. When drawing
+//! the scene normally, each fragment gets moved into the light's clip space
+//! and compared against that depth: if something else was closer to the
+//! light, the fragment is in shadow.
+//!
+//! * A [`SpotShadow`] is the easy case: one perspective view from the light.
+//! * A [`CascadedShadowMap`] is for a directional light (like the sun). One
+//!   map big enough for the whole view would have far too few texels near
+//!   the camera, so the camera's view is cut into slices ("cascades") by
+//!   distance and each slice gets its own map, packed side by side into one
+//!   depth texture.
+//!
+//! The GLSL side is in [`SHADOW_GLSL`], which you paste into your lighting
+//! shader after the `#version` line. The matching uniforms are set by the
+//! `set_uniforms` methods here.
+//!
+//! ## Drawing the shadow pass
+//! ```ignore
+//! csm.update(-sun_direction, camera.view_matrix(), &camera.projection);
+//! csm.map.begin(&mut state_cache);
+//! for (i, cascade) in csm.cascades().iter().enumerate() {
+//!   csm.map.begin_tile(i as u32);
+//!   draw_scene_depth(cascade.view_projection);
+//! }
+//! ShadowMap::end();
+//! resizer.reset_viewport();
+//! ```
+
+use crate::{
+  camera::Projection,
+  framebuffer::{
+    DepthBuffer, Framebuffer, FramebufferTarget, RenderTarget, RenderTargetDesc,
+  },
+  state::{CompareFunc, CullState, DepthState, RenderState, StateCache},
+  texture::{
+    self, MagFilter, MinFilter, Texture, TextureFormat, TextureTarget,
+  },
+  ShaderProgram,
+};
+use core::convert::TryInto;
+use ogl33::*;
+use ultraviolet::{projection, Mat4, Vec3, Vec4};
+
+/// The most cascades that [`SHADOW_GLSL`] supports.
+pub const MAX_CASCADES: usize = 4;
+
+/// GLSL helpers for sampling shadow maps.
+///
+/// All of these give `1.0` for fully lit and `0.0` for fully shadowed.
+///
+/// * `float cascaded_shadow(vec3 world_pos, vec3 normal, vec3 to_light,
+///   float view_depth)`: the directional light's shadow. `view_depth` is the
+///   distance in front of the camera, `-(view * world_pos).z`.
+/// * `float spot_shadow(vec3 world_pos, vec3 normal, vec3 to_light)`: the
+///   spot light's shadow.
+/// * `float shadow_bias(vec3 normal, vec3 to_light)`: the depth bias from the
+///   bias uniforms, bigger when the light hits the surface at an angle.
+/// * `float shadow_pcf(sampler2DShadow map, vec3 coords, vec2 tile_min, vec2
+///   tile_max, float bias)`: percentage-closer filtering over a square of
+///   `2 * shadow_pcf_radius + 1` texels, staying inside the given tile.
+pub const SHADOW_GLSL: &str = r#"
+  const int MAX_SHADOW_CASCADES = 4;
+
+  uniform float shadow_constant_bias;
+  uniform float shadow_slope_bias;
+  uniform float shadow_normal_offset;
+  uniform int shadow_pcf_radius;
+
+  uniform sampler2DShadow cascade_shadow_map;
+  uniform mat4 cascade_shadow_matrices[MAX_SHADOW_CASCADES];
+  uniform float cascade_shadow_splits[MAX_SHADOW_CASCADES];
+  uniform int cascade_shadow_count;
+
+  uniform sampler2DShadow spot_shadow_map;
+  uniform mat4 spot_shadow_matrix;
+
+  float shadow_bias(vec3 normal, vec3 to_light) {
+    float cos_theta = clamp(dot(normal, to_light), 0.0, 1.0);
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    float tan_theta = min(sin_theta / max(cos_theta, 0.001), 10.0);
+    return shadow_constant_bias + shadow_slope_bias * tan_theta;
+  }
+
+  vec3 shadow_offset_position(vec3 world_pos, vec3 normal, vec3 to_light) {
+    float cos_theta = clamp(dot(normal, to_light), 0.0, 1.0);
+    return world_pos + normal * shadow_normal_offset * (1.0 - cos_theta);
+  }
+
+  float shadow_pcf(
+    sampler2DShadow map, vec3 coords, vec2 tile_min, vec2 tile_max, float bias
+  ) {
+    vec2 texel = 1.0 / vec2(textureSize(map, 0));
+    vec2 lo = tile_min + texel * 0.5;
+    vec2 hi = tile_max - texel * 0.5;
+    float sum = 0.0;
+    float count = 0.0;
+    for (int x = -shadow_pcf_radius; x <= shadow_pcf_radius; ++x) {
+      for (int y = -shadow_pcf_radius; y <= shadow_pcf_radius; ++y) {
+        vec2 uv = clamp(coords.xy + vec2(x, y) * texel, lo, hi);
+        sum += texture(map, vec3(uv, coords.z - bias));
+        count += 1.0;
+      }
+    }
+    return sum / count;
+  }
+
+  float shadow_tile(
+    sampler2DShadow map, int tile, int tiles, vec4 light_clip, float bias
+  ) {
+    vec3 p = light_clip.xyz / light_clip.w * 0.5 + 0.5;
+    if (any(lessThan(p, vec3(0.0))) || any(greaterThan(p, vec3(1.0)))) {
+      return 1.0;
+    }
+    float w = 1.0 / float(tiles);
+    vec2 tile_min = vec2(float(tile) * w, 0.0);
+    vec2 tile_max = vec2(float(tile + 1) * w, 1.0);
+    p.x = (float(tile) + p.x) * w;
+    return shadow_pcf(map, p, tile_min, tile_max, bias);
+  }
+
+  float cascaded_shadow(
+    vec3 world_pos, vec3 normal, vec3 to_light, float view_depth
+  ) {
+    int cascade = -1;
+    for (int i = 0; i < cascade_shadow_count; ++i) {
+      if (view_depth <= cascade_shadow_splits[i]) {
+        cascade = i;
+        break;
+      }
+    }
+    if (cascade < 0) {
+      return 1.0;
+    }
+    vec3 p = shadow_offset_position(world_pos, normal, to_light);
+    vec4 light_clip = cascade_shadow_matrices[cascade] * vec4(p, 1.0);
+    float bias = shadow_bias(normal, to_light);
+    return shadow_tile(
+      cascade_shadow_map, cascade, cascade_shadow_count, light_clip, bias
+    );
+  }
+
+  float spot_shadow(vec3 world_pos, vec3 normal, vec3 to_light) {
+    vec3 p = shadow_offset_position(world_pos, normal, to_light);
+    vec4 light_clip = spot_shadow_matrix * vec4(p, 1.0);
+    float bias = shadow_bias(normal, to_light);
+    return shadow_tile(spot_shadow_map, 0, 1, light_clip, bias);
+  }
+"#;
+
+/// The knobs for fighting shadow acne and peter-panning.
+///
+/// Too little bias and lit surfaces shadow themselves in stripes ("acne").
+/// Too much and shadows come loose from the objects casting them
+/// ("peter-panning"). The right values depend on the scene's scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+  /// Depth bias that's always applied, in light clip space depth.
+  pub constant_bias: f32,
+  /// Extra depth bias, scaled by how steep the light hits the surface.
+  pub slope_bias: f32,
+  /// How far to push the lookup position out along the surface normal, in
+  /// world units.
+  pub normal_offset: f32,
+  /// The PCF kernel is `2 * pcf_radius + 1` texels wide. `0` is just the
+  /// hardware's 2x2 filtering.
+  pub pcf_radius: u32,
+}
+impl Default for ShadowSettings {
+  fn default() -> Self {
+    Self {
+      constant_bias: 0.0005,
+      slope_bias: 0.001,
+      normal_offset: 0.02,
+      pcf_radius: 1,
+    }
+  }
+}
+impl ShadowSettings {
+  /// Sets the `shadow_*` uniforms of a program using [`SHADOW_GLSL`].
+  ///
+  /// The program must be in use.
+  pub fn set_uniforms(&self, program: &ShaderProgram) {
+    unsafe {
+      if let Some(loc) = program.uniform_location("shadow_constant_bias") {
+        glUniform1f(loc, self.constant_bias);
+      }
+      if let Some(loc) = program.uniform_location("shadow_slope_bias") {
+        glUniform1f(loc, self.slope_bias);
+      }
+      if let Some(loc) = program.uniform_location("shadow_normal_offset") {
+        glUniform1f(loc, self.normal_offset);
+      }
+      if let Some(loc) = program.uniform_location("shadow_pcf_radius") {
+        glUniform1i(loc, self.pcf_radius.try_into().unwrap());
+      }
+    }
+  }
+}
+
+/// A depth-only render target with one or more square tiles side by side.
+pub struct ShadowMap {
+  target: RenderTarget,
+  tile_size: u32,
+  tiles: u32,
+}
+impl ShadowMap {
+  /// Makes a shadow map with `tiles` tiles of `tile_size` squared texels.
+  ///
+  /// The depth texture is set up for `sampler2DShadow` lookups.
+  pub fn new(tile_size: u32, tiles: u32) -> Result<Self, String> {
+    let desc = RenderTargetDesc {
+      color: Vec::new(),
+      depth: Some(TextureFormat::Depth32F),
+      depth_as_texture: true,
+      samples: 0,
+    };
+    let target = RenderTarget::new(tile_size * tiles.max(1), tile_size, desc)?;
+    let out = Self { target, tile_size, tiles: tiles.max(1) };
+    out.depth_texture().bind(TextureTarget::Texture2D);
+    texture::set_filters(
+      TextureTarget::Texture2D,
+      MinFilter::Linear,
+      MagFilter::Linear,
+    );
+    texture::set_depth_compare(
+      TextureTarget::Texture2D,
+      Some(CompareFunc::LessEqual),
+    );
+    Texture::clear_binding(TextureTarget::Texture2D);
+    Ok(out)
+  }
+
+  /// The depth texture.
+  pub fn depth_texture(&self) -> &Texture {
+    match &self.target.depth {
+      Some(DepthBuffer::Texture(tex)) => tex,
+      _ => unreachable!("shadow maps always have a depth texture"),
+    }
+  }
+
+  /// The width and height of each tile.
+  pub fn tile_size(&self) -> u32 {
+    self.tile_size
+  }
+
+  /// The number of tiles.
+  pub fn tiles(&self) -> u32 {
+    self.tiles
+  }
+
+  /// The state for drawing into a shadow map: depth only, back faces culled.
+  pub fn render_state() -> RenderState {
+    RenderState {
+      depth: DepthState { test: true, ..DepthState::default() },
+      cull: CullState { enabled: true, ..CullState::default() },
+      color_mask: [false; 4],
+      ..RenderState::default()
+    }
+  }
+
+  /// Binds the shadow map, applies the [`render_state`](Self::render_state),
+  /// and clears all the tiles.
+  pub fn begin(&self, cache: &mut StateCache) {
+    self.target.bind();
+    cache.apply(&Self::render_state());
+    unsafe { glClear(GL_DEPTH_BUFFER_BIT) }
+  }
+
+  /// Sets the viewport to cover just one tile.
+  pub fn begin_tile(&self, tile: u32) {
+    assert!(tile < self.tiles);
+    let x = (tile * self.tile_size).try_into().unwrap();
+    crate::viewport(x, 0, self.tile_size, self.tile_size);
+  }
+
+  /// Goes back to drawing to the window.
+  ///
+  /// You'll also need to reset the viewport.
+  pub fn end() {
+    Framebuffer::clear_binding(FramebufferTarget::Both);
+  }
+
+  /// Binds the depth texture to a texture unit.
+  pub fn bind_texture(&self, unit: u32) {
+    texture::active_texture(unit);
+    self.depth_texture().bind(TextureTarget::Texture2D);
+  }
+
+  /// Deletes the framebuffer and depth texture.
+  pub fn delete(self) {
+    self.target.delete()
+  }
+}
+
+/// The distances that split `near ..= far` into `count` cascades.
+///
+/// Gives the far distance of each cascade. `lambda` blends between evenly
+/// spaced splits (`0.0`) and logarithmic splits (`1.0`). Logarithmic splits
+/// match how perspective spreads texels out, but they make the first cascade
+/// tiny, so something in between (like `0.75`) usually looks best.
+pub fn cascade_splits(
+  near: f32,
+  far: f32,
+  count: usize,
+  lambda: f32,
+) -> Vec<f32> {
+  (1..=count)
+    .map(|i| {
+      let t = i as f32 / count as f32;
+      let log = near * (far / near).powf(t);
+      let uniform = near + (far - near) * t;
+      lambda * log + (1.0 - lambda) * uniform
+    })
+    .collect()
+}
+
+/// The eight corners of the view volume of `view_projection`, in world
+/// space.
+pub fn frustum_corners(view_projection: Mat4) -> [Vec3; 8] {
+  let inv = view_projection.inversed();
+  let mut out = [Vec3::zero(); 8];
+  for (i, corner) in out.iter_mut().enumerate() {
+    let ndc = Vec4::new(
+      if i & 1 == 0 { -1.0 } else { 1.0 },
+      if i & 2 == 0 { -1.0 } else { 1.0 },
+      if i & 4 == 0 { -1.0 } else { 1.0 },
+      1.0,
+    );
+    let p = inv * ndc;
+    *corner = p.truncated() / p.w;
+  }
+  out
+}
+
+/// An up vector that isn't parallel to the light's direction.
+fn light_up(direction: Vec3) -> Vec3 {
+  if direction.y.abs() > 0.99 {
+    Vec3::unit_z()
+  } else {
+    Vec3::unit_y()
+  }
+}
+
+/// One slice of a [`CascadedShadowMap`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cascade {
+  /// The light's view-projection for this slice.
+  pub view_projection: Mat4,
+  /// The far edge of this slice, as a distance in front of the camera.
+  pub split_far: f32,
+}
+
+/// The shadows of a directional light, in cascades.
+pub struct CascadedShadowMap {
+  /// The shadow map, with one tile per cascade.
+  pub map: ShadowMap,
+  /// The blend between even and logarithmic splits, see [`cascade_splits`].
+  pub split_lambda: f32,
+  /// How far behind each slice (towards the light) to still catch shadow
+  /// casters, in world units.
+  pub caster_margin: f32,
+  cascades: Vec<Cascade>,
+}
+impl CascadedShadowMap {
+  /// Makes a shadow map with `count` cascades of `tile_size` squared texels.
+  pub fn new(tile_size: u32, count: usize) -> Result<Self, String> {
+    if count == 0 || count > MAX_CASCADES {
+      return Err(format!(
+        "Cascade count must be 1 to {}, got {}",
+        MAX_CASCADES, count
+      ));
+    }
+    Ok(Self {
+      map: ShadowMap::new(tile_size, count as u32)?,
+      split_lambda: 0.75,
+      caster_margin: 50.0,
+      cascades: vec![
+        Cascade {
+          view_projection: Mat4::identity(),
+          split_far: 0.0
+        };
+        count
+      ],
+    })
+  }
+
+  /// The cascades, nearest first.
+  pub fn cascades(&self) -> &[Cascade] {
+    &self.cascades
+  }
+
+  /// Fits the cascades around the camera's current view.
+  ///
+  /// `light_direction` is the way the light travels (eg: down, for a noon
+  /// sun). Each cascade is fitted to a sphere around its slice of the view,
+  /// and snapped to whole texels, so that the shadow edges don't shimmer as
+  /// the camera moves and turns.
+  pub fn update(
+    &mut self,
+    light_direction: Vec3,
+    view: Mat4,
+    projection: &Projection,
+  ) {
+    let dir = light_direction.normalized();
+    let (near, far) = projection.near_far();
+    let splits =
+      cascade_splits(near, far, self.cascades.len(), self.split_lambda);
+    let texels_per_ndc = self.map.tile_size() as f32 / 2.0;
+    let mut slice_near = near;
+    for (cascade, &split_far) in self.cascades.iter_mut().zip(splits.iter()) {
+      let slice = projection.with_near_far(slice_near, split_far).matrix();
+      let corners = frustum_corners(slice * view);
+      let center =
+        corners.iter().fold(Vec3::zero(), |a, &c| a + c) / corners.len() as f32;
+      let radius =
+        corners.iter().map(|&c| (c - center).mag()).fold(0.0_f32, f32::max);
+      // keeping the size steady keeps the texel size steady
+      let radius = (radius * 16.0).ceil() / 16.0;
+      let eye = center - dir * (radius + self.caster_margin);
+      let light_view = Mat4::look_at(eye, center, light_up(dir));
+      let mut ortho = projection::orthographic_gl(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + self.caster_margin,
+      );
+      let origin = (ortho * light_view) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+      let snap = |v: f32| {
+        let texels = v * texels_per_ndc;
+        (texels.round() - texels) / texels_per_ndc
+      };
+      ortho.cols[3].x += snap(origin.x);
+      ortho.cols[3].y += snap(origin.y);
+      *cascade = Cascade { view_projection: ortho * light_view, split_far };
+      slice_near = split_far;
+    }
+  }
+
+  /// Binds the map to a texture unit and sets the `cascade_shadow_*`
+  /// uniforms of a program using [`SHADOW_GLSL`].
+  ///
+  /// The program must be in use.
+  pub fn set_uniforms(&self, program: &ShaderProgram, unit: u32) {
+    self.map.bind_texture(unit);
+    let count = self.cascades.len();
+    let matrices: Vec<Mat4> =
+      self.cascades.iter().map(|c| c.view_projection).collect();
+    let splits: Vec<f32> = self.cascades.iter().map(|c| c.split_far).collect();
+    unsafe {
+      if let Some(loc) = program.uniform_location("cascade_shadow_map") {
+        glUniform1i(loc, unit.try_into().unwrap());
+      }
+      if let Some(loc) = program.uniform_location("cascade_shadow_matrices") {
+        glUniformMatrix4fv(
+          loc,
+          count.try_into().unwrap(),
+          GL_FALSE,
+          matrices.as_ptr().cast(),
+        );
+      }
+      if let Some(loc) = program.uniform_location("cascade_shadow_splits") {
+        glUniform1fv(loc, count.try_into().unwrap(), splits.as_ptr());
+      }
+      if let Some(loc) = program.uniform_location("cascade_shadow_count") {
+        glUniform1i(loc, count.try_into().unwrap());
+      }
+    }
+  }
+
+  /// Deletes the shadow map.
+  pub fn delete(self) {
+    self.map.delete()
+  }
+}
+
+/// The shadow of a spot light.
+pub struct SpotShadow {
+  /// The shadow map, with one tile.
+  pub map: ShadowMap,
+  view_projection: Mat4,
+}
+impl SpotShadow {
+  /// Makes a spot light shadow map of `size` squared texels.
+  pub fn new(size: u32) -> Result<Self, String> {
+    Ok(Self {
+      map: ShadowMap::new(size, 1)?,
+      view_projection: Mat4::identity(),
+    })
+  }
+
+  /// The light's view-projection, for drawing into the map.
+  pub fn view_projection(&self) -> Mat4 {
+    self.view_projection
+  }
+
+  /// Points the shadow's view along the light.
+  ///
+  /// `outer_cone_deg` is the angle from the center of the spot to its outer
+  /// edge. Keep `near` as big as the scene allows, depth precision is spent
+  /// mostly close to the near plane.
+  pub fn update(
+    &mut self,
+    position: Vec3,
+    direction: Vec3,
+    outer_cone_deg: f32,
+    near: f32,
+    far: f32,
+  ) {
+    let dir = direction.normalized();
+    let view = Mat4::look_at(position, position + dir, light_up(dir));
+    let fov = (2.0 * outer_cone_deg).clamp(1.0, 170.0).to_radians();
+    self.view_projection =
+      projection::perspective_gl(fov, 1.0, near, far) * view;
+  }
+
+  /// Binds the map to a texture unit and sets the `spot_shadow_*` uniforms of
+  /// a program using [`SHADOW_GLSL`].
+  ///
+  /// The program must be in use.
+  pub fn set_uniforms(&self, program: &ShaderProgram, unit: u32) {
+    self.map.bind_texture(unit);
+    unsafe {
+      if let Some(loc) = program.uniform_location("spot_shadow_map") {
+        glUniform1i(loc, unit.try_into().unwrap());
+      }
+      if let Some(loc) = program.uniform_location("spot_shadow_matrix") {
+        glUniformMatrix4fv(loc, 1, GL_FALSE, self.view_projection.as_ptr());
+      }
+    }
+  }
+
+  /// Deletes the shadow map.
+  pub fn delete(self) {
+    self.map.delete()
+  }
+}
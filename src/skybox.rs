@@ -0,0 +1,169 @@
+//! Drawing a [`TextureCube`] as the background of a scene.
+//!
+//! The skybox is a cube around the camera. Only the camera's _rotation_
+//! is used, so no matter how far you walk the sky stays the same distance
+//! away. The vertex shader also forces every vertex to the far plane, so draw
+//! the skybox _after_ the opaque geometry and it only fills in the pixels
+//! that nothing else covered.
+
+use crate::{
+  buffer_data,
+  cubemap::TextureCube,
+  state::{CompareFunc, DepthState, RenderState, StateCache},
+  texture, Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{convert::TryInto, mem::size_of};
+use ogl33::*;
+use ultraviolet::{Mat4, Vec4};
+
+const VERT_SHADER: &str = r#"#version 330 core
+  layout (location = 0) in vec3 pos;
+
+  uniform mat4 view_projection;
+
+  out vec3 direction;
+
+  void main() {
+    direction = pos;
+    vec4 p = view_projection * vec4(pos, 1.0);
+    gl_Position = p.xyww;
+  }
+"#;
+
+const FRAG_SHADER: &str = r#"#version 330 core
+  in vec3 direction;
+
+  uniform samplerCube sky;
+
+  out vec4 final_color;
+
+  void main() {
+    final_color = texture(sky, direction);
+  }
+"#;
+
+/// A cube from `-1.0` to `1.0` on each axis, as 36 triangle list positions.
+#[rustfmt::skip]
+pub(crate) const CUBE_VERTICES: [[f32; 3]; 36] = [
+  [-1.0,  1.0, -1.0], [-1.0, -1.0, -1.0], [ 1.0, -1.0, -1.0],
+  [ 1.0, -1.0, -1.0], [ 1.0,  1.0, -1.0], [-1.0,  1.0, -1.0],
+
+  [-1.0, -1.0,  1.0], [-1.0, -1.0, -1.0], [-1.0,  1.0, -1.0],
+  [-1.0,  1.0, -1.0], [-1.0,  1.0,  1.0], [-1.0, -1.0,  1.0],
+
+  [ 1.0, -1.0, -1.0], [ 1.0, -1.0,  1.0], [ 1.0,  1.0,  1.0],
+  [ 1.0,  1.0,  1.0], [ 1.0,  1.0, -1.0], [ 1.0, -1.0, -1.0],
+
+  [-1.0, -1.0,  1.0], [-1.0,  1.0,  1.0], [ 1.0,  1.0,  1.0],
+  [ 1.0,  1.0,  1.0], [ 1.0, -1.0,  1.0], [-1.0, -1.0,  1.0],
+
+  [-1.0,  1.0, -1.0], [ 1.0,  1.0, -1.0], [ 1.0,  1.0,  1.0],
+  [ 1.0,  1.0,  1.0], [-1.0,  1.0,  1.0], [-1.0,  1.0, -1.0],
+
+  [-1.0, -1.0, -1.0], [-1.0, -1.0,  1.0], [ 1.0, -1.0, -1.0],
+  [ 1.0, -1.0, -1.0], [-1.0, -1.0,  1.0], [ 1.0, -1.0,  1.0],
+];
+
+/// Strips the translation out of a view matrix, leaving only the rotation.
+pub fn rotation_only(view: Mat4) -> Mat4 {
+  let mut out = view;
+  out.cols[3] = Vec4::new(0.0, 0.0, 0.0, 1.0);
+  out
+}
+
+/// Draws a cube map as the scene's background.
+pub struct Skybox {
+  vao: VertexArray,
+  vbo: Buffer,
+  program: ShaderProgram,
+  view_projection_loc: GLint,
+}
+impl Skybox {
+  /// Makes the cube mesh and compiles the skybox shader.
+  pub fn new() -> Result<Self, String> {
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    vao.bind();
+    let vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&CUBE_VERTICES),
+      GL_STATIC_DRAW,
+    );
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        3,
+        GL_FLOAT,
+        GL_FALSE,
+        size_of::<[f32; 3]>().try_into().unwrap(),
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+    }
+    VertexArray::clear_binding();
+
+    let program = ShaderProgram::from_vert_frag(VERT_SHADER, FRAG_SHADER)?;
+    let view_projection_loc = program
+      .uniform_location("view_projection")
+      .ok_or("Skybox shader is missing `view_projection`")?;
+    program.use_program();
+    if let Some(sky_loc) = program.uniform_location("sky") {
+      unsafe { glUniform1i(sky_loc, 0) };
+    }
+    // otherwise the edges of the sky's faces show
+    texture::enable_seamless_cube_maps();
+    Ok(Self { vao, vbo, program, view_projection_loc })
+  }
+
+  /// The state that the skybox draws with.
+  ///
+  /// The depth test uses `LessEqual` so that the sky (which is exactly at the
+  /// far plane) still passes against a cleared depth buffer, and it doesn't
+  /// write depth. The cube is seen from the inside, so culling is off.
+  pub fn render_state() -> RenderState {
+    RenderState {
+      depth: DepthState {
+        test: true,
+        func: CompareFunc::LessEqual,
+        write: false,
+      },
+      ..RenderState::default()
+    }
+  }
+
+  /// Draws the sky.
+  ///
+  /// Pass the camera's normal view matrix, the translation is removed here.
+  /// This uses texture unit 0, and leaves the skybox program in use.
+  pub fn draw(
+    &self,
+    sky: &TextureCube,
+    view: Mat4,
+    projection: Mat4,
+    cache: &mut StateCache,
+  ) {
+    cache.apply(&Self::render_state());
+    let view_projection = projection * rotation_only(view);
+    self.program.use_program();
+    sky.bind(0);
+    self.vao.bind();
+    unsafe {
+      glUniformMatrix4fv(
+        self.view_projection_loc,
+        1,
+        GL_FALSE,
+        view_projection.as_ptr(),
+      );
+      glDrawArrays(GL_TRIANGLES, 0, CUBE_VERTICES.len() as GLsizei);
+    }
+    VertexArray::clear_binding();
+  }
+
+  /// Deletes the GL objects.
+  pub fn delete(self) {
+    self.vao.delete();
+    self.vbo.delete();
+    self.program.delete();
+  }
+}
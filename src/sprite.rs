@@ -0,0 +1,511 @@
+//! Drawing lots of 2D textured quads, and a camera to look at them with.
+//!
+//! A [`SpriteBatch`] collects sprites during a frame and draws them all at
+//! [`flush`](SpriteBatch::flush). The sprites are sorted so that each run of
+//! sprites with the same texture is one draw call, and all their vertices go
+//! into the GPU in one upload.
+//!
+//! The sprites live in a 2D world with `+Y` up, and a [`Camera2D`] decides
+//! which part of that world is on screen. At a zoom of `1.0` one world unit
+//! is one pixel.
+
+use crate::{
+  atlas::{Atlas, UvRect},
+  buffer_data,
+  state::{RenderState, StateCache},
+  texture::{self, Texture, TextureTarget},
+  Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{convert::TryInto, mem::size_of};
+use ogl33::*;
+use ultraviolet::{projection, Mat4, Vec2, Vec3, Vec4};
+
+const VERT_SHADER: &str = r#"#version 330 core
+  layout (location = 0) in vec2 pos;
+  layout (location = 1) in vec2 uv;
+  layout (location = 2) in vec4 tint;
+
+  uniform mat4 view_projection;
+
+  out vec2 frag_uv;
+  out vec4 frag_tint;
+
+  void main() {
+    frag_uv = uv;
+    frag_tint = tint;
+    gl_Position = view_projection * vec4(pos, 0.0, 1.0);
+  }
+"#;
+
+const FRAG_SHADER: &str = r#"#version 330 core
+  in vec2 frag_uv;
+  in vec4 frag_tint;
+
+  uniform sampler2D sprite;
+
+  out vec4 final_color;
+
+  void main() {
+    final_color = texture(sprite, frag_uv) * frag_tint;
+  }
+"#;
+
+/// An orthographic camera for a 2D world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera2D {
+  /// The world point at the center of the screen.
+  pub position: Vec2,
+  /// How many pixels one world unit takes up.
+  pub zoom: f32,
+  /// Rotation of the camera, in radians, counter-clockwise.
+  pub rotation: f32,
+  /// The size of the screen, in pixels.
+  pub viewport: Vec2,
+}
+impl Camera2D {
+  /// A camera at the origin with no zoom or rotation.
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      position: Vec2::zero(),
+      zoom: 1.0,
+      rotation: 0.0,
+      viewport: Vec2::new(width as f32, height as f32),
+    }
+  }
+
+  /// Changes the screen size, call this when the window is resized.
+  pub fn set_viewport(&mut self, width: u32, height: u32) {
+    self.viewport = Vec2::new(width as f32, height as f32);
+  }
+
+  /// The camera's view matrix.
+  pub fn view_matrix(&self) -> Mat4 {
+    Mat4::from_rotation_z(-self.rotation)
+      * Mat4::from_translation(Vec3::new(
+        -self.position.x,
+        -self.position.y,
+        0.0,
+      ))
+  }
+
+  /// The camera's projection matrix.
+  pub fn projection_matrix(&self) -> Mat4 {
+    let half = self.viewport / (2.0 * self.zoom.max(f32::EPSILON));
+    projection::orthographic_gl(-half.x, half.x, -half.y, half.y, -1.0, 1.0)
+  }
+
+  /// The projection matrix times the view matrix.
+  pub fn view_projection_matrix(&self) -> Mat4 {
+    self.projection_matrix() * self.view_matrix()
+  }
+
+  /// Turns a pixel position (from the top left, like mouse positions) into a
+  /// world position.
+  pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+    let ndc = Vec4::new(
+      screen.x / self.viewport.x * 2.0 - 1.0,
+      1.0 - screen.y / self.viewport.y * 2.0,
+      0.0,
+      1.0,
+    );
+    let world = self.view_projection_matrix().inversed() * ndc;
+    Vec2::new(world.x, world.y)
+  }
+
+  /// Turns a world position into a pixel position (from the top left).
+  pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+    let clip =
+      self.view_projection_matrix() * Vec4::new(world.x, world.y, 0.0, 1.0);
+    Vec2::new(
+      (clip.x + 1.0) * 0.5 * self.viewport.x,
+      (1.0 - clip.y) * 0.5 * self.viewport.y,
+    )
+  }
+
+  /// Zooms by `factor` while keeping the world point under `screen` in the
+  /// same place, like zooming toward the mouse in an editor.
+  pub fn zoom_at(&mut self, screen: Vec2, factor: f32) {
+    let before = self.screen_to_world(screen);
+    self.zoom *= factor;
+    let after = self.screen_to_world(screen);
+    self.position += before - after;
+  }
+}
+
+/// One textured quad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+  /// Where the origin point of the sprite goes, in world units.
+  pub position: Vec2,
+  /// Width and height, in world units.
+  pub size: Vec2,
+  /// Rotation around the origin point, in radians, counter-clockwise.
+  pub rotation: f32,
+  /// The point that the sprite is placed and rotated around, from `(0, 0)`
+  /// at the bottom left to `(1, 1)` at the top right.
+  pub origin: Vec2,
+  /// The part of the texture to show.
+  pub uv: UvRect,
+  /// Multiplies the texture's colors.
+  pub tint: Vec4,
+  /// Sprites on higher layers are drawn over lower ones.
+  pub layer: f32,
+}
+impl Default for Sprite {
+  /// A 1x1 sprite at the origin, centered, showing the whole texture.
+  fn default() -> Self {
+    Self {
+      position: Vec2::zero(),
+      size: Vec2::one(),
+      rotation: 0.0,
+      origin: Vec2::new(0.5, 0.5),
+      uv: UvRect { min: Vec2::zero(), max: Vec2::one() },
+      tint: Vec4::one(),
+      layer: 0.0,
+    }
+  }
+}
+impl Sprite {
+  /// A centered sprite showing the whole texture.
+  pub fn new(position: Vec2, size: Vec2) -> Self {
+    Self { position, size, ..Self::default() }
+  }
+
+  /// A centered sprite showing one image of an atlas, at its pixel size.
+  ///
+  /// ## Panics
+  /// * If the index is out of bounds.
+  pub fn from_atlas(atlas: &Atlas, index: usize, position: Vec2) -> Self {
+    let rect = atlas.rect(index);
+    Self {
+      position,
+      size: Vec2::new(rect.width as f32, rect.height as f32),
+      uv: atlas.uv(index),
+      ..Self::default()
+    }
+  }
+
+  /// The four corners, counter-clockwise from the bottom left, with their
+  /// texture coordinates.
+  fn corners(&self) -> [(Vec2, Vec2); 4] {
+    let (sin, cos) = self.rotation.sin_cos();
+    let uv = self.uv;
+    let corner = |x: f32, y: f32| {
+      let local = (Vec2::new(x, y) - self.origin) * self.size;
+      let rotated =
+        Vec2::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos);
+      let tex = Vec2::new(
+        uv.min.x + (uv.max.x - uv.min.x) * x,
+        uv.min.y + (uv.max.y - uv.min.y) * y,
+      );
+      (self.position + rotated, tex)
+    };
+    [corner(0.0, 0.0), corner(1.0, 0.0), corner(1.0, 1.0), corner(0.0, 1.0)]
+  }
+}
+
+/// The order that a [`SpriteBatch`] draws in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteSort {
+  /// By layer, then by texture within each layer. This is right for
+  /// see-through sprites, and still groups textures when it can.
+  Layer,
+  /// By texture only, for the fewest draw calls. Layers are ignored, so only
+  /// use this when the sprites don't overlap.
+  Texture,
+  /// In the order they were added.
+  Submission,
+}
+
+/// `x, y, u, v, r, g, b, a`
+type SpriteVertex = [f32; 8];
+
+/// Collects sprites over a frame and draws them in as few calls as it can.
+///
+/// ```ignore
+/// let mut batch = SpriteBatch::new()?;
+/// let camera = Camera2D::new(800, 600);
+/// // each frame
+/// batch.draw(&atlas.texture, &Sprite::from_atlas(&atlas, player, pos));
+/// for tile in &tiles {
+///   batch.draw(&tile_texture, tile);
+/// }
+/// batch.flush(camera.view_projection_matrix(), &mut cache);
+/// ```
+pub struct SpriteBatch {
+  vao: VertexArray,
+  vbo: Buffer,
+  ebo: Buffer,
+  program: ShaderProgram,
+  view_projection_loc: GLint,
+  /// How many quads the index buffer has indexes for.
+  index_capacity: usize,
+  /// Texture, layer, and the quad's vertices.
+  sprites: Vec<(GLuint, f32, [SpriteVertex; 4])>,
+  vertices: Vec<SpriteVertex>,
+  draw_calls: usize,
+  /// The draw order. Defaults to [`SpriteSort::Layer`].
+  pub sort: SpriteSort,
+  /// The state that sprites draw with. Defaults to
+  /// [`RenderState::overlay`].
+  pub render_state: RenderState,
+}
+impl SpriteBatch {
+  /// Makes the buffers and compiles the sprite shader.
+  pub fn new() -> Result<Self, String> {
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    vao.bind();
+    let vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    vbo.bind(BufferType::Array);
+    let ebo = Buffer::new().ok_or("Couldn't make an EBO")?;
+    ebo.bind(BufferType::ElementArray);
+    let stride: GLsizei = size_of::<SpriteVertex>().try_into().unwrap();
+    let float = size_of::<f32>();
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        2,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+      glVertexAttribPointer(
+        1,
+        2,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        (2 * float) as *const _,
+      );
+      glEnableVertexAttribArray(1);
+      glVertexAttribPointer(
+        2,
+        4,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        (4 * float) as *const _,
+      );
+      glEnableVertexAttribArray(2);
+    }
+    VertexArray::clear_binding();
+    Buffer::clear_binding(BufferType::Array);
+    Buffer::clear_binding(BufferType::ElementArray);
+
+    let program = ShaderProgram::from_vert_frag(VERT_SHADER, FRAG_SHADER)?;
+    let view_projection_loc = program
+      .uniform_location("view_projection")
+      .ok_or("Sprite shader is missing `view_projection`")?;
+    program.use_program();
+    if let Some(sprite_loc) = program.uniform_location("sprite") {
+      unsafe { glUniform1i(sprite_loc, 0) };
+    }
+    Ok(Self {
+      vao,
+      vbo,
+      ebo,
+      program,
+      view_projection_loc,
+      index_capacity: 0,
+      sprites: Vec::new(),
+      vertices: Vec::new(),
+      draw_calls: 0,
+      sort: SpriteSort::Layer,
+      render_state: RenderState::overlay(),
+    })
+  }
+
+  /// Adds a sprite to the batch.
+  pub fn draw(&mut self, texture: &Texture, sprite: &Sprite) {
+    let c = sprite.tint;
+    let mut quad = [[0.0; 8]; 4];
+    for (v, (pos, uv)) in quad.iter_mut().zip(sprite.corners().iter()) {
+      *v = [pos.x, pos.y, uv.x, uv.y, c.x, c.y, c.z, c.w];
+    }
+    self.sprites.push((texture.0, sprite.layer, quad));
+  }
+
+  /// The number of sprites waiting to be drawn.
+  pub fn len(&self) -> usize {
+    self.sprites.len()
+  }
+
+  /// If no sprites are waiting.
+  pub fn is_empty(&self) -> bool {
+    self.sprites.is_empty()
+  }
+
+  /// The number of draw calls the last [`flush`](Self::flush) made.
+  pub fn last_draw_calls(&self) -> usize {
+    self.draw_calls
+  }
+
+  /// Draws every sprite added since the last flush, and empties the batch.
+  ///
+  /// This uses texture unit 0, and leaves the sprite program in use.
+  pub fn flush(&mut self, view_projection: Mat4, cache: &mut StateCache) {
+    self.draw_calls = 0;
+    if self.sprites.is_empty() {
+      return;
+    }
+    // these are stable sorts, so ties keep the order they were added in
+    match self.sort {
+      SpriteSort::Layer => {
+        self.sprites.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+      }
+      SpriteSort::Texture => self.sprites.sort_by_key(|s| s.0),
+      SpriteSort::Submission => (),
+    }
+    self.vertices.clear();
+    self.vertices.extend(self.sprites.iter().flat_map(|s| s.2.iter()));
+
+    self.vao.bind();
+    self.vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&self.vertices),
+      GL_STREAM_DRAW,
+    );
+    if self.index_capacity < self.sprites.len() {
+      let quads = self.sprites.len().next_power_of_two();
+      let indexes: Vec<u32> = (0..quads as u32)
+        .flat_map(|q| {
+          let i = q * 4;
+          [i, i + 1, i + 2, i + 2, i + 3, i].to_vec()
+        })
+        .collect();
+      // the VAO is bound, so this is its element buffer
+      buffer_data(
+        BufferType::ElementArray,
+        bytemuck::cast_slice(&indexes),
+        GL_STATIC_DRAW,
+      );
+      self.index_capacity = quads;
+    }
+    Buffer::clear_binding(BufferType::Array);
+
+    cache.apply(&self.render_state);
+    self.program.use_program();
+    unsafe {
+      glUniformMatrix4fv(
+        self.view_projection_loc,
+        1,
+        GL_FALSE,
+        view_projection.as_ptr(),
+      )
+    };
+    texture::active_texture(0);
+    let mut start = 0;
+    while start < self.sprites.len() {
+      let tex = self.sprites[start].0;
+      let end = self.sprites[start..]
+        .iter()
+        .position(|s| s.0 != tex)
+        .map(|n| start + n)
+        .unwrap_or(self.sprites.len());
+      unsafe {
+        glBindTexture(TextureTarget::Texture2D as GLenum, tex);
+        glDrawElements(
+          GL_TRIANGLES,
+          ((end - start) * 6) as GLsizei,
+          GL_UNSIGNED_INT,
+          (start * 6 * size_of::<u32>()) as *const _,
+        );
+      }
+      self.draw_calls += 1;
+      start = end;
+    }
+    VertexArray::clear_binding();
+    self.sprites.clear();
+  }
+
+  /// Deletes the GL objects.
+  pub fn delete(self) {
+    self.vao.delete();
+    self.vbo.delete();
+    self.ebo.delete();
+    self.program.delete();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn close(a: Vec2, b: Vec2) -> bool {
+    (a - b).mag() < 1e-3
+  }
+
+  fn cameras() -> Vec<Camera2D> {
+    let plain = Camera2D::new(800, 600);
+    let moved = Camera2D {
+      position: Vec2::new(120.0, -45.0),
+      zoom: 2.5,
+      rotation: 0.7,
+      ..Camera2D::new(640, 480)
+    };
+    let far = Camera2D { zoom: 0.25, rotation: -2.0, ..moved };
+    vec![plain, moved, far]
+  }
+
+  #[test]
+  fn screen_and_world_round_trip() {
+    let points = [
+      Vec2::new(0.0, 0.0),
+      Vec2::new(17.0, 300.0),
+      Vec2::new(640.0, 480.0),
+      Vec2::new(-50.0, 1000.0),
+    ];
+    for camera in cameras() {
+      for &screen in points.iter() {
+        let world = camera.screen_to_world(screen);
+        assert!(close(camera.world_to_screen(world), screen));
+        // and the other way, using the same numbers as world points
+        let back = camera.screen_to_world(camera.world_to_screen(screen));
+        assert!(close(back, screen));
+      }
+      // the middle of the screen is the camera's position
+      let middle = camera.viewport / 2.0;
+      assert!(close(camera.screen_to_world(middle), camera.position));
+    }
+  }
+
+  #[test]
+  fn screen_is_y_down_and_world_is_y_up() {
+    let camera = Camera2D { zoom: 2.0, ..Camera2D::new(800, 600) };
+    assert!(close(
+      camera.screen_to_world(Vec2::zero()),
+      Vec2::new(-200.0, 150.0)
+    ));
+    assert!(close(
+      camera.world_to_screen(Vec2::new(10.0, 10.0)),
+      Vec2::new(420.0, 280.0)
+    ));
+    let turned = Camera2D { rotation: core::f32::consts::FRAC_PI_2, ..camera };
+    // turning the camera left makes the world turn right on screen
+    assert!(close(
+      turned.world_to_screen(Vec2::new(10.0, 0.0)),
+      Vec2::new(400.0, 320.0)
+    ));
+  }
+
+  #[test]
+  fn zoom_at_keeps_the_point_still() {
+    for mut camera in cameras() {
+      let mouse = Vec2::new(100.0, 50.0);
+      let world = camera.screen_to_world(mouse);
+      let zoom = camera.zoom;
+      camera.zoom_at(mouse, 1.5);
+      assert!((camera.zoom - zoom * 1.5).abs() < 1e-5);
+      assert!(close(camera.screen_to_world(mouse), world));
+      camera.zoom_at(mouse, 0.1);
+      assert!(close(camera.world_to_screen(world), mouse));
+    }
+    // zooming at the middle doesn't move the camera
+    let mut camera = Camera2D::new(800, 600);
+    camera.zoom_at(Vec2::new(400.0, 300.0), 3.0);
+    assert!(close(camera.position, Vec2::zero()));
+  }
+}
@@ -0,0 +1,535 @@
+//! Fixed function state: depth, stencil, blending, culling, and friends.
+//!
+//! OpenGL keeps all of this as global state, so if one pass turns on
+//! blending and forgets to turn it off then every later pass gets blending
+//! too. Instead, each pass can describe _all_ of the state it wants as a
+//! [`RenderState`] and hand that to a [`StateCache`]. The cache remembers
+//! what it last set and only makes the GL calls for fields that changed.
+
+use crate::PolygonMode;
+use ogl33::*;
+
+/// The comparison used by the depth and stencil tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareFunc {
+  /// Never passes.
+  Never = GL_NEVER as isize,
+  /// Passes if the incoming value is less than the stored value.
+  Less = GL_LESS as isize,
+  /// Passes if the values are equal.
+  Equal = GL_EQUAL as isize,
+  /// Passes if the incoming value is less than or equal to the stored value.
+  LessEqual = GL_LEQUAL as isize,
+  /// Passes if the incoming value is greater than the stored value.
+  Greater = GL_GREATER as isize,
+  /// Passes if the values are not equal.
+  NotEqual = GL_NOTEQUAL as isize,
+  /// Passes if the incoming value is greater than or equal to the stored
+  /// value.
+  GreaterEqual = GL_GEQUAL as isize,
+  /// Always passes.
+  Always = GL_ALWAYS as isize,
+}
+
+/// Depth buffer settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthState {
+  /// If the depth test is performed at all.
+  pub test: bool,
+  /// The depth test to use.
+  pub func: CompareFunc,
+  /// If passing fragments write their depth.
+  pub write: bool,
+}
+impl Default for DepthState {
+  /// GL's default: no test, `Less`, writes on.
+  fn default() -> Self {
+    Self { test: false, func: CompareFunc::Less, write: true }
+  }
+}
+
+/// What to do to the stencil value when a test passes or fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StencilOp {
+  /// Keep the current value.
+  Keep = GL_KEEP as isize,
+  /// Set it to zero.
+  Zero = GL_ZERO as isize,
+  /// Set it to the reference value.
+  Replace = GL_REPLACE as isize,
+  /// Add one, up to the max value.
+  Incr = GL_INCR as isize,
+  /// Add one, wrapping to zero.
+  IncrWrap = GL_INCR_WRAP as isize,
+  /// Subtract one, down to zero.
+  Decr = GL_DECR as isize,
+  /// Subtract one, wrapping to the max value.
+  DecrWrap = GL_DECR_WRAP as isize,
+  /// Flip all the bits.
+  Invert = GL_INVERT as isize,
+}
+
+/// Stencil settings for one side of the polygons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilFace {
+  /// The stencil test.
+  pub func: CompareFunc,
+  /// The value to compare against (and to write with `Replace`).
+  pub reference: i32,
+  /// Bits of the stored value and reference used in the test.
+  pub read_mask: u32,
+  /// Bits of the stored value that can be written.
+  pub write_mask: u32,
+  /// Action when the stencil test fails.
+  pub stencil_fail: StencilOp,
+  /// Action when the stencil test passes but the depth test fails.
+  pub depth_fail: StencilOp,
+  /// Action when both tests pass.
+  pub pass: StencilOp,
+}
+impl Default for StencilFace {
+  /// GL's default: always pass, keep everything, all bits.
+  fn default() -> Self {
+    Self {
+      func: CompareFunc::Always,
+      reference: 0,
+      read_mask: u32::MAX,
+      write_mask: u32::MAX,
+      stencil_fail: StencilOp::Keep,
+      depth_fail: StencilOp::Keep,
+      pass: StencilOp::Keep,
+    }
+  }
+}
+
+/// Stencil buffer settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StencilState {
+  /// If the stencil test is performed at all.
+  pub test: bool,
+  /// Settings for front facing polygons.
+  pub front: StencilFace,
+  /// Settings for back facing polygons.
+  pub back: StencilFace,
+}
+impl StencilState {
+  /// Uses the same settings for both faces.
+  pub const fn both(face: StencilFace) -> Self {
+    Self { test: true, front: face, back: face }
+  }
+}
+
+/// How the incoming and stored colors are combined when blending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendEquation {
+  /// `src * src_factor + dst * dst_factor`
+  Add = GL_FUNC_ADD as isize,
+  /// `src * src_factor - dst * dst_factor`
+  Subtract = GL_FUNC_SUBTRACT as isize,
+  /// `dst * dst_factor - src * src_factor`
+  ReverseSubtract = GL_FUNC_REVERSE_SUBTRACT as isize,
+  /// `min(src, dst)`, factors are ignored.
+  Min = GL_MIN as isize,
+  /// `max(src, dst)`, factors are ignored.
+  Max = GL_MAX as isize,
+}
+
+/// What each side of the blend equation gets multiplied by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum BlendFactor {
+  Zero = GL_ZERO as isize,
+  One = GL_ONE as isize,
+  SrcColor = GL_SRC_COLOR as isize,
+  OneMinusSrcColor = GL_ONE_MINUS_SRC_COLOR as isize,
+  DstColor = GL_DST_COLOR as isize,
+  OneMinusDstColor = GL_ONE_MINUS_DST_COLOR as isize,
+  SrcAlpha = GL_SRC_ALPHA as isize,
+  OneMinusSrcAlpha = GL_ONE_MINUS_SRC_ALPHA as isize,
+  DstAlpha = GL_DST_ALPHA as isize,
+  OneMinusDstAlpha = GL_ONE_MINUS_DST_ALPHA as isize,
+  ConstantColor = GL_CONSTANT_COLOR as isize,
+  OneMinusConstantColor = GL_ONE_MINUS_CONSTANT_COLOR as isize,
+  ConstantAlpha = GL_CONSTANT_ALPHA as isize,
+  OneMinusConstantAlpha = GL_ONE_MINUS_CONSTANT_ALPHA as isize,
+  SrcAlphaSaturate = GL_SRC_ALPHA_SATURATE as isize,
+}
+
+/// Blending settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlendState {
+  /// If blending happens at all.
+  pub enabled: bool,
+  /// Equation for the RGB channels.
+  pub color_equation: BlendEquation,
+  /// Equation for the alpha channel.
+  pub alpha_equation: BlendEquation,
+  /// Factor for the incoming RGB.
+  pub src_color: BlendFactor,
+  /// Factor for the stored RGB.
+  pub dst_color: BlendFactor,
+  /// Factor for the incoming alpha.
+  pub src_alpha: BlendFactor,
+  /// Factor for the stored alpha.
+  pub dst_alpha: BlendFactor,
+  /// The color used by the `Constant*` factors.
+  pub constant: [f32; 4],
+}
+impl BlendState {
+  /// No blending, the incoming color replaces what was there.
+  pub const REPLACE: Self = Self {
+    enabled: false,
+    color_equation: BlendEquation::Add,
+    alpha_equation: BlendEquation::Add,
+    src_color: BlendFactor::One,
+    dst_color: BlendFactor::Zero,
+    src_alpha: BlendFactor::One,
+    dst_alpha: BlendFactor::Zero,
+    constant: [0.0; 4],
+  };
+
+  /// Normal "alpha blending" with straight (not premultiplied) alpha.
+  pub const ALPHA: Self = Self {
+    enabled: true,
+    src_color: BlendFactor::SrcAlpha,
+    dst_color: BlendFactor::OneMinusSrcAlpha,
+    src_alpha: BlendFactor::One,
+    dst_alpha: BlendFactor::OneMinusSrcAlpha,
+    ..Self::REPLACE
+  };
+
+  /// Alpha blending for colors that have already been multiplied by alpha.
+  pub const PREMULTIPLIED_ALPHA: Self = Self {
+    enabled: true,
+    src_color: BlendFactor::One,
+    dst_color: BlendFactor::OneMinusSrcAlpha,
+    src_alpha: BlendFactor::One,
+    dst_alpha: BlendFactor::OneMinusSrcAlpha,
+    ..Self::REPLACE
+  };
+
+  /// Adds the incoming color on top, good for lights and particles.
+  pub const ADDITIVE: Self = Self {
+    enabled: true,
+    src_color: BlendFactor::One,
+    dst_color: BlendFactor::One,
+    src_alpha: BlendFactor::One,
+    dst_alpha: BlendFactor::One,
+    ..Self::REPLACE
+  };
+}
+impl Default for BlendState {
+  /// Same as [`BlendState::REPLACE`], which is GL's default.
+  fn default() -> Self {
+    Self::REPLACE
+  }
+}
+
+/// Which side of polygons gets culled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullFace {
+  /// Cull the front faces.
+  Front = GL_FRONT as isize,
+  /// Cull the back faces.
+  Back = GL_BACK as isize,
+  /// Cull everything (lines and points still draw).
+  FrontAndBack = GL_FRONT_AND_BACK as isize,
+}
+
+/// Which winding order counts as the front of a polygon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontFace {
+  /// Counter-clockwise is the front (the default).
+  CounterClockwise = GL_CCW as isize,
+  /// Clockwise is the front.
+  Clockwise = GL_CW as isize,
+}
+
+/// Face culling settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CullState {
+  /// If culling happens at all.
+  pub enabled: bool,
+  /// The side to cull.
+  pub face: CullFace,
+  /// The winding order of front faces.
+  pub front_face: FrontFace,
+}
+impl Default for CullState {
+  /// GL's default: off, but set to cull back faces with CCW as the front.
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      face: CullFace::Back,
+      front_face: FrontFace::CounterClockwise,
+    }
+  }
+}
+
+/// A rectangle in window pixels, with `(0, 0)` at the bottom left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScissorRect {
+  /// Left edge.
+  pub x: i32,
+  /// Bottom edge.
+  pub y: i32,
+  /// Width.
+  pub width: i32,
+  /// Height.
+  pub height: i32,
+}
+
+/// All the fixed function state that a pass might care about.
+///
+/// The `Default` value is GL's own initial state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderState {
+  /// Depth settings.
+  pub depth: DepthState,
+  /// Stencil settings.
+  pub stencil: StencilState,
+  /// Blend settings.
+  pub blend: BlendState,
+  /// Culling settings.
+  pub cull: CullState,
+  /// The scissor rectangle, if the scissor test should be on.
+  pub scissor: Option<ScissorRect>,
+  /// Which of the red, green, blue, and alpha channels get written.
+  pub color_mask: [bool; 4],
+  /// How polygons are filled in.
+  pub polygon_mode: PolygonMode,
+}
+impl Default for RenderState {
+  fn default() -> Self {
+    Self {
+      depth: DepthState::default(),
+      stencil: StencilState::default(),
+      blend: BlendState::default(),
+      cull: CullState::default(),
+      scissor: None,
+      color_mask: [true; 4],
+      polygon_mode: PolygonMode::Fill,
+    }
+  }
+}
+impl RenderState {
+  /// Usual settings for solid 3D geometry: depth test with `Less`, depth
+  /// writes, and back face culling.
+  pub fn opaque() -> Self {
+    Self {
+      depth: DepthState { test: true, ..DepthState::default() },
+      cull: CullState { enabled: true, ..CullState::default() },
+      ..Self::default()
+    }
+  }
+
+  /// Usual settings for see-through 3D geometry: depth tested but not
+  /// written, with alpha blending and no culling.
+  pub fn transparent() -> Self {
+    Self {
+      depth: DepthState { test: true, write: false, ..DepthState::default() },
+      blend: BlendState::ALPHA,
+      ..Self::default()
+    }
+  }
+
+  /// Usual settings for 2D overlays: no depth, alpha blending.
+  pub fn overlay() -> Self {
+    Self { blend: BlendState::ALPHA, ..Self::default() }
+  }
+}
+
+fn set_enabled(cap: GLenum, enabled: bool) {
+  unsafe {
+    if enabled {
+      glEnable(cap)
+    } else {
+      glDisable(cap)
+    }
+  }
+}
+
+fn gl_bool(b: bool) -> GLboolean {
+  if b {
+    GL_TRUE
+  } else {
+    GL_FALSE
+  }
+}
+
+fn apply_stencil_face(
+  gl_face: GLenum,
+  face: &StencilFace,
+  old: Option<StencilFace>,
+) {
+  let changed = |f: &dyn Fn(&StencilFace) -> bool| match &old {
+    Some(o) => !f(o),
+    None => true,
+  };
+  if changed(&|o| {
+    (o.func, o.reference, o.read_mask)
+      == (face.func, face.reference, face.read_mask)
+  }) {
+    unsafe {
+      glStencilFuncSeparate(
+        gl_face,
+        face.func as GLenum,
+        face.reference,
+        face.read_mask,
+      )
+    };
+  }
+  if changed(&|o| o.write_mask == face.write_mask) {
+    unsafe { glStencilMaskSeparate(gl_face, face.write_mask) };
+  }
+  if changed(&|o| {
+    (o.stencil_fail, o.depth_fail, o.pass)
+      == (face.stencil_fail, face.depth_fail, face.pass)
+  }) {
+    unsafe {
+      glStencilOpSeparate(
+        gl_face,
+        face.stencil_fail as GLenum,
+        face.depth_fail as GLenum,
+        face.pass as GLenum,
+      )
+    };
+  }
+}
+
+/// Remembers the last [`RenderState`] set so that it can skip redundant GL
+/// calls.
+///
+/// The cache can only know about state that goes through it, so if you call
+/// something like `glEnable` directly you should
+/// [`invalidate`](Self::invalidate) the cache afterward.
+#[derive(Debug, Clone, Default)]
+pub struct StateCache {
+  current: Option<RenderState>,
+}
+impl StateCache {
+  /// A cache that doesn't know the current state yet.
+  ///
+  /// The first [`apply`](Self::apply) sets every field.
+  pub const fn new() -> Self {
+    Self { current: None }
+  }
+
+  /// A cache that assumes the GL context is in its initial state, such as
+  /// right after it was created.
+  pub fn assume_initial_state() -> Self {
+    Self { current: Some(RenderState::default()) }
+  }
+
+  /// Forgets what state was set, so the next apply sets every field.
+  pub fn invalidate(&mut self) {
+    self.current = None;
+  }
+
+  /// The state that the cache thinks is currently set.
+  pub fn current(&self) -> Option<&RenderState> {
+    self.current.as_ref()
+  }
+
+  /// Makes the GL state match `state`, only changing what's needed.
+  pub fn apply(&mut self, state: &RenderState) {
+    let old = self.current;
+    let changed = |f: &dyn Fn(&RenderState) -> bool| match &old {
+      Some(o) => !f(o),
+      None => true,
+    };
+
+    // depth
+    let d = state.depth;
+    if changed(&|o| o.depth.test == d.test) {
+      set_enabled(GL_DEPTH_TEST, d.test);
+    }
+    if changed(&|o| o.depth.func == d.func) {
+      unsafe { glDepthFunc(d.func as GLenum) };
+    }
+    if changed(&|o| o.depth.write == d.write) {
+      unsafe { glDepthMask(gl_bool(d.write)) };
+    }
+
+    // stencil
+    let s = state.stencil;
+    if changed(&|o| o.stencil.test == s.test) {
+      set_enabled(GL_STENCIL_TEST, s.test);
+    }
+    apply_stencil_face(GL_FRONT, &s.front, old.map(|o| o.stencil.front));
+    apply_stencil_face(GL_BACK, &s.back, old.map(|o| o.stencil.back));
+
+    // blend
+    let b = state.blend;
+    if changed(&|o| o.blend.enabled == b.enabled) {
+      set_enabled(GL_BLEND, b.enabled);
+    }
+    if changed(&|o| {
+      (o.blend.color_equation, o.blend.alpha_equation)
+        == (b.color_equation, b.alpha_equation)
+    }) {
+      unsafe {
+        glBlendEquationSeparate(
+          b.color_equation as GLenum,
+          b.alpha_equation as GLenum,
+        )
+      };
+    }
+    if changed(&|o| {
+      (
+        o.blend.src_color,
+        o.blend.dst_color,
+        o.blend.src_alpha,
+        o.blend.dst_alpha,
+      ) == (b.src_color, b.dst_color, b.src_alpha, b.dst_alpha)
+    }) {
+      unsafe {
+        glBlendFuncSeparate(
+          b.src_color as GLenum,
+          b.dst_color as GLenum,
+          b.src_alpha as GLenum,
+          b.dst_alpha as GLenum,
+        )
+      };
+    }
+    if changed(&|o| o.blend.constant == b.constant) {
+      let [r, g, bl, a] = b.constant;
+      unsafe { glBlendColor(r, g, bl, a) };
+    }
+
+    // cull
+    let c = state.cull;
+    if changed(&|o| o.cull.enabled == c.enabled) {
+      set_enabled(GL_CULL_FACE, c.enabled);
+    }
+    if changed(&|o| o.cull.face == c.face) {
+      unsafe { glCullFace(c.face as GLenum) };
+    }
+    if changed(&|o| o.cull.front_face == c.front_face) {
+      unsafe { glFrontFace(c.front_face as GLenum) };
+    }
+
+    // scissor
+    if changed(&|o| o.scissor.is_some() == state.scissor.is_some()) {
+      set_enabled(GL_SCISSOR_TEST, state.scissor.is_some());
+    }
+    if let Some(r) = state.scissor {
+      if changed(&|o| o.scissor == state.scissor) {
+        unsafe { glScissor(r.x, r.y, r.width, r.height) };
+      }
+    }
+
+    // color mask
+    if changed(&|o| o.color_mask == state.color_mask) {
+      let [r, g, b, a] = state.color_mask;
+      unsafe { glColorMask(gl_bool(r), gl_bool(g), gl_bool(b), gl_bool(a)) };
+    }
+
+    // polygon mode
+    if changed(&|o| o.polygon_mode == state.polygon_mode) {
+      crate::polygon_mode(state.polygon_mode);
+    }
+
+    self.current = Some(*state);
+  }
+}
@@ -0,0 +1,324 @@
+//! Knowing when the GPU is done: [Sync
+//! Objects](https://www.khronos.org/opengl/wiki/Sync_Object) and pixel
+//! readback that doesn't stall.
+//!
+//! GL commands only get queued up when you call them, the GPU gets to them
+//! later. A [`Fence`] goes into that queue like any other command, and is
+//! "signaled" once the GPU reaches it, so checking a fence tells you if
+//! everything before it is done.
+//!
+//! `glReadPixels` into plain memory makes the CPU wait for the GPU to catch
+//! up, which can easily cost a whole frame. [`AsyncReadback`] reads into a
+//! pixel pack buffer instead, puts down a fence, and hands back the pixels a
+//! few frames later, once the fence says they're ready.
+
+use crate::{
+  image::{Image, ImageData},
+  texture::{self, TextureFormat},
+  Buffer, BufferType,
+};
+use core::time::Duration;
+use ogl33::*;
+
+/// How a wait on a [`Fence`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+  /// The fence was already signaled when the wait started.
+  AlreadySignaled,
+  /// The fence became signaled during the wait.
+  ConditionSatisfied,
+  /// The time ran out first.
+  TimeoutExpired,
+  /// Something went wrong, usually a lost context.
+  WaitFailed,
+}
+impl WaitResult {
+  /// If the fence is signaled, one way or the other.
+  pub fn is_signaled(self) -> bool {
+    matches!(self, Self::AlreadySignaled | Self::ConditionSatisfied)
+  }
+}
+
+/// Basic wrapper for a fence Sync Object.
+pub struct Fence(pub GLsync);
+impl Fence {
+  /// Puts a new fence into the command queue.
+  pub fn new() -> Option<Self> {
+    let sync = unsafe { glFenceSync(GL_SYNC_GPU_COMMANDS_COMPLETE, 0) };
+    if !sync.is_null() {
+      Some(Self(sync))
+    } else {
+      None
+    }
+  }
+
+  /// Makes the CPU wait for the fence, for at most `timeout`.
+  ///
+  /// With `flush` the queued commands are sent to the GPU first. Without it,
+  /// waiting on a fence that was never flushed might never finish.
+  pub fn client_wait(&self, timeout: Duration, flush: bool) -> WaitResult {
+    let flags = if flush { GL_SYNC_FLUSH_COMMANDS_BIT } else { 0 };
+    let nanos = timeout.as_nanos().min(u128::from(u64::MAX)) as u64;
+    match unsafe { glClientWaitSync(self.0, flags, nanos) } {
+      GL_ALREADY_SIGNALED => WaitResult::AlreadySignaled,
+      GL_CONDITION_SATISFIED => WaitResult::ConditionSatisfied,
+      GL_TIMEOUT_EXPIRED => WaitResult::TimeoutExpired,
+      _ => WaitResult::WaitFailed,
+    }
+  }
+
+  /// Checks the fence without waiting.
+  ///
+  /// This flushes the queue, so a fence that's polled enough times is sure
+  /// to be signaled eventually.
+  pub fn is_signaled(&self) -> bool {
+    self.client_wait(Duration::from_secs(0), true).is_signaled()
+  }
+
+  /// Makes the _GPU_ wait for the fence before running any later commands.
+  ///
+  /// The CPU carries on right away. This is only useful when the fence was
+  /// made by another context that shares objects with this one.
+  pub fn gpu_wait(&self) {
+    unsafe { glWaitSync(self.0, 0, GL_TIMEOUT_IGNORED) }
+  }
+
+  /// Deletes the fence.
+  pub fn delete(self) {
+    unsafe { glDeleteSync(self.0) }
+  }
+}
+
+/// Pixels read back by an [`AsyncReadback`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Readback {
+  /// The number [`AsyncReadback::request`] gave when this was asked for.
+  pub id: u64,
+  /// The left edge of the area that was read.
+  pub x: i32,
+  /// The bottom edge of the area that was read.
+  pub y: i32,
+  /// Width in pixels.
+  pub width: u32,
+  /// Height in pixels.
+  pub height: u32,
+  /// The format the pixels were read as.
+  pub format: TextureFormat,
+  /// The pixels, tightly packed, with rows from bottom to top like GL has
+  /// them.
+  pub data: Vec<u8>,
+}
+impl Readback {
+  /// Converts to an [`Image`], flipping the rows to top to bottom.
+  ///
+  /// This only works for `RGBA8` and `SRGB8Alpha8` reads.
+  pub fn to_image(&self) -> Option<Image> {
+    let srgb = match self.format {
+      TextureFormat::RGBA8 => false,
+      TextureFormat::SRGB8Alpha8 => true,
+      _ => return None,
+    };
+    let mut image = Image {
+      width: self.width,
+      height: self.height,
+      data: ImageData::Rgba8(self.data.clone()),
+      srgb,
+    };
+    image.flip_vertical();
+    Some(image)
+  }
+}
+
+/// One pixel pack buffer, and what's waiting in it.
+struct ReadbackSlot {
+  buffer: Buffer,
+  capacity: usize,
+  pending: Option<(Fence, Readback)>,
+}
+
+/// Reads pixels from the framebuffer without waiting for the GPU.
+///
+/// Each [`request`](Self::request) uses the next buffer in a ring, and
+/// [`poll`](Self::poll) gives back the requests that are done. With a ring
+/// of three, a request made each frame is usually ready two frames later.
+///
+/// ```ignore
+/// let mut readback = AsyncReadback::new(3)?;
+/// // each frame, after drawing
+/// readback.request(0, 0, width, height, TextureFormat::RGBA8)?;
+/// while let Some(pixels) = readback.poll() {
+///   save_frame(pixels.id, pixels.to_image().unwrap());
+/// }
+/// ```
+pub struct AsyncReadback {
+  slots: Vec<ReadbackSlot>,
+  next: usize,
+  next_id: u64,
+  dropped: u64,
+}
+impl AsyncReadback {
+  /// Makes a ring of `frames` buffers. They get their storage on first use.
+  pub fn new(frames: usize) -> Result<Self, String> {
+    let frames = frames.max(1);
+    let mut slots = Vec::with_capacity(frames);
+    for _ in 0..frames {
+      match Buffer::new() {
+        Some(buffer) => {
+          slots.push(ReadbackSlot { buffer, capacity: 0, pending: None })
+        }
+        None => {
+          slots.into_iter().for_each(|s| s.buffer.delete());
+          return Err("Couldn't make a pixel pack buffer".to_string());
+        }
+      }
+    }
+    Ok(Self { slots, next: 0, next_id: 0, dropped: 0 })
+  }
+
+  /// Starts reading an area of the bound read framebuffer, giving an id to
+  /// match up with the [`Readback`] later.
+  ///
+  /// If the ring is full, the oldest request that wasn't picked up yet is
+  /// thrown away to make room.
+  pub fn request(
+    &mut self,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+  ) -> Result<u64, String> {
+    let size = area_bytes(format, width, height)
+      .ok_or("The read back area is too big")?;
+    if size == 0 {
+      return Err("Can't read back an empty area".to_string());
+    }
+    let slot = &mut self.slots[self.next];
+    if let Some((fence, _)) = slot.pending.take() {
+      fence.delete();
+      self.dropped += 1;
+    }
+    slot.buffer.bind(BufferType::PixelPack);
+    if slot.capacity < size {
+      unsafe {
+        glBufferData(
+          GL_PIXEL_PACK_BUFFER,
+          size as GLsizeiptr,
+          core::ptr::null(),
+          GL_STREAM_READ,
+        )
+      };
+      slot.capacity = size;
+    }
+    let mut alignment = 4;
+    unsafe {
+      glGetIntegerv(GL_PACK_ALIGNMENT, &mut alignment);
+      glPixelStorei(GL_PACK_ALIGNMENT, 1);
+      glReadPixels(
+        x,
+        y,
+        width as GLsizei,
+        height as GLsizei,
+        format.pixel_format(),
+        format.pixel_type(),
+        core::ptr::null_mut(),
+      );
+      glPixelStorei(GL_PACK_ALIGNMENT, alignment);
+    }
+    Buffer::clear_binding(BufferType::PixelPack);
+    let fence = Fence::new().ok_or("Couldn't make a fence")?;
+    let id = self.next_id;
+    let info = Readback { id, x, y, width, height, format, data: Vec::new() };
+    slot.pending = Some((fence, info));
+    self.next_id += 1;
+    self.next = (self.next + 1) % self.slots.len();
+    Ok(id)
+  }
+
+  /// Gives back the oldest request that's done, if any, without waiting.
+  ///
+  /// Call this until it gives `None` to get everything that's ready.
+  pub fn poll(&mut self) -> Option<Readback> {
+    let slot = self
+      .slots
+      .iter_mut()
+      .filter(|s| s.pending.is_some())
+      .min_by_key(|s| s.pending.as_ref().map(|(_, r)| r.id))?;
+    if !slot.pending.as_ref()?.0.is_signaled() {
+      return None;
+    }
+    let (fence, info) = slot.pending.take()?;
+    fence.delete();
+    Some(read_slot(slot, info))
+  }
+
+  /// Waits for every request, giving them back oldest first.
+  pub fn finish(&mut self) -> Vec<Readback> {
+    let mut slots: Vec<&mut ReadbackSlot> =
+      self.slots.iter_mut().filter(|s| s.pending.is_some()).collect();
+    slots.sort_by_key(|s| s.pending.as_ref().map(|(_, r)| r.id));
+    let mut out = Vec::with_capacity(slots.len());
+    for slot in slots {
+      if let Some((fence, info)) = slot.pending.take() {
+        fence.client_wait(Duration::from_secs(u64::MAX), true);
+        fence.delete();
+        out.push(read_slot(slot, info));
+      }
+    }
+    out
+  }
+
+  /// The number of requests still waiting on the GPU.
+  pub fn pending(&self) -> usize {
+    self.slots.iter().filter(|s| s.pending.is_some()).count()
+  }
+
+  /// How many requests were thrown away because the ring was full. If this
+  /// keeps going up, use more frames or poll more often.
+  pub fn dropped(&self) -> u64 {
+    self.dropped
+  }
+
+  /// Deletes the buffers and any fences.
+  pub fn delete(self) {
+    for slot in self.slots {
+      if let Some((fence, _)) = slot.pending {
+        fence.delete();
+      }
+      slot.buffer.delete();
+    }
+  }
+}
+
+/// The size of an area of pixels in bytes, if that fits in a `usize`.
+fn area_bytes(format: TextureFormat, width: u32, height: u32) -> Option<usize> {
+  texture::bytes_per_pixel(format)
+    .checked_mul(width as usize)?
+    .checked_mul(height as usize)
+}
+
+/// Copies a finished read out of its buffer.
+fn read_slot(slot: &ReadbackSlot, mut info: Readback) -> Readback {
+  // `request` already checked that this fits
+  let size = area_bytes(info.format, info.width, info.height).unwrap_or(0);
+  info.data = vec![0; size];
+  slot.buffer.bind(BufferType::PixelPack);
+  unsafe {
+    let ptr = glMapBufferRange(
+      GL_PIXEL_PACK_BUFFER,
+      0,
+      size as GLsizeiptr,
+      GL_MAP_READ_BIT,
+    );
+    if !ptr.is_null() {
+      core::ptr::copy_nonoverlapping(
+        ptr.cast::<u8>(),
+        info.data.as_mut_ptr(),
+        size,
+      );
+      glUnmapBuffer(GL_PIXEL_PACK_BUFFER);
+    }
+  }
+  Buffer::clear_binding(BufferType::PixelPack);
+  info
+}
@@ -0,0 +1,865 @@
+//! Putting text on the screen with bitmap fonts.
+//!
+//! A [`Font`] is a set of glyph images in one or more texture _pages_, along
+//! with where each glyph sits relative to the pen and how far the pen moves
+//! after it. You can get one two ways:
+//! * [`Font::load_bmfont`] loads an [AngelCode
+//!   BMFont](http://www.angelcode.com/products/bmfont/doc/file_format.html)
+//!   `.fnt` file (the text version) and its page images. Lots of tools can
+//!   export these.
+//! * [`Font::from_truetype`] renders the characters you ask for out of a
+//!   `.ttf` file into a new atlas, at one pixel size.
+//!
+//! [`Font::layout`] places a string's glyphs, with kerning and optional word
+//! wrapping, and a [`TextRenderer`] batches up the glyph quads of any amount
+//! of text and draws them in one call per page.
+//!
+//! ```ignore
+//! let font = Font::load_bmfont("assets/mono.fnt")?;
+//! let mut text = TextRenderer::new()?;
+//! // each frame
+//! let fps_text = format!("{:.0} fps", fps);
+//! text.queue(&font, &fps_text, Vec2::new(8.0, 8.0), &TextStyle::default());
+//! text.flush(width, height, &mut cache);
+//! ```
+
+use crate::{
+  atlas::{AtlasBuilder, AtlasRect, UvRect},
+  buffer_data,
+  image::{Image, ImageData},
+  state::{RenderState, StateCache},
+  texture::{self, MagFilter, MinFilter, Texture, TextureTarget, TextureWrap},
+  truetype::TrueType,
+  Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{convert::TryInto, mem::size_of};
+use ogl33::*;
+use std::{collections::HashMap, path::Path};
+use ultraviolet::{Vec2, Vec4};
+
+const VERT_SHADER: &str = r#"#version 330 core
+  layout (location = 0) in vec2 pos;
+  layout (location = 1) in vec2 uv;
+  layout (location = 2) in vec4 color;
+
+  uniform vec2 screen_size;
+
+  out vec2 frag_uv;
+  out vec4 frag_color;
+
+  void main() {
+    frag_uv = uv;
+    frag_color = color;
+    vec2 ndc = pos / screen_size * 2.0 - 1.0;
+    gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+  }
+"#;
+
+const FRAG_SHADER: &str = r#"#version 330 core
+  in vec2 frag_uv;
+  in vec4 frag_color;
+
+  uniform sampler2D page;
+
+  out vec4 final_color;
+
+  void main() {
+    final_color = texture(page, frag_uv) * frag_color;
+  }
+"#;
+
+/// The most pages a BMFont file can have.
+const MAX_BMFONT_PAGES: usize = 256;
+
+/// One glyph of a font, in unscaled pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Glyph {
+  /// Where the glyph's image is in its page, empty for glyphs like space.
+  pub rect: AtlasRect,
+  /// From the pen position (at the top of the line) to the top left of the
+  /// glyph's image.
+  pub offset: Vec2,
+  /// How far the pen moves after this glyph.
+  pub advance: f32,
+  /// Which page the image is in.
+  pub page: usize,
+}
+
+/// The contents of a BMFont `.fnt` file, before any pages are loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BmFontFile {
+  /// The size the font was made at.
+  pub size: f32,
+  /// The distance from one line to the next.
+  pub line_height: f32,
+  /// The distance from the top of a line to the baseline.
+  pub base: f32,
+  /// The page image files, by page number.
+  pub pages: Vec<String>,
+  /// All the glyphs.
+  pub glyphs: Vec<(char, Glyph)>,
+  /// Kerning between pairs of characters.
+  pub kerning: Vec<((char, char), f32)>,
+}
+impl BmFontFile {
+  /// Parses the text version of the format.
+  ///
+  /// Page ids must be less than the `common` line's `pages` count (if it has
+  /// one), and less than 256.
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut out = Self::default();
+    let mut page_count = MAX_BMFONT_PAGES;
+    for (number, line) in text.lines().enumerate() {
+      let (tag, fields) = split_bmfont_line(line);
+      let get = |key: &str| -> Result<f32, String> {
+        fields
+          .iter()
+          .find(|(k, _)| *k == key)
+          .ok_or_else(|| {
+            format!("BMFont line {} has no `{}`", number + 1, key)
+          })?
+          .1
+          .parse()
+          .map_err(|_| {
+            format!("BMFont line {} has a bad `{}`", number + 1, key)
+          })
+      };
+      let get_char = |key: &str| -> Result<char, String> {
+        core::char::from_u32(get(key)? as u32).ok_or_else(|| {
+          format!("BMFont line {} has a bad character", number + 1)
+        })
+      };
+      match tag {
+        "info" => out.size = get("size")?.abs(),
+        "common" => {
+          out.line_height = get("lineHeight")?;
+          out.base = get("base")?;
+          if let Ok(pages) = get("pages") {
+            page_count = page_count.min(pages as usize);
+          }
+        }
+        "page" => {
+          let id = get("id")? as usize;
+          if id >= page_count {
+            return Err(format!(
+              "BMFont line {} has page {}, but there are only {} pages",
+              number + 1,
+              id,
+              page_count
+            ));
+          }
+          let file = fields
+            .iter()
+            .find(|(k, _)| *k == "file")
+            .map(|(_, file)| *file)
+            .ok_or_else(|| format!("BMFont line {} has no file", number + 1))?;
+          if out.pages.len() <= id {
+            out.pages.resize(id + 1, String::new());
+          }
+          out.pages[id] = file.to_string();
+        }
+        "char" => {
+          let glyph = Glyph {
+            rect: AtlasRect {
+              x: get("x")? as u32,
+              y: get("y")? as u32,
+              width: get("width")? as u32,
+              height: get("height")? as u32,
+            },
+            offset: Vec2::new(get("xoffset")?, get("yoffset")?),
+            advance: get("xadvance")?,
+            page: get("page")? as usize,
+          };
+          out.glyphs.push((get_char("id")?, glyph));
+        }
+        "kerning" => {
+          let pair = (get_char("first")?, get_char("second")?);
+          out.kerning.push((pair, get("amount")?));
+        }
+        _ => (),
+      }
+    }
+    if out.line_height <= 0.0 {
+      return Err("BMFont file has no `common` line".to_string());
+    }
+    if let Some((c, g)) =
+      out.glyphs.iter().find(|(_, g)| g.page >= out.pages.len())
+    {
+      return Err(format!("Glyph {:?} is on missing page {}", c, g.page));
+    }
+    Ok(out)
+  }
+}
+
+/// Splits `tag key=value key="quoted value"` into its parts.
+fn split_bmfont_line(line: &str) -> (&str, Vec<(&str, &str)>) {
+  let line = line.trim();
+  let (tag, mut rest) = match line.find(' ') {
+    Some(i) => (&line[..i], line[i..].trim_start()),
+    None => (line, ""),
+  };
+  let mut fields = Vec::new();
+  while let Some(eq) = rest.find('=') {
+    let key = rest[..eq].trim();
+    let after = &rest[eq + 1..];
+    let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+      let end = quoted.find('"').unwrap_or(quoted.len());
+      (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+    } else {
+      let end = after.find(' ').unwrap_or(after.len());
+      (&after[..end], &after[end..])
+    };
+    fields.push((key, value));
+    rest = next.trim_start();
+  }
+  (tag, fields)
+}
+
+/// A font page texture.
+struct Page {
+  texture: Texture,
+  width: u32,
+  height: u32,
+}
+
+/// A glyph placed by [`Font::layout`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+  /// The character.
+  pub c: char,
+  /// The top left corner, relative to the top left of the text.
+  pub pos: Vec2,
+  /// The size on screen.
+  pub size: Vec2,
+  /// Which page of the font the glyph is on.
+  pub page: usize,
+  /// Where the glyph is in the page.
+  pub uv: UvRect,
+}
+
+/// A string's glyphs, placed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextLayout {
+  /// The glyphs that have images. Spaces take up room but aren't listed.
+  pub glyphs: Vec<PlacedGlyph>,
+  /// The size of the whole block of text.
+  pub size: Vec2,
+  /// The number of lines, after wrapping.
+  pub lines: usize,
+}
+
+/// A bitmap font, with its pages on the GPU.
+pub struct Font {
+  size: f32,
+  line_height: f32,
+  base: f32,
+  glyphs: HashMap<char, Glyph>,
+  kerning: HashMap<(char, char), f32>,
+  pages: Vec<Page>,
+}
+impl Font {
+  /// Loads a BMFont `.fnt` file, along with its pages (which are looked for
+  /// next to the `.fnt` file).
+  pub fn load_bmfont<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+      .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+    let file = BmFontFile::parse(&text)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let images = file
+      .pages
+      .iter()
+      .map(|page| {
+        Image::load(dir.join(page))
+          .map_err(|e| format!("Couldn't load font page {}: {}", page, e))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    Self::from_bmfont(&file, &images)
+  }
+
+  /// Makes a font from a parsed BMFont file and its page images.
+  pub fn from_bmfont(
+    file: &BmFontFile,
+    pages: &[Image],
+  ) -> Result<Self, String> {
+    if pages.len() < file.pages.len() {
+      return Err(format!(
+        "The font has {} pages, but only {} images were given",
+        file.pages.len(),
+        pages.len()
+      ));
+    }
+    let pages = pages.iter().map(upload_page).collect::<Result<Vec<_>, _>>()?;
+    Ok(Self {
+      size: file.size,
+      line_height: file.line_height,
+      base: file.base,
+      glyphs: file.glyphs.iter().copied().collect(),
+      kerning: file.kerning.iter().copied().collect(),
+      pages,
+    })
+  }
+
+  /// Renders characters from a TrueType font into a new single page font.
+  ///
+  /// `size` is the em size in pixels. Characters the font doesn't have are
+  /// left out, and `'?'` is drawn in their place if the font has it. Kerning
+  /// only comes from the old `kern` table, so some fonts won't have any.
+  pub fn from_truetype(
+    ttf: &TrueType,
+    size: f32,
+    chars: impl IntoIterator<Item = char>,
+  ) -> Result<Self, String> {
+    let scale = ttf.scale_for_em(size);
+    let v = ttf.v_metrics();
+    // in f32, since these are i16 values straight out of the font file
+    let ascent = f32::from(v.ascent);
+    let descent = f32::from(v.descent);
+    let line_gap = f32::from(v.line_gap);
+    let base = (ascent * scale).ceil();
+    let line_height = ((ascent - descent + line_gap) * scale).ceil();
+    let mut builder = AtlasBuilder::new();
+    let mut found = Vec::new();
+    for c in chars {
+      let index = match ttf.glyph_index(c) {
+        Some(index) => index,
+        None if c == ' ' => 0,
+        None => continue,
+      };
+      let advance = f32::from(ttf.advance_width(index)) * scale;
+      let mut glyph = Glyph { advance, ..Glyph::default() };
+      let slot = ttf.rasterize(index, scale).map(|bitmap| {
+        glyph.offset = Vec2::new(bitmap.left as f32, base - bitmap.top as f32);
+        let rgba = bitmap
+          .coverage
+          .iter()
+          .flat_map(|&a| [255, 255, 255, a].to_vec())
+          .collect();
+        builder.add_rgba8(bitmap.width, bitmap.height, rgba)
+      });
+      found.push((c, index, glyph, slot));
+    }
+    let mut glyphs = HashMap::with_capacity(found.len());
+    let pages = if builder.is_empty() {
+      Vec::new()
+    } else {
+      let (image, rects) = builder.pack()?;
+      for (_, _, glyph, slot) in found.iter_mut() {
+        if let Some(slot) = slot {
+          glyph.rect = rects[*slot];
+        }
+      }
+      vec![upload_page(&image)?]
+    };
+    let mut kerning = HashMap::new();
+    for (left, left_index, glyph, _) in &found {
+      glyphs.insert(*left, *glyph);
+      for (right, right_index, _, _) in &found {
+        let amount = ttf.kerning(*left_index, *right_index);
+        if amount != 0 {
+          kerning.insert((*left, *right), f32::from(amount) * scale);
+        }
+      }
+    }
+    Ok(Self { size, line_height, base, glyphs, kerning, pages })
+  }
+
+  /// The size the font was made at, in pixels.
+  pub fn size(&self) -> f32 {
+    self.size
+  }
+
+  /// The distance from one line to the next, in unscaled pixels.
+  pub fn line_height(&self) -> f32 {
+    self.line_height
+  }
+
+  /// The distance from the top of a line to the baseline, in unscaled
+  /// pixels.
+  pub fn base(&self) -> f32 {
+    self.base
+  }
+
+  /// The glyph for a character, if the font has one.
+  pub fn glyph(&self, c: char) -> Option<&Glyph> {
+    self.glyphs.get(&c)
+  }
+
+  /// The kerning between two characters, in unscaled pixels.
+  pub fn kerning(&self, left: char, right: char) -> f32 {
+    self.kerning.get(&(left, right)).copied().unwrap_or(0.0)
+  }
+
+  /// The glyph to draw for a character, falling back to `'?'`.
+  fn glyph_or_fallback(&self, c: char) -> Option<(char, &Glyph)> {
+    self
+      .glyphs
+      .get(&c)
+      .map(|g| (c, g))
+      .or_else(|| self.glyphs.get(&'?').map(|g| ('?', g)))
+  }
+
+  /// The width of a word with no wrapping, in unscaled pixels.
+  fn word_width(&self, word: &str) -> f32 {
+    let mut width = 0.0;
+    let mut prev = None;
+    for c in word.chars() {
+      if let Some((c, glyph)) = self.glyph_or_fallback(c) {
+        if let Some(p) = prev {
+          width += self.kerning(p, c);
+        }
+        width += glyph.advance;
+        prev = Some(c);
+      }
+    }
+    width
+  }
+
+  /// Places the glyphs of some text, with the top left of the text at the
+  /// origin and `+y` going down.
+  ///
+  /// Text breaks onto a new line at each `'\n'`. With a `max_width`, lines
+  /// are also wrapped between words, or in the middle of a word that's too
+  /// long to fit on a line by itself.
+  pub fn layout(
+    &self,
+    text: &str,
+    scale: f32,
+    max_width: Option<f32>,
+  ) -> TextLayout {
+    let line_height = self.line_height * scale;
+    let space = self.glyphs.get(&' ').map(|g| g.advance).unwrap_or(0.0) * scale;
+    let mut out = TextLayout::default();
+    let (mut x, mut y) = (0.0_f32, 0.0_f32);
+    let mut width = 0.0_f32;
+    for (i, paragraph) in text.split('\n').enumerate() {
+      if i > 0 {
+        x = 0.0;
+        y += line_height;
+      }
+      for (j, word) in paragraph.split(' ').enumerate() {
+        if j > 0 {
+          let word_width = self.word_width(word) * scale;
+          match max_width {
+            Some(max) if x > 0.0 && x + space + word_width > max => {
+              x = 0.0;
+              y += line_height;
+            }
+            _ => x += space,
+          }
+        }
+        let mut prev = None;
+        for c in word.chars() {
+          let (c, glyph) = match self.glyph_or_fallback(c) {
+            Some(found) => found,
+            None => continue,
+          };
+          if let Some(p) = prev {
+            x += self.kerning(p, c) * scale;
+          }
+          if let Some(max) = max_width {
+            if x > 0.0 && x + glyph.advance * scale > max {
+              x = 0.0;
+              y += line_height;
+            }
+          }
+          if glyph.rect.width > 0 && glyph.rect.height > 0 {
+            if let Some(page) = self.pages.get(glyph.page) {
+              out.glyphs.push(PlacedGlyph {
+                c,
+                pos: Vec2::new(x, y) + glyph.offset * scale,
+                size: Vec2::new(
+                  glyph.rect.width as f32,
+                  glyph.rect.height as f32,
+                ) * scale,
+                page: glyph.page,
+                uv: page_uv(page, glyph.rect),
+              });
+            }
+          }
+          x += glyph.advance * scale;
+          width = width.max(x);
+          prev = Some(c);
+        }
+      }
+    }
+    out.size = Vec2::new(width, y + line_height);
+    out.lines = (y / line_height.max(f32::EPSILON)).round() as usize + 1;
+    out
+  }
+
+  /// The size that some text takes up, see [`layout`](Self::layout).
+  pub fn measure(
+    &self,
+    text: &str,
+    scale: f32,
+    max_width: Option<f32>,
+  ) -> Vec2 {
+    self.layout(text, scale, max_width).size
+  }
+
+  /// Deletes the page textures.
+  pub fn delete(self) {
+    for page in self.pages {
+      page.texture.delete();
+    }
+  }
+}
+
+/// Where a rect of a page is in texture coordinates.
+fn page_uv(page: &Page, r: AtlasRect) -> UvRect {
+  let (w, h) = (page.width as f32, page.height as f32);
+  UvRect {
+    min: Vec2::new(r.x as f32 / w, 1.0 - (r.y + r.height) as f32 / h),
+    max: Vec2::new((r.x + r.width) as f32 / w, 1.0 - r.y as f32 / h),
+  }
+}
+
+/// Uploads a page image, with linear filtering and no mipmaps, like an
+/// [`Atlas`](crate::atlas::Atlas).
+fn upload_page(image: &Image) -> Result<Page, String> {
+  let mut flipped = image.clone();
+  flipped.flip_vertical();
+  let pixels = match &flipped.data {
+    ImageData::Rgba8(pixels) => pixels,
+    ImageData::RgbF32(_) => {
+      return Err("Font pages must be 8-bit images".to_string())
+    }
+  };
+  let tex = Texture::new().ok_or("Couldn't allocate a texture")?;
+  tex.bind(TextureTarget::Texture2D);
+  texture::tex_image_2d(
+    image.texture_format(),
+    0,
+    image.width,
+    image.height,
+    Some(pixels),
+  );
+  texture::set_filters(
+    TextureTarget::Texture2D,
+    MinFilter::Linear,
+    MagFilter::Linear,
+  );
+  texture::set_wrap(TextureTarget::Texture2D, TextureWrap::ClampToEdge);
+  Texture::clear_binding(TextureTarget::Texture2D);
+  Ok(Page { texture: tex, width: image.width, height: image.height })
+}
+
+/// How queued text looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+  /// Multiplies the font's size.
+  pub scale: f32,
+  /// Multiplies the glyph colors, so with white glyphs this is the text
+  /// color.
+  pub color: Vec4,
+  /// Wraps lines that would be wider than this.
+  pub max_width: Option<f32>,
+}
+impl Default for TextStyle {
+  /// Unscaled white text with no wrapping.
+  fn default() -> Self {
+    Self { scale: 1.0, color: Vec4::one(), max_width: None }
+  }
+}
+
+/// `x, y, u, v, r, g, b, a`
+type TextVertex = [f32; 8];
+
+/// Batches up text and draws it.
+pub struct TextRenderer {
+  vao: VertexArray,
+  vbo: Buffer,
+  program: ShaderProgram,
+  screen_size_loc: GLint,
+  /// Each glyph quad, as two triangles, with its page's texture name.
+  quads: Vec<(GLuint, [TextVertex; 6])>,
+  vertices: Vec<TextVertex>,
+}
+impl TextRenderer {
+  /// Makes the glyph buffer and compiles the text shader.
+  pub fn new() -> Result<Self, String> {
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    vao.bind();
+    let vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    vbo.bind(BufferType::Array);
+    let stride: GLsizei = size_of::<TextVertex>().try_into().unwrap();
+    let float = size_of::<f32>();
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        2,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+      glVertexAttribPointer(
+        1,
+        2,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        (2 * float) as *const _,
+      );
+      glEnableVertexAttribArray(1);
+      glVertexAttribPointer(
+        2,
+        4,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        (4 * float) as *const _,
+      );
+      glEnableVertexAttribArray(2);
+    }
+    VertexArray::clear_binding();
+    Buffer::clear_binding(BufferType::Array);
+
+    let program = ShaderProgram::from_vert_frag(VERT_SHADER, FRAG_SHADER)?;
+    let screen_size_loc = program
+      .uniform_location("screen_size")
+      .ok_or("Text shader is missing `screen_size`")?;
+    program.use_program();
+    if let Some(page_loc) = program.uniform_location("page") {
+      unsafe { glUniform1i(page_loc, 0) };
+    }
+    Ok(Self {
+      vao,
+      vbo,
+      program,
+      screen_size_loc,
+      quads: Vec::new(),
+      vertices: Vec::new(),
+    })
+  }
+
+  /// Queues up some text, with its top left corner at `pos` (in pixels from
+  /// the top left of the screen). Gives the size of the text.
+  pub fn queue(
+    &mut self,
+    font: &Font,
+    text: &str,
+    pos: Vec2,
+    style: &TextStyle,
+  ) -> Vec2 {
+    let layout = font.layout(text, style.scale, style.max_width);
+    self.queue_layout(font, &layout, pos, style.color);
+    layout.size
+  }
+
+  /// Queues up text that was already laid out with [`Font::layout`].
+  pub fn queue_layout(
+    &mut self,
+    font: &Font,
+    layout: &TextLayout,
+    pos: Vec2,
+    color: Vec4,
+  ) {
+    let c = color;
+    for g in &layout.glyphs {
+      let page = match font.pages.get(g.page) {
+        Some(page) => page.texture.0,
+        None => continue,
+      };
+      let (min, max) = (pos + g.pos, pos + g.pos + g.size);
+      let v = |x: f32, y: f32, u: f32, v: f32| [x, y, u, v, c.x, c.y, c.z, c.w];
+      let top_left = v(min.x, min.y, g.uv.min.x, g.uv.max.y);
+      let top_right = v(max.x, min.y, g.uv.max.x, g.uv.max.y);
+      let bottom_left = v(min.x, max.y, g.uv.min.x, g.uv.min.y);
+      let bottom_right = v(max.x, max.y, g.uv.max.x, g.uv.min.y);
+      self.quads.push((
+        page,
+        [
+          top_left,
+          bottom_left,
+          bottom_right,
+          bottom_right,
+          top_right,
+          top_left,
+        ],
+      ));
+    }
+  }
+
+  /// The number of glyphs waiting to be drawn.
+  pub fn len(&self) -> usize {
+    self.quads.len()
+  }
+
+  /// If no glyphs are waiting.
+  pub fn is_empty(&self) -> bool {
+    self.quads.is_empty()
+  }
+
+  /// Draws everything queued, over whatever's already there, and clears the
+  /// queue.
+  ///
+  /// All the glyphs go into one buffer, with one draw call for each font
+  /// page used. The screen size is in pixels. This uses texture unit 0, and
+  /// leaves the text program in use.
+  pub fn flush(&mut self, width: u32, height: u32, cache: &mut StateCache) {
+    if self.quads.is_empty() {
+      return;
+    }
+    // stable, so text on the same page keeps its queued order
+    self.quads.sort_by_key(|(page, _)| *page);
+    self.vertices.clear();
+    self.vertices.extend(self.quads.iter().flat_map(|(_, q)| q.iter()));
+    self.vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&self.vertices),
+      GL_STREAM_DRAW,
+    );
+    Buffer::clear_binding(BufferType::Array);
+
+    cache.apply(&RenderState::overlay());
+    self.program.use_program();
+    unsafe { glUniform2f(self.screen_size_loc, width as f32, height as f32) };
+    texture::active_texture(0);
+    self.vao.bind();
+    let mut start = 0;
+    while start < self.quads.len() {
+      let page = self.quads[start].0;
+      let end = self.quads[start..]
+        .iter()
+        .position(|(p, _)| *p != page)
+        .map(|n| start + n)
+        .unwrap_or(self.quads.len());
+      unsafe {
+        glBindTexture(GL_TEXTURE_2D, page);
+        glDrawArrays(
+          GL_TRIANGLES,
+          (start * 6) as GLsizei,
+          ((end - start) * 6) as GLsizei,
+        );
+      }
+      start = end;
+    }
+    VertexArray::clear_binding();
+    self.quads.clear();
+  }
+
+  /// Deletes the GL objects.
+  pub fn delete(self) {
+    self.vao.delete();
+    self.vbo.delete();
+    self.program.delete();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FNT: &str = r#"info face="Some Font" size=-16 bold=0
+common lineHeight=18 base=14 scaleW=64 scaleH=64 pages=1
+page id=0 file="some font_0.png"
+chars count=3
+char id=65 x=0 y=0 width=8 height=10 xoffset=1 yoffset=4 xadvance=9 page=0
+char id=86 x=8 y=0 width=8 height=10 xoffset=0 yoffset=4 xadvance=8 page=0
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=4 page=0
+kerning first=65 second=86 amount=-2
+"#;
+
+  /// A font with no page textures, which is fine for layout.
+  fn font() -> Font {
+    let file = BmFontFile::parse(FNT).unwrap();
+    Font {
+      size: file.size,
+      line_height: file.line_height,
+      base: file.base,
+      glyphs: file.glyphs.iter().copied().collect(),
+      kerning: file.kerning.iter().copied().collect(),
+      pages: vec![Page { texture: Texture(1), width: 64, height: 64 }],
+    }
+  }
+
+  #[test]
+  fn split_lines() {
+    assert_eq!(split_bmfont_line("chars"), ("chars", vec![]));
+    assert_eq!(
+      split_bmfont_line("  char id=65   x=3 "),
+      ("char", vec![("id", "65"), ("x", "3")])
+    );
+    assert_eq!(
+      split_bmfont_line(r#"page id=0 file="my font.png" x=1"#),
+      ("page", vec![("id", "0"), ("file", "my font.png"), ("x", "1")])
+    );
+    assert_eq!(
+      split_bmfont_line(r#"info face="" size=2"#),
+      ("info", vec![("face", ""), ("size", "2")])
+    );
+    // an unclosed quote runs to the end of the line
+    assert_eq!(
+      split_bmfont_line(r#"page file="open id=1"#),
+      ("page", vec![("file", "open id=1")])
+    );
+  }
+
+  #[test]
+  fn parse_fnt() {
+    let file = BmFontFile::parse(FNT).unwrap();
+    assert_eq!(file.size, 16.0);
+    assert_eq!((file.line_height, file.base), (18.0, 14.0));
+    assert_eq!(file.pages, vec!["some font_0.png".to_string()]);
+    assert_eq!(file.glyphs.len(), 3);
+    let (c, a) = file.glyphs[0];
+    assert_eq!(c, 'A');
+    assert_eq!(a.rect, AtlasRect { x: 0, y: 0, width: 8, height: 10 });
+    assert_eq!(a.offset, Vec2::new(1.0, 4.0));
+    assert_eq!(a.advance, 9.0);
+    assert_eq!(file.kerning, vec![(('A', 'V'), -2.0)]);
+  }
+
+  #[test]
+  fn bad_fnt_files_are_errors() {
+    let with = |from: &str, to: &str| BmFontFile::parse(&FNT.replace(from, to));
+    assert!(BmFontFile::parse("").is_err());
+    assert!(with("common", "uncommon").is_err());
+    assert!(with("lineHeight=18", "lineHeight=x").is_err());
+    assert!(with(" xadvance=9", "").is_err());
+    assert!(with(r#" file="some font_0.png""#, "").is_err());
+    // a surrogate isn't a `char`
+    assert!(with("id=65", "id=55296").is_err());
+    assert!(with("xadvance=8 page=0", "xadvance=8 page=3").is_err());
+    // page ids past the count, or past any sane count
+    assert!(with("page id=0", "page id=1").is_err());
+    assert!(with("page id=0", "page id=4000000000").is_err());
+    assert!(with(" pages=1", "").is_ok());
+    assert_eq!(with(" pages=1", "").unwrap().pages.len(), 1);
+    let no_count = FNT.replace(" pages=1", "");
+    let far_page = no_count.replace("page id=0", "page id=300");
+    assert!(BmFontFile::parse(&far_page).is_err());
+  }
+
+  #[test]
+  fn layout_kerns_and_wraps() {
+    let font = font();
+    let layout = font.layout("AV", 1.0, None);
+    assert_eq!(layout.lines, 1);
+    assert_eq!(layout.glyphs.len(), 2);
+    assert_eq!(layout.glyphs[0].pos, Vec2::new(1.0, 4.0));
+    // 9 for the `A`, pulled 2 closer
+    assert_eq!(layout.glyphs[1].pos, Vec2::new(7.0, 4.0));
+    assert_eq!(layout.size, Vec2::new(15.0, 18.0));
+
+    let doubled = font.layout("AV", 2.0, None);
+    assert_eq!(doubled.size, layout.size * 2.0);
+
+    // "AV AV" is 34 wide, so it wraps at the space
+    let wrapped = font.layout("AV AV", 1.0, Some(20.0));
+    assert_eq!(wrapped.lines, 2);
+    assert_eq!(wrapped.glyphs[2].pos, Vec2::new(1.0, 22.0));
+    assert_eq!(wrapped.size, Vec2::new(15.0, 36.0));
+
+    // one long word breaks in the middle
+    let long = font.layout("AAAA", 1.0, Some(20.0));
+    assert_eq!(long.lines, 2);
+    assert_eq!(font.layout("A\n\nA", 1.0, None).lines, 3);
+    // unknown characters are skipped when there's no `?`
+    assert_eq!(font.layout("A~A", 1.0, None).glyphs.len(), 2);
+  }
+}
@@ -0,0 +1,533 @@
+//! Basic wrappers for [Texture](https://www.khronos.org/opengl/wiki/Texture)
+//! objects.
+//!
+//! Like the buffer wrapper, a [`Texture`] is just a name. What kind of texture
+//! it is gets decided by the [`TextureTarget`] you first bind it to.
+
+use crate::state::CompareFunc;
+use core::convert::TryInto;
+use ogl33::*;
+
+/// The kinds of texture that you can bind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureTarget {
+  /// A normal 2D image.
+  Texture2D = GL_TEXTURE_2D as isize,
+  /// Six square 2D images forming the sides of a cube.
+  CubeMap = GL_TEXTURE_CUBE_MAP as isize,
+  /// A 2D image with several samples per texel, for MSAA render targets.
+  ///
+  /// These can't be filtered or mipmapped, and shaders read them with
+  /// `sampler2DMS` and `texelFetch`.
+  Texture2DMultisample = GL_TEXTURE_2D_MULTISAMPLE as isize,
+  /// A stack of same sized 2D images, picked by layer index in the shader.
+  ///
+  /// Shaders read them with `sampler2DArray`, and filtering never blends
+  /// between layers.
+  Texture2DArray = GL_TEXTURE_2D_ARRAY as isize,
+  /// A volume of texels, sampled with a 3D coordinate.
+  Texture3D = GL_TEXTURE_3D as isize,
+}
+
+/// The faces of a cube map, in the order GL numbers them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+  /// The `+X` face.
+  PositiveX = GL_TEXTURE_CUBE_MAP_POSITIVE_X as isize,
+  /// The `-X` face.
+  NegativeX = GL_TEXTURE_CUBE_MAP_NEGATIVE_X as isize,
+  /// The `+Y` face.
+  PositiveY = GL_TEXTURE_CUBE_MAP_POSITIVE_Y as isize,
+  /// The `-Y` face.
+  NegativeY = GL_TEXTURE_CUBE_MAP_NEGATIVE_Y as isize,
+  /// The `+Z` face.
+  PositiveZ = GL_TEXTURE_CUBE_MAP_POSITIVE_Z as isize,
+  /// The `-Z` face.
+  NegativeZ = GL_TEXTURE_CUBE_MAP_NEGATIVE_Z as isize,
+}
+impl CubeFace {
+  /// All the faces, in GL order.
+  pub const ALL: [CubeFace; 6] = [
+    CubeFace::PositiveX,
+    CubeFace::NegativeX,
+    CubeFace::PositiveY,
+    CubeFace::NegativeY,
+    CubeFace::PositiveZ,
+    CubeFace::NegativeZ,
+  ];
+}
+
+/// The storage formats that textures and renderbuffers can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+  /// One channel, 8-bit unsigned normalized.
+  R8,
+  /// Two channels, 8-bit unsigned normalized.
+  RG8,
+  /// Two channels, 16-bit float.
+  RG16F,
+  /// Three channels, 16-bit float.
+  RGB16F,
+  /// Three channels, 32-bit float.
+  RGB32F,
+  /// Four channels, 8-bit unsigned normalized.
+  RGBA8,
+  /// Four channels, 8-bit sRGB color with linear alpha.
+  SRGB8Alpha8,
+  /// Four channels, 16-bit float.
+  RGBA16F,
+  /// Four channels, 32-bit float.
+  RGBA32F,
+  /// 24-bit depth.
+  Depth24,
+  /// 32-bit float depth.
+  Depth32F,
+  /// 24-bit depth and 8-bit stencil, packed together.
+  Depth24Stencil8,
+}
+impl TextureFormat {
+  /// The format value to pass as `internalformat`.
+  pub const fn internal_format(self) -> GLenum {
+    match self {
+      TextureFormat::R8 => GL_R8,
+      TextureFormat::RG8 => GL_RG8,
+      TextureFormat::RG16F => GL_RG16F,
+      TextureFormat::RGB16F => GL_RGB16F,
+      TextureFormat::RGB32F => GL_RGB32F,
+      TextureFormat::RGBA8 => GL_RGBA8,
+      TextureFormat::SRGB8Alpha8 => GL_SRGB8_ALPHA8,
+      TextureFormat::RGBA16F => GL_RGBA16F,
+      TextureFormat::RGBA32F => GL_RGBA32F,
+      TextureFormat::Depth24 => GL_DEPTH_COMPONENT24,
+      TextureFormat::Depth32F => GL_DEPTH_COMPONENT32F,
+      TextureFormat::Depth24Stencil8 => GL_DEPTH24_STENCIL8,
+    }
+  }
+
+  /// The pixel layout used when uploading data in this format.
+  pub const fn pixel_format(self) -> GLenum {
+    match self {
+      TextureFormat::R8 => GL_RED,
+      TextureFormat::RG8 | TextureFormat::RG16F => GL_RG,
+      TextureFormat::RGB16F | TextureFormat::RGB32F => GL_RGB,
+      TextureFormat::RGBA8
+      | TextureFormat::SRGB8Alpha8
+      | TextureFormat::RGBA16F
+      | TextureFormat::RGBA32F => GL_RGBA,
+      TextureFormat::Depth24 | TextureFormat::Depth32F => GL_DEPTH_COMPONENT,
+      TextureFormat::Depth24Stencil8 => GL_DEPTH_STENCIL,
+    }
+  }
+
+  /// The component type used when uploading data in this format.
+  pub const fn pixel_type(self) -> GLenum {
+    match self {
+      TextureFormat::R8
+      | TextureFormat::RG8
+      | TextureFormat::RGBA8
+      | TextureFormat::SRGB8Alpha8 => GL_UNSIGNED_BYTE,
+      TextureFormat::RG16F
+      | TextureFormat::RGB16F
+      | TextureFormat::RGB32F
+      | TextureFormat::RGBA16F
+      | TextureFormat::RGBA32F
+      | TextureFormat::Depth32F => GL_FLOAT,
+      TextureFormat::Depth24 => GL_UNSIGNED_INT,
+      TextureFormat::Depth24Stencil8 => GL_UNSIGNED_INT_24_8,
+    }
+  }
+
+  /// If this is a depth (or depth-stencil) format.
+  pub const fn is_depth(self) -> bool {
+    matches!(
+      self,
+      TextureFormat::Depth24
+        | TextureFormat::Depth32F
+        | TextureFormat::Depth24Stencil8
+    )
+  }
+
+  /// If this format also has stencil bits.
+  pub const fn has_stencil(self) -> bool {
+    matches!(self, TextureFormat::Depth24Stencil8)
+  }
+}
+
+/// How a texture is sampled when it's drawn smaller than it really is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinFilter {
+  /// Use the closest texel.
+  Nearest = GL_NEAREST as isize,
+  /// Blend the closest four texels.
+  Linear = GL_LINEAR as isize,
+  /// Closest texel of the closest mip level.
+  NearestMipmapNearest = GL_NEAREST_MIPMAP_NEAREST as isize,
+  /// Blended texels of the closest mip level.
+  LinearMipmapNearest = GL_LINEAR_MIPMAP_NEAREST as isize,
+  /// Closest texel of the two closest mip levels, blended.
+  NearestMipmapLinear = GL_NEAREST_MIPMAP_LINEAR as isize,
+  /// Blended texels of the two closest mip levels, blended ("trilinear").
+  LinearMipmapLinear = GL_LINEAR_MIPMAP_LINEAR as isize,
+}
+
+/// How a texture is sampled when it's drawn bigger than it really is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MagFilter {
+  /// Use the closest texel, this gives a "pixel art" look.
+  Nearest = GL_NEAREST as isize,
+  /// Blend the closest four texels.
+  Linear = GL_LINEAR as isize,
+}
+
+/// What happens with texture coordinates outside of `0.0 ..= 1.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureWrap {
+  /// The image repeats.
+  Repeat = GL_REPEAT as isize,
+  /// The image repeats, flipping each time.
+  MirroredRepeat = GL_MIRRORED_REPEAT as isize,
+  /// The edge texels are stretched out forever.
+  ClampToEdge = GL_CLAMP_TO_EDGE as isize,
+  /// The border color is used.
+  ClampToBorder = GL_CLAMP_TO_BORDER as isize,
+}
+
+/// Basic wrapper for a [Texture
+/// Object](https://www.khronos.org/opengl/wiki/Texture).
+pub struct Texture(pub GLuint);
+impl Texture {
+  /// Makes a new texture
+  pub fn new() -> Option<Self> {
+    let mut tex = 0;
+    unsafe { glGenTextures(1, &mut tex) };
+    if tex != 0 {
+      Some(Self(tex))
+    } else {
+      None
+    }
+  }
+
+  /// Bind this texture to the given target of the active texture unit.
+  pub fn bind(&self, target: TextureTarget) {
+    unsafe { glBindTexture(target as GLenum, self.0) }
+  }
+
+  /// Clear the texture binding for the given target of the active texture
+  /// unit.
+  pub fn clear_binding(target: TextureTarget) {
+    unsafe { glBindTexture(target as GLenum, 0) }
+  }
+
+  /// Deletes the texture.
+  pub fn delete(self) {
+    unsafe { glDeleteTextures(1, &self.0) }
+  }
+}
+
+/// Sets which texture unit the texture binding functions affect.
+///
+/// Unit `0` is `GL_TEXTURE0`, unit `1` is `GL_TEXTURE1`, and so on.
+pub fn active_texture(unit: u32) {
+  unsafe { glActiveTexture(GL_TEXTURE0 + unit) }
+}
+
+/// Sets the min and mag filters of the texture bound to a target.
+pub fn set_filters(target: TextureTarget, min: MinFilter, mag: MagFilter) {
+  unsafe {
+    glTexParameteri(target as GLenum, GL_TEXTURE_MIN_FILTER, min as GLint);
+    glTexParameteri(target as GLenum, GL_TEXTURE_MAG_FILTER, mag as GLint);
+  }
+}
+
+/// Sets the wrapping of the texture bound to a target, on all axes.
+pub fn set_wrap(target: TextureTarget, wrap: TextureWrap) {
+  unsafe {
+    glTexParameteri(target as GLenum, GL_TEXTURE_WRAP_S, wrap as GLint);
+    glTexParameteri(target as GLenum, GL_TEXTURE_WRAP_T, wrap as GLint);
+    glTexParameteri(target as GLenum, GL_TEXTURE_WRAP_R, wrap as GLint);
+  }
+}
+
+/// Sets the border color used with [`TextureWrap::ClampToBorder`].
+pub fn set_border_color(target: TextureTarget, color: [f32; 4]) {
+  unsafe {
+    glTexParameterfv(target as GLenum, GL_TEXTURE_BORDER_COLOR, color.as_ptr())
+  }
+}
+
+/// Sets the depth comparison of the depth texture bound to a target.
+///
+/// With `Some(func)` the texture has to be sampled with a shadow sampler
+/// (eg: `sampler2DShadow`). Each lookup compares the coordinate's `z` against
+/// the stored depth and gives `1.0` if it passes and `0.0` if not. With
+/// linear filtering the GPU blends four such results for free. With `None`
+/// the texture is sampled normally and gives the stored depth.
+pub fn set_depth_compare(target: TextureTarget, func: Option<CompareFunc>) {
+  let (mode, func) = match func {
+    Some(func) => (GL_COMPARE_REF_TO_TEXTURE, func as GLenum),
+    None => (GL_NONE, GL_LEQUAL),
+  };
+  unsafe {
+    glTexParameteri(target as GLenum, GL_TEXTURE_COMPARE_MODE, mode as GLint);
+    glTexParameteri(target as GLenum, GL_TEXTURE_COMPARE_FUNC, func as GLint);
+  }
+}
+
+/// Generates the mipmaps of the texture bound to a target, based on the base
+/// level.
+pub fn generate_mipmap(target: TextureTarget) {
+  unsafe { glGenerateMipmap(target as GLenum) }
+}
+
+/// Turns on seamless filtering between the faces of cube maps.
+///
+/// Without this, filtering near the edge of a face doesn't blend with the
+/// next face over, and you can see the seams. There's no reason to not turn
+/// this on, it's only off by default for backwards compatibility.
+///
+/// This is a global setting, not per texture. Making a
+/// [`TextureCube`](crate::cubemap::TextureCube) or a
+/// [`Skybox`](crate::skybox::Skybox) turns it on for you.
+pub fn enable_seamless_cube_maps() {
+  unsafe { glEnable(GL_TEXTURE_CUBE_MAP_SEAMLESS) }
+}
+
+/// Allocates storage for mip level `level` of the bound 2D texture.
+///
+/// If `data` is given it's uploaded as the initial contents, using the
+/// format's [`pixel_format`](TextureFormat::pixel_format) and
+/// [`pixel_type`](TextureFormat::pixel_type). Otherwise the contents are
+/// undefined, which is what you want for render targets. Rows of `data` are
+/// tightly packed, with no padding between them.
+///
+/// ## Panics
+/// * If `data` is too small for the given size, or the size doesn't fit in a
+///   `usize`.
+pub fn tex_image_2d(
+  format: TextureFormat,
+  level: u32,
+  width: u32,
+  height: u32,
+  data: Option<&[u8]>,
+) {
+  tex_image_2d_target(GL_TEXTURE_2D, format, level, width, height, data)
+}
+
+/// Allocates storage for mip level `level` of one face of the bound cube map.
+///
+/// Works just like [`tex_image_2d`]. Each face must be square, and all faces
+/// must be the same size.
+///
+/// ## Panics
+/// * If `data` is too small for the given size, or the size doesn't fit in a
+///   `usize`.
+pub fn tex_image_cube_face(
+  face: CubeFace,
+  format: TextureFormat,
+  level: u32,
+  size: u32,
+  data: Option<&[u8]>,
+) {
+  tex_image_2d_target(face as GLenum, format, level, size, size, data)
+}
+
+fn tex_image_2d_target(
+  image_target: GLenum,
+  format: TextureFormat,
+  level: u32,
+  width: u32,
+  height: u32,
+  data: Option<&[u8]>,
+) {
+  let ptr = match data {
+    Some(d) => {
+      assert!(d.len() >= upload_len(format, &[width, height]));
+      d.as_ptr().cast()
+    }
+    None => core::ptr::null(),
+  };
+  with_packed_rows(|| unsafe {
+    glTexImage2D(
+      image_target,
+      level.try_into().unwrap(),
+      format.internal_format() as GLint,
+      width.try_into().unwrap(),
+      height.try_into().unwrap(),
+      0,
+      format.pixel_format(),
+      format.pixel_type(),
+      ptr,
+    )
+  })
+}
+
+/// The number of bytes in an upload of the given size, with no row padding.
+///
+/// ## Panics
+/// * If the size doesn't fit in a `usize`.
+fn upload_len(format: TextureFormat, size: &[u32]) -> usize {
+  size
+    .iter()
+    .try_fold(bytes_per_pixel(format), |n, &d| n.checked_mul(d as usize))
+    .expect("The texture upload is too big to address")
+}
+
+/// Runs `f` with `GL_UNPACK_ALIGNMENT` set to 1, then puts the old value
+/// back.
+///
+/// Our pixel data never pads its rows, but GL assumes 4 byte rows by default,
+/// which would read past the end of formats like `R8`.
+fn with_packed_rows(f: impl FnOnce()) {
+  let mut alignment = 4;
+  unsafe {
+    glGetIntegerv(GL_UNPACK_ALIGNMENT, &mut alignment);
+    glPixelStorei(GL_UNPACK_ALIGNMENT, 1);
+  }
+  f();
+  unsafe { glPixelStorei(GL_UNPACK_ALIGNMENT, alignment) };
+}
+
+/// Replaces part of mip level `level` of the bound 2D texture.
+///
+/// The region starts at `(x, y)` and is `width` by `height` pixels.
+///
+/// ## Panics
+/// * If `data` is too small for the given size, or the size doesn't fit in a
+///   `usize`.
+pub fn tex_sub_image_2d(
+  format: TextureFormat,
+  level: u32,
+  [x, y]: [u32; 2],
+  width: u32,
+  height: u32,
+  data: &[u8],
+) {
+  assert!(data.len() >= upload_len(format, &[width, height]));
+  with_packed_rows(|| unsafe {
+    glTexSubImage2D(
+      GL_TEXTURE_2D,
+      level.try_into().unwrap(),
+      x.try_into().unwrap(),
+      y.try_into().unwrap(),
+      width.try_into().unwrap(),
+      height.try_into().unwrap(),
+      format.pixel_format(),
+      format.pixel_type(),
+      data.as_ptr().cast(),
+    )
+  })
+}
+
+/// Allocates storage for mip level `level` of the array or 3D texture bound
+/// to `target`.
+///
+/// For a [`Texture2DArray`](TextureTarget::Texture2DArray) the `depth` is the
+/// number of layers. Otherwise this works just like [`tex_image_2d`].
+///
+/// ## Panics
+/// * If `data` is too small for the given size, or the size doesn't fit in a
+///   `usize`.
+pub fn tex_image_3d(
+  target: TextureTarget,
+  format: TextureFormat,
+  level: u32,
+  [width, height, depth]: [u32; 3],
+  data: Option<&[u8]>,
+) {
+  let ptr = match data {
+    Some(d) => {
+      assert!(d.len() >= upload_len(format, &[width, height, depth]));
+      d.as_ptr().cast()
+    }
+    None => core::ptr::null(),
+  };
+  with_packed_rows(|| unsafe {
+    glTexImage3D(
+      target as GLenum,
+      level.try_into().unwrap(),
+      format.internal_format() as GLint,
+      width.try_into().unwrap(),
+      height.try_into().unwrap(),
+      depth.try_into().unwrap(),
+      0,
+      format.pixel_format(),
+      format.pixel_type(),
+      ptr,
+    )
+  })
+}
+
+/// Replaces a box of mip level `level` of the array or 3D texture bound to
+/// `target`.
+///
+/// The box starts at `offset` and is `size` texels big. To upload a single
+/// array layer use an offset of `[0, 0, layer]` and a size of `[width,
+/// height, 1]`.
+///
+/// ## Panics
+/// * If `data` is too small for the given size, or the size doesn't fit in a
+///   `usize`.
+pub fn tex_sub_image_3d(
+  target: TextureTarget,
+  format: TextureFormat,
+  level: u32,
+  offset: [u32; 3],
+  size: [u32; 3],
+  data: &[u8],
+) {
+  assert!(data.len() >= upload_len(format, &size));
+  with_packed_rows(|| unsafe {
+    glTexSubImage3D(
+      target as GLenum,
+      level.try_into().unwrap(),
+      offset[0].try_into().unwrap(),
+      offset[1].try_into().unwrap(),
+      offset[2].try_into().unwrap(),
+      size[0].try_into().unwrap(),
+      size[1].try_into().unwrap(),
+      size[2].try_into().unwrap(),
+      format.pixel_format(),
+      format.pixel_type(),
+      data.as_ptr().cast(),
+    )
+  })
+}
+
+/// Allocates storage for the bound multisample texture.
+///
+/// The contents start out undefined. The sample locations are the same for
+/// every texel, which is required if the texture will share a framebuffer
+/// with a multisample renderbuffer.
+pub fn tex_image_2d_multisample(
+  format: TextureFormat,
+  samples: u32,
+  width: u32,
+  height: u32,
+) {
+  unsafe {
+    glTexImage2DMultisample(
+      GL_TEXTURE_2D_MULTISAMPLE,
+      samples.try_into().unwrap(),
+      format.internal_format(),
+      width.try_into().unwrap(),
+      height.try_into().unwrap(),
+      GL_TRUE,
+    )
+  }
+}
+
+/// The number of bytes per pixel of upload data for a format.
+pub const fn bytes_per_pixel(format: TextureFormat) -> usize {
+  match format {
+    TextureFormat::R8 => 1,
+    TextureFormat::RG8 => 2,
+    TextureFormat::RGBA8
+    | TextureFormat::SRGB8Alpha8
+    | TextureFormat::Depth24
+    | TextureFormat::Depth32F
+    | TextureFormat::Depth24Stencil8 => 4,
+    TextureFormat::RG16F => 8,
+    TextureFormat::RGB16F | TextureFormat::RGB32F => 12,
+    TextureFormat::RGBA16F | TextureFormat::RGBA32F => 16,
+  }
+}
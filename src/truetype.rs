@@ -0,0 +1,890 @@
+//! Just enough
+//! [TrueType](https://docs.microsoft.com/en-us/typography/opentype/spec/)
+//! reading to turn glyphs into bitmaps.
+//!
+//! This handles `.ttf` files with `glyf` outlines: character lookup through
+//! `cmap` (formats 4 and 12), metrics, simple and composite glyphs, and pair
+//! kerning from the old `kern` table. It doesn't do hinting, `GPOS` kerning,
+//! or CFF outlines (most `.otf` files), so [`TrueType::parse`] rejects those.
+//!
+//! Outlines are rasterized with plain antialiasing: 4 sub-scanlines per row,
+//! with exact horizontal coverage on each. That's fine for text at normal UI
+//! sizes, which is all [`text`](crate::text) needs.
+
+use std::collections::HashMap;
+
+/// How many sub-scanlines each row of pixels is sampled at.
+const SUBSAMPLES: usize = 4;
+
+/// The biggest glyph bitmap [`TrueType::rasterize`] makes, on each side.
+pub const MAX_GLYPH_PIXELS: u32 = 4096;
+
+/// Big-endian reads that give `None` past the end.
+#[derive(Clone, Copy)]
+struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a> {
+  fn u8(self, at: usize) -> Option<u8> {
+    self.0.get(at).copied()
+  }
+  fn u16(self, at: usize) -> Option<u16> {
+    let s = self.0.get(at..at.checked_add(2)?)?;
+    Some(u16::from_be_bytes([s[0], s[1]]))
+  }
+  fn i16(self, at: usize) -> Option<i16> {
+    self.u16(at).map(|v| v as i16)
+  }
+  fn u32(self, at: usize) -> Option<u32> {
+    let s = self.0.get(at..at.checked_add(4)?)?;
+    Some(u32::from_be_bytes([s[0], s[1], s[2], s[3]]))
+  }
+  /// A 2.14 fixed point number.
+  fn f2dot14(self, at: usize) -> Option<f32> {
+    self.i16(at).map(|v| f32::from(v) / 16384.0)
+  }
+}
+
+/// A point of an outline, and if it's on the curve (rather than a control
+/// point).
+type Point = ([f32; 2], bool);
+
+/// Where a table is in the file.
+#[derive(Debug, Clone, Copy)]
+struct Table {
+  offset: usize,
+  len: usize,
+}
+
+/// A glyph turned into an 8-bit coverage bitmap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphBitmap {
+  /// Width in pixels.
+  pub width: u32,
+  /// Height in pixels.
+  pub height: u32,
+  /// Pixels from the pen position to the left edge of the bitmap.
+  pub left: i32,
+  /// Pixels from the baseline _up_ to the top edge of the bitmap.
+  pub top: i32,
+  /// Coverage, `0` to `255`, rows from top to bottom.
+  pub coverage: Vec<u8>,
+}
+
+/// Vertical metrics, in font units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VMetrics {
+  /// Height of the tallest glyphs above the baseline.
+  pub ascent: i16,
+  /// Depth of the lowest glyphs below the baseline (so this is negative).
+  pub descent: i16,
+  /// Extra space between lines.
+  pub line_gap: i16,
+}
+
+/// A parsed TrueType font file.
+#[derive(Debug, Clone)]
+pub struct TrueType {
+  data: Vec<u8>,
+  units_per_em: u16,
+  long_loca: bool,
+  num_glyphs: u16,
+  num_h_metrics: u16,
+  v_metrics: VMetrics,
+  cmap: usize,
+  loca: Table,
+  glyf: Table,
+  hmtx: Table,
+  kern: Option<Table>,
+}
+impl TrueType {
+  /// Reads the tables that glyph rendering needs.
+  pub fn parse(data: Vec<u8>) -> Result<Self, String> {
+    Self::parse_tables(&data)
+      .ok_or_else(|| "Malformed TrueType font".to_string())?
+      .map(|parts| Self { data, ..parts })
+  }
+
+  /// Does the work of `parse`, with `None` for data that ends early.
+  fn parse_tables(data: &[u8]) -> Option<Result<Self, String>> {
+    let r = Reader(data);
+    match r.u32(0)? {
+      0x0001_0000 | 0x7472_7565 => (),
+      0x4F54_544F => {
+        return Some(Err("CFF (OpenType) outlines aren't supported".into()))
+      }
+      0x7474_6366 => {
+        return Some(Err("Font collections aren't supported".into()))
+      }
+      _ => return Some(Err("Not a TrueType font".into())),
+    }
+    let mut tables = HashMap::new();
+    for i in 0..usize::from(r.u16(4)?) {
+      let record = 12 + i * 16;
+      let tag = data.get(record..record + 4)?;
+      let offset = r.u32(record + 8)? as usize;
+      let len = r.u32(record + 12)? as usize;
+      if offset.checked_add(len)? > data.len() {
+        return None;
+      }
+      tables.insert(tag, Table { offset, len });
+    }
+    for tag in ["cmap", "glyf", "head", "hhea", "hmtx", "loca", "maxp"].iter() {
+      if !tables.contains_key(tag.as_bytes()) {
+        return Some(Err(format!("Font is missing the `{}` table", tag)));
+      }
+    }
+    let head = tables[&b"head"[..]].offset;
+    let hhea = tables[&b"hhea"[..]].offset;
+    let maxp = tables[&b"maxp"[..]].offset;
+    let cmap = match find_cmap(r, tables[&b"cmap"[..]].offset) {
+      Some(cmap) => cmap,
+      None => return Some(Err("Font has no Unicode character map".into())),
+    };
+    Some(Ok(Self {
+      data: Vec::new(),
+      units_per_em: r.u16(head + 18)?.max(1),
+      long_loca: r.i16(head + 50)? != 0,
+      num_glyphs: r.u16(maxp + 4)?,
+      num_h_metrics: r.u16(hhea + 34)?.max(1),
+      v_metrics: VMetrics {
+        ascent: r.i16(hhea + 4)?,
+        descent: r.i16(hhea + 6)?,
+        line_gap: r.i16(hhea + 8)?,
+      },
+      cmap,
+      loca: tables[&b"loca"[..]],
+      glyf: tables[&b"glyf"[..]],
+      hmtx: tables[&b"hmtx"[..]],
+      kern: tables.get(&b"kern"[..]).copied(),
+    }))
+  }
+
+  fn reader(&self) -> Reader<'_> {
+    Reader(&self.data)
+  }
+
+  /// Font units per em. Multiply font units by `pixels / units_per_em` to
+  /// get pixels.
+  pub fn units_per_em(&self) -> u16 {
+    self.units_per_em
+  }
+
+  /// The scale that makes one em `pixels` tall.
+  pub fn scale_for_em(&self, pixels: f32) -> f32 {
+    pixels / f32::from(self.units_per_em)
+  }
+
+  /// Vertical metrics, in font units.
+  pub fn v_metrics(&self) -> VMetrics {
+    self.v_metrics
+  }
+
+  /// The number of glyphs in the font.
+  pub fn num_glyphs(&self) -> u16 {
+    self.num_glyphs
+  }
+
+  /// The glyph for a character, if the font has one.
+  pub fn glyph_index(&self, c: char) -> Option<u16> {
+    let r = self.reader();
+    let c = c as u32;
+    let glyph = match r.u16(self.cmap)? {
+      4 => {
+        if c > 0xFFFF {
+          return None;
+        }
+        let seg_x2 = usize::from(r.u16(self.cmap + 6)?);
+        let ends = self.cmap + 14;
+        let starts = ends + seg_x2 + 2;
+        let deltas = starts + seg_x2;
+        let range_offsets = deltas + seg_x2;
+        // the segments are sorted by their end code
+        let (mut lo, mut hi) = (0, seg_x2 / 2);
+        while lo < hi {
+          let mid = (lo + hi) / 2;
+          if u32::from(r.u16(ends + mid * 2)?) < c {
+            lo = mid + 1;
+          } else {
+            hi = mid;
+          }
+        }
+        let seg = lo * 2;
+        let start = u32::from(r.u16(starts + seg)?);
+        if lo == seg_x2 / 2 || c < start {
+          return None;
+        }
+        let delta = r.u16(deltas + seg)?;
+        let range_offset = usize::from(r.u16(range_offsets + seg)?);
+        if range_offset == 0 {
+          (c as u16).wrapping_add(delta)
+        } else {
+          let at =
+            range_offsets + seg + range_offset + (c - start) as usize * 2;
+          match r.u16(at)? {
+            0 => 0,
+            g => g.wrapping_add(delta),
+          }
+        }
+      }
+      12 => {
+        let groups = r.u32(self.cmap + 12)? as usize;
+        let (mut lo, mut hi) = (0, groups);
+        loop {
+          if lo >= hi {
+            return None;
+          }
+          let mid = (lo + hi) / 2;
+          let group = self.cmap + 16 + mid * 12;
+          let (start, end) = (r.u32(group)?, r.u32(group + 4)?);
+          if c < start {
+            hi = mid;
+          } else if c > end {
+            lo = mid + 1;
+          } else {
+            let glyph = r.u32(group + 8)?.checked_add(c - start)?;
+            break core::convert::TryFrom::try_from(glyph).ok()?;
+          }
+        }
+      }
+      _ => return None,
+    };
+    if glyph != 0 && glyph < self.num_glyphs {
+      Some(glyph)
+    } else {
+      None
+    }
+  }
+
+  /// How far the pen moves after a glyph, in font units.
+  pub fn advance_width(&self, glyph: u16) -> u16 {
+    let last = self.num_h_metrics - 1;
+    let at = self.hmtx.offset + usize::from(glyph.min(last)) * 4;
+    self.reader().u16(at).unwrap_or(0)
+  }
+
+  /// Extra space between a pair of glyphs, in font units. This is usually
+  /// negative (eg: "AV" pulls closer together).
+  ///
+  /// Only the first horizontal subtable of the old `kern` table is used.
+  pub fn kerning(&self, left: u16, right: u16) -> i16 {
+    self.kern_pair(left, right).unwrap_or(0)
+  }
+
+  fn kern_pair(&self, left: u16, right: u16) -> Option<i16> {
+    let r = self.reader();
+    let kern = self.kern?.offset;
+    if r.u16(kern)? != 0 || r.u16(kern + 2)? == 0 {
+      return None;
+    }
+    let sub = kern + 4;
+    let coverage = r.u16(sub + 4)?;
+    // format 0, horizontal, not cross-stream
+    if coverage >> 8 != 0 || coverage & 0b101 != 0b001 {
+      return None;
+    }
+    let pairs = usize::from(r.u16(sub + 6)?);
+    let key = u32::from(left) << 16 | u32::from(right);
+    let (mut lo, mut hi) = (0, pairs);
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      let pair = sub + 14 + mid * 6;
+      let here = r.u32(pair)?;
+      if here < key {
+        lo = mid + 1;
+      } else if here > key {
+        hi = mid;
+      } else {
+        return r.i16(pair + 4);
+      }
+    }
+    None
+  }
+
+  /// The bytes of a glyph's outline, empty for glyphs like space.
+  fn glyph_data(&self, glyph: u16) -> Option<&[u8]> {
+    let r = self.reader();
+    let i = usize::from(glyph);
+    let (start, end) = if self.long_loca {
+      let at = self.loca.offset + i * 4;
+      (r.u32(at)? as usize, r.u32(at + 4)? as usize)
+    } else {
+      let at = self.loca.offset + i * 2;
+      (usize::from(r.u16(at)?) * 2, usize::from(r.u16(at + 2)?) * 2)
+    };
+    if start >= end || end > self.glyf.len {
+      return Some(&[]);
+    }
+    self.data.get(self.glyf.offset + start..self.glyf.offset + end)
+  }
+
+  /// A glyph's outline as closed polylines, in font units with y up.
+  ///
+  /// Curves are split into `curve_steps` straight pieces each.
+  pub fn outline(&self, glyph: u16, curve_steps: u32) -> Vec<Vec<[f32; 2]>> {
+    let mut contours = Vec::new();
+    self.append_outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut |c| {
+      contours.push(flatten(c, curve_steps.max(1)))
+    });
+    contours
+  }
+
+  /// Gives each contour of a glyph to `out`, as `(x, y, on_curve)` points
+  /// with the transform `[a, b, c, d, dx, dy]` applied.
+  fn append_outline(
+    &self,
+    glyph: u16,
+    t: [f32; 6],
+    depth: u32,
+    out: &mut dyn FnMut(&[Point]),
+  ) -> Option<()> {
+    let data = self.glyph_data(glyph)?;
+    if data.is_empty() || depth > 8 {
+      return Some(());
+    }
+    let r = Reader(data);
+    let contours = r.i16(0)?;
+    if contours >= 0 {
+      let points = simple_points(r, contours as usize)?;
+      let mut start = 0;
+      for end in points.1 {
+        let contour: Vec<Point> = points.0[start..=end]
+          .iter()
+          .map(|&([x, y], on)| {
+            ([t[0] * x + t[2] * y + t[4], t[1] * x + t[3] * y + t[5]], on)
+          })
+          .collect();
+        out(&contour);
+        start = end + 1;
+      }
+      return Some(());
+    }
+    // composite glyph
+    const WORDS: u16 = 0x0001;
+    const XY_VALUES: u16 = 0x0002;
+    const SCALE: u16 = 0x0008;
+    const MORE: u16 = 0x0020;
+    const XY_SCALE: u16 = 0x0040;
+    const TWO_BY_TWO: u16 = 0x0080;
+    let mut at = 10;
+    loop {
+      let flags = r.u16(at)?;
+      let part = r.u16(at + 2)?;
+      at += 4;
+      let (dx, dy) = if flags & WORDS != 0 {
+        at += 4;
+        (f32::from(r.i16(at - 4)?), f32::from(r.i16(at - 2)?))
+      } else {
+        at += 2;
+        (f32::from(r.u8(at - 2)? as i8), f32::from(r.u8(at - 1)? as i8))
+      };
+      // point matching offsets are rare, and are skipped
+      let (dx, dy) = if flags & XY_VALUES != 0 { (dx, dy) } else { (0.0, 0.0) };
+      let [a, b, c, d] = if flags & SCALE != 0 {
+        at += 2;
+        let s = r.f2dot14(at - 2)?;
+        [s, 0.0, 0.0, s]
+      } else if flags & XY_SCALE != 0 {
+        at += 4;
+        [r.f2dot14(at - 4)?, 0.0, 0.0, r.f2dot14(at - 2)?]
+      } else if flags & TWO_BY_TWO != 0 {
+        at += 8;
+        [
+          r.f2dot14(at - 8)?,
+          r.f2dot14(at - 6)?,
+          r.f2dot14(at - 4)?,
+          r.f2dot14(at - 2)?,
+        ]
+      } else {
+        [1.0, 0.0, 0.0, 1.0]
+      };
+      // the part's transform, then this glyph's
+      let combined = [
+        t[0] * a + t[2] * b,
+        t[1] * a + t[3] * b,
+        t[0] * c + t[2] * d,
+        t[1] * c + t[3] * d,
+        t[0] * dx + t[2] * dy + t[4],
+        t[1] * dx + t[3] * dy + t[5],
+      ];
+      self.append_outline(part, combined, depth + 1, out)?;
+      if flags & MORE == 0 {
+        return Some(());
+      }
+    }
+  }
+
+  /// Turns a glyph into a coverage bitmap, at `scale` pixels per font unit.
+  ///
+  /// Glyphs with no outline (like space) give `None`, and so do glyphs that
+  /// would be more than [`MAX_GLYPH_PIXELS`] on a side.
+  pub fn rasterize(&self, glyph: u16, scale: f32) -> Option<GlyphBitmap> {
+    let steps = (scale * f32::from(self.units_per_em) / 8.0).ceil() as u32;
+    let contours = self.outline(glyph, steps.clamp(2, 16));
+    let points = contours.iter().flatten();
+    let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+    let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+    for p in points {
+      min_x = min_x.min(p[0]);
+      min_y = min_y.min(p[1]);
+      max_x = max_x.max(p[0]);
+      max_y = max_y.max(p[1]);
+    }
+    if min_x >= max_x || min_y >= max_y {
+      return None;
+    }
+    let (left, right) = ((min_x * scale).floor(), (max_x * scale).ceil());
+    let (bottom, top) = ((min_y * scale).floor(), (max_y * scale).ceil());
+    // composite glyphs can scale their parts up a lot, so check the size
+    // before it turns into an allocation. This is false for NaN too.
+    let max = MAX_GLYPH_PIXELS as f32;
+    let fits = |low: f32, high: f32| {
+      low.abs() <= f32::from(u16::MAX) && high - low <= max
+    };
+    if !fits(left, right) || !fits(bottom, top) {
+      return None;
+    }
+    let width = (right - left).max(1.0) as u32;
+    let height = (top - bottom).max(1.0) as u32;
+    let (left, top) = (left as i32, top as i32);
+    // into bitmap pixels, with y down
+    let edges: Vec<[f32; 4]> = contours
+      .iter()
+      .flat_map(|c| c.windows(2).map(|w| (w[0], w[1])))
+      .map(|(a, b)| {
+        [
+          a[0] * scale - left as f32,
+          top as f32 - a[1] * scale,
+          b[0] * scale - left as f32,
+          top as f32 - b[1] * scale,
+        ]
+      })
+      .filter(|e| e[1] != e[3])
+      .collect();
+    let coverage = fill_edges(&edges, width as usize, height as usize);
+    Some(GlyphBitmap { width, height, left, top, coverage })
+  }
+}
+
+/// Picks the best Unicode subtable, giving its offset.
+fn find_cmap(r: Reader<'_>, cmap: usize) -> Option<usize> {
+  let mut best: Option<(u8, usize)> = None;
+  for i in 0..usize::from(r.u16(cmap + 2)?) {
+    let record = cmap + 4 + i * 8;
+    let platform = r.u16(record)?;
+    let encoding = r.u16(record + 2)?;
+    let offset = cmap + r.u32(record + 4)? as usize;
+    let unicode =
+      platform == 0 || (platform == 3 && matches!(encoding, 1 | 10));
+    let rank = match r.u16(offset)? {
+      12 if unicode => 2,
+      4 if unicode => 1,
+      _ => continue,
+    };
+    if best.map(|(b, _)| rank > b).unwrap_or(true) {
+      best = Some((rank, offset));
+    }
+  }
+  best.map(|(_, offset)| offset)
+}
+
+/// Reads a simple glyph's points, and the index of each contour's last point.
+fn simple_points(
+  r: Reader<'_>,
+  contours: usize,
+) -> Option<(Vec<Point>, Vec<usize>)> {
+  const ON_CURVE: u8 = 0x01;
+  const X_SHORT: u8 = 0x02;
+  const Y_SHORT: u8 = 0x04;
+  const REPEAT: u8 = 0x08;
+  const X_SAME: u8 = 0x10;
+  const Y_SAME: u8 = 0x20;
+  let mut ends = Vec::with_capacity(contours);
+  for i in 0..contours {
+    let end = usize::from(r.u16(10 + i * 2)?);
+    if ends.last().map(|&last| end < last).unwrap_or(false) {
+      return None;
+    }
+    ends.push(end);
+  }
+  let count = ends.last().map(|e| e + 1).unwrap_or(0);
+  let mut at = 10 + contours * 2;
+  at += 2 + usize::from(r.u16(at)?);
+  let mut flags = Vec::with_capacity(count);
+  while flags.len() < count {
+    let flag = r.u8(at)?;
+    at += 1;
+    flags.push(flag);
+    if flag & REPEAT != 0 {
+      let times = r.u8(at)?;
+      at += 1;
+      for _ in 0..times {
+        flags.push(flag);
+      }
+    }
+  }
+  flags.truncate(count);
+  let mut read_coords = |short: u8, same: u8| -> Option<Vec<f32>> {
+    let mut value = 0_i32;
+    let mut out = Vec::with_capacity(count);
+    for &flag in &flags {
+      if flag & short != 0 {
+        let d = i32::from(r.u8(at)?);
+        at += 1;
+        value = value.wrapping_add(if flag & same != 0 { d } else { -d });
+      } else if flag & same == 0 {
+        value = value.wrapping_add(i32::from(r.i16(at)?));
+        at += 2;
+      }
+      out.push(value as f32);
+    }
+    Some(out)
+  };
+  let xs = read_coords(X_SHORT, X_SAME)?;
+  let ys = read_coords(Y_SHORT, Y_SAME)?;
+  let points = xs
+    .into_iter()
+    .zip(ys)
+    .zip(flags.iter())
+    .map(|((x, y), f)| ([x, y], f & ON_CURVE != 0))
+    .collect();
+  Some((points, ends))
+}
+
+/// Turns a contour of on and off curve points into a closed polyline.
+fn flatten(contour: &[Point], steps: u32) -> Vec<[f32; 2]> {
+  let mid =
+    |a: [f32; 2], b: [f32; 2]| [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+  let n = contour.len();
+  if n == 0 {
+    return Vec::new();
+  }
+  // start on a point that's on the curve, making one up if there are none
+  let first_on = contour.iter().position(|p| p.1);
+  let (start, rotate) = match first_on {
+    Some(i) => (contour[i].0, i),
+    None => (mid(contour[0].0, contour[n - 1].0), 0),
+  };
+  let mut out = vec![start];
+  let mut control: Option<[f32; 2]> = None;
+  let mut pen = start;
+  let skip = if first_on.is_some() { 1 } else { 0 };
+  for i in skip..=n {
+    // the loop wraps back around to `start` at the end
+    let (p, on) =
+      if i == n { (start, true) } else { contour[(rotate + i) % n] };
+    match (control, on) {
+      (None, true) => out.push(p),
+      (None, false) => {
+        control = Some(p);
+        continue;
+      }
+      (Some(c), true) => {
+        curve(&mut out, [pen, c, p], steps);
+        control = None;
+      }
+      (Some(c), false) => {
+        let implied = mid(c, p);
+        curve(&mut out, [pen, c, implied], steps);
+        control = Some(p);
+        pen = implied;
+        continue;
+      }
+    }
+    pen = p;
+  }
+  out
+}
+
+/// Adds the points of a quadratic curve, after the first one.
+fn curve(out: &mut Vec<[f32; 2]>, [a, c, b]: [[f32; 2]; 3], steps: u32) {
+  for s in 1..=steps {
+    let t = s as f32 / steps as f32;
+    let u = 1.0 - t;
+    out.push([
+      u * u * a[0] + 2.0 * u * t * c[0] + t * t * b[0],
+      u * u * a[1] + 2.0 * u * t * c[1] + t * t * b[1],
+    ]);
+  }
+}
+
+/// Fills closed edges `[x0, y0, x1, y1]` with the non-zero winding rule.
+fn fill_edges(edges: &[[f32; 4]], width: usize, height: usize) -> Vec<u8> {
+  let mut coverage = vec![0.0_f32; width * height];
+  let mut crossings: Vec<(f32, i32)> = Vec::new();
+  for row in 0..height {
+    let cells = &mut coverage[row * width..(row + 1) * width];
+    for sub in 0..SUBSAMPLES {
+      let y = row as f32 + (sub as f32 + 0.5) / SUBSAMPLES as f32;
+      crossings.clear();
+      for e in edges {
+        let (y0, y1) = (e[1], e[3]);
+        if (y0 <= y && y < y1) || (y1 <= y && y < y0) {
+          let x = e[0] + (y - y0) * (e[2] - e[0]) / (y1 - y0);
+          crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+        }
+      }
+      crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+      let mut winding = 0;
+      let mut span_start = 0.0;
+      for &(x, dir) in &crossings {
+        let was_inside = winding != 0;
+        winding += dir;
+        if !was_inside && winding != 0 {
+          span_start = x;
+        } else if was_inside && winding == 0 {
+          add_span(cells, span_start, x);
+        }
+      }
+    }
+  }
+  coverage
+    .into_iter()
+    .map(|c| ((c / SUBSAMPLES as f32).min(1.0) * 255.0 + 0.5) as u8)
+    .collect()
+}
+
+/// Adds the part of each cell that's between `from` and `to`.
+fn add_span(cells: &mut [f32], from: f32, to: f32) {
+  let from = from.max(0.0);
+  let to = to.min(cells.len() as f32);
+  if from >= to {
+    return;
+  }
+  for (x, cell) in
+    cells.iter_mut().enumerate().take(to.ceil() as usize).skip(from as usize)
+  {
+    let x = x as f32;
+    *cell += to.min(x + 1.0) - from.max(x);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn put16(out: &mut Vec<u8>, values: &[u16]) {
+    for v in values {
+      out.extend_from_slice(&v.to_be_bytes());
+    }
+  }
+
+  fn put32(out: &mut Vec<u8>, values: &[u32]) {
+    for v in values {
+      out.extend_from_slice(&v.to_be_bytes());
+    }
+  }
+
+  /// A format 4 subtable: `A..=C` to glyphs 10 to 12 by delta, `a` to 20
+  /// and `b` to nothing through the glyph array, then the end segment.
+  fn cmap_format_4() -> Vec<u8> {
+    let mut t = Vec::new();
+    put16(&mut t, &[4, 0, 0, 6, 4, 1, 2]);
+    put16(&mut t, &[67, 98, 0xFFFF, 0]);
+    put16(&mut t, &[65, 97, 0xFFFF]);
+    put16(&mut t, &[10_u16.wrapping_sub(65), 0, 1]);
+    put16(&mut t, &[0, 4, 0]);
+    put16(&mut t, &[20, 0]);
+    t
+  }
+
+  /// A format 12 subtable: `A..=Z` to glyphs 1 to 26, a block of emoji from
+  /// glyph 50, and a group that runs off the end of the glyph ids.
+  fn cmap_format_12() -> Vec<u8> {
+    let mut t = Vec::new();
+    put16(&mut t, &[12, 0]);
+    put32(&mut t, &[0, 0, 3]);
+    put32(&mut t, &[0x41, 0x5A, 1]);
+    put32(&mut t, &[0x1F600, 0x1F64F, 50]);
+    put32(&mut t, &[0x20000, 0x20001, u32::MAX]);
+    t
+  }
+
+  fn with_cmap(data: Vec<u8>) -> TrueType {
+    let empty = Table { offset: 0, len: 0 };
+    TrueType {
+      data,
+      units_per_em: 1000,
+      long_loca: false,
+      num_glyphs: 100,
+      num_h_metrics: 1,
+      v_metrics: VMetrics { ascent: 800, descent: -200, line_gap: 0 },
+      cmap: 0,
+      loca: empty,
+      glyf: empty,
+      hmtx: empty,
+      kern: None,
+    }
+  }
+
+  /// A whole font with one glyph, a 500 unit square, for `A`.
+  fn square_font() -> Vec<u8> {
+    let mut cmap = Vec::new();
+    put16(&mut cmap, &[0, 1, 3, 1]);
+    put32(&mut cmap, &[12]);
+    let mut format_4 = Vec::new();
+    put16(&mut format_4, &[4, 0, 0, 4, 4, 1, 0]);
+    put16(&mut format_4, &[65, 0xFFFF, 0, 65, 0xFFFF]);
+    put16(&mut format_4, &[1_u16.wrapping_sub(65), 1, 0, 0]);
+    cmap.extend(format_4);
+    let mut glyf = Vec::new();
+    put16(&mut glyf, &[1, 0, 0, 500, 500, 3, 0]);
+    glyf.extend_from_slice(&[1, 1, 1, 1]);
+    put16(&mut glyf, &[0, 500, 0, 500_u16.wrapping_neg()]);
+    put16(&mut glyf, &[0, 0, 500, 0]);
+    let mut head = vec![0; 54];
+    head[18..20].copy_from_slice(&1000_u16.to_be_bytes());
+    let mut hhea = vec![0; 36];
+    hhea[4..6].copy_from_slice(&800_i16.to_be_bytes());
+    hhea[6..8].copy_from_slice(&(-200_i16).to_be_bytes());
+    hhea[34..36].copy_from_slice(&2_u16.to_be_bytes());
+    let mut hmtx = Vec::new();
+    put16(&mut hmtx, &[250, 0, 600, 0]);
+    let mut loca = Vec::new();
+    put16(&mut loca, &[0, 0, glyf.len() as u16 / 2]);
+    let mut maxp = Vec::new();
+    put16(&mut maxp, &[0, 0x5000, 2]);
+    let tables = vec![
+      (b"cmap", cmap),
+      (b"glyf", glyf),
+      (b"head", head),
+      (b"hhea", hhea),
+      (b"hmtx", hmtx),
+      (b"loca", loca),
+      (b"maxp", maxp),
+    ];
+    let mut out = Vec::new();
+    put32(&mut out, &[0x0001_0000]);
+    put16(&mut out, &[tables.len() as u16, 0, 0, 0]);
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, table) in &tables {
+      out.extend_from_slice(&tag[..]);
+      put32(&mut out, &[0, offset as u32, table.len() as u32]);
+      offset += table.len();
+    }
+    for (_, table) in tables {
+      out.extend(table);
+    }
+    out
+  }
+
+  #[test]
+  fn cmap_format_4_lookups() {
+    let font = with_cmap(cmap_format_4());
+    assert_eq!(font.glyph_index('A'), Some(10));
+    assert_eq!(font.glyph_index('C'), Some(12));
+    assert_eq!(font.glyph_index('a'), Some(20));
+    assert_eq!(font.glyph_index('b'), None);
+    assert_eq!(font.glyph_index('@'), None);
+    assert_eq!(font.glyph_index('D'), None);
+    assert_eq!(font.glyph_index('z'), None);
+    assert_eq!(font.glyph_index('\u{1F600}'), None);
+  }
+
+  #[test]
+  fn cmap_format_12_lookups() {
+    let font = with_cmap(cmap_format_12());
+    assert_eq!(font.glyph_index('A'), Some(1));
+    assert_eq!(font.glyph_index('Z'), Some(26));
+    assert_eq!(font.glyph_index('\u{1F600}'), Some(50));
+    assert_eq!(font.glyph_index('@'), None);
+    // past `num_glyphs`, and past `u16::MAX` or `u32::MAX`
+    assert_eq!(font.glyph_index('\u{1F64F}'), None);
+    assert_eq!(font.glyph_index('\u{20000}'), None);
+    assert_eq!(font.glyph_index('\u{20001}'), None);
+  }
+
+  #[test]
+  fn truncated_cmaps_dont_panic() {
+    for table in [cmap_format_4(), cmap_format_12()].iter() {
+      for len in 0..table.len() {
+        let font = with_cmap(table[..len].to_vec());
+        for c in ['A', 'a', 'Z', '\u{1F600}'].iter() {
+          let _ = font.glyph_index(*c);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn flatten_square_and_curve() {
+    let square = [([0.0, 0.0], true), ([2.0, 0.0], true), ([2.0, 2.0], true)];
+    assert_eq!(
+      flatten(&square, 4),
+      vec![[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 0.0]]
+    );
+    let bump = [([0.0, 0.0], true), ([1.0, 2.0], false), ([2.0, 0.0], true)];
+    assert_eq!(
+      flatten(&bump, 2),
+      vec![[0.0, 0.0], [1.0, 1.0], [2.0, 0.0], [0.0, 0.0]]
+    );
+    // no points on the curve starts halfway between the last and first
+    let off = [([0.0, 0.0], false), ([2.0, 0.0], false), ([2.0, 2.0], false)];
+    assert_eq!(flatten(&off, 1)[0], [1.0, 1.0]);
+    assert!(flatten(&[], 4).is_empty());
+  }
+
+  #[test]
+  fn fill_edges_covers_a_square() {
+    // a 2x2 square at (1, 1) in a 4x4 bitmap
+    let edges = [[3.0, 1.0, 3.0, 3.0], [1.0, 3.0, 1.0, 1.0]];
+    let coverage = fill_edges(&edges, 4, 4);
+    for y in 0..4 {
+      for x in 0..4 {
+        let inside = (1..3).contains(&x) && (1..3).contains(&y);
+        let expected = if inside { 255 } else { 0 };
+        assert_eq!(coverage[y * 4 + x], expected, "({}, {})", x, y);
+      }
+    }
+    // half of each of two cells
+    let edges = [[1.5, 0.0, 1.5, 1.0], [0.5, 1.0, 0.5, 0.0]];
+    assert_eq!(fill_edges(&edges, 3, 1), vec![128, 128, 0]);
+  }
+
+  #[test]
+  fn parse_and_rasterize_a_square() {
+    let font = TrueType::parse(square_font()).unwrap();
+    assert_eq!(font.num_glyphs(), 2);
+    assert_eq!(font.units_per_em(), 1000);
+    assert_eq!(font.v_metrics().descent, -200);
+    assert_eq!(font.glyph_index('A'), Some(1));
+    assert_eq!(font.advance_width(1), 600);
+    let bitmap = font.rasterize(1, 0.01).unwrap();
+    assert_eq!((bitmap.width, bitmap.height), (5, 5));
+    assert_eq!((bitmap.left, bitmap.top), (0, 5));
+    assert!(bitmap.coverage.iter().all(|&c| c == 255));
+    assert_eq!(font.rasterize(0, 0.01), None);
+  }
+
+  #[test]
+  fn huge_glyphs_give_none() {
+    let font = TrueType::parse(square_font()).unwrap();
+    assert!(font.rasterize(1, 8.0).is_some());
+    assert_eq!(font.rasterize(1, 10.0), None);
+    assert_eq!(font.rasterize(1, 1.0e30), None);
+    assert_eq!(font.rasterize(1, f32::NAN), None);
+  }
+
+  #[test]
+  fn bad_fonts_dont_panic() {
+    assert!(TrueType::parse(Vec::new()).is_err());
+    assert!(TrueType::parse(b"not a font at all".to_vec()).is_err());
+    let good = square_font();
+    let poke = |bytes: Vec<u8>| {
+      if let Ok(font) = TrueType::parse(bytes) {
+        if let Some(glyph) = font.glyph_index('A') {
+          let _ = font.rasterize(glyph, 0.01);
+          let _ = font.advance_width(glyph);
+        }
+      }
+    };
+    for len in 0..good.len() {
+      poke(good[..len].to_vec());
+    }
+    for at in 0..good.len() {
+      for &byte in [0x00, 0x7F, 0x80, 0xFF].iter() {
+        let mut bad = good.clone();
+        bad[at] = byte;
+        poke(bad);
+      }
+    }
+  }
+}
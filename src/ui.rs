@@ -0,0 +1,996 @@
+//! A small immediate-mode debug UI, drawn over the scene.
+//!
+//! This works like [Dear ImGui](https://github.com/ocornut/imgui): there are
+//! no widget objects to keep around. Each frame you call a function for each
+//! widget, passing in your own values, and the function draws the widget and
+//! tells you if the user changed anything. Tweaking `camera_speed` is then
+//! just one slider call instead of a recompile.
+//!
+//! A [`Ui`] gets its input from the same [`InputEvent`] values as an
+//! [`InputState`](crate::input::InputState). Give each event to
+//! [`Ui::handle`] first, and only pass it on if the UI didn't use it, so that
+//! dragging a slider doesn't also spin the camera. The [`Ui`] itself doesn't
+//! touch GL at all, a [`UiRenderer`] draws what it built with a [`Font`].
+//! The `015-fps-movement` example puts its camera speed and mouse
+//! sensitivity in a window like this, shown with Tab.
+//!
+//! ```ignore
+//! let mut ui = Ui::new(UiStyle::for_font(&font, 1.0));
+//! let mut ui_renderer = UiRenderer::new()?;
+//! // each frame
+//! input.begin_frame();
+//! ui.begin_frame();
+//! for event in events {
+//!   if !ui.handle(&event) {
+//!     input.handle(&event);
+//!   }
+//! }
+//! ui.window("Tweaks", Vec2::new(10.0, 10.0), 260.0, |ui| {
+//!   ui.slider_f32("camera speed", &mut camera_speed, 1.0, 500.0);
+//!   ui.slider_f32("sensitivity", &mut sensitivity, 0.01, 1.0);
+//!   ui.checkbox("wireframe", &mut wireframe);
+//!   ui.label(&format!("{:.2} ms", frame_ms));
+//! });
+//! // draw the scene, then
+//! ui_renderer.draw(&ui, &font, width, height, &mut cache);
+//! ```
+//!
+//! Each widget's id comes from its label and its window's title, so labels
+//! should be unique within a window. Anything after a `##` in a label is only
+//! used for the id and isn't shown, so `"x##light"` and `"x##camera"` can
+//! both show up as "x".
+
+use crate::{
+  buffer_data,
+  input::{InputEvent, Key, MouseButton},
+  state::{RenderState, StateCache},
+  text::{Font, TextRenderer},
+  Buffer, BufferType, ShaderProgram, VertexArray,
+};
+use core::{
+  convert::TryInto,
+  hash::{Hash, Hasher},
+  mem::size_of,
+  ops::Range,
+};
+use ogl33::*;
+use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+use ultraviolet::{Vec2, Vec4};
+
+const VERT_SHADER: &str = r#"#version 330 core
+  layout (location = 0) in vec2 pos;
+  layout (location = 1) in vec4 color;
+
+  uniform vec2 screen_size;
+
+  out vec4 frag_color;
+
+  void main() {
+    frag_color = color;
+    vec2 ndc = pos / screen_size * 2.0 - 1.0;
+    gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+  }
+"#;
+
+const FRAG_SHADER: &str = r#"#version 330 core
+  in vec4 frag_color;
+
+  out vec4 final_color;
+
+  void main() {
+    final_color = frag_color;
+  }
+"#;
+
+/// A rectangle in pixels, from the top left of the window with `+y` going
+/// down.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UiRect {
+  /// The top left corner.
+  pub pos: Vec2,
+  /// Width and height.
+  pub size: Vec2,
+}
+impl UiRect {
+  /// Makes a rect from its top left corner and size.
+  pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+    Self { pos: Vec2::new(x, y), size: Vec2::new(width, height) }
+  }
+
+  /// If the point is inside the rect.
+  pub fn contains(&self, p: Vec2) -> bool {
+    let max = self.pos + self.size;
+    p.x >= self.pos.x && p.y >= self.pos.y && p.x < max.x && p.y < max.y
+  }
+
+  /// The rect made smaller by `amount` on every side.
+  pub fn shrink(&self, amount: f32) -> Self {
+    let size = self.size - Vec2::broadcast(2.0 * amount);
+    Self {
+      pos: self.pos + Vec2::broadcast(amount),
+      size: Vec2::new(size.x.max(0.0), size.y.max(0.0)),
+    }
+  }
+}
+
+/// Sizes and colors for the UI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiStyle {
+  /// Multiplies the font's size.
+  pub text_scale: f32,
+  /// The height of the title bar and of each widget.
+  pub row_height: f32,
+  /// Space between a window's edge and its widgets.
+  pub padding: f32,
+  /// Space between widgets.
+  pub spacing: f32,
+  /// Window background.
+  pub window: Vec4,
+  /// Window title bar.
+  pub title: Vec4,
+  /// Widget background.
+  pub widget: Vec4,
+  /// Widget background with the mouse over it.
+  pub hovered: Vec4,
+  /// Widget background while it's held down.
+  pub active: Vec4,
+  /// Slider fill and checkbox mark.
+  pub accent: Vec4,
+  /// Text.
+  pub text: Vec4,
+}
+impl Default for UiStyle {
+  /// Dark and see-through, with rows that fit a font about 16 pixels tall.
+  fn default() -> Self {
+    Self {
+      text_scale: 1.0,
+      row_height: 20.0,
+      padding: 6.0,
+      spacing: 4.0,
+      window: Vec4::new(0.08, 0.08, 0.1, 0.85),
+      title: Vec4::new(0.18, 0.24, 0.4, 0.95),
+      widget: Vec4::new(0.22, 0.22, 0.26, 1.0),
+      hovered: Vec4::new(0.3, 0.3, 0.36, 1.0),
+      active: Vec4::new(0.38, 0.38, 0.46, 1.0),
+      accent: Vec4::new(0.35, 0.55, 0.9, 1.0),
+      text: Vec4::one(),
+    }
+  }
+}
+impl UiStyle {
+  /// The default style, with rows sized to fit the font at `text_scale`.
+  pub fn for_font(font: &Font, text_scale: f32) -> Self {
+    Self {
+      text_scale,
+      row_height: (font.line_height() * text_scale).ceil() + 4.0,
+      ..Self::default()
+    }
+  }
+}
+
+/// How text sits within its rect. It's always centered vertically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+  /// Against the left edge.
+  Left,
+  /// In the middle.
+  Center,
+}
+
+/// Some text for the renderer to draw.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiText {
+  /// The text.
+  pub text: String,
+  /// The area to place it in. Text that doesn't fit isn't cut off.
+  pub rect: UiRect,
+  /// Where it goes in the area.
+  pub align: TextAlign,
+  /// Text color.
+  pub color: Vec4,
+}
+
+/// Everything one window draws, back to front: first the rects, then the
+/// text on top.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UiLayer {
+  /// Solid colored rects.
+  pub rects: Vec<(UiRect, Vec4)>,
+  /// Text.
+  pub texts: Vec<UiText>,
+}
+
+/// The window being built right now.
+#[derive(Debug, Clone, Copy)]
+struct WindowBuild {
+  id: u64,
+  pos: Vec2,
+  width: f32,
+  cursor: f32,
+  hovered: bool,
+  layer: usize,
+}
+
+/// One widget's slot in a window.
+#[derive(Debug, Clone, Copy)]
+struct Row {
+  id: u64,
+  layer: usize,
+  rect: UiRect,
+  window_hovered: bool,
+}
+
+/// The immediate-mode UI state.
+///
+/// Call [`begin_frame`](Self::begin_frame) before handling the frame's
+/// events, then build the UI with [`window`](Self::window). Widgets only do
+/// anything when they're called inside a window.
+///
+/// Whether the mouse is over the UI is checked against where the windows
+/// were last frame, since events come in before this frame's UI is built.
+#[derive(Debug, Clone, Default)]
+pub struct Ui {
+  /// Sizes and colors. Changes show up on the next window built.
+  pub style: UiStyle,
+  mouse: Vec2,
+  mouse_down: bool,
+  clicked: bool,
+  released: bool,
+  /// Buttons that went down over the UI and haven't come up yet.
+  buttons: HashSet<MouseButton>,
+  /// Keys the UI used that haven't come up yet.
+  keys_held: HashSet<Key>,
+  /// Keys the UI used this frame.
+  keys: Vec<Key>,
+  active: Option<u64>,
+  focused: Option<u64>,
+  drag_offset: Vec2,
+  window_pos: HashMap<u64, Vec2>,
+  window_rects: Vec<(u64, UiRect)>,
+  last_rects: Vec<(u64, UiRect)>,
+  current: Option<WindowBuild>,
+  layers: Vec<UiLayer>,
+}
+impl Ui {
+  /// Makes a new UI with the given style.
+  pub fn new(style: UiStyle) -> Self {
+    Self { style, ..Self::default() }
+  }
+
+  /// Clears out last frame's clicks, keys, and widgets.
+  pub fn begin_frame(&mut self) {
+    self.clicked = false;
+    self.released = false;
+    self.keys.clear();
+    self.layers.clear();
+    self.current = None;
+    self.last_rects = core::mem::take(&mut self.window_rects);
+    if !self.mouse_down {
+      self.active = None;
+    }
+  }
+
+  /// Updates the UI with an event, giving `true` if the UI used it.
+  ///
+  /// Used events shouldn't go on to the rest of the program:
+  /// * Mouse motion and the wheel are used while the mouse is over a window,
+  ///   or while a button that went down over a window is still held.
+  /// * A button press is used if it's over a window, and then so is that
+  ///   button's release. Pressing a button anywhere else takes the keyboard
+  ///   focus away from the UI.
+  /// * While a slider has the keyboard focus, the left and right arrows (and
+  ///   their releases) are used to nudge it, and escape is used to drop the
+  ///   focus. All other keys are left alone.
+  pub fn handle(&mut self, event: &InputEvent) -> bool {
+    match *event {
+      InputEvent::MouseMotion { x, y, .. } => {
+        self.mouse = Vec2::new(x as f32, y as f32);
+        self.wants_mouse()
+      }
+      InputEvent::MouseWheel { .. } => self.wants_mouse(),
+      InputEvent::MouseButton { button, pressed: true } => {
+        if !self.wants_mouse() {
+          self.focused = None;
+          return false;
+        }
+        self.buttons.insert(button);
+        if button == MouseButton::LEFT {
+          // a widget takes the focus back when it sees the click
+          self.focused = None;
+          self.mouse_down = true;
+          self.clicked = true;
+        }
+        true
+      }
+      InputEvent::MouseButton { button, pressed: false } => {
+        if !self.buttons.remove(&button) {
+          return false;
+        }
+        if button == MouseButton::LEFT {
+          self.mouse_down = false;
+          self.released = true;
+        }
+        true
+      }
+      InputEvent::Key { key, pressed: true } => {
+        if self.focused.is_none()
+          || ![Key::LEFT, Key::RIGHT, Key::ESCAPE].contains(&key)
+        {
+          return false;
+        }
+        if key == Key::ESCAPE {
+          self.focused = None;
+        } else {
+          self.keys.push(key);
+        }
+        self.keys_held.insert(key);
+        true
+      }
+      InputEvent::Key { key, pressed: false } => self.keys_held.remove(&key),
+    }
+  }
+
+  /// If the UI is using the mouse: it's over a window, or something in the
+  /// UI is being held down.
+  pub fn wants_mouse(&self) -> bool {
+    !self.buttons.is_empty()
+      || self.last_rects.iter().any(|(_, r)| r.contains(self.mouse))
+  }
+
+  /// If a widget has the keyboard focus.
+  pub fn wants_keyboard(&self) -> bool {
+    self.focused.is_some()
+  }
+
+  /// What to draw this frame, one layer per window in the order they were
+  /// built.
+  pub fn layers(&self) -> &[UiLayer] {
+    &self.layers
+  }
+
+  /// Builds a window, calling `build` to add its widgets.
+  ///
+  /// The window starts out at `pos`, and after that stays wherever the user
+  /// drags it by the title bar. Its height fits the widgets. Windows built
+  /// later are drawn on top.
+  pub fn window(
+    &mut self,
+    title: &str,
+    pos: Vec2,
+    width: f32,
+    build: impl FnOnce(&mut Self),
+  ) {
+    let style = self.style;
+    let id = hash_id(0, title);
+    let mut pos = *self.window_pos.entry(id).or_insert(pos);
+    let hovered = self
+      .last_rects
+      .iter()
+      .rev()
+      .find(|(_, r)| r.contains(self.mouse))
+      .map(|(top, _)| *top)
+      == Some(id);
+
+    let title_id = hash_id(id, "##title");
+    let title_rect = UiRect { pos, size: Vec2::new(width, style.row_height) };
+    if hovered && self.clicked && title_rect.contains(self.mouse) {
+      self.active = Some(title_id);
+      self.drag_offset = self.mouse - pos;
+    }
+    if self.active == Some(title_id) && self.mouse_down {
+      pos = self.mouse - self.drag_offset;
+      self.window_pos.insert(id, pos);
+    }
+
+    let layer = self.layers.len();
+    self.layers.push(UiLayer::default());
+    // the background is sized once the widgets are in
+    self.push_rect(layer, UiRect::default(), style.window);
+    let title_rect = UiRect { pos, size: Vec2::new(width, style.row_height) };
+    self.push_rect(layer, title_rect, style.title);
+    let title_text = UiRect {
+      pos: pos + Vec2::new(style.padding, 0.0),
+      size: Vec2::new(width - 2.0 * style.padding, style.row_height),
+    };
+    self.push_text(layer, title, title_text, TextAlign::Left, style.text);
+
+    let outer = self.current.replace(WindowBuild {
+      id,
+      pos,
+      width,
+      cursor: pos.y + style.row_height + style.padding,
+      hovered,
+      layer,
+    });
+    build(self);
+    let built = core::mem::replace(&mut self.current, outer);
+    let cursor = built.map_or(pos.y, |w| w.cursor);
+    let height =
+      (cursor - style.spacing + style.padding - pos.y).max(style.row_height);
+    let rect = UiRect { pos, size: Vec2::new(width, height) };
+    self.layers[layer].rects[0].0 = rect;
+    self.window_rects.push((id, rect));
+  }
+
+  /// A line of text.
+  pub fn label(&mut self, text: &str) {
+    if let Some(row) = self.next_row(text) {
+      let color = self.style.text;
+      self.push_text(row.layer, text, row.rect, TextAlign::Left, color);
+    }
+  }
+
+  /// A button as wide as the window. Gives `true` on the frame it's clicked,
+  /// which is when the mouse is let go over it.
+  pub fn button(&mut self, label: &str) -> bool {
+    let row = match self.next_row(label) {
+      Some(row) => row,
+      None => return false,
+    };
+    let hovered = row.window_hovered && row.rect.contains(self.mouse);
+    if hovered && self.clicked {
+      self.active = Some(row.id);
+    }
+    let clicked = hovered && self.released && self.active == Some(row.id);
+    let color = self.widget_color(row.id, hovered);
+    self.push_rect(row.layer, row.rect, color);
+    let text_color = self.style.text;
+    self.push_text(row.layer, label, row.rect, TextAlign::Center, text_color);
+    clicked
+  }
+
+  /// A box that flips `value` when clicked. Gives `true` if it changed.
+  pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+    let row = match self.next_row(label) {
+      Some(row) => row,
+      None => return false,
+    };
+    let style = self.style;
+    let hovered = row.window_hovered && row.rect.contains(self.mouse);
+    let changed = hovered && self.clicked;
+    if changed {
+      *value = !*value;
+      self.active = Some(row.id);
+    }
+    let size = style.row_height;
+    let check = UiRect { pos: row.rect.pos, size: Vec2::broadcast(size) };
+    let color = self.widget_color(row.id, hovered);
+    self.push_rect(row.layer, check, color);
+    if *value {
+      self.push_rect(row.layer, check.shrink(4.0), style.accent);
+    }
+    let text = UiRect {
+      pos: row.rect.pos + Vec2::new(size + style.spacing, 0.0),
+      size: Vec2::new(row.rect.size.x - size - style.spacing, size),
+    };
+    self.push_text(row.layer, label, text, TextAlign::Left, style.text);
+    changed
+  }
+
+  /// A slider for a value from `min` to `max`. Gives `true` if it changed.
+  ///
+  /// Drag it with the mouse, or after clicking it use the arrow keys to
+  /// nudge it by a hundredth of the range. The value is only clamped to the
+  /// range once the user touches the slider.
+  pub fn slider_f32(
+    &mut self,
+    label: &str,
+    value: &mut f32,
+    min: f32,
+    max: f32,
+  ) -> bool {
+    let old = *value;
+    let span = max - min;
+    self.slider(label, |drag, steps| {
+      if let Some(t) = drag {
+        *value = min + t * span;
+      }
+      if drag.is_some() || steps != 0 {
+        *value += steps as f32 * span / 100.0;
+        *value = value.clamp(min.min(max), min.max(max));
+      }
+      let t = if span != 0.0 { (*value - min) / span } else { 0.0 };
+      (t, format!("{:.3}", *value))
+    });
+    *value != old
+  }
+
+  /// A slider for a whole number from `min` to `max`. Gives `true` if it
+  /// changed.
+  ///
+  /// The arrow keys nudge it by one, otherwise this works like
+  /// [`slider_f32`](Self::slider_f32).
+  pub fn slider_i32(
+    &mut self,
+    label: &str,
+    value: &mut i32,
+    min: i32,
+    max: i32,
+  ) -> bool {
+    let old = *value;
+    let span = max as f32 - min as f32;
+    self.slider(label, |drag, steps| {
+      if let Some(t) = drag {
+        *value = min.saturating_add((t * span).round() as i32);
+      }
+      if drag.is_some() || steps != 0 {
+        *value = value.saturating_add(steps);
+        *value = (*value).clamp(min.min(max), min.max(max));
+      }
+      let t =
+        if span != 0.0 { (*value as f32 - min as f32) / span } else { 0.0 };
+      (t, value.to_string())
+    });
+    *value != old
+  }
+
+  /// The shared part of the sliders.
+  ///
+  /// `update` gets how far along the track the mouse is dragging (from 0.0
+  /// to 1.0) and how many steps the keys moved it. It gives back how far
+  /// along the track the value now is, and the text to show for it.
+  fn slider(
+    &mut self,
+    label: &str,
+    update: impl FnOnce(Option<f32>, i32) -> (f32, String),
+  ) {
+    let row = match self.next_row(label) {
+      Some(row) => row,
+      None => return,
+    };
+    let style = self.style;
+    let track_width = (row.rect.size.x * 0.6).floor();
+    let track = UiRect {
+      pos: row.rect.pos,
+      size: Vec2::new(track_width, style.row_height),
+    };
+    let hovered = row.window_hovered && track.contains(self.mouse);
+    if hovered && self.clicked {
+      self.active = Some(row.id);
+      self.focused = Some(row.id);
+    }
+    // a click that's let go within the frame still sets the value
+    let held = self.mouse_down || self.clicked;
+    let drag = if self.active == Some(row.id) && held {
+      let t = (self.mouse.x - track.pos.x) / track.size.x.max(1.0);
+      Some(t.clamp(0.0, 1.0))
+    } else {
+      None
+    };
+    let steps = if self.focused == Some(row.id) {
+      self
+        .keys
+        .iter()
+        .map(|&k| match k {
+          Key::LEFT => -1,
+          Key::RIGHT => 1,
+          _ => 0,
+        })
+        .sum()
+    } else {
+      0
+    };
+    let (t, value_text) = update(drag, steps);
+
+    let color = if self.focused == Some(row.id) && self.active.is_none() {
+      style.hovered
+    } else {
+      self.widget_color(row.id, hovered)
+    };
+    self.push_rect(row.layer, track, color);
+    let fill = UiRect {
+      pos: track.pos,
+      size: Vec2::new(track.size.x * t.clamp(0.0, 1.0), track.size.y),
+    };
+    self.push_rect(row.layer, fill.shrink(2.0), style.accent);
+    self.push_text(
+      row.layer,
+      &value_text,
+      track,
+      TextAlign::Center,
+      style.text,
+    );
+    let text = UiRect {
+      pos: row.rect.pos + Vec2::new(track_width + style.spacing, 0.0),
+      size: Vec2::new(
+        row.rect.size.x - track_width - style.spacing,
+        style.row_height,
+      ),
+    };
+    self.push_text(row.layer, label, text, TextAlign::Left, style.text);
+  }
+
+  /// Takes the next row of the current window, if there is one.
+  fn next_row(&mut self, label: &str) -> Option<Row> {
+    let style = self.style;
+    let window = self.current.as_mut()?;
+    let rect = UiRect {
+      pos: Vec2::new(window.pos.x + style.padding, window.cursor),
+      size: Vec2::new(window.width - 2.0 * style.padding, style.row_height),
+    };
+    window.cursor += style.row_height + style.spacing;
+    Some(Row {
+      id: hash_id(window.id, label),
+      layer: window.layer,
+      rect,
+      window_hovered: window.hovered,
+    })
+  }
+
+  fn widget_color(&self, id: u64, hovered: bool) -> Vec4 {
+    if self.active == Some(id) {
+      self.style.active
+    } else if hovered {
+      self.style.hovered
+    } else {
+      self.style.widget
+    }
+  }
+
+  fn push_rect(&mut self, layer: usize, rect: UiRect, color: Vec4) {
+    self.layers[layer].rects.push((rect, color));
+  }
+
+  fn push_text(
+    &mut self,
+    layer: usize,
+    label: &str,
+    rect: UiRect,
+    align: TextAlign,
+    color: Vec4,
+  ) {
+    let text = label.split("##").next().unwrap_or_default();
+    if !text.is_empty() {
+      self.layers[layer].texts.push(UiText {
+        text: text.to_string(),
+        rect,
+        align,
+        color,
+      });
+    }
+  }
+}
+
+fn hash_id(parent: u64, label: &str) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  parent.hash(&mut hasher);
+  label.hash(&mut hasher);
+  hasher.finish()
+}
+
+/// `x, y, r, g, b, a`
+type UiVertex = [f32; 6];
+
+/// Draws a [`Ui`].
+pub struct UiRenderer {
+  vao: VertexArray,
+  vbo: Buffer,
+  program: ShaderProgram,
+  screen_size_loc: GLint,
+  vertices: Vec<UiVertex>,
+  text: TextRenderer,
+}
+impl UiRenderer {
+  /// Makes the rect buffer and compiles the shaders.
+  pub fn new() -> Result<Self, String> {
+    let vao = VertexArray::new().ok_or("Couldn't make a VAO")?;
+    vao.bind();
+    let vbo = Buffer::new().ok_or("Couldn't make a VBO")?;
+    vbo.bind(BufferType::Array);
+    let stride: GLsizei = size_of::<UiVertex>().try_into().unwrap();
+    unsafe {
+      glVertexAttribPointer(
+        0,
+        2,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        core::ptr::null(),
+      );
+      glEnableVertexAttribArray(0);
+      glVertexAttribPointer(
+        1,
+        4,
+        GL_FLOAT,
+        GL_FALSE,
+        stride,
+        (2 * size_of::<f32>()) as *const _,
+      );
+      glEnableVertexAttribArray(1);
+    }
+    VertexArray::clear_binding();
+    Buffer::clear_binding(BufferType::Array);
+
+    let program = ShaderProgram::from_vert_frag(VERT_SHADER, FRAG_SHADER)?;
+    let screen_size_loc = program
+      .uniform_location("screen_size")
+      .ok_or("UI shader is missing `screen_size`")?;
+    let text = TextRenderer::new()?;
+    Ok(Self { vao, vbo, program, screen_size_loc, vertices: Vec::new(), text })
+  }
+
+  /// Draws this frame's UI over whatever's already there.
+  ///
+  /// All the rects go into one buffer. Each window then takes one draw call
+  /// for its rects, and one per font page for its text. The screen size is
+  /// in pixels.
+  pub fn draw(
+    &mut self,
+    ui: &Ui,
+    font: &Font,
+    width: u32,
+    height: u32,
+    cache: &mut StateCache,
+  ) {
+    let layers = ui.layers();
+    if layers.is_empty() {
+      return;
+    }
+    self.vertices.clear();
+    let mut ranges: Vec<Range<usize>> = Vec::with_capacity(layers.len());
+    for layer in layers {
+      let start = self.vertices.len();
+      for (rect, c) in &layer.rects {
+        let (min, max) = (rect.pos, rect.pos + rect.size);
+        let v = |x: f32, y: f32| [x, y, c.x, c.y, c.z, c.w];
+        self.vertices.extend_from_slice(&[
+          v(min.x, min.y),
+          v(min.x, max.y),
+          v(max.x, max.y),
+          v(max.x, max.y),
+          v(max.x, min.y),
+          v(min.x, min.y),
+        ]);
+      }
+      ranges.push(start..self.vertices.len());
+    }
+    self.vbo.bind(BufferType::Array);
+    buffer_data(
+      BufferType::Array,
+      bytemuck::cast_slice(&self.vertices),
+      GL_STREAM_DRAW,
+    );
+    Buffer::clear_binding(BufferType::Array);
+
+    let scale = ui.style.text_scale;
+    for (layer, range) in layers.iter().zip(ranges) {
+      if !range.is_empty() {
+        cache.apply(&RenderState::overlay());
+        self.program.use_program();
+        unsafe {
+          glUniform2f(self.screen_size_loc, width as f32, height as f32);
+        }
+        self.vao.bind();
+        unsafe {
+          glDrawArrays(
+            GL_TRIANGLES,
+            range.start as GLint,
+            range.len() as GLsizei,
+          )
+        };
+        VertexArray::clear_binding();
+      }
+      for text in &layer.texts {
+        let layout = font.layout(&text.text, scale, None);
+        let x = match text.align {
+          TextAlign::Left => text.rect.pos.x,
+          TextAlign::Center => {
+            text.rect.pos.x + (text.rect.size.x - layout.size.x) / 2.0
+          }
+        };
+        let y = text.rect.pos.y + (text.rect.size.y - layout.size.y) / 2.0;
+        let pos = Vec2::new(x.round(), y.round());
+        self.text.queue_layout(font, &layout, pos, text.color);
+      }
+      self.text.flush(width, height, cache);
+    }
+  }
+
+  /// Deletes the GL objects.
+  pub fn delete(self) {
+    self.vao.delete();
+    self.vbo.delete();
+    self.program.delete();
+    self.text.delete();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WINDOW_POS: Vec2 = Vec2 { x: 10.0, y: 10.0 };
+
+  fn motion(x: i32, y: i32) -> InputEvent {
+    InputEvent::MouseMotion { x, y, dx: 0, dy: 0 }
+  }
+
+  fn mouse(pressed: bool) -> InputEvent {
+    InputEvent::MouseButton { button: MouseButton::LEFT, pressed }
+  }
+
+  fn key(key: Key, pressed: bool) -> InputEvent {
+    InputEvent::Key { key, pressed }
+  }
+
+  /// Runs one frame: the events (giving what `handle` said for each), then a
+  /// 200 wide window at (10, 10).
+  ///
+  /// With the default style the title bar is y 10 to 30, and the widget rows
+  /// are 20 tall starting at y 36, 60, and 84. Slider tracks are x 16 to 128.
+  fn frame(
+    ui: &mut Ui,
+    events: &[InputEvent],
+    build: impl FnOnce(&mut Ui),
+  ) -> Vec<bool> {
+    ui.begin_frame();
+    let used = events.iter().map(|e| ui.handle(e)).collect();
+    ui.window("Tweaks", WINDOW_POS, 200.0, build);
+    used
+  }
+
+  #[test]
+  fn only_events_over_the_ui_are_used() {
+    let mut ui = Ui::default();
+    // nothing was built last frame, so nothing is over the UI yet
+    assert_eq!(frame(&mut ui, &[motion(50, 50)], |_| ()), [false]);
+    let events = [
+      motion(50, 20),
+      InputEvent::MouseWheel { x: 0, y: 1 },
+      motion(500, 500),
+      InputEvent::MouseWheel { x: 0, y: 1 },
+      key(Key::RIGHT, true),
+      key(Key::RIGHT, false),
+    ];
+    assert_eq!(
+      frame(&mut ui, &events, |_| ()),
+      [true, true, false, false, false, false]
+    );
+    assert!(!ui.wants_mouse());
+    assert!(!ui.wants_keyboard());
+  }
+
+  #[test]
+  fn presses_and_releases_pair_up() {
+    let mut ui = Ui::default();
+    frame(&mut ui, &[], |_| ());
+    // down over the UI, then dragged off: the UI keeps it all
+    let events = [motion(50, 20), mouse(true), motion(500, 500), mouse(false)];
+    assert_eq!(frame(&mut ui, &events, |_| ()), [true, true, true, true]);
+    // down off the UI, then let go over it: the UI never sees it
+    let events = [mouse(true), motion(50, 20), mouse(false), motion(500, 500)];
+    assert_eq!(frame(&mut ui, &events, |_| ()), [false, true, false, false]);
+    // other buttons pair up the same way
+    let right =
+      |pressed| InputEvent::MouseButton { button: MouseButton::RIGHT, pressed };
+    let events = [motion(50, 20), right(true), motion(500, 500), right(false)];
+    assert_eq!(frame(&mut ui, &events, |_| ()), [true, true, true, true]);
+  }
+
+  #[test]
+  fn buttons_click_on_release() {
+    let mut ui = Ui::default();
+    let mut clicks = Vec::new();
+    let mut button = |ui: &mut Ui| clicks.push(ui.button("go"));
+    frame(&mut ui, &[], &mut button);
+    frame(&mut ui, &[motion(50, 45), mouse(true)], &mut button);
+    frame(&mut ui, &[mouse(false)], &mut button);
+    // dragging off before letting go doesn't click
+    frame(&mut ui, &[mouse(true)], &mut button);
+    frame(&mut ui, &[motion(50, 70), mouse(false)], &mut button);
+    assert_eq!(clicks, [false, false, true, false, false]);
+  }
+
+  #[test]
+  fn checkboxes_flip_on_press() {
+    let mut ui = Ui::default();
+    let mut value = false;
+    let mut changes = Vec::new();
+    let mut build = |ui: &mut Ui| {
+      ui.label("a label");
+      changes.push(ui.checkbox("on", &mut value));
+    };
+    frame(&mut ui, &[], &mut build);
+    frame(&mut ui, &[motion(20, 70), mouse(true)], &mut build);
+    frame(&mut ui, &[mouse(false)], &mut build);
+    assert_eq!(changes, [false, true, false]);
+    assert!(value);
+  }
+
+  #[test]
+  fn sliders_drag_and_take_focus() {
+    fn slider(
+      ui: &mut Ui,
+      events: &[InputEvent],
+      speed: &mut f32,
+    ) -> Vec<bool> {
+      frame(ui, events, |ui| {
+        ui.slider_f32("camera speed", speed, 0.0, 200.0);
+      })
+    }
+    let mut ui = Ui::default();
+    let mut speed = 100.0;
+    slider(&mut ui, &[], &mut speed);
+    assert_eq!(speed, 100.0);
+    slider(&mut ui, &[motion(44, 45), mouse(true)], &mut speed);
+    assert_eq!(speed, 50.0);
+    assert!(ui.wants_keyboard());
+    // still dragging when the mouse leaves the window
+    slider(&mut ui, &[motion(500, 45)], &mut speed);
+    assert_eq!(speed, 200.0);
+    slider(&mut ui, &[motion(72, 45)], &mut speed);
+    assert_eq!(speed, 100.0);
+    slider(&mut ui, &[mouse(false), motion(16, 45)], &mut speed);
+    assert_eq!(speed, 100.0);
+
+    // the arrows nudge it while it has the focus
+    let events = [key(Key::RIGHT, true), key(Key::RIGHT, false)];
+    assert_eq!(slider(&mut ui, &events, &mut speed), [true, true]);
+    assert_eq!(speed, 102.0);
+    let events = [key(Key::LEFT, true), key(Key::LEFT, true)];
+    assert_eq!(slider(&mut ui, &events, &mut speed), [true, true]);
+    assert_eq!(speed, 98.0);
+    // other keys go to the program
+    let w = Key(i32::from(b'w'));
+    assert_eq!(slider(&mut ui, &[key(w, true)], &mut speed), [false]);
+    // escape drops the focus, and then the arrows go to the program too
+    let events = [key(Key::ESCAPE, true), key(Key::RIGHT, true)];
+    assert_eq!(slider(&mut ui, &events, &mut speed), [true, false]);
+    assert!(!ui.wants_keyboard());
+    assert_eq!(speed, 98.0);
+  }
+
+  #[test]
+  fn clicking_away_drops_the_focus() {
+    fn slider(
+      ui: &mut Ui,
+      events: &[InputEvent],
+      count: &mut i32,
+    ) -> Vec<bool> {
+      frame(ui, events, |ui| {
+        ui.slider_i32("count", count, 0, 10);
+      })
+    }
+    let mut ui = Ui::default();
+    let mut count = 5;
+    slider(&mut ui, &[], &mut count);
+    slider(&mut ui, &[motion(16, 45), mouse(true), mouse(false)], &mut count);
+    assert_eq!(count, 0);
+    assert!(ui.wants_keyboard());
+    let events = [motion(500, 500), mouse(true), key(Key::RIGHT, true)];
+    assert_eq!(slider(&mut ui, &events, &mut count), [false, false, false]);
+    assert!(!ui.wants_keyboard());
+    assert_eq!(count, 0);
+  }
+
+  #[test]
+  fn windows_drag_by_the_title() {
+    let mut ui = Ui::default();
+    let window_rect = |ui: &Ui| ui.layers()[0].rects[0].0;
+    frame(&mut ui, &[], |_| ());
+    assert_eq!(window_rect(&ui).pos, WINDOW_POS);
+    frame(&mut ui, &[motion(50, 20), mouse(true)], |_| ());
+    frame(&mut ui, &[motion(150, 120)], |_| ());
+    assert_eq!(window_rect(&ui).pos, Vec2::new(110.0, 110.0));
+    frame(&mut ui, &[mouse(false)], |_| ());
+    // and it stays there, even though the window asks for (10, 10)
+    frame(&mut ui, &[motion(500, 500)], |_| ());
+    assert_eq!(window_rect(&ui).pos, Vec2::new(110.0, 110.0));
+    // pressing in the body doesn't drag
+    frame(&mut ui, &[motion(150, 145), mouse(true), motion(300, 300)], |_| ());
+    assert_eq!(window_rect(&ui).pos, Vec2::new(110.0, 110.0));
+  }
+
+  #[test]
+  fn labels_hide_ids() {
+    let mut ui = Ui::default();
+    frame(&mut ui, &[], |ui| {
+      ui.label("shown##hidden");
+      ui.label("##all hidden");
+    });
+    let texts: Vec<&str> =
+      ui.layers()[0].texts.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(texts, ["Tweaks", "shown"]);
+    assert_eq!(ui.layers()[0].rects[0].0.size, Vec2::new(200.0, 76.0));
+  }
+}
@@ -0,0 +1,473 @@
+//! Uniform buffers, and the `std140` layout that they use.
+//!
+//! A _uniform block_ is a group of uniforms in a shader whose values come
+//! from a buffer instead of from `glUniform*` calls:
+//!
+//! ```glsl
+//! layout (std140) uniform Camera {
+//!   mat4 view;
+//!   mat4 projection;
+//! };
+//! ```
+//!
+//! The same buffer can feed that block in every program at once, so values
+//! that every draw needs (like the camera) get uploaded just once per frame.
+//!
+//! The catch is that the buffer's bytes have to match the `std140` layout
+//! rules, which has a lot of padding that `#[repr(C)]` doesn't (a `vec3` is
+//! aligned like a `vec4`, each element of an array is padded out to 16
+//! bytes, and so on). The [`Std140`] trait writes values in that layout, and
+//! the [`std140_struct!`](crate::std140_struct) macro implements it for your
+//! own structs.
+
+use crate::{buffer_data, Buffer, BufferType, ShaderProgram};
+use core::marker::PhantomData;
+use ogl33::*;
+use std::collections::HashMap;
+use ultraviolet::{Mat2, Mat3, Mat4, Vec2, Vec3, Vec4};
+
+/// Rounds `offset` up to the next multiple of `align`.
+pub const fn align_to(offset: usize, align: usize) -> usize {
+  offset.div_ceil(align) * align
+}
+
+/// A type that can be written into a uniform buffer with the `std140` layout.
+pub trait Std140 {
+  /// The alignment of the type in a block.
+  const ALIGN: usize;
+  /// The size of the type in a block. This _doesn't_ include padding after
+  /// the value, which the next field's alignment takes care of.
+  const SIZE: usize;
+
+  /// Writes the value into the start of `out`.
+  ///
+  /// ## Panics
+  /// * If `out` is smaller than `SIZE`.
+  fn write_std140(&self, out: &mut [u8]);
+
+  /// The value as `std140` bytes, padded out to a multiple of its alignment.
+  fn to_std140_bytes(&self) -> Vec<u8> {
+    let mut out = vec![0; align_to(Self::SIZE, Self::ALIGN)];
+    self.write_std140(&mut out);
+    out
+  }
+}
+
+fn write_f32s(out: &mut [u8], values: &[f32]) {
+  let bytes: &[u8] = bytemuck::cast_slice(values);
+  out[..bytes.len()].copy_from_slice(bytes);
+}
+
+impl Std140 for f32 {
+  const ALIGN: usize = 4;
+  const SIZE: usize = 4;
+  fn write_std140(&self, out: &mut [u8]) {
+    out[..4].copy_from_slice(&self.to_ne_bytes());
+  }
+}
+impl Std140 for i32 {
+  const ALIGN: usize = 4;
+  const SIZE: usize = 4;
+  fn write_std140(&self, out: &mut [u8]) {
+    out[..4].copy_from_slice(&self.to_ne_bytes());
+  }
+}
+impl Std140 for u32 {
+  const ALIGN: usize = 4;
+  const SIZE: usize = 4;
+  fn write_std140(&self, out: &mut [u8]) {
+    out[..4].copy_from_slice(&self.to_ne_bytes());
+  }
+}
+/// GLSL `bool`s are stored as a 4 byte `0` or `1`.
+impl Std140 for bool {
+  const ALIGN: usize = 4;
+  const SIZE: usize = 4;
+  fn write_std140(&self, out: &mut [u8]) {
+    (*self as u32).write_std140(out)
+  }
+}
+impl Std140 for Vec2 {
+  const ALIGN: usize = 8;
+  const SIZE: usize = 8;
+  fn write_std140(&self, out: &mut [u8]) {
+    write_f32s(out, &[self.x, self.y]);
+  }
+}
+/// A `vec3` has the alignment of a `vec4`, but a scalar can go in the gap
+/// after it.
+impl Std140 for Vec3 {
+  const ALIGN: usize = 16;
+  const SIZE: usize = 12;
+  fn write_std140(&self, out: &mut [u8]) {
+    write_f32s(out, &[self.x, self.y, self.z]);
+  }
+}
+impl Std140 for Vec4 {
+  const ALIGN: usize = 16;
+  const SIZE: usize = 16;
+  fn write_std140(&self, out: &mut [u8]) {
+    write_f32s(out, &[self.x, self.y, self.z, self.w]);
+  }
+}
+/// Matrices are stored as an array of columns, and like any array each
+/// column is padded out to 16 bytes.
+impl Std140 for Mat2 {
+  const ALIGN: usize = 16;
+  const SIZE: usize = 32;
+  fn write_std140(&self, out: &mut [u8]) {
+    for (i, col) in self.cols.iter().enumerate() {
+      col.write_std140(&mut out[i * 16..]);
+    }
+  }
+}
+impl Std140 for Mat3 {
+  const ALIGN: usize = 16;
+  const SIZE: usize = 48;
+  fn write_std140(&self, out: &mut [u8]) {
+    for (i, col) in self.cols.iter().enumerate() {
+      col.write_std140(&mut out[i * 16..]);
+    }
+  }
+}
+impl Std140 for Mat4 {
+  const ALIGN: usize = 16;
+  const SIZE: usize = 64;
+  fn write_std140(&self, out: &mut [u8]) {
+    write_f32s(out, self.as_slice());
+  }
+}
+/// Each array element is padded out to a multiple of 16 bytes.
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+  const ALIGN: usize = align_to(T::ALIGN, 16);
+  const SIZE: usize = align_to(T::SIZE, Self::ALIGN) * N;
+  fn write_std140(&self, out: &mut [u8]) {
+    let stride = align_to(T::SIZE, Self::ALIGN);
+    for (i, value) in self.iter().enumerate() {
+      value.write_std140(&mut out[i * stride..]);
+    }
+  }
+}
+
+/// Declares a struct and implements [`Std140`](crate::uniform::Std140) for
+/// it.
+///
+/// Every field must also be `Std140`. The fields are laid out in order with
+/// the `std140` rules, so the struct should list the same fields as the GLSL
+/// block. Structs made this way can be fields of other ones, like GLSL
+/// structs can.
+///
+/// ```
+/// use learn_opengl::{std140_struct, uniform::Std140};
+/// use ultraviolet::{Vec3, Vec4};
+///
+/// std140_struct! {
+///   /// A light, matching the GLSL struct.
+///   pub struct Light {
+///     pub position: Vec3,
+///     pub radius: f32,
+///     pub color: Vec4,
+///   }
+/// }
+/// // the `radius` fits in the gap after `position`.
+/// assert_eq!(Light::SIZE, 32);
+/// ```
+#[macro_export]
+macro_rules! std140_struct {
+  (
+    $(#[$meta:meta])*
+    $vis:vis struct $name:ident {
+      $(
+        $(#[$field_meta:meta])*
+        $field_vis:vis $field:ident : $ty:ty
+      ),* $(,)?
+    }
+  ) => {
+    $(#[$meta])*
+    $vis struct $name {
+      $(
+        $(#[$field_meta])*
+        $field_vis $field: $ty,
+      )*
+    }
+    impl $crate::uniform::Std140 for $name {
+      // structs are aligned like a vec4, or more if a member needs it.
+      const ALIGN: usize = {
+        let mut align = 16;
+        $(
+          if <$ty as $crate::uniform::Std140>::ALIGN > align {
+            align = <$ty as $crate::uniform::Std140>::ALIGN;
+          }
+        )*
+        align
+      };
+      const SIZE: usize = {
+        let mut offset = 0;
+        $(
+          offset = $crate::uniform::align_to(
+            offset,
+            <$ty as $crate::uniform::Std140>::ALIGN,
+          ) + <$ty as $crate::uniform::Std140>::SIZE;
+        )*
+        $crate::uniform::align_to(offset, Self::ALIGN)
+      };
+      #[allow(unused_assignments)]
+      fn write_std140(&self, out: &mut [u8]) {
+        let mut offset = 0;
+        $(
+          offset = $crate::uniform::align_to(
+            offset,
+            <$ty as $crate::uniform::Std140>::ALIGN,
+          );
+          $crate::uniform::Std140::write_std140(
+            &self.$field,
+            &mut out[offset..],
+          );
+          offset += <$ty as $crate::uniform::Std140>::SIZE;
+        )*
+      }
+    }
+  };
+}
+
+/// The number of uniform buffer binding points
+/// (`GL_MAX_UNIFORM_BUFFER_BINDINGS`).
+pub fn max_uniform_buffer_bindings() -> u32 {
+  let mut max = 0;
+  unsafe { glGetIntegerv(GL_MAX_UNIFORM_BUFFER_BINDINGS, &mut max) };
+  max.max(0) as u32
+}
+
+/// A uniform buffer holding one `T`, attached to a binding point.
+pub struct UniformBuffer<T: Std140> {
+  /// The buffer object.
+  pub buffer: Buffer,
+  binding: u32,
+  scratch: Vec<u8>,
+  _marker: PhantomData<T>,
+}
+impl<T: Std140> UniformBuffer<T> {
+  /// Makes the buffer, uploads the first value, and attaches it to a
+  /// binding point.
+  pub fn new(binding: u32, value: &T) -> Result<Self, String> {
+    let buffer = Buffer::new().ok_or("Couldn't make a uniform buffer")?;
+    let scratch = value.to_std140_bytes();
+    buffer.bind(BufferType::Uniform);
+    buffer_data(BufferType::Uniform, &scratch, GL_DYNAMIC_DRAW);
+    Buffer::clear_binding(BufferType::Uniform);
+    let out = Self { buffer, binding, scratch, _marker: PhantomData };
+    out.bind_base();
+    Ok(out)
+  }
+
+  /// The binding point this buffer attaches to.
+  pub fn binding(&self) -> u32 {
+    self.binding
+  }
+
+  /// Attaches the buffer to its binding point again, in case something else
+  /// was attached there since.
+  pub fn bind_base(&self) {
+    unsafe { glBindBufferBase(GL_UNIFORM_BUFFER, self.binding, self.buffer.0) }
+  }
+
+  /// Uploads a new value.
+  pub fn update(&mut self, value: &T) {
+    value.write_std140(&mut self.scratch);
+    self.buffer.bind(BufferType::Uniform);
+    unsafe {
+      glBufferSubData(
+        GL_UNIFORM_BUFFER,
+        0,
+        self.scratch.len() as GLsizeiptr,
+        self.scratch.as_ptr().cast(),
+      );
+    }
+    Buffer::clear_binding(BufferType::Uniform);
+  }
+
+  /// Deletes the buffer.
+  pub fn delete(self) {
+    self.buffer.delete()
+  }
+}
+
+/// Hands out binding points to uniform block names.
+///
+/// Every program that has a block by a registered name gets that block
+/// connected to the name's binding point when you [`connect`](Self::connect)
+/// it. Then one [`UniformBuffer`] on that binding point feeds all of them.
+#[derive(Debug, Clone, Default)]
+pub struct BlockBindings {
+  points: HashMap<String, u32>,
+}
+impl BlockBindings {
+  /// Makes an empty set of bindings.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The binding point for a block name, picking the next free one if the
+  /// name is new.
+  pub fn binding(&mut self, name: &str) -> Result<u32, String> {
+    if let Some(point) = self.points.get(name) {
+      return Ok(*point);
+    }
+    let point = self.points.len() as u32;
+    let max = max_uniform_buffer_bindings();
+    if point >= max {
+      return Err(format!(
+        "Block `{}` needs binding point {}, past the limit of {}",
+        name, point, max
+      ));
+    }
+    self.points.insert(name.to_string(), point);
+    Ok(point)
+  }
+
+  /// Connects each registered block that the program has to its binding
+  /// point. Gives the number of blocks connected.
+  pub fn connect(&self, program: &ShaderProgram) -> usize {
+    self
+      .points
+      .iter()
+      .filter(|(name, point)| program.bind_uniform_block(name, **point))
+      .count()
+  }
+}
+
+/// GLSL for the [`CameraBlock`], to paste into shaders after the
+/// `#version` line.
+pub const CAMERA_BLOCK_GLSL: &str = r#"
+  layout (std140) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_pos;
+  };
+"#;
+
+std140_struct! {
+  /// The usual per-frame camera values, matching [`CAMERA_BLOCK_GLSL`].
+  #[derive(Debug, Clone, Copy, PartialEq)]
+  pub struct CameraBlock {
+    /// The view matrix.
+    pub view: Mat4,
+    /// The projection matrix.
+    pub projection: Mat4,
+    /// `projection * view`.
+    pub view_projection: Mat4,
+    /// The camera position in world space.
+    pub camera_pos: Vec3,
+  }
+}
+impl CameraBlock {
+  /// The block name used in [`CAMERA_BLOCK_GLSL`].
+  pub const NAME: &'static str = "Camera";
+
+  /// Fills in the block, working out `view_projection`.
+  pub fn new(view: Mat4, projection: Mat4, camera_pos: Vec3) -> Self {
+    Self { view, projection, view_projection: projection * view, camera_pos }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_ne_bytes([
+      bytes[offset],
+      bytes[offset + 1],
+      bytes[offset + 2],
+      bytes[offset + 3],
+    ])
+  }
+
+  #[test]
+  fn camera_block_layout() {
+    assert_eq!(CameraBlock::ALIGN, 16);
+    // three mat4s, then a vec3 padded out to 16 bytes
+    assert_eq!(CameraBlock::SIZE, 3 * 64 + 16);
+    let block = CameraBlock {
+      view: Mat4::from_scale(1.0),
+      projection: Mat4::from_scale(2.0),
+      view_projection: Mat4::from_scale(3.0),
+      camera_pos: Vec3::new(4.0, 5.0, 6.0),
+    };
+    let bytes = block.to_std140_bytes();
+    assert_eq!(bytes.len(), 208);
+    assert_eq!(f32_at(&bytes, 0), 1.0);
+    assert_eq!(f32_at(&bytes, 64), 2.0);
+    assert_eq!(f32_at(&bytes, 128), 3.0);
+    assert_eq!(f32_at(&bytes, 128 + 60), 1.0);
+    assert_eq!(f32_at(&bytes, 192), 4.0);
+    assert_eq!(f32_at(&bytes, 196), 5.0);
+    assert_eq!(f32_at(&bytes, 200), 6.0);
+  }
+
+  std140_struct! {
+    struct Inner {
+      a: Vec3,
+      b: f32,
+    }
+  }
+
+  std140_struct! {
+    struct Mixed {
+      a: f32,
+      b: Vec2,
+      c: Vec3,
+      d: f32,
+      e: [f32; 2],
+      f: Mat3,
+      g: Inner,
+      h: bool,
+    }
+  }
+
+  #[test]
+  fn mixed_layout() {
+    // a @ 0, b @ 8, c @ 16, d @ 28 (in the vec3's gap), e @ 32 with a 16
+    // byte stride, f @ 64 with 16 byte columns, g @ 112, h @ 128
+    assert_eq!(Inner::SIZE, 16);
+    assert_eq!(<[f32; 2]>::SIZE, 32);
+    assert_eq!(Mixed::SIZE, 144);
+    let value = Mixed {
+      a: 1.0,
+      b: Vec2::new(2.0, 3.0),
+      c: Vec3::new(4.0, 5.0, 6.0),
+      d: 7.0,
+      e: [8.0, 9.0],
+      f: Mat3::new(
+        Vec3::new(10.0, 11.0, 12.0),
+        Vec3::new(13.0, 14.0, 15.0),
+        Vec3::new(16.0, 17.0, 18.0),
+      ),
+      g: Inner { a: Vec3::new(19.0, 20.0, 21.0), b: 22.0 },
+      h: true,
+    };
+    let bytes = value.to_std140_bytes();
+    assert_eq!(bytes.len(), 144);
+    let expected = [
+      (0, 1.0),
+      (8, 2.0),
+      (12, 3.0),
+      (16, 4.0),
+      (24, 6.0),
+      (28, 7.0),
+      (32, 8.0),
+      (48, 9.0),
+      (64, 10.0),
+      (80, 13.0),
+      (96, 16.0),
+      (104, 18.0),
+      (112, 19.0),
+      (124, 22.0),
+    ];
+    for &(offset, v) in expected.iter() {
+      assert_eq!(f32_at(&bytes, offset), v, "at offset {}", offset);
+    }
+    assert_eq!(&bytes[128..132], &1_u32.to_ne_bytes());
+  }
+}
@@ -0,0 +1,199 @@
+//! Handing out texture units to a program's sampler uniforms.
+//!
+//! Using a texture in a shader takes two steps that have to agree with each
+//! other: bind the texture to some unit `n`, and set the sampler uniform to
+//! `n`. Doing that by hand for every draw gets out of sync easily. Instead,
+//! list the textures a draw wants by uniform name and let [`TextureUnits`]
+//! pick the units, set the uniforms, and bind everything.
+//!
+//! Like the [`StateCache`](crate::state::StateCache), it remembers what it
+//! last bound and skips any calls that wouldn't change anything.
+
+use crate::{
+  sampler::Sampler,
+  texture::{self, Texture, TextureTarget},
+  ShaderProgram,
+};
+use ogl33::*;
+use std::collections::HashMap;
+
+/// The number of texture units available across all shader stages
+/// (`GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS`).
+pub fn max_texture_units() -> u32 {
+  let mut max = 0;
+  unsafe { glGetIntegerv(GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS, &mut max) };
+  max.max(0) as u32
+}
+
+/// One texture for a draw: which sampler uniform reads it, and how.
+#[derive(Clone, Copy)]
+pub struct TextureBinding<'a> {
+  /// The name of the sampler uniform.
+  pub name: &'a str,
+  /// The kind of texture.
+  pub target: TextureTarget,
+  /// The texture itself.
+  pub texture: &'a Texture,
+  /// A sampler to override the texture's own settings, if any.
+  pub sampler: Option<&'a Sampler>,
+}
+impl<'a> TextureBinding<'a> {
+  /// A 2D texture read with its own settings.
+  pub fn new(name: &'a str, texture: &'a Texture) -> Self {
+    Self { name, target: TextureTarget::Texture2D, texture, sampler: None }
+  }
+
+  /// Changes the kind of texture.
+  pub fn with_target(self, target: TextureTarget) -> Self {
+    Self { target, ..self }
+  }
+
+  /// Reads the texture through a sampler.
+  pub fn with_sampler(self, sampler: &'a Sampler) -> Self {
+    Self { sampler: Some(sampler), ..self }
+  }
+}
+
+/// Binds textures and samplers to units, skipping redundant calls.
+///
+/// The tracking only knows about binds that go through it, so if you bind
+/// textures or samplers directly you should [`invalidate`](Self::invalidate)
+/// afterward. The same goes for deleting things: GL can hand a deleted name
+/// out again, so tell the tracker with [`forget_texture`](Self::forget_texture),
+/// [`forget_sampler`](Self::forget_sampler), or
+/// [`forget_program`](Self::forget_program) before you delete one.
+#[derive(Debug, Clone, Default)]
+pub struct TextureUnits {
+  max_units: u32,
+  active: Option<u32>,
+  /// `(unit, target)` to texture name.
+  textures: HashMap<(u32, GLenum), GLuint>,
+  /// unit to sampler name.
+  samplers: HashMap<u32, GLuint>,
+  /// `(program, uniform location)` to the unit it was last set to.
+  uniforms: HashMap<(GLuint, GLint), u32>,
+  /// program to uniform name to location, `None` if it doesn't have one.
+  locations: HashMap<GLuint, HashMap<String, Option<GLint>>>,
+}
+impl TextureUnits {
+  /// Makes a new tracker, asking GL how many units there are.
+  pub fn new() -> Self {
+    Self { max_units: max_texture_units(), ..Self::default() }
+  }
+
+  /// The number of units that can be used.
+  pub fn max_units(&self) -> u32 {
+    self.max_units
+  }
+
+  /// Forgets everything that's been bound.
+  pub fn invalidate(&mut self) {
+    self.active = None;
+    self.textures.clear();
+    self.samplers.clear();
+    self.uniforms.clear();
+    self.locations.clear();
+  }
+
+  /// Forgets the uniform values of a program, call this when you delete one.
+  ///
+  /// GL can reuse the name of a deleted program for the next new one, and
+  /// the new program's uniforms won't have the old values.
+  pub fn forget_program(&mut self, program: &ShaderProgram) {
+    self.uniforms.retain(|(p, _), _| *p != program.0);
+    self.locations.remove(&program.0);
+  }
+
+  /// Forgets where a texture is bound, call this when you delete one.
+  ///
+  /// GL unbinds a deleted texture, and can reuse its name for the next new
+  /// one, which would otherwise look like it's already bound.
+  pub fn forget_texture(&mut self, texture: &Texture) {
+    self.textures.retain(|_, t| *t != texture.0);
+  }
+
+  /// Forgets where a sampler is bound, call this when you delete one.
+  ///
+  /// Same as with [`forget_texture`](Self::forget_texture), a new sampler can
+  /// get the deleted one's name.
+  pub fn forget_sampler(&mut self, sampler: &Sampler) {
+    self.samplers.retain(|_, s| *s != sampler.0);
+  }
+
+  /// Looks up a uniform location, asking GL only the first time.
+  fn location(&mut self, program: &ShaderProgram, name: &str) -> Option<GLint> {
+    let names = self.locations.entry(program.0).or_default();
+    match names.get(name) {
+      Some(loc) => *loc,
+      None => {
+        let loc = program.uniform_location(name);
+        names.insert(name.to_string(), loc);
+        loc
+      }
+    }
+  }
+
+  /// Binds a texture, and optionally a sampler, to a unit.
+  pub fn bind_unit(
+    &mut self,
+    unit: u32,
+    target: TextureTarget,
+    texture: &Texture,
+    sampler: Option<&Sampler>,
+  ) -> Result<(), String> {
+    if unit >= self.max_units {
+      return Err(format!(
+        "Texture unit {} is past the limit of {}",
+        unit, self.max_units
+      ));
+    }
+    let key = (unit, target as GLenum);
+    if self.textures.get(&key) != Some(&texture.0) {
+      if self.active != Some(unit) {
+        texture::active_texture(unit);
+        self.active = Some(unit);
+      }
+      texture.bind(target);
+      self.textures.insert(key, texture.0);
+    }
+    let sampler = sampler.map(|s| s.0).unwrap_or(0);
+    if self.samplers.get(&unit).copied().unwrap_or(0) != sampler {
+      unsafe { glBindSampler(unit, sampler) };
+      self.samplers.insert(unit, sampler);
+    }
+    Ok(())
+  }
+
+  /// Binds everything a draw needs, and points the program's sampler
+  /// uniforms at the right units.
+  ///
+  /// The bindings get units `0`, `1`, `2`, and so on, in order. Bindings for
+  /// uniforms that the program doesn't have (maybe because the compiler
+  /// removed an unused one) are skipped. The program must be in use.
+  pub fn apply(
+    &mut self,
+    program: &ShaderProgram,
+    bindings: &[TextureBinding<'_>],
+  ) -> Result<(), String> {
+    if bindings.len() > self.max_units as usize {
+      return Err(format!(
+        "{} textures is more than the limit of {} texture units",
+        bindings.len(),
+        self.max_units
+      ));
+    }
+    for (unit, binding) in bindings.iter().enumerate() {
+      let unit = unit as u32;
+      let loc = match self.location(program, binding.name) {
+        Some(loc) => loc,
+        None => continue,
+      };
+      if self.uniforms.get(&(program.0, loc)) != Some(&unit) {
+        unsafe { glUniform1i(loc, unit as GLint) };
+        self.uniforms.insert((program.0, loc), unit);
+      }
+      self.bind_unit(unit, binding.target, binding.texture, binding.sampler)?;
+    }
+    Ok(())
+  }
+}